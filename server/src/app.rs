@@ -1,19 +1,222 @@
+use anyhow::{Context, Result};
 use axum::{
     extract::DefaultBodyLimit,
-    routing::{delete, get, post},
+    http::{header, HeaderName, HeaderValue, Method},
+    routing::{delete, get, post, put},
     Router,
 };
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
 use crate::{handlers, state::AppState};
 
-pub fn build_router(state: AppState) -> Router {
-    Router::new()
-        .route("/upload", post(handlers::upload_file))
+/// Default for `MAX_BODY_SIZE_MB`, the cap on any buffered request body
+pub const DEFAULT_MAX_BODY_SIZE: usize = 100 * 1024 * 1024;
+
+/// `max_body_size` caps bodies read into memory by extractors. `/upload`
+/// only ever needs a text message, so it gets the much lower text limit and
+/// oversized requests are refused before being buffered. Direct file uploads
+/// stream to disk and enforce the storage backend's own limit.
+///
+/// Without `cors` browsers on other origins cannot call the API.
+pub fn build_router(state: AppState, max_body_size: usize, cors: Option<CorsLayer>) -> Router {
+    let router = Router::new()
+        .route(
+            "/upload",
+            post(handlers::upload_file)
+                .layer(DefaultBodyLimit::max(handlers::MAX_TEXT_SIZE.min(max_body_size))),
+        )
         .route("/upload/callback", post(handlers::qiniu_upload_callback))
+        .route("/upload/local/:key", put(handlers::local_upload))
+        .route("/blob/:id", get(handlers::download_blob))
         .route("/download/:id", get(handlers::download_file))
         .route("/files", get(handlers::list_files))
         .route("/files/:id", delete(handlers::delete_file))
         .route("/health", get(handlers::health_check))
-    .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
-        .with_state(state)
+        .route("/healthz", get(handlers::liveness))
+        .route("/metrics", get(handlers::metrics))
+        .layer(DefaultBodyLimit::max(max_body_size))
+        .with_state(state);
+    match cors {
+        Some(cors) => router.layer(cors),
+        None => router,
+    }
+}
+
+/// Build the CORS policy from `ALLOWED_ORIGINS`: `*` or a comma-separated
+/// list of origins such as `https://ui.example.com`. Empty means no CORS.
+pub fn cors_layer(allowed_origins: &str) -> Result<Option<CorsLayer>> {
+    let spec = allowed_origins.trim();
+    if spec.is_empty() {
+        return Ok(None);
+    }
+    let origin = if spec == "*" {
+        AllowOrigin::any()
+    } else {
+        let origins = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(|origin| {
+                HeaderValue::from_str(origin).with_context(|| format!("Invalid origin {:?}", origin))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+    let layer = CorsLayer::new()
+        .allow_origin(origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            HeaderName::from_static("x-upload-type"),
+            HeaderName::from_static("x-filename"),
+            HeaderName::from_static("x-download-limit"),
+            HeaderName::from_static("x-expire-secs"),
+        ]);
+    Ok(Some(layer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    async fn post_text(router: Router, len: usize) -> StatusCode {
+        let request = Request::post("/upload")
+            .header("x-upload-type", "text")
+            .body(Body::from(vec![b'a'; len]))
+            .unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn limits_text_upload_bodies() {
+        let router = build_router(AppState::default(), DEFAULT_MAX_BODY_SIZE, None);
+        assert_eq!(post_text(router.clone(), 16).await, StatusCode::OK);
+        assert_eq!(
+            post_text(router, handlers::MAX_TEXT_SIZE + 1).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let router = build_router(AppState::default(), 8, None);
+        assert_eq!(post_text(router, 16).await, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn reports_health_as_json() {
+        let router = build_router(AppState::default(), DEFAULT_MAX_BODY_SIZE, None);
+        assert_eq!(post_text(router.clone(), 4).await, StatusCode::OK);
+
+        let request = Request::get("/health").body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["file_count"], 1);
+        assert_eq!(health["qiniu_configured"], false);
+        assert!(health["storage"].is_null());
+        assert!(health["uptime_secs"].is_u64());
+
+        let request = Request::get("/healthz").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        assert_eq!(&body[..], b"OK");
+    }
+
+    async fn get_status(router: Router, path: &str) -> StatusCode {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn local_blobs_honor_download_limit() {
+        use crate::{
+            records::{ContentType, FileRecord, StorageType},
+            storage::LocalBackend,
+        };
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir().join(format!("xtool-blob-test-{}", std::process::id()));
+        let state = AppState {
+            storage: Some(Arc::new(LocalBackend::new(dir.clone(), 1024).unwrap())),
+            ..AppState::default()
+        };
+        std::fs::write(dir.join("xtool_123456"), b"data").unwrap();
+        state
+            .files
+            .insert(FileRecord {
+                id: "123456".to_string(),
+                filename: Some("a.bin".to_string()),
+                content_type: ContentType::File,
+                storage: StorageType::Local("xtool_123456".to_string()),
+                uploaded_at: 0,
+                expires_at: u64::MAX,
+                downloads_remaining: Some(1),
+            })
+            .unwrap();
+        let router = build_router(state, DEFAULT_MAX_BODY_SIZE, None);
+
+        // Handing out the link does not use up the download; fetching it does
+        for _ in 0..2 {
+            assert_eq!(get_status(router.clone(), "/download/123456").await, StatusCode::OK);
+        }
+        assert_eq!(get_status(router.clone(), "/blob/xtool_123456").await, StatusCode::NOT_FOUND);
+        assert_eq!(get_status(router.clone(), "/blob/123456").await, StatusCode::OK);
+        assert_eq!(get_status(router.clone(), "/blob/123456").await, StatusCode::GONE);
+        assert_eq!(get_status(router, "/download/123456").await, StatusCode::GONE);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    async fn preflight(router: Router, path: &str, origin: &str) -> axum::response::Response {
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri(path)
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-upload-type")
+            .body(Body::empty())
+            .unwrap();
+        router.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn answers_cors_preflight_for_allowed_origins() {
+        let cors = cors_layer("https://ui.example.com, http://localhost:5173").unwrap();
+        let router = build_router(AppState::default(), DEFAULT_MAX_BODY_SIZE, cors);
+        for path in ["/upload", "/download/123456", "/files"] {
+            let response = preflight(router.clone(), path, "http://localhost:5173").await;
+            assert_eq!(response.status(), StatusCode::OK, "{path}");
+            let headers = response.headers();
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "http://localhost:5173");
+            assert!(headers[header::ACCESS_CONTROL_ALLOW_METHODS]
+                .to_str()
+                .unwrap()
+                .contains("POST"));
+            assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+                .to_str()
+                .unwrap()
+                .contains("x-upload-type"));
+        }
+
+        let response = preflight(router, "/files", "https://evil.example.com").await;
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let cors = cors_layer("*").unwrap();
+        let router = build_router(AppState::default(), DEFAULT_MAX_BODY_SIZE, cors);
+        let response = preflight(router, "/upload", "https://any.example.com").await;
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[tokio::test]
+    async fn cors_is_off_by_default() {
+        assert!(cors_layer("  ").unwrap().is_none());
+        assert!(cors_layer("bad\norigin").is_err());
+        let router = build_router(AppState::default(), DEFAULT_MAX_BODY_SIZE, None);
+        let response = preflight(router, "/upload", "http://localhost:5173").await;
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
 }
@@ -1,23 +1,33 @@
 use axum::{
-    body::Bytes,
+    body::{Body, Bytes},
     extract::{Form, Path, State},
-    http::{HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
     Json,
 };
+use futures_util::StreamExt;
 use log::{error, info};
 use rand::Rng;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
 use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
+    metrics::StoreGauges,
     state::AppState,
     records::{ContentType, FileRecord, StorageType},
+    storage::{Download, UploadTarget},
+    store::DownloadOutcome,
+    tokens,
 };
 
-const MAX_TEXT_SIZE: usize = 10 * 1024 * 1024; // 10MB for text
+pub const MAX_TEXT_SIZE: usize = 10 * 1024 * 1024; // 10MB for text
+/// Longest TTL a client may request, and the default when none is given
 const MAX_FILE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const MIN_FILE_AGE: Duration = Duration::from_secs(60);
+const MAX_DOWNLOAD_LIMIT: u8 = 10;
 
 #[derive(serde::Serialize)]
 pub struct UploadResponse {
@@ -43,10 +53,62 @@ pub struct ListResponse {
     pub files: Vec<FileRecord>,
 }
 
-pub async fn health_check() -> &'static str {
+#[derive(serde::Serialize)]
+pub struct HealthResponse {
+    /// `ok`, or `error` when the record store cannot be read
+    pub status: &'static str,
+    pub uptime_secs: u64,
+    pub file_count: Option<u64>,
+    pub qiniu_configured: bool,
+    /// Storage backend for file uploads, if any
+    pub storage: Option<&'static str>,
+}
+
+pub async fn health_check(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let storage = state.storage.as_ref().map(|storage| storage.name());
+    let file_count = match state.files.list() {
+        Ok(files) => Some(files.len() as u64),
+        Err(e) => {
+            error!("Health check could not read records: {:#}", e);
+            None
+        }
+    };
+    let status = if file_count.is_some() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let health = HealthResponse {
+        status: if file_count.is_some() { "ok" } else { "error" },
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        file_count,
+        qiniu_configured: storage == Some("qiniu"),
+        storage,
+    };
+    (status, Json(health))
+}
+
+/// Plain liveness probe for checks that expect the literal `OK`
+pub async fn liveness() -> &'static str {
     "OK"
 }
 
+pub async fn metrics(State(state): State<AppState>) -> Result<Response, StatusCode> {
+    let files = state.files.list().map_err(store_error)?;
+    let memory_bytes = files
+        .iter()
+        .map(|record| match &record.storage {
+            StorageType::Memory(content) => content.len() as u64,
+            _ => 0,
+        })
+        .sum();
+    let body = state.metrics.render(&StoreGauges {
+        active_files: files.len() as u64,
+        memory_bytes,
+    });
+    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response())
+}
+
 pub async fn upload_file(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -56,28 +118,42 @@ pub async fn upload_file(
         .get("x-upload-type")
         .and_then(|v| v.to_str().ok())
         .unwrap_or("file"); // default to file
+    let download_limit = parse_download_limit(&headers)?;
+    let ttl = parse_ttl(&headers)?;
 
-    let id = generate_token();
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
+    let expires_at = now + ttl.as_secs();
+    let id = unused_token(&state, now, expires_at)?;
 
     if upload_type == "text" {
         if body.len() > MAX_TEXT_SIZE {
+            state.tokens.release(&id);
             return Err(StatusCode::PAYLOAD_TOO_LARGE);
         }
-        let content = String::from_utf8(body.to_vec()).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let content = match String::from_utf8(body.to_vec()) {
+            Ok(content) => content,
+            Err(_) => {
+                state.tokens.release(&id);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        };
         
-        let mut files = state.files.lock().expect("State lock poisoned");
-        files.insert(id.clone(), FileRecord {
+        let inserted = state.files.insert(FileRecord {
             id: id.clone(),
             filename: None,
             content_type: ContentType::Text,
             storage: StorageType::Memory(content),
             uploaded_at: now,
+            expires_at,
+            downloads_remaining: Some(download_limit),
         });
-        
+        state.tokens.release(&id);
+        inserted.map_err(store_error)?;
+        state.metrics.record_upload();
+
         info!("Text uploaded: id: {}", id);
         return Ok(Json(UploadResponse {
             id,
@@ -86,31 +162,42 @@ pub async fn upload_file(
             upload_url: None,
         }));
     } else {
-        // File upload - Qiniu
         let filename = headers
             .get("x-filename")
             .and_then(|v| v.to_str().ok())
             .unwrap_or("unnamed_file");
 
-        let qiniu = state.qiniu_config.as_ref().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        let Some(storage) = state.storage.as_ref() else {
+            state.tokens.release(&id);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
         
         let random_part = random_suffix();
-        let save_as_name = format!("xtool_{}_{}_{}", id, random_part, now);
-        let token_lifetime = Duration::from_secs(10 * 60);
+        // The limit and expiry ride along in the object key so finalizing
+        // the upload can restore them when the record is created
+        let save_as_name = format!(
+            "xtool_{}_{}_{}_{}_{}",
+            id, random_part, now, download_limit, expires_at
+        );
         
-        let upload_token = qiniu.generate_upload_token(&save_as_name, token_lifetime)
+        let target = storage.generate_upload_target(&save_as_name, ttl)
             .map_err(|e| {
-                error!("Failed to generate qiniu token: {}", e);
+                error!("Failed to prepare upload: {:#}", e);
+                state.tokens.release(&id);
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
+        let (upload_token, upload_url) = match target {
+            UploadTarget::Token(token) => (Some(token), None),
+            UploadTarget::Url(url) => (None, Some(url)),
+        };
 
         info!("File upload prepared: {} (save_as: {})", filename, save_as_name);
         
         return Ok(Json(UploadResponse {
             id,
             filename: Some(filename.to_string()),
-            upload_token: Some(upload_token),
-            upload_url: None,
+            upload_token,
+            upload_url,
         }));
     }
 }
@@ -125,58 +212,123 @@ pub async fn qiniu_upload_callback(
     State(state): State<AppState>,
     Form(payload): Form<QiniuCallbackPayload>,
 ) -> Result<Json<UploadResponse>, StatusCode> {
-    let (id, filename) = parse_key_and_filename(&payload.key, payload.fname.as_deref());
+    let response = register_upload(&state, &payload.key, payload.fname.as_deref())?;
+    info!("Qiniu callback registered file: {:?} (id: {})", response.filename, response.id);
+    Ok(Json(response))
+}
+
+/// Receive a direct upload for backends that store files on this server.
+/// `key` must have been issued by `/upload`.
+pub async fn local_upload(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<UploadResponse>, StatusCode> {
+    let storage = state.storage.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let path = storage.upload_path(&key).ok_or(StatusCode::NOT_FOUND)?;
+    let max_size = storage.max_upload_size_bytes();
+
+    let partial = path.with_extension("part");
+    let result = async {
+        let mut file = tokio::fs::File::create(&partial).await.map_err(io_error)?;
+        let mut written: u64 = 0;
+        let mut stream = body.into_data_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+            written += chunk.len() as u64;
+            if written > max_size {
+                return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            }
+            file.write_all(&chunk).await.map_err(io_error)?;
+        }
+        file.flush().await.map_err(io_error)?;
+        tokio::fs::rename(&partial, &path).await.map_err(io_error)
+    }
+    .await;
+    if let Err(status) = result {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(status);
+    }
+
+    let fname = headers.get("x-filename").and_then(|v| v.to_str().ok());
+    let response = register_upload(&state, &key, fname)?;
+    info!("Local upload registered file: {:?} (id: {})", response.filename, response.id);
+    Ok(Json(response))
+}
+
+/// Finalize an uploaded object and create its record from the key.
+fn register_upload(
+    state: &AppState,
+    key: &str,
+    fname: Option<&str>,
+) -> Result<UploadResponse, StatusCode> {
+    let storage = state.storage.as_ref().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stored = storage.finalize(key).map_err(|e| {
+        error!("Failed to finalize upload: {:#}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let KeyInfo {
+        id,
+        filename,
+        download_limit,
+        expires_at,
+    } = parse_key_and_filename(key, fname);
 
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
 
-    let mut files = state.files.lock().expect("State lock poisoned");
-    files.insert(
-        id.clone(),
-        FileRecord {
+    state
+        .files
+        .insert(FileRecord {
             id: id.clone(),
             filename: Some(filename.clone()),
             content_type: ContentType::File,
-            storage: StorageType::Qiniu(payload.key.clone()),
+            storage: stored,
             uploaded_at: now,
-        },
-    );
-
-    info!("Qiniu callback registered file: {} (id: {})", filename, id);
-
-    Ok(Json(UploadResponse {
+            expires_at: expires_at.unwrap_or(now + MAX_FILE_AGE.as_secs()),
+            downloads_remaining: download_limit,
+        })
+        .map_err(store_error)?;
+    state.tokens.release(&id);
+    state.metrics.record_upload();
+
+    Ok(UploadResponse {
         id,
         filename: Some(filename),
         upload_token: None,
         upload_url: None,
-    }))
+    })
 }
 
 pub async fn download_file(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Response, StatusCode> {
-    let mut files = state.files.lock().expect("State lock poisoned");
-    
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
 
-    if let Some(record) = files.get(&id) {
-        if now.saturating_sub(record.uploaded_at) > MAX_FILE_AGE.as_secs() {
-            info!("File expired: {}", id);
-            files.remove(&id);
-            return Err(StatusCode::NOT_FOUND); 
-        }
+    // Files on this server are counted when `/blob` serves them, so the
+    // link handed out here is no good once they are used up or expired
+    let peeked = ready_record(&id, state.files.peek_download(&id, now).map_err(store_error)?)?;
+    if let StorageType::Local(_) = peeked.storage {
+        let resp = DownloadResponse {
+            url: Some(format!("/blob/{}", id)),
+            content: None,
+            filename: peeked.filename.clone(),
+            content_type: peeked.content_type.clone(),
+        };
+        return Ok(Json(resp).into_response());
     }
 
-    let record = files.get(&id).cloned().ok_or(StatusCode::NOT_FOUND)?;
-    
-    // Unlock early
-    drop(files);
+    // The store checks expiry and counts the download atomically, so
+    // concurrent requests cannot both take the last one
+    let record = take_download(&state, &id, now)?;
 
     match &record.storage {
         StorageType::Memory(content) => {
@@ -188,11 +340,14 @@ pub async fn download_file(
             };
             Ok(Json(resp).into_response())
         }
-        StorageType::Qiniu(key) => {
-             let qiniu = state.qiniu_config.as_ref().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-             let url = qiniu.get_download_url(key);
-             
-             let resp = DownloadResponse {
+        StorageType::Qiniu(key) | StorageType::Local(key) => {
+            let storage = state.storage.as_ref().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+            let url = match storage.download_url_or_bytes(key).map_err(storage_error)? {
+                Download::Url(url) => url,
+                Download::File(_) => format!("/blob/{}", id),
+            };
+
+            let resp = DownloadResponse {
                 url: Some(url),
                 content: None,
                 filename: record.filename.clone(),
@@ -203,29 +358,164 @@ pub async fn download_file(
     }
 }
 
-pub async fn list_files(State(state): State<AppState>) -> Json<ListResponse> {
-    let files = state.files.lock().expect("State lock poisoned");
-    let file_list: Vec<FileRecord> = files.values().cloned().collect();
-    Json(ListResponse { files: file_list })
+/// Serve a file kept by a local storage backend, using up one of its downloads.
+pub async fn download_blob(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let storage = state.storage.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let record = take_download(&state, &id, now)?;
+    let StorageType::Local(key) = &record.storage else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    match storage.download_url_or_bytes(key).map_err(|_| StatusCode::NOT_FOUND)? {
+        Download::Url(url) => Ok(Redirect::temporary(&url).into_response()),
+        Download::File(path) => {
+            let file = tokio::fs::File::open(&path)
+                .await
+                .map_err(|_| StatusCode::NOT_FOUND)?;
+            let body = Body::from_stream(ReaderStream::new(file));
+            Ok(([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response())
+        }
+    }
+}
+
+/// Use up one download of `id` and count it.
+fn take_download(state: &AppState, id: &str, now: u64) -> Result<FileRecord, StatusCode> {
+    let record = ready_record(id, state.files.take_download(id, now).map_err(store_error)?)?;
+    state.metrics.record_download();
+    if let Some(remaining) = record.downloads_remaining {
+        info!("Download of {}: {} remaining", id, remaining);
+    }
+    Ok(record)
+}
+
+fn ready_record(id: &str, outcome: DownloadOutcome) -> Result<FileRecord, StatusCode> {
+    match outcome {
+        DownloadOutcome::Ready(record) => Ok(record),
+        DownloadOutcome::NotFound => Err(StatusCode::NOT_FOUND),
+        DownloadOutcome::Expired => {
+            info!("File expired: {}", id);
+            Err(StatusCode::NOT_FOUND)
+        }
+        DownloadOutcome::LimitReached => {
+            info!("Download limit reached: {}", id);
+            Err(StatusCode::GONE)
+        }
+    }
+}
+
+pub async fn list_files(State(state): State<AppState>) -> Result<Json<ListResponse>, StatusCode> {
+    let files = state.files.list().map_err(store_error)?;
+    Ok(Json(ListResponse { files }))
 }
 
 pub async fn delete_file(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<StatusCode, StatusCode> {
-    let mut files = state.files.lock().expect("State lock poisoned");
-    if files.remove(&id).is_some() {
+    if let Some(record) = state.files.delete(&id).map_err(store_error)? {
         info!("File deleted: {}", id);
+        release_storage(&state, vec![record]).await;
         Ok(StatusCode::NO_CONTENT)
     } else {
         Err(StatusCode::NOT_FOUND)
     }
 }
 
-fn generate_token() -> String {
-    let mut rng = rand::rng();
-    let token: u32 = rng.random_range(100000..999999);
-    token.to_string()
+fn store_error(e: anyhow::Error) -> StatusCode {
+    error!("Record store error: {:#}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn storage_error(e: anyhow::Error) -> StatusCode {
+    error!("Storage backend error: {:#}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn io_error(e: std::io::Error) -> StatusCode {
+    error!("Failed to write upload: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Ask the storage backend to drop the objects behind removed records.
+/// Failures are only logged; the records are already gone.
+async fn release_storage(state: &AppState, records: Vec<FileRecord>) {
+    let Some(storage) = state.storage.clone() else {
+        return;
+    };
+    let keys: Vec<String> = records
+        .into_iter()
+        .filter_map(|r| match r.storage {
+            StorageType::Qiniu(key) | StorageType::Local(key) => Some(key),
+            StorageType::Memory(_) => None,
+        })
+        .collect();
+    if keys.is_empty() {
+        return;
+    }
+    // Backends may block on network calls
+    let result = tokio::task::spawn_blocking(move || {
+        for key in keys {
+            if let Err(e) = storage.set_lifecycle(&key, Duration::ZERO) {
+                error!("Failed to remove stored object: {:#}", e);
+            }
+        }
+    })
+    .await;
+    if let Err(e) = result {
+        error!("Storage cleanup task failed: {}", e);
+    }
+}
+
+/// Read `x-download-limit` (1-10). Missing means a single download.
+fn parse_download_limit(headers: &HeaderMap) -> Result<u8, StatusCode> {
+    let Some(value) = headers.get("x-download-limit") else {
+        return Ok(1);
+    };
+    let limit = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u8>().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if !(1..=MAX_DOWNLOAD_LIMIT).contains(&limit) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(limit)
+}
+
+/// Read `x-expire-secs`, clamped to the server's allowed range.
+/// Missing means the maximum.
+fn parse_ttl(headers: &HeaderMap) -> Result<Duration, StatusCode> {
+    let Some(value) = headers.get("x-expire-secs") else {
+        return Ok(MAX_FILE_AGE);
+    };
+    let secs = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    Ok(Duration::from_secs(secs).clamp(MIN_FILE_AGE, MAX_FILE_AGE))
+}
+
+/// Reserve a token held by neither a stored record nor an upload still in
+/// flight. The reservation is released once the record is inserted.
+fn unused_token(state: &AppState, now: u64, expires_at: u64) -> Result<String, StatusCode> {
+    match state
+        .tokens
+        .allocate(state.files.as_ref(), now, expires_at)
+        .map_err(store_error)?
+    {
+        Some(id) => Ok(id),
+        None => {
+            error!("Could not find an unused token");
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
 }
 
 fn random_suffix() -> String {
@@ -244,19 +534,35 @@ fn sanitize_filename(filename: &str) -> String {
         .replace('\\', "_")
 }
 
-fn parse_key_and_filename(key: &str, fallback_name: Option<&str>) -> (String, String) {
+struct KeyInfo {
+    id: String,
+    filename: String,
+    download_limit: Option<u8>,
+    expires_at: Option<u64>,
+}
+
+/// Split `xtool_<id>_<random>_<time>[_<limit>[_<expires_at>]]`.
+/// Keys issued before limits existed allow unlimited downloads.
+fn parse_key_and_filename(key: &str, fallback_name: Option<&str>) -> KeyInfo {
     let mut parts = key.split('_');
     let _prefix = parts.next();
     let id = parts.next().unwrap_or_default().to_string();
+    let download_limit = parts.nth(2).and_then(|v| v.parse::<u8>().ok());
+    let expires_at = parts.next().and_then(|v| v.parse::<u64>().ok());
 
     let filename = fallback_name
         .map(|name| sanitize_filename(name))
         .filter(|name| !name.is_empty())
         .unwrap_or_else(|| "file.bin".to_string());
 
-    let id = if id.is_empty() { generate_token() } else { id };
+    let id = if id.is_empty() { tokens::generate_token() } else { id };
 
-    (id, filename)
+    KeyInfo {
+        id,
+        filename,
+        download_limit,
+        expires_at,
+    }
 }
 
 pub async fn cleanup_expired_files_task(state: AppState) {
@@ -269,35 +575,29 @@ pub async fn cleanup_expired_files_task(state: AppState) {
     loop {
         interval.tick().await;
         info!("Running cleanup task...");
+        state.metrics.record_cleanup_run();
         
-        // Use a block to ensure lock is dropped quickly
-        let removed_count = {
-            let mut files = match state.files.lock() {
-                Ok(guard) => guard,
-                Err(poisoned) => {
-                    error!("State lock poisoned during cleanup");
-                    poisoned.into_inner()
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let removed_count = match state.files.remove_expired(now) {
+            Ok(removed) => {
+                for record in &removed {
+                    let age = now.saturating_sub(record.uploaded_at);
+                    info!("Cleanup removing expired file: {} (age: {}s)", record.id, age);
                 }
-            };
-
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            
-            let initial_count = files.len();
-            files.retain(|id, record| {
-                let age = now.saturating_sub(record.uploaded_at);
-                if age > MAX_FILE_AGE.as_secs() {
-                    info!("Cleanup removing expired file: {} (age: {}s)", id, age);
-                    false
-                } else {
-                    true
-                }
-            });
-            initial_count - files.len()
+                let count = removed.len();
+                release_storage(&state, removed).await;
+                count
+            }
+            Err(e) => {
+                error!("Cleanup failed: {:#}", e);
+                0
+            }
         };
-        
+
         if removed_count > 0 {
             info!("Cleanup task removed {} expired file(s)", removed_count);
         }
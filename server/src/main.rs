@@ -1,13 +1,17 @@
 mod app;
 mod handlers;
+mod metrics;
 mod state;
 mod records;
 mod qiniu;
+mod storage;
+mod store;
+mod tokens;
 
 use app::build_router;
 use log::{info, error};
 use state::AppState;
-use std::{env, fs::OpenOptions};
+use std::{env, fs::OpenOptions, path::PathBuf, process, sync::Arc};
 use env_logger::Target;
 use qiniu::QiniuClient;
 
@@ -31,8 +35,81 @@ async fn main() {
 
     info!("Starting transfer server...");
 
-    let mut state = AppState::new();
+    // DATABASE_PATH keeps records in SQLite across restarts; otherwise in memory
+    let mut state = match env::var("DATABASE_PATH") {
+        Ok(path) if !path.trim().is_empty() => {
+            let path = PathBuf::from(path.trim());
+            let store = store::SqliteStore::open(&path).expect("Failed to open database");
+            info!("Storing records in {}", path.display());
+            AppState::new(Arc::new(store))
+        }
+        _ => {
+            info!("Storing records in memory (set DATABASE_PATH to persist them)");
+            AppState::default()
+        }
+    };
+
+    // STORAGE_BACKEND picks where uploaded files are kept
+    let backend = env::var("STORAGE_BACKEND").unwrap_or_else(|_| "qiniu".to_string());
+    match backend.trim() {
+        "qiniu" => state.storage = qiniu_backend(),
+        "local" => {
+            let dir = env::var("TEMP_DIR")
+                .ok()
+                .filter(|dir| !dir.trim().is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| env::temp_dir().join("xtool-transfer"));
+            let max_upload_size_bytes = upload_max_size_mb("UPLOAD_MAX_SIZE_MB") * 1024 * 1024;
+            let local = storage::LocalBackend::new(dir.clone(), max_upload_size_bytes)
+                .expect("Failed to set up local storage");
+            info!("Storing uploaded files in {}", dir.display());
+            state.storage = Some(Arc::new(local));
+        }
+        other => {
+            error!("Unsupported STORAGE_BACKEND {:?} (expected qiniu or local)", other);
+            process::exit(1);
+        }
+    }
+
+    // Spawn background cleanup task
+    tokio::spawn(handlers::cleanup_expired_files_task(state.clone()));
+
+    let max_body_size = env::var("MAX_BODY_SIZE_MB")
+        .ok()
+        .and_then(|mb| mb.trim().parse::<usize>().ok())
+        .map_or(app::DEFAULT_MAX_BODY_SIZE, |mb| mb * 1024 * 1024);
+    // ALLOWED_ORIGINS opens the API to browser apps on other origins
+    let cors = match app::cors_layer(&env::var("ALLOWED_ORIGINS").unwrap_or_default()) {
+        Ok(cors) => cors,
+        Err(e) => {
+            error!("Invalid ALLOWED_ORIGINS: {:#}", e);
+            process::exit(1);
+        }
+    };
+    let app = build_router(state, max_body_size, cors);
+
+    let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
+    let addr = format!("0.0.0.0:{}", port);
+    info!("Listening on {}", addr);
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .expect("Failed to bind address");
+
+    axum::serve(listener, app)
+        .await
+        .expect("Server error");
+}
 
+fn upload_max_size_mb(var: &str) -> u64 {
+    env::var(var)
+        .unwrap_or_else(|_| "100".to_string())
+        .trim()
+        .parse::<u64>()
+        .unwrap_or(100)
+}
+
+fn qiniu_backend() -> Option<Arc<dyn storage::StorageBackend>> {
     if let (Ok(ak), Ok(sk), Ok(domain), Ok(bucket)) = (
         env::var("QINIU_ACCESS_KEY"),
         env::var("QINIU_SECRET_KEY"),
@@ -42,16 +119,10 @@ async fn main() {
         let scheme = env::var("QINIU_SCHEME").unwrap_or_else(|_| "http".to_string());
         let callback_url = env::var("QINIU_CALLBACK_URL")
             .unwrap_or_else(|_| "http://a.debin.cc:8080/upload/callback".to_string());
-        let max_upload_size_bytes = env::var("QINIU_UPLOAD_MAX_SIZE_MB")
-            .unwrap_or_else(|_| "100".to_string())
-            .trim()
-            .parse::<u64>()
-            .unwrap_or(100)
-            * 1024
-            * 1024;
+        let max_upload_size_bytes = upload_max_size_mb("QINIU_UPLOAD_MAX_SIZE_MB") * 1024 * 1024;
         
         info!("Qiniu configuration found. Bucket: {}", bucket);
-        state.qiniu_config = Some(QiniuClient::new(
+        Some(Arc::new(QiniuClient::new(
             ak,
             sk,
             domain,
@@ -59,28 +130,12 @@ async fn main() {
             bucket,
             callback_url,
             max_upload_size_bytes,
-        ));
+        )))
     } else {
         error!("Qiniu configuration missing (QINIU_ACCESS_KEY, QINIU_SECRET_KEY, QINIU_DOMAIN, QINIU_BUCKET)");
         // Depending on requirements, maybe we should panic or just run in memory mode?
         // User said "Upload to qiniu", so it is likely required.
         // But for development maybe optional?
+        None
     }
-
-    // Spawn background cleanup task
-    tokio::spawn(handlers::cleanup_expired_files_task(state.clone()));
-
-    let app = build_router(state);
-
-    let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    info!("Listening on {}", addr);
-
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .expect("Failed to bind address");
-
-    axum::serve(listener, app)
-        .await
-        .expect("Server error");
 }
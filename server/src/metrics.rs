@@ -0,0 +1,97 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Counters kept since the server started, reported by `/metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    uploads: AtomicU64,
+    downloads: AtomicU64,
+    cleanup_runs: AtomicU64,
+}
+
+/// Values derived from the record store when `/metrics` is scraped
+pub struct StoreGauges {
+    pub active_files: u64,
+    pub memory_bytes: u64,
+}
+
+impl Metrics {
+    pub fn record_upload(&self) {
+        self.uploads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_download(&self) {
+        self.downloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_cleanup_run(&self) {
+        self.cleanup_runs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render the counters and `gauges` in the Prometheus text format.
+    pub fn render(&self, gauges: &StoreGauges) -> String {
+        let metrics = [
+            (
+                "xtool_uploads_total",
+                "counter",
+                "Completed text and file uploads",
+                self.uploads.load(Ordering::Relaxed),
+            ),
+            (
+                "xtool_downloads_total",
+                "counter",
+                "Downloads handed out",
+                self.downloads.load(Ordering::Relaxed),
+            ),
+            (
+                "xtool_cleanup_runs_total",
+                "counter",
+                "Runs of the expired file cleanup task",
+                self.cleanup_runs.load(Ordering::Relaxed),
+            ),
+            (
+                "xtool_active_files",
+                "gauge",
+                "Records currently stored",
+                gauges.active_files,
+            ),
+            (
+                "xtool_memory_bytes",
+                "gauge",
+                "Bytes of text uploads held in the record store",
+                gauges.memory_bytes,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = writeln!(out, "{name} {value}");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::default();
+        metrics.record_upload();
+        metrics.record_upload();
+        metrics.record_download();
+        let text = metrics.render(&StoreGauges {
+            active_files: 2,
+            memory_bytes: 11,
+        });
+        assert!(text.contains("# TYPE xtool_uploads_total counter\nxtool_uploads_total 2\n"));
+        assert!(text.contains("\nxtool_downloads_total 1\n"));
+        assert!(text.contains("\nxtool_cleanup_runs_total 0\n"));
+        assert!(text.contains("\nxtool_active_files 2\n"));
+        assert!(text.contains("\nxtool_memory_bytes 11\n"));
+    }
+}
@@ -34,11 +34,20 @@ impl QiniuClient {
         }
     }
 
-    pub fn generate_upload_token(&self, save_as_name: &str, lifetime: Duration) -> Result<String> {
+    /// `object_lifetime` is how long Qiniu keeps the object; its lifecycle
+    /// rules work in whole days, so it is rounded up to at least one day.
+    pub fn generate_upload_token(
+        &self,
+        save_as_name: &str,
+        lifetime: Duration,
+        object_lifetime: Duration,
+    ) -> Result<String> {
+        const DAY: u64 = 24 * 60 * 60;
+        let object_days = object_lifetime.as_secs().div_ceil(DAY).max(1);
         let callback_body = "key=$(key)&fname=$(fname)&fsize=$(fsize)&etag=$(etag)";
         let upload_policy = UploadPolicy::new_for_bucket(&self.bucket_name, lifetime)
             .insert_only()
-            .object_lifetime(Duration::from_secs(24 * 60 * 60))
+            .object_lifetime(Duration::from_secs(object_days * DAY))
             .save_as(save_as_name, true)
             .file_size_limitation(..=self.max_upload_size_bytes)
             .callback(
@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 pub enum StorageType {
     Qiniu(String), // key
     Memory(String), // content
+    Local(String), // key
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -29,4 +30,9 @@ pub struct FileRecord {
     pub content_type: ContentType,
     pub storage: StorageType,
     pub uploaded_at: u64,
+    /// Unix time after which the record is no longer served
+    pub expires_at: u64,
+    /// Downloads left before the record is gone; `None` means unlimited
+    #[serde(default)]
+    pub downloads_remaining: Option<u8>,
 }
@@ -1,21 +1,38 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::{sync::Arc, time::Instant};
 
-use crate::{records::FileRecord, qiniu::QiniuClient};
+use crate::{
+    metrics::Metrics,
+    storage::StorageBackend,
+    store::{MemoryStore, RecordStore},
+    tokens::TokenPool,
+};
 
 #[derive(Clone)]
 pub struct AppState {
-    pub files: Arc<Mutex<HashMap<String, FileRecord>>>,
-    pub qiniu_config: Option<QiniuClient>,
+    pub files: Arc<dyn RecordStore>,
+    /// Backend for file uploads; text uploads stay in the record store
+    pub storage: Option<Arc<dyn StorageBackend>>,
+    pub metrics: Arc<Metrics>,
+    /// Tokens issued to uploads whose record does not exist yet
+    pub tokens: Arc<TokenPool>,
+    /// When the server started, for the uptime in `/health`
+    pub started_at: Instant,
 }
 
 impl AppState {
-    pub fn new() -> Self {
+    pub fn new(files: Arc<dyn RecordStore>) -> Self {
         Self {
-            files: Arc::new(Mutex::new(HashMap::new())),
-            qiniu_config: None,
+            files,
+            storage: None,
+            metrics: Arc::default(),
+            tokens: Arc::default(),
+            started_at: Instant::now(),
         }
     }
 }
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new(Arc::new(MemoryStore::default()))
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{bail, Context, Result};
+use qiniu_sdk::objects::{apis::credential::Credential, AfterDays, ObjectsManager};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{qiniu::QiniuClient, records::StorageType};
+
+/// How the client should deliver the file bytes
+pub enum UploadTarget {
+    /// Upload directly to Qiniu with this token
+    Token(String),
+    /// PUT the bytes to this path on the transfer server
+    Url(String),
+}
+
+/// Where a stored file can be fetched from
+pub enum Download {
+    /// The client fetches it from this URL
+    Url(String),
+    /// The transfer server serves it from this local file
+    File(PathBuf),
+}
+
+/// Where uploaded file contents live. Records only hold the object key;
+/// everything else goes through the backend.
+pub trait StorageBackend: Send + Sync {
+    /// Short backend name reported by `/health`
+    fn name(&self) -> &'static str;
+    /// Prepare an upload of object `key`, kept for at least `ttl`.
+    fn generate_upload_target(&self, key: &str, ttl: Duration) -> Result<UploadTarget>;
+    /// Confirm the upload of `key` finished, returning how the record refers to it.
+    fn finalize(&self, key: &str) -> Result<StorageType>;
+    fn download_url_or_bytes(&self, key: &str) -> Result<Download>;
+    /// Change how long `key` is kept. `Duration::ZERO` removes it now.
+    /// May block on network calls.
+    fn set_lifecycle(&self, key: &str, ttl: Duration) -> Result<()>;
+    /// Largest file this backend accepts.
+    fn max_upload_size_bytes(&self) -> u64;
+    /// File path a direct upload of `key` is written to, if this backend
+    /// accepts uploads through the transfer server and `key` was issued.
+    fn upload_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Qiniu rounds object lifetimes up to whole days.
+fn lifetime_days(ttl: Duration) -> isize {
+    const DAY: u64 = 24 * 60 * 60;
+    ttl.as_secs().div_ceil(DAY).max(1) as isize
+}
+
+impl StorageBackend for QiniuClient {
+    fn name(&self) -> &'static str {
+        "qiniu"
+    }
+
+    fn generate_upload_target(&self, key: &str, ttl: Duration) -> Result<UploadTarget> {
+        let token_lifetime = Duration::from_secs(10 * 60);
+        let token = self.generate_upload_token(key, token_lifetime, ttl)?;
+        Ok(UploadTarget::Token(token))
+    }
+
+    fn finalize(&self, key: &str) -> Result<StorageType> {
+        // Qiniu only calls back once the object is stored
+        Ok(StorageType::Qiniu(key.to_string()))
+    }
+
+    fn download_url_or_bytes(&self, key: &str) -> Result<Download> {
+        Ok(Download::Url(self.get_download_url(key)))
+    }
+
+    fn set_lifecycle(&self, key: &str, ttl: Duration) -> Result<()> {
+        let manager =
+            ObjectsManager::builder(Credential::new(&self.access_key, &self.secret_key)).build();
+        let bucket = manager.bucket(self.bucket_name.as_str());
+        if ttl.is_zero() {
+            bucket
+                .delete_object(key)
+                .call()
+                .with_context(|| format!("Failed to delete Qiniu object {}", key))?;
+        } else {
+            bucket
+                .modify_object_life_cycle(key)
+                .delete_after_days(AfterDays::new(lifetime_days(ttl)))
+                .call()
+                .with_context(|| format!("Failed to set lifecycle of Qiniu object {}", key))?;
+        }
+        Ok(())
+    }
+
+    fn max_upload_size_bytes(&self) -> u64 {
+        self.max_upload_size_bytes
+    }
+}
+
+/// Files kept on the server's own disk. Clients PUT them to
+/// `/upload/local/<key>` and download them from `/blob/<id>`.
+pub struct LocalBackend {
+    dir: PathBuf,
+    max_upload_size_bytes: u64,
+    /// Keys handed out but not yet uploaded, with when they stop being accepted
+    pending: Mutex<HashMap<String, Instant>>,
+}
+
+/// How long an issued local upload URL stays valid
+const LOCAL_UPLOAD_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+impl LocalBackend {
+    pub fn new(dir: PathBuf, max_upload_size_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create storage directory {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            max_upload_size_bytes,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Keys are generated by the server; reject anything that could leave `dir`.
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!("Invalid object key {:?}", key);
+        }
+        Ok(self.dir.join(key))
+    }
+}
+
+impl StorageBackend for LocalBackend {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn generate_upload_target(&self, key: &str, _ttl: Duration) -> Result<UploadTarget> {
+        self.path(key)?;
+        let now = Instant::now();
+        let mut pending = self.pending.lock().expect("Pending uploads lock poisoned");
+        pending.retain(|_, deadline| *deadline > now);
+        pending.insert(key.to_string(), now + LOCAL_UPLOAD_WINDOW);
+        Ok(UploadTarget::Url(format!("/upload/local/{}", key)))
+    }
+
+    fn finalize(&self, key: &str) -> Result<StorageType> {
+        let issued = self
+            .pending
+            .lock()
+            .expect("Pending uploads lock poisoned")
+            .remove(key)
+            .is_some();
+        if !issued {
+            bail!("Upload of {} was not requested", key);
+        }
+        if !self.path(key)?.is_file() {
+            bail!("Upload of {} is missing", key);
+        }
+        Ok(StorageType::Local(key.to_string()))
+    }
+
+    fn download_url_or_bytes(&self, key: &str) -> Result<Download> {
+        Ok(Download::File(self.path(key)?))
+    }
+
+    fn set_lifecycle(&self, key: &str, ttl: Duration) -> Result<()> {
+        // Expiry of local files is driven by their records; only removal acts here
+        if ttl.is_zero() {
+            let path = self.path(key)?;
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn max_upload_size_bytes(&self) -> u64 {
+        self.max_upload_size_bytes
+    }
+
+    fn upload_path(&self, key: &str) -> Option<PathBuf> {
+        let pending = self.pending.lock().expect("Pending uploads lock poisoned");
+        match pending.get(key) {
+            Some(deadline) if *deadline > Instant::now() => self.path(key).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_backend_only_accepts_issued_keys() {
+        let dir = tempdir();
+        let backend = LocalBackend::new(dir.clone(), 1024).unwrap();
+
+        assert!(backend.upload_path("xtool_1").is_none());
+        assert!(backend
+            .generate_upload_target("../etc", Duration::ZERO)
+            .is_err());
+
+        let UploadTarget::Url(url) = backend
+            .generate_upload_target("xtool_1", Duration::ZERO)
+            .unwrap()
+        else {
+            panic!("expected an upload url");
+        };
+        assert_eq!(url, "/upload/local/xtool_1");
+        let path = backend.upload_path("xtool_1").unwrap();
+        assert!(backend.finalize("xtool_1").is_err(), "nothing written yet");
+
+        backend
+            .generate_upload_target("xtool_1", Duration::ZERO)
+            .unwrap();
+        fs::write(&path, b"data").unwrap();
+        assert!(matches!(
+            backend.finalize("xtool_1").unwrap(),
+            StorageType::Local(_)
+        ));
+        assert!(backend.finalize("xtool_1").is_err(), "keys are single use");
+
+        backend.set_lifecycle("xtool_1", Duration::ZERO).unwrap();
+        assert!(!path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn tempdir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("xtool-storage-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+}
@@ -0,0 +1,317 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::Mutex,
+};
+
+use crate::records::{ContentType, FileRecord, StorageType};
+
+/// Result of trying to take one download of a record
+pub enum DownloadOutcome {
+    Ready(FileRecord),
+    NotFound,
+    Expired,
+    LimitReached,
+}
+
+/// Where file records live. All handlers and the cleanup task go through this.
+pub trait RecordStore: Send + Sync {
+    fn insert(&self, record: FileRecord) -> Result<()>;
+    fn contains(&self, id: &str) -> Result<bool>;
+    /// Check expiry and decrement the download count as one atomic step.
+    fn take_download(&self, id: &str, now: u64) -> Result<DownloadOutcome>;
+    /// Same checks as `take_download`, without using up a download.
+    fn peek_download(&self, id: &str, now: u64) -> Result<DownloadOutcome>;
+    fn list(&self) -> Result<Vec<FileRecord>>;
+    /// Remove a record, returning it if it existed.
+    fn delete(&self, id: &str) -> Result<Option<FileRecord>>;
+    /// Drop every record expired at `now`, returning the removed records.
+    fn remove_expired(&self, now: u64) -> Result<Vec<FileRecord>>;
+}
+
+/// Decrement `record`'s download count, or say why it cannot be downloaded.
+fn check_download(record: &mut FileRecord, now: u64) -> Option<DownloadOutcome> {
+    if now > record.expires_at {
+        return Some(DownloadOutcome::Expired);
+    }
+    if let Some(remaining) = record.downloads_remaining.as_mut() {
+        if *remaining == 0 {
+            return Some(DownloadOutcome::LimitReached);
+        }
+        *remaining -= 1;
+    }
+    None
+}
+
+/// Records kept in memory; lost on restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    files: Mutex<HashMap<String, FileRecord>>,
+}
+
+impl RecordStore for MemoryStore {
+    fn insert(&self, record: FileRecord) -> Result<()> {
+        let mut files = self.files.lock().expect("State lock poisoned");
+        files.insert(record.id.clone(), record);
+        Ok(())
+    }
+
+    fn contains(&self, id: &str) -> Result<bool> {
+        Ok(self.files.lock().expect("State lock poisoned").contains_key(id))
+    }
+
+    fn take_download(&self, id: &str, now: u64) -> Result<DownloadOutcome> {
+        let mut files = self.files.lock().expect("State lock poisoned");
+        let Some(record) = files.get_mut(id) else {
+            return Ok(DownloadOutcome::NotFound);
+        };
+        match check_download(record, now) {
+            Some(DownloadOutcome::Expired) => {
+                files.remove(id);
+                Ok(DownloadOutcome::Expired)
+            }
+            Some(outcome) => Ok(outcome),
+            None => Ok(DownloadOutcome::Ready(record.clone())),
+        }
+    }
+
+    fn peek_download(&self, id: &str, now: u64) -> Result<DownloadOutcome> {
+        let files = self.files.lock().expect("State lock poisoned");
+        let Some(mut record) = files.get(id).cloned() else {
+            return Ok(DownloadOutcome::NotFound);
+        };
+        Ok(check_download(&mut record, now).unwrap_or(DownloadOutcome::Ready(record)))
+    }
+
+    fn list(&self) -> Result<Vec<FileRecord>> {
+        Ok(self.files.lock().expect("State lock poisoned").values().cloned().collect())
+    }
+
+    fn delete(&self, id: &str) -> Result<Option<FileRecord>> {
+        Ok(self.files.lock().expect("State lock poisoned").remove(id))
+    }
+
+    fn remove_expired(&self, now: u64) -> Result<Vec<FileRecord>> {
+        let mut files = self.files.lock().expect("State lock poisoned");
+        let expired: Vec<String> = files
+            .values()
+            .filter(|r| now > r.expires_at)
+            .map(|r| r.id.clone())
+            .collect();
+        Ok(expired.iter().filter_map(|id| files.remove(id)).collect())
+    }
+}
+
+/// Records persisted in a SQLite database, surviving restarts.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    id                  TEXT PRIMARY KEY,
+    filename            TEXT,
+    content_type        TEXT NOT NULL,
+    storage_kind        TEXT NOT NULL,
+    storage_value       TEXT NOT NULL,
+    uploaded_at         INTEGER NOT NULL,
+    expires_at          INTEGER NOT NULL,
+    downloads_remaining INTEGER
+);
+CREATE INDEX IF NOT EXISTS files_expires_at ON files (expires_at);
+";
+
+const COLUMNS: &str = "id, filename, content_type, storage_kind, storage_value, \
+                       uploaded_at, expires_at, downloads_remaining";
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open database {}", path.display()))?;
+        conn.execute_batch(SCHEMA).context("Failed to create schema")?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<FileRecord> {
+        let content_type: String = row.get(2)?;
+        let storage_kind: String = row.get(3)?;
+        let storage_value: String = row.get(4)?;
+        Ok(FileRecord {
+            id: row.get(0)?,
+            filename: row.get(1)?,
+            content_type: match content_type.as_str() {
+                "text" => ContentType::Text,
+                _ => ContentType::File,
+            },
+            storage: match storage_kind.as_str() {
+                "memory" => StorageType::Memory(storage_value),
+                "local" => StorageType::Local(storage_value),
+                _ => StorageType::Qiniu(storage_value),
+            },
+            uploaded_at: row.get::<_, i64>(5)? as u64,
+            expires_at: row.get::<_, i64>(6)? as u64,
+            downloads_remaining: row.get(7)?,
+        })
+    }
+
+    fn get(conn: &Connection, id: &str) -> Result<Option<FileRecord>> {
+        conn.query_row(
+            &format!("SELECT {COLUMNS} FROM files WHERE id = ?1"),
+            [id],
+            Self::from_row,
+        )
+        .optional()
+        .context("Failed to query record")
+    }
+}
+
+impl RecordStore for SqliteStore {
+    fn insert(&self, record: FileRecord) -> Result<()> {
+        let (kind, value) = match &record.storage {
+            StorageType::Memory(content) => ("memory", content),
+            StorageType::Qiniu(key) => ("qiniu", key),
+            StorageType::Local(key) => ("local", key),
+        };
+        let content_type = match record.content_type {
+            ContentType::Text => "text",
+            ContentType::File => "file",
+        };
+        let conn = self.conn.lock().expect("Database lock poisoned");
+        conn.execute(
+            &format!("INSERT OR REPLACE INTO files ({COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"),
+            params![
+                record.id,
+                record.filename,
+                content_type,
+                kind,
+                value,
+                record.uploaded_at as i64,
+                record.expires_at as i64,
+                record.downloads_remaining,
+            ],
+        )
+        .context("Failed to insert record")?;
+        Ok(())
+    }
+
+    fn contains(&self, id: &str) -> Result<bool> {
+        let conn = self.conn.lock().expect("Database lock poisoned");
+        Ok(Self::get(&conn, id)?.is_some())
+    }
+
+    fn take_download(&self, id: &str, now: u64) -> Result<DownloadOutcome> {
+        // The connection mutex serializes this read-modify-write
+        let conn = self.conn.lock().expect("Database lock poisoned");
+        let Some(mut record) = Self::get(&conn, id)? else {
+            return Ok(DownloadOutcome::NotFound);
+        };
+        match check_download(&mut record, now) {
+            Some(DownloadOutcome::Expired) => {
+                conn.execute("DELETE FROM files WHERE id = ?1", [id])
+                    .context("Failed to delete record")?;
+                Ok(DownloadOutcome::Expired)
+            }
+            Some(outcome) => Ok(outcome),
+            None => {
+                conn.execute(
+                    "UPDATE files SET downloads_remaining = ?1 WHERE id = ?2",
+                    params![record.downloads_remaining, id],
+                )
+                .context("Failed to update download count")?;
+                Ok(DownloadOutcome::Ready(record))
+            }
+        }
+    }
+
+    fn peek_download(&self, id: &str, now: u64) -> Result<DownloadOutcome> {
+        let conn = self.conn.lock().expect("Database lock poisoned");
+        let Some(mut record) = Self::get(&conn, id)? else {
+            return Ok(DownloadOutcome::NotFound);
+        };
+        Ok(check_download(&mut record, now).unwrap_or(DownloadOutcome::Ready(record)))
+    }
+
+    fn list(&self) -> Result<Vec<FileRecord>> {
+        let conn = self.conn.lock().expect("Database lock poisoned");
+        let mut stmt = conn.prepare(&format!("SELECT {COLUMNS} FROM files ORDER BY uploaded_at"))?;
+        let records = stmt
+            .query_map([], Self::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to list records")?;
+        Ok(records)
+    }
+
+    fn delete(&self, id: &str) -> Result<Option<FileRecord>> {
+        let conn = self.conn.lock().expect("Database lock poisoned");
+        let record = Self::get(&conn, id)?;
+        if record.is_some() {
+            conn.execute("DELETE FROM files WHERE id = ?1", [id])
+                .context("Failed to delete record")?;
+        }
+        Ok(record)
+    }
+
+    fn remove_expired(&self, now: u64) -> Result<Vec<FileRecord>> {
+        let conn = self.conn.lock().expect("Database lock poisoned");
+        let mut stmt = conn.prepare(&format!("SELECT {COLUMNS} FROM files WHERE expires_at < ?1"))?;
+        let expired = stmt
+            .query_map([now as i64], Self::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to query expired records")?;
+        conn.execute("DELETE FROM files WHERE expires_at < ?1", [now as i64])
+            .context("Failed to delete expired records")?;
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, expires_at: u64, downloads: Option<u8>) -> FileRecord {
+        FileRecord {
+            id: id.to_string(),
+            filename: Some("a.txt".to_string()),
+            content_type: ContentType::File,
+            storage: StorageType::Qiniu(format!("xtool_{id}")),
+            uploaded_at: 100,
+            expires_at,
+            downloads_remaining: downloads,
+        }
+    }
+
+    fn exercise(store: &dyn RecordStore) {
+        store.insert(record("1", 200, Some(2))).unwrap();
+        store.insert(record("2", 150, None)).unwrap();
+        assert!(store.contains("1").unwrap());
+
+        assert!(matches!(store.peek_download("1", 120).unwrap(), DownloadOutcome::Ready(_)));
+        for _ in 0..2 {
+            assert!(matches!(store.take_download("1", 120).unwrap(), DownloadOutcome::Ready(_)));
+        }
+        assert!(matches!(store.take_download("1", 120).unwrap(), DownloadOutcome::LimitReached));
+        assert!(matches!(store.peek_download("1", 120).unwrap(), DownloadOutcome::LimitReached));
+        assert!(matches!(store.take_download("x", 120).unwrap(), DownloadOutcome::NotFound));
+
+        let removed = store.remove_expired(160).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id, "2");
+        assert_eq!(store.list().unwrap().len(), 1);
+        assert!(matches!(store.take_download("1", 300).unwrap(), DownloadOutcome::Expired));
+        assert!(store.delete("1").unwrap().is_none());
+    }
+
+    #[test]
+    fn memory_store_counts_downloads_and_expires() {
+        exercise(&MemoryStore::default());
+    }
+
+    #[test]
+    fn sqlite_store_counts_downloads_and_expires() {
+        exercise(&SqliteStore::open(Path::new(":memory:")).unwrap());
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use rand::Rng;
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::store::RecordStore;
+
+/// Hands out download tokens that no other upload holds.
+///
+/// File uploads get their token from `/upload` but only create their record
+/// once the object has been stored, so a token is reserved here until its
+/// record exists. Every token is allocated under this lock, which makes the
+/// "is it free" check and the reservation a single step.
+#[derive(Default)]
+pub struct TokenPool {
+    /// Reserved tokens and the unix time after which the reservation lapses
+    pending: Mutex<HashMap<String, u64>>,
+}
+
+impl TokenPool {
+    /// Reserve a token that is neither stored nor pending, until
+    /// `expires_at`. Returns `None` if no free token turned up.
+    pub fn allocate(
+        &self,
+        store: &dyn RecordStore,
+        now: u64,
+        expires_at: u64,
+    ) -> Result<Option<String>> {
+        let mut pending = self.pending.lock().expect("Token lock poisoned");
+        pending.retain(|_, until| *until >= now);
+        for _ in 0..16 {
+            let id = generate_token();
+            if !pending.contains_key(&id) && !store.contains(&id)? {
+                pending.insert(id.clone(), expires_at);
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Drop the reservation once the token's record is in the store.
+    pub fn release(&self, id: &str) {
+        self.pending.lock().expect("Token lock poisoned").remove(id);
+    }
+}
+
+pub fn generate_token() -> String {
+    let mut rng = rand::rng();
+    let token: u32 = rng.random_range(100000..999999);
+    token.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::{ContentType, FileRecord, StorageType};
+    use crate::store::MemoryStore;
+    use std::collections::HashSet;
+
+    #[test]
+    fn never_hands_out_a_token_twice() {
+        let pool = TokenPool::default();
+        let store = MemoryStore::default();
+        let mut seen = HashSet::new();
+        for i in 0..2000 {
+            let id = pool.allocate(&store, 0, 100).unwrap().unwrap();
+            assert!(seen.insert(id.clone()), "duplicate token {id}");
+            // Half become records, half stay pending; both must stay unique
+            if i % 2 == 0 {
+                store
+                    .insert(FileRecord {
+                        id: id.clone(),
+                        filename: None,
+                        content_type: ContentType::Text,
+                        storage: StorageType::Memory(String::new()),
+                        uploaded_at: 0,
+                        expires_at: 100,
+                        downloads_remaining: None,
+                    })
+                    .unwrap();
+                pool.release(&id);
+            }
+        }
+    }
+
+    #[test]
+    fn lapsed_reservations_are_dropped() {
+        let pool = TokenPool::default();
+        let store = MemoryStore::default();
+        pool.allocate(&store, 0, 10).unwrap().unwrap();
+        pool.allocate(&store, 11, 20).unwrap().unwrap();
+        assert_eq!(pool.pending.lock().unwrap().len(), 1);
+    }
+}
@@ -1,13 +1,23 @@
-use log::info;
+use anyhow::Context;
+use log::{error, info};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::serial::config::SerialConfig;
+use crate::serial::monitor::{BackspaceKey, LineEnding};
 use crate::tftp::client::config::ClientConfig;
 use crate::tftp::client::config::TftpcConfigFile;
 use crate::tftp::server::config::Config as TftpdConfig;
 
+/// Config file looked for in the current directory when none is given
+pub const DEFAULT_CONFIG_PATH: &str = ".xtool.toml";
+
+/// Environment variable naming the config file, used when `--config` is absent
+pub const CONFIG_ENV: &str = "XTOOL_CONFIG";
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 pub struct AppConfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tftpd: Option<TftpdConfig>,
@@ -18,16 +28,78 @@ pub struct AppConfig {
 }
 
 impl AppConfig {
-    pub fn load_from_file(path: &str) -> anyhow::Result<Self> {
+    /// Parse and validate a config file. Parse errors give the offending key
+    /// and its line/column; validation errors list every bad value.
+    pub fn load_from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         let content = fs::read_to_string(path)?;
-        let config: AppConfig = toml::from_str(&content)?;
+        let config: AppConfig = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("{}", e.to_string().trim_end()))?;
+        let problems = config.validate();
+        if !problems.is_empty() {
+            anyhow::bail!("invalid configuration:\n  - {}", problems.join("\n  - "));
+        }
         Ok(config)
     }
 
+    /// Check values serde cannot, returning every problem found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(tftpd) = &self.tftpd {
+            tftpd.validate(&mut problems);
+        }
+        if let Some(tftpc) = &self.tftpc {
+            tftpc.validate(&mut problems);
+        }
+        if let Some(serial) = &self.serial {
+            serial.validate(&mut problems);
+        }
+        problems
+    }
+
+    /// Load the config file chosen by `--config`, then `XTOOL_CONFIG`, then
+    /// `./.xtool.toml`.
+    ///
+    /// An explicitly named file must exist and parse. The default file is
+    /// optional, and a broken one is reported and ignored.
+    pub fn load(explicit: Option<&Path>) -> anyhow::Result<Option<Self>> {
+        let explicit = explicit.map(Path::to_path_buf).or_else(|| {
+            std::env::var_os(CONFIG_ENV)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        });
+
+        if let Some(path) = explicit {
+            let config = Self::load_from_file(&path).with_context(|| {
+                format!("Failed to load configuration file {}", path.display())
+            })?;
+            log_config_path(&path);
+            return Ok(Some(config));
+        }
+
+        let path = Path::new(DEFAULT_CONFIG_PATH);
+        if !path.exists() {
+            return Ok(None);
+        }
+        match Self::load_from_file(path) {
+            Ok(config) => {
+                log_config_path(path);
+                Ok(Some(config))
+            }
+            Err(e) => {
+                error!(
+                    "Failed to load configuration file {}: {:#}, using defaults",
+                    path.display(),
+                    e
+                );
+                Ok(None)
+            }
+        }
+    }
+
     pub fn generate_config_file(force: bool) -> anyhow::Result<()> {
         use std::io::Write;
 
-        let config_path = ".xtool.toml";
+        let config_path = DEFAULT_CONFIG_PATH;
 
         // Check if file already exists
         if std::path::Path::new(config_path).exists() && !force {
@@ -62,6 +134,9 @@ impl AppConfig {
                 baud: Some(115200),
                 net_port: Some(5432),
                 net_bind: Some("0.0.0.0".to_string()),
+                net_token: None,
+                eol: Some(LineEnding::Cr),
+                backspace: Some(BackspaceKey::Del),
             }),
         };
 
@@ -72,3 +147,38 @@ impl AppConfig {
         )
     }
 }
+
+fn log_config_path(path: &Path) {
+    let abs_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    info!("Using configuration file: {}", abs_path.display());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_config_is_valid() {
+        let config: AppConfig = toml::from_str(&AppConfig::generate_full_config()).unwrap();
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_fields_with_location() {
+        let err = toml::from_str::<AppConfig>("[tftpd]\nport = 69\nprot = 70\n").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("prot"), "{message}");
+        assert!(message.contains("line 3"), "{message}");
+    }
+
+    #[test]
+    fn reports_every_invalid_value() {
+        let config: AppConfig = toml::from_str(
+            "[tftpc.get]\nblock_size = 4\nwindow_size = 0\n[serial]\nbaud = 0\n",
+        )
+        .unwrap();
+        let problems = config.validate();
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert!(problems[0].starts_with("tftpc.get.block_size"));
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{builder::TypedValueParser, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -7,10 +7,24 @@ pub struct DiskCli {
     #[arg(long, value_name = "PATH")]
     pub disk: PathBuf,
 
-    /// Partition selector: index or name
-    #[arg(long, value_name = "ID|NAME")]
+    /// Partition selector: index, name or uuid:<GUID>
+    #[arg(long, value_name = "ID|NAME|uuid:GUID")]
     pub part: Option<String>,
 
+    /// Logical sector size used for GPT LBAs
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value = "512",
+        value_parser = clap::builder::PossibleValuesParser::new(["512", "4096"])
+            .map(|s| s.parse::<u64>().expect("validated sector size")),
+    )]
+    pub sector_size: u64,
+
+    /// Don't take the advisory lock that keeps concurrent xtool runs off the same image
+    #[arg(long)]
+    pub no_lock: bool,
+
     #[command(subcommand)]
     pub action: DiskAction,
 }
@@ -26,11 +40,15 @@ pub enum DiskAction {
         /// Allow overwrite existing file
         #[arg(long)]
         overwrite: bool,
+
+        /// Write zeros to allocate the whole image instead of creating a sparse file
+        #[arg(long)]
+        no_sparse: bool,
     },
 
-    /// Create GPT partition table using parameter.txt
+    /// Create GPT partition table using parameter.txt or a TOML/JSON layout
     Mkgpt {
-        /// Parameter file path (e.g. parameter.txt)
+        /// Layout file path (parameter.txt, layout.toml or layout.json)
         #[arg(short = 'f', long, value_name = "PATH")]
         file: PathBuf,
 
@@ -43,6 +61,22 @@ pub enum DiskAction {
         yes: bool,
     },
 
+    /// Resize a GPT partition
+    #[command(group(clap::ArgGroup::new("mode").required(true).args(["grow", "size"])))]
+    Resize {
+        /// Grow to the next partition or end of disk
+        #[arg(long)]
+        grow: bool,
+
+        /// New partition size (bytes or with K/M/G suffix)
+        #[arg(long, value_name = "SIZE")]
+        size: Option<String>,
+
+        /// Skip confirmation
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+
     /// Format filesystem on partition or whole disk
     Mkfs {
         /// Filesystem type (ext4/fat32)
@@ -53,9 +87,21 @@ pub enum DiskAction {
         #[arg(long, value_name = "LABEL")]
         label: Option<String>,
 
+        /// FAT variant for --fstype fat32 (default: 32; auto picks by partition size)
+        #[arg(long, value_enum, value_name = "12|16|32|auto")]
+        fat_type: Option<FatBits>,
+
+        /// FAT cluster size in bytes, a power of two from 512 to 32768 (default: by partition size)
+        #[arg(long, value_name = "BYTES")]
+        cluster_size: Option<u32>,
+
         /// Skip confirmation
         #[arg(short = 'y', long)]
         yes: bool,
+
+        /// Mount the new filesystem afterwards to confirm it is usable
+        #[arg(long)]
+        verify: bool,
     },
 
     /// List files in directory
@@ -63,6 +109,41 @@ pub enum DiskAction {
         /// Directory path inside image
         #[arg(value_name = "PATH", default_value = "/")]
         path: String,
+
+        /// Long listing with sizes and modification times
+        #[arg(short = 'l', long)]
+        long: bool,
+    },
+
+    /// Print directory tree inside image
+    Tree {
+        /// Directory path inside image
+        #[arg(value_name = "PATH", default_value = "/")]
+        path: String,
+
+        /// Max depth to descend
+        #[arg(long, value_name = "N")]
+        depth: Option<usize>,
+    },
+
+    /// Find files by name and type inside image
+    Find {
+        /// Directory to search from
+        #[arg(value_name = "PATH", default_value = "/")]
+        path: String,
+
+        /// Only print entries whose name matches this glob (e.g. '*.so')
+        #[arg(long, value_name = "GLOB")]
+        name: Option<String>,
+
+        /// Only print regular files (f) or directories (d)
+        #[arg(
+            long = "type",
+            value_name = "f|d",
+            value_parser = clap::builder::PossibleValuesParser::new(["f", "d"])
+                .map(|s| s.chars().next().expect("validated type")),
+        )]
+        kind: Option<char>,
     },
 
     /// Copy files between host and image
@@ -83,6 +164,18 @@ pub enum DiskAction {
         /// Preserve timestamps (best effort)
         #[arg(long)]
         preserve: bool,
+
+        /// Append to the destination file instead of replacing it
+        #[arg(long, conflicts_with_all = ["recursive", "force"])]
+        append: bool,
+
+        /// Read each written file back and compare its CRC32 with the source
+        #[arg(long, conflicts_with = "append")]
+        verify: bool,
+
+        /// Don't show progress bars (they only appear on a terminal, for files of 8 MiB or more)
+        #[arg(long)]
+        no_progress: bool,
     },
 
     /// Move/rename files between host and image
@@ -125,6 +218,21 @@ pub enum DiskAction {
         parents: bool,
     },
 
+    /// Create a link inside image (ext4 only)
+    Ln {
+        /// Create a symbolic link
+        #[arg(short = 's', long)]
+        symbolic: bool,
+
+        /// Path the link points to (stored as given; may be relative)
+        #[arg(value_name = "TARGET")]
+        target: String,
+
+        /// Link to create
+        #[arg(value_name = "LINK")]
+        link: String,
+    },
+
     /// Print file content inside image
     Cat {
         #[arg(value_name = "PATH")]
@@ -137,6 +245,67 @@ pub enum DiskAction {
         /// Start offset
         #[arg(long, value_name = "N")]
         offset: Option<u64>,
+
+        /// Print only the last N lines (overrides --bytes and --offset)
+        #[arg(long, value_name = "N")]
+        tail: Option<usize>,
+    },
+
+    /// Write stdin to a file inside image
+    Write {
+        #[arg(value_name = "PATH")]
+        path: String,
+
+        /// Overwrite existing file
+        #[arg(short = 'f', long)]
+        force: bool,
+
+        /// Append to the file instead of replacing it
+        #[arg(short = 'a', long, conflicts_with = "force")]
+        append: bool,
+    },
+
+    /// Compare files or directories between image and host, or within the image
+    Diff {
+        #[arg(value_name = "A")]
+        a: String,
+        #[arg(value_name = "B")]
+        b: String,
+
+        /// Compare directories recursively
+        #[arg(short = 'r', long)]
+        recursive: bool,
+    },
+
+    /// Print checksum of a file inside image
+    Sum {
+        #[arg(value_name = "PATH")]
+        path: String,
+
+        /// Hash algorithm
+        #[arg(long, value_enum, default_value = "sha256")]
+        algo: SumAlgo,
+    },
+
+    /// Show type, size, ownership, permissions and timestamps of a path
+    Stat {
+        #[arg(value_name = "PATH")]
+        path: String,
+
+        /// JSON output
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show filesystem usage of partition
+    Du {
+        /// Summarize per-directory usage under PATH
+        #[arg(value_name = "PATH")]
+        path: Option<String>,
+
+        /// JSON output
+        #[arg(long)]
+        json: bool,
     },
 
     /// Show disk and partition info
@@ -145,6 +314,32 @@ pub enum DiskAction {
         #[arg(long)]
         json: bool,
     },
+
+    /// Write a zstd-compressed copy of the image
+    Compress {
+        /// Output path (e.g. disk.img.zst)
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// zstd compression level (1-22)
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(i32).range(1..=22))]
+        level: i32,
+
+        /// Allow overwrite existing file
+        #[arg(long)]
+        overwrite: bool,
+    },
+
+    /// Expand a zstd-compressed image (--disk) into a sparse raw image
+    Decompress {
+        /// Output image path
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Allow overwrite existing file
+        #[arg(long)]
+        overwrite: bool,
+    },
 }
 
 #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
@@ -152,3 +347,20 @@ pub enum FsType {
     Ext4,
     Fat32,
 }
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatBits {
+    #[value(name = "12")]
+    Fat12,
+    #[value(name = "16")]
+    Fat16,
+    #[value(name = "32")]
+    Fat32,
+    Auto,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SumAlgo {
+    Sha256,
+    Crc32,
+}
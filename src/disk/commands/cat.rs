@@ -2,7 +2,7 @@ use anyhow::Result;
 use std::io::Write;
 use std::path::Path;
 
-use super::super::fs::read_file;
+use super::super::fs::{read_file, read_tail};
 use super::super::types::PartitionTarget;
 
 pub fn cat(
@@ -11,9 +11,13 @@ pub fn cat(
     path: &str,
     bytes: Option<usize>,
     offset: Option<u64>,
+    tail: Option<usize>,
 ) -> Result<()> {
-    let offset = offset.unwrap_or(0);
-    let data = read_file(disk, target, path, offset, bytes)?;
+    // --tail picks its own region, so --bytes/--offset do not apply
+    let data = match tail {
+        Some(lines) => read_tail(disk, target, path, lines)?,
+        None => read_file(disk, target, path, offset.unwrap_or(0), bytes)?,
+    };
 
     let mut stdout = std::io::stdout();
     stdout.write_all(&data)?;
@@ -0,0 +1,128 @@
+use anyhow::{Context, Result, bail};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Write a zstd-compressed copy of `disk` to `out`.
+pub fn compress(disk: &Path, out: &Path, level: i32, overwrite: bool) -> Result<()> {
+    let input = File::open(disk).with_context(|| format!("failed to open {}", disk.display()))?;
+    let total = input.metadata()?.len();
+    let output = create_output(disk, out, overwrite)?;
+
+    let pb = progress_bar(total);
+    let mut reader = pb.wrap_read(input);
+    let mut encoder = zstd::Encoder::new(output, level).context("failed to start zstd encoder")?;
+    // Lets `decompress` (and other zstd tools) size the output up front
+    encoder.set_pledged_src_size(Some(total))?;
+    std::io::copy(&mut reader, &mut encoder)
+        .with_context(|| format!("failed to compress {}", disk.display()))?;
+    let output = encoder.finish().context("failed to finish zstd stream")?;
+    pb.finish_and_clear();
+
+    let compressed = output.metadata()?.len();
+    println!(
+        "{} -> {} ({} -> {} bytes, {:.1}%)",
+        disk.display(),
+        out.display(),
+        total,
+        compressed,
+        percent(compressed, total)
+    );
+    Ok(())
+}
+
+/// Expand the zstd-compressed image `disk` into `out`. Runs of zeros are
+/// skipped rather than written, so the result is sparse where the
+/// filesystem supports it.
+pub fn decompress(disk: &Path, out: &Path, overwrite: bool) -> Result<()> {
+    let input = File::open(disk).with_context(|| format!("failed to open {}", disk.display()))?;
+    let total = input.metadata()?.len();
+    let mut output = create_output(disk, out, overwrite)?;
+
+    let pb = progress_bar(total);
+    let mut decoder = zstd::Decoder::with_buffer(BufReader::new(pb.wrap_read(input)))
+        .context("failed to start zstd decoder")?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut written: u64 = 0;
+    loop {
+        let n = read_full(&mut decoder, &mut buf)
+            .with_context(|| format!("failed to decompress {}", disk.display()))?;
+        if n == 0 {
+            break;
+        }
+        let chunk = &buf[..n];
+        if chunk.iter().all(|&b| b == 0) {
+            output.seek(SeekFrom::Current(n as i64))?;
+        } else {
+            output.write_all(chunk)?;
+        }
+        written += n as u64;
+    }
+    // A trailing hole only moved the cursor; fix the length
+    output.set_len(written)?;
+    output.flush()?;
+    pb.finish_and_clear();
+
+    println!(
+        "{} -> {} ({} bytes)",
+        disk.display(),
+        out.display(),
+        written
+    );
+    Ok(())
+}
+
+fn create_output(disk: &Path, out: &Path, overwrite: bool) -> Result<File> {
+    if out.exists() && !overwrite {
+        bail!(
+            "{} already exists, use --overwrite to replace",
+            out.display()
+        );
+    }
+    // Truncating the output would destroy the input before it is read
+    if let (Ok(a), Ok(b)) = (disk.canonicalize(), out.canonicalize())
+        && a == b
+    {
+        bail!("output {} is the input image", out.display());
+    }
+    OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(out)
+        .with_context(|| format!("failed to create {}", out.display()))
+}
+
+/// Fill `buf` unless the stream ends first, so zero detection sees whole chunks.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn progress_bar(total: u64) -> ProgressBar {
+    let pb = ProgressBar::new(total);
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} {bytes}/{total_bytes} ({percent}%) [{bar:40.cyan/blue}] {eta}",
+        )
+        .unwrap()
+        .progress_chars("=>-"),
+    );
+    pb
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 * 100.0 / total as f64
+}
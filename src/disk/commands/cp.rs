@@ -1,46 +1,124 @@
 use anyhow::{anyhow, bail, Result};
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 
-use super::super::fs::{copy_host_to_image, copy_image_to_host, copy_image_to_image, is_dir};
+use super::super::fs::{
+    append_file_streaming, append_image_file, copy_host_to_image, copy_image_to_host,
+    copy_image_to_image, glob, is_dir, read_file_chunked, write_file_streaming_checked, CopyOptions,
+};
 use super::super::types::{PartitionTarget, PathKind};
-use super::super::utils::{host_path, normalize_image_path, path_kind};
+use super::super::utils::{has_glob, host_path, normalize_image_path, path_kind};
+
+/// Flags of `disk cp`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpOptions {
+    /// Copy directories recursively
+    pub recursive: bool,
+    /// Overwrite existing destinations
+    pub force: bool,
+    /// Preserve timestamps (best effort)
+    pub preserve: bool,
+    /// Read files written into the image back and check their CRC32
+    pub verify: bool,
+}
 
 pub fn cp(
     disk: &Path,
     target: &PartitionTarget,
     src: &str,
     dst: &str,
-    recursive: bool,
-    force: bool,
-    _preserve: bool,
+    options: CpOptions,
 ) -> Result<()> {
+    let CpOptions {
+        recursive,
+        force,
+        verify,
+        ..
+    } = options;
     let overwrite = force;
     let src_kind = path_kind(src);
-    let dst_kind = path_kind(dst);
 
-    match (src_kind, dst_kind) {
+    if verify && path_kind(dst) != PathKind::Image {
+        bail!("--verify only applies to copies into the image");
+    }
+
+    if src == HOST_STDIO || dst == HOST_STDIO {
+        return cp_stdio(disk, target, src, dst, overwrite, verify);
+    }
+
+    if src_kind == PathKind::Image && has_glob(src) {
+        let matches = glob(disk, target, src)?;
+        if matches.is_empty() {
+            if force {
+                return Ok(());
+            }
+            bail!("no match for {}", src);
+        }
+        // Several sources can only go into a directory; the trailing slash
+        // makes the destination checks below insist on one
+        let dst = if matches.len() > 1 && !dst.ends_with('/') {
+            format!("{dst}/")
+        } else {
+            dst.to_string()
+        };
+        for src in &matches {
+            cp_one(disk, target, src, &dst, recursive, overwrite, verify)?;
+        }
+        return Ok(());
+    }
+
+    cp_one(disk, target, src, dst, recursive, overwrite, verify)
+}
+
+fn cp_one(
+    disk: &Path,
+    target: &PartitionTarget,
+    src: &str,
+    dst: &str,
+    recursive: bool,
+    overwrite: bool,
+    verify: bool,
+) -> Result<()> {
+    match (path_kind(src), path_kind(dst)) {
         (PathKind::Host, PathKind::Image) => {
             let host = host_path(src)?;
             let image = normalize_image_path(dst);
             let image = resolve_host_to_image_dst(disk, target, &host, &image)?;
-            copy_host_to_image(disk, target, &host, &image, recursive, overwrite)?;
+            let options = CopyOptions {
+                recursive,
+                force: overwrite,
+                verify,
+            };
+            copy_host_to_image(disk, target, &host, &image, options)?;
             println!("{}", image);
             Ok(())
         }
         (PathKind::Image, PathKind::Host) => {
             let image = normalize_image_path(src);
             let host = host_path(dst)?;
-            let host = resolve_image_to_host_dst(&image, &host)?;
+            // The image root maps onto the destination directory itself
+            // rather than a nested folder named after the source.
+            let host = if is_image_root(src) {
+                host
+            } else {
+                resolve_image_to_host_dst(&image, &host)?
+            };
+            let image = if is_image_root(src) { "/".to_string() } else { image };
             copy_image_to_host(disk, target, &image, &host, recursive, overwrite)?;
             println!("{}", host.display());
             Ok(())
         }
         (PathKind::Image, PathKind::Image) => {
+            if is_image_root(src) {
+                bail!("cannot copy the image root into itself");
+            }
             let src_image = normalize_image_path(src);
             let dst_image = normalize_image_path(dst);
             let dst_image = resolve_image_to_image_dst(disk, target, &src_image, &dst_image)?;
-            copy_image_to_image(disk, target, &src_image, &dst_image, recursive, overwrite)?;
+            copy_image_to_image(
+                disk, target, &src_image, &dst_image, recursive, overwrite, verify,
+            )?;
             println!("{}", dst_image);
             Ok(())
         }
@@ -48,6 +126,76 @@ pub fn cp(
     }
 }
 
+/// Append the contents of file `src` (host, stdin or image) to image file
+/// `dst`, creating it if missing.
+pub fn append(disk: &Path, target: &PartitionTarget, src: &str, dst: &str) -> Result<()> {
+    if path_kind(dst) != PathKind::Image {
+        bail!("--append only writes into the image");
+    }
+    let image = normalize_image_path(dst);
+    if image.ends_with('/') || is_dir(disk, target, &image).unwrap_or(false) {
+        bail!("destination must be a file path when appending");
+    }
+
+    if src == HOST_STDIO {
+        append_file_streaming(disk, target, &image, &mut std::io::stdin().lock())?;
+    } else if path_kind(src) == PathKind::Host {
+        let host = host_path(src)?;
+        let mut file = std::fs::File::open(&host)
+            .map_err(|e| anyhow!("read {} failed: {e}", host.display()))?;
+        append_file_streaming(disk, target, &image, &mut file)?;
+    } else {
+        append_image_file(disk, target, src, &image)?;
+    }
+    println!("{}", image);
+    Ok(())
+}
+
+const HOST_STDIO: &str = "host:-";
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+fn is_image_root(path: &str) -> bool {
+    matches!(path, "/" | "/:")
+}
+
+fn cp_stdio(
+    disk: &Path,
+    target: &PartitionTarget,
+    src: &str,
+    dst: &str,
+    overwrite: bool,
+    verify: bool,
+) -> Result<()> {
+    if src == HOST_STDIO {
+        if path_kind(dst) != PathKind::Image {
+            bail!("stdin can only be copied into the image");
+        }
+        let image = normalize_image_path(dst);
+        if image.ends_with('/') || is_dir(disk, target, &image).unwrap_or(false) {
+            bail!("destination must be a file path when reading from stdin");
+        }
+        let mut stdin = std::io::stdin().lock();
+        write_file_streaming_checked(disk, target, &image, &mut stdin, overwrite, verify)?;
+        println!("{}", image);
+        return Ok(());
+    }
+
+    if path_kind(src) != PathKind::Image {
+        bail!("only image files can be copied to stdout");
+    }
+    let image = normalize_image_path(src);
+    if is_dir(disk, target, &image)? {
+        bail!("cannot copy a directory to stdout");
+    }
+    let mut stdout = std::io::stdout().lock();
+    read_file_chunked(disk, target, &image, CHUNK_SIZE, |chunk| {
+        stdout.write_all(chunk)?;
+        Ok(())
+    })?;
+    stdout.flush()?;
+    Ok(())
+}
+
 fn resolve_host_to_image_dst(
     disk: &Path,
     target: &PartitionTarget,
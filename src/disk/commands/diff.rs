@@ -0,0 +1,257 @@
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::super::fs::{FsOps, with_fs};
+use super::super::types::{PartitionTarget, PathKind};
+use super::super::utils::{host_path, normalize_image_path, path_kind};
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// What a directory entry is, without following links
+#[derive(PartialEq, Eq)]
+enum Kind {
+    File,
+    Dir,
+    Link(String),
+}
+
+impl Kind {
+    fn describe(&self) -> &'static str {
+        match self {
+            Kind::File => "file",
+            Kind::Dir => "directory",
+            Kind::Link(_) => "symlink",
+        }
+    }
+}
+
+/// One side of the comparison
+enum Side {
+    Image(String),
+    Host(PathBuf),
+}
+
+impl Side {
+    fn parse(path: &str) -> Result<Self> {
+        Ok(match path_kind(path) {
+            PathKind::Image => Side::Image(normalize_image_path(path)),
+            PathKind::Host => Side::Host(host_path(path)?),
+        })
+    }
+
+    fn child(&self, name: &str) -> Self {
+        match self {
+            Side::Image(path) => Side::Image(format!("{}/{}", path.trim_end_matches('/'), name)),
+            Side::Host(path) => Side::Host(path.join(name)),
+        }
+    }
+
+    fn is_dir(&self, fs: &mut dyn FsOps) -> Result<bool> {
+        match self {
+            Side::Image(path) => fs.is_dir(path),
+            Side::Host(path) => Ok(std::fs::metadata(path)
+                .with_context(|| format!("failed to stat {}", path.display()))?
+                .is_dir()),
+        }
+    }
+
+    /// Entry names in this directory, mapped to what each one is
+    fn list(&self, fs: &mut dyn FsOps) -> Result<BTreeMap<String, Kind>> {
+        match self {
+            Side::Image(path) => Ok(fs
+                .list_dir(path)?
+                .into_iter()
+                .map(|e| {
+                    let kind = match e.symlink_target {
+                        Some(link) => Kind::Link(link),
+                        None if e.is_dir => Kind::Dir,
+                        None => Kind::File,
+                    };
+                    (e.name, kind)
+                })
+                .collect()),
+            Side::Host(path) => {
+                let mut out = BTreeMap::new();
+                let entries = std::fs::read_dir(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                for entry in entries {
+                    let entry = entry?;
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    let file_type = entry.file_type()?;
+                    let kind = if file_type.is_symlink() {
+                        let link = std::fs::read_link(entry.path())?;
+                        Kind::Link(link.to_string_lossy().into_owned())
+                    } else if file_type.is_dir() {
+                        Kind::Dir
+                    } else {
+                        Kind::File
+                    };
+                    out.insert(name, kind);
+                }
+                Ok(out)
+            }
+        }
+    }
+
+    fn reader(&self) -> Result<Reader> {
+        match self {
+            Side::Image(path) => Ok(Reader::Image {
+                path: path.clone(),
+                offset: 0,
+            }),
+            Side::Host(path) => {
+                Ok(Reader::Host(File::open(path).with_context(|| {
+                    format!("failed to open {}", path.display())
+                })?))
+            }
+        }
+    }
+}
+
+enum Reader {
+    Image { path: String, offset: u64 },
+    Host(File),
+}
+
+impl Reader {
+    /// Next chunk of the file; empty at the end.
+    fn next_chunk(&mut self, fs: &mut dyn FsOps) -> Result<Vec<u8>> {
+        match self {
+            Reader::Image { path, offset } => {
+                let chunk = fs.read_file(path, *offset, Some(CHUNK_SIZE))?;
+                *offset += chunk.len() as u64;
+                Ok(chunk)
+            }
+            Reader::Host(file) => {
+                let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+                file.by_ref()
+                    .take(CHUNK_SIZE as u64)
+                    .read_to_end(&mut chunk)?;
+                Ok(chunk)
+            }
+        }
+    }
+}
+
+/// Compare `a` and `b`, printing what differs. Returns whether they are
+/// identical. Either side may be an image path or a `host:` path.
+pub fn diff(
+    disk: &Path,
+    target: &PartitionTarget,
+    a: &str,
+    b: &str,
+    recursive: bool,
+) -> Result<bool> {
+    if path_kind(a) == PathKind::Host && path_kind(b) == PathKind::Host {
+        bail!("host <-> host is not supported by xtool disk");
+    }
+    let (side_a, side_b) = (Side::parse(a)?, Side::parse(b)?);
+
+    let identical = with_fs(disk, target, |fs| {
+        let (a_dir, b_dir) = (side_a.is_dir(fs)?, side_b.is_dir(fs)?);
+        if a_dir != b_dir {
+            println!("{a} and {b} differ: only one is a directory");
+            return Ok(false);
+        }
+        if a_dir {
+            if !recursive {
+                bail!("{a}: is a directory, use -r");
+            }
+            return diff_dirs(fs, &side_a, &side_b, "");
+        }
+        match first_difference(fs, &side_a, &side_b)? {
+            Some(offset) => {
+                println!("{a} and {b} differ at offset {offset}");
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    })?;
+
+    if identical {
+        println!("{a} and {b} are identical");
+    }
+    Ok(identical)
+}
+
+fn diff_dirs(fs: &mut dyn FsOps, a: &Side, b: &Side, rel: &str) -> Result<bool> {
+    let (a_entries, b_entries) = (a.list(fs)?, b.list(fs)?);
+    let mut names: Vec<&String> = a_entries.keys().chain(b_entries.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let mut identical = true;
+    for name in names {
+        let rel_name = if rel.is_empty() {
+            name.clone()
+        } else {
+            format!("{rel}/{name}")
+        };
+        let same = match (a_entries.get(name), b_entries.get(name)) {
+            (Some(_), None) => {
+                println!("removed: {rel_name}");
+                false
+            }
+            (None, Some(_)) => {
+                println!("added: {rel_name}");
+                false
+            }
+            (Some(Kind::Dir), Some(Kind::Dir)) => {
+                diff_dirs(fs, &a.child(name), &b.child(name), &rel_name)?
+            }
+            (Some(Kind::Link(a_link)), Some(Kind::Link(b_link))) => {
+                if a_link != b_link {
+                    println!("changed: {rel_name} (link to {a_link} vs {b_link})");
+                }
+                a_link == b_link
+            }
+            (Some(a_kind), Some(b_kind)) if a_kind != b_kind => {
+                println!(
+                    "changed: {rel_name} ({} vs {})",
+                    a_kind.describe(),
+                    b_kind.describe()
+                );
+                false
+            }
+            _ => match first_difference(fs, &a.child(name), &b.child(name))? {
+                Some(offset) => {
+                    println!("changed: {rel_name} (first difference at offset {offset})");
+                    false
+                }
+                None => true,
+            },
+        };
+        identical &= same;
+    }
+    Ok(identical)
+}
+
+/// Offset of the first byte that differs, or where the shorter file ends.
+fn first_difference(fs: &mut dyn FsOps, a: &Side, b: &Side) -> Result<Option<u64>> {
+    let (mut a, mut b) = (a.reader()?, b.reader()?);
+    let (mut a_buf, mut b_buf) = (Vec::new(), Vec::new());
+    let mut offset = 0u64;
+    loop {
+        if a_buf.is_empty() {
+            a_buf = a.next_chunk(fs)?;
+        }
+        if b_buf.is_empty() {
+            b_buf = b.next_chunk(fs)?;
+        }
+        // Either side may return a short chunk; compare the overlap and
+        // carry the rest over
+        let n = a_buf.len().min(b_buf.len());
+        if n == 0 {
+            return Ok((a_buf.len() != b_buf.len()).then_some(offset));
+        }
+        if let Some(i) = a_buf[..n].iter().zip(&b_buf[..n]).position(|(x, y)| x != y) {
+            return Ok(Some(offset + i as u64));
+        }
+        a_buf.drain(..n);
+        b_buf.drain(..n);
+        offset += n as u64;
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use super::super::fs::{dir_usage, usage};
+use super::super::types::{DirUsage, FsUsage, PartitionTarget};
+use super::super::utils::format_mib;
+
+#[derive(Serialize)]
+struct DuReport {
+    #[serde(flatten)]
+    usage: FsUsage,
+    used_blocks: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    directories: Vec<DirUsage>,
+}
+
+pub fn du(disk: &Path, target: &PartitionTarget, path: Option<&str>, json: bool) -> Result<()> {
+    let usage = usage(disk, target)?;
+    let directories = match path {
+        Some(path) => dir_usage(disk, target, path)?,
+        None => Vec::new(),
+    };
+
+    if json {
+        let report = DuReport {
+            used_blocks: usage.used_blocks(),
+            usage,
+            directories,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let block_size = usage.block_size;
+    println!("Filesystem: {} (block size {} bytes)", usage.fstype, block_size);
+    println!(
+        "Total: {} blocks ({} M)",
+        usage.total_blocks,
+        format_mib(usage.total_blocks * block_size)
+    );
+    println!(
+        "Used:  {} blocks ({} M)",
+        usage.used_blocks(),
+        format_mib(usage.used_blocks() * block_size)
+    );
+    println!(
+        "Free:  {} blocks ({} M)",
+        usage.free_blocks,
+        format_mib(usage.free_blocks * block_size)
+    );
+
+    if !directories.is_empty() {
+        println!();
+        for dir in directories {
+            println!("{:>12} {}", dir.bytes, dir.path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+
+use super::super::fs::{FsOps, with_fs};
+use super::super::types::PartitionTarget;
+use super::super::utils::normalize_image_path;
+use crate::utils::glob_match;
+
+/// Filters of `disk find`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FindOptions<'a> {
+    /// Glob the entry name must match, like find(1) `-name`
+    pub name: Option<&'a str>,
+    /// `f` for regular files, `d` for directories
+    pub kind: Option<char>,
+}
+
+impl FindOptions<'_> {
+    fn matches(&self, name: &str, is_dir: bool, is_link: bool) -> bool {
+        let kind_ok = match self.kind {
+            Some('f') => !is_dir && !is_link,
+            Some('d') => is_dir,
+            _ => true,
+        };
+        kind_ok && self.name.is_none_or(|pattern| glob_match(pattern, name))
+    }
+}
+
+/// Write the full path of every entry under `path` (and `path` itself) that
+/// passes `options` to `out`, one per line. The tree is walked depth-first
+/// within a single mount, and symbolic links are not followed. Returns
+/// whether anything matched.
+pub fn find(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    options: FindOptions,
+    out: &mut impl Write,
+) -> Result<bool> {
+    let path = normalize_image_path(path);
+    let start_name = path.rsplit('/').find(|s| !s.is_empty()).unwrap_or("/");
+
+    with_fs(disk, target, |fs| {
+        let start_is_dir = fs.is_dir(&path)?;
+        let mut found = false;
+        if options.matches(start_name, start_is_dir, false) {
+            writeln!(out, "{path}")?;
+            found = true;
+        }
+        if start_is_dir {
+            found |= walk(fs, &path, options, out)?;
+        }
+        Ok(found)
+    })
+}
+
+fn walk(fs: &mut dyn FsOps, path: &str, options: FindOptions, out: &mut impl Write) -> Result<bool> {
+    let mut entries = fs.list_dir(path)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut found = false;
+    for entry in entries {
+        let child = format!("{}/{}", path.trim_end_matches('/'), entry.name);
+        let is_link = entry.symlink_target.is_some();
+        if options.matches(&entry.name, entry.is_dir, is_link) {
+            writeln!(out, "{child}")?;
+            found = true;
+        }
+        if entry.is_dir && !is_link {
+            found |= walk(fs, &child, options, out)?;
+        }
+    }
+    Ok(found)
+}
@@ -1,23 +1,49 @@
 use anyhow::Result;
-use std::fs::OpenOptions;
-use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 
+use super::super::fs::probe;
 use super::super::gpt::{map_partitions, open_gpt};
-use super::super::types::DiskInfo;
+use super::super::types::{DiskInfo, FsUsage, PartitionTarget};
+use super::super::utils::format_mib;
 
-pub fn info(disk: &Path, json: bool) -> Result<()> {
+pub fn info(disk: &Path, sector_size: u64, json: bool) -> Result<()> {
     let disk_size = std::fs::metadata(disk)?.len();
 
-    let partitions = match open_gpt(disk, false) {
-        Ok(gdisk) => map_partitions(&gdisk)?,
+    let mut partitions = match open_gpt(disk, false, sector_size) {
+        Ok(gdisk) => map_partitions(&gdisk, sector_size)?,
         Err(_) => Vec::new(),
     };
+    for p in &mut partitions {
+        let usage = probe_quiet(
+            disk,
+            &PartitionTarget {
+                offset_bytes: p.start_bytes,
+                size_bytes: p.size_bytes,
+            },
+        );
+        (p.fs_type, p.used_bytes, p.free_bytes) = split_usage(usage);
+    }
+    // Without a GPT the filesystem, if any, starts at the beginning of the disk
+    let whole_disk = if partitions.is_empty() {
+        probe_quiet(
+            disk,
+            &PartitionTarget {
+                offset_bytes: 0,
+                size_bytes: disk_size,
+            },
+        )
+    } else {
+        None
+    };
 
     if json {
+        let (fs_type, used_bytes, free_bytes) = split_usage(whole_disk);
         let info = DiskInfo {
             disk: disk.display().to_string(),
             size_bytes: disk_size,
+            fs_type,
+            used_bytes,
+            free_bytes,
             partitions,
         };
         println!("{}", serde_json::to_string_pretty(&info)?);
@@ -32,59 +58,64 @@ pub fn info(disk: &Path, json: bool) -> Result<()> {
     );
     if partitions.is_empty() {
         println!("No GPT partitions found.");
-        let fs_type = detect_fs_type(disk)?;
+        let (fs_type, used, free) = split_usage(whole_disk);
         println!(
-            "Filesystem: {}",
-            fs_type.as_deref().unwrap_or("unknown")
+            "Filesystem: {}{}",
+            fs_type.as_deref().unwrap_or("unknown"),
+            space_summary(used, free)
         );
         return Ok(());
     }
 
     for p in partitions {
         println!(
-            "{:>3} {:<16} start={} M size={} M",
+            "{:>3} {:<16} start={} M size={} M fs={}{} uuid={}",
             p.index,
             p.name,
             format_mib(p.start_bytes),
-            format_mib(p.size_bytes)
+            format_mib(p.size_bytes),
+            p.fs_type.as_deref().unwrap_or("-"),
+            space_summary(p.used_bytes, p.free_bytes),
+            p.uuid
         );
     }
     Ok(())
 }
 
-fn format_mib(bytes: u64) -> String {
-    const MIB: u64 = 1024 * 1024;
-    if bytes.is_multiple_of(MIB) {
-        format!("{}", bytes / MIB)
-    } else {
-        format!("{:.1}", bytes as f64 / MIB as f64)
-    }
+/// A filesystem that is recognized but fails to mount is reported as
+/// unknown rather than failing the whole listing.
+fn probe_quiet(disk: &Path, target: &PartitionTarget) -> Option<FsUsage> {
+    probe(disk, target).unwrap_or_else(|e| {
+        log::warn!(
+            "failed to read filesystem at offset {}: {e}",
+            target.offset_bytes
+        );
+        None
+    })
 }
 
-fn detect_fs_type(disk: &Path) -> Result<Option<String>> {
-    let mut file = OpenOptions::new().read(true).open(disk)?;
-
-    let mut ext_magic = [0u8; 2];
-    if file.seek(SeekFrom::Start(1024 + 56)).is_ok()
-        && file.read_exact(&mut ext_magic).is_ok()
-        && u16::from_le_bytes(ext_magic) == 0xEF53
-    {
-        return Ok(Some("ext4".to_string()));
-    }
-
-    let mut boot = [0u8; 512];
-    let read = file.read(&mut boot)?;
-    if read >= 512 && boot[510] == 0x55 && boot[511] == 0xAA {
-        if boot.get(82..87) == Some(b"FAT32") {
-            return Ok(Some("fat32".to_string()));
-        }
-        if boot.get(54..59) == Some(b"FAT16") {
-            return Ok(Some("fat16".to_string()));
-        }
-        if boot.get(54..59) == Some(b"FAT12") {
-            return Ok(Some("fat12".to_string()));
+fn split_usage(usage: Option<FsUsage>) -> (Option<String>, Option<u64>, Option<u64>) {
+    match usage {
+        Some(u) => {
+            let used = u.used_blocks() * u.block_size;
+            (
+                Some(u.fstype),
+                Some(used),
+                Some(u.free_blocks * u.block_size),
+            )
         }
+        None => (None, None, None),
     }
+}
 
-    Ok(None)
+fn space_summary(used: Option<u64>, free: Option<u64>) -> String {
+    match (used, free) {
+        (Some(used), Some(free)) => format!(
+            " total={} M used={} M free={} M",
+            format_mib(used + free),
+            format_mib(used),
+            format_mib(free)
+        ),
+        _ => String::new(),
+    }
 }
@@ -0,0 +1,18 @@
+use anyhow::{Result, bail};
+use std::path::Path;
+
+use super::super::fs::symlink;
+use super::super::types::PartitionTarget;
+
+pub fn ln(
+    disk: &Path,
+    target: &PartitionTarget,
+    link_target: &str,
+    link_path: &str,
+    symbolic: bool,
+) -> Result<()> {
+    if !symbolic {
+        bail!("hard links are not supported, use -s");
+    }
+    symlink(disk, target, link_target, link_path)
+}
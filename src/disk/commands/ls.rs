@@ -3,15 +3,34 @@ use std::path::Path;
 
 use super::super::fs::list_dir;
 use super::super::types::PartitionTarget;
+use super::super::utils::format_timestamp;
+use crate::utils::format_size;
 
-pub fn ls(disk: &Path, target: &PartitionTarget, path: &str) -> Result<()> {
+pub fn ls(disk: &Path, target: &PartitionTarget, path: &str, long: bool) -> Result<()> {
     let entries = list_dir(disk, target, path)?;
 
     for entry in entries {
-        if entry.is_dir {
-            println!("{}/", entry.name);
+        let name = if let Some(link) = &entry.symlink_target {
+            format!("{} -> {}", entry.name, link)
+        } else if entry.is_dir {
+            format!("{}/", entry.name)
         } else {
-            println!("{}", entry.name);
+            entry.name
+        };
+
+        if long {
+            let kind = if entry.symlink_target.is_some() {
+                'l'
+            } else if entry.is_dir {
+                'd'
+            } else {
+                '-'
+            };
+            let size = entry.size.map_or_else(|| "-".to_string(), format_size);
+            let mtime = entry.mtime.map_or_else(|| "-".to_string(), format_timestamp);
+            println!("{kind} {size:>10} {mtime:<19} {name}");
+        } else {
+            println!("{name}");
         }
     }
     Ok(())
@@ -1,23 +1,84 @@
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow, bail};
 use std::path::Path;
 
-use super::super::cli::FsType;
-use super::super::fs::{mkfs_ext4, mkfs_fat32};
+use super::super::cli::{FatBits, FsType};
+use super::super::fatfs::FatType;
+use super::super::fs::{list_dir, mkfs_ext4, mkfs_fat, probe};
 use super::super::types::PartitionTarget;
 use super::super::utils::confirm_or_yes;
 
+/// Flags of `disk mkfs`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MkfsOptions<'a> {
+    /// Volume label
+    pub label: Option<&'a str>,
+    /// FAT variant; FAT32 when unset
+    pub fat_type: Option<FatBits>,
+    /// FAT cluster size in bytes; picked by partition size when unset
+    pub cluster_size: Option<u32>,
+    /// Skip confirmation
+    pub yes: bool,
+    /// Mount the new filesystem afterwards to confirm it is usable
+    pub verify: bool,
+}
+
 pub fn mkfs(
     disk: &Path,
     target: &PartitionTarget,
     fstype: FsType,
-    label: Option<&str>,
-    yes: bool,
+    options: MkfsOptions,
 ) -> Result<()> {
+    let MkfsOptions {
+        label,
+        fat_type: fat_bits,
+        cluster_size,
+        yes,
+        verify,
+    } = options;
+    if fstype == FsType::Ext4 {
+        if fat_bits.is_some() {
+            bail!("--fat-type only applies to --fstype fat32");
+        }
+        if cluster_size.is_some() {
+            bail!("--cluster-size only applies to --fstype fat32; ext4 always uses 4096-byte blocks");
+        }
+    }
+    // FAT32 unless asked otherwise; `auto` leaves the choice to the size
+    let fat_type = match fat_bits.unwrap_or(FatBits::Fat32) {
+        FatBits::Fat12 => Some(FatType::Fat12),
+        FatBits::Fat16 => Some(FatType::Fat16),
+        FatBits::Fat32 => Some(FatType::Fat32),
+        FatBits::Auto => None,
+    };
+
     let prompt = format!("Format {}? This will erase data.", disk.display());
     confirm_or_yes(yes, &prompt)?;
-
     match fstype {
-        FsType::Ext4 => mkfs_ext4(disk, target, label),
-        FsType::Fat32 => mkfs_fat32(disk, target, label),
+        FsType::Ext4 => mkfs_ext4(disk, target, label)?,
+        FsType::Fat32 => mkfs_fat(disk, target, label, fat_type, cluster_size)?,
+    }
+
+    if verify {
+        verify_mount(disk, target, fstype)?;
+    }
+    Ok(())
+}
+
+/// Mount the new filesystem and list its root, so a format that left
+/// something unreadable fails now rather than at the next command.
+fn verify_mount(disk: &Path, target: &PartitionTarget, fstype: FsType) -> Result<()> {
+    let usage = probe(disk, target)
+        .context("verify failed: cannot read the new filesystem")?
+        .ok_or_else(|| anyhow!("verify failed: no filesystem found after mkfs"))?;
+    let expected = match fstype {
+        FsType::Ext4 => "ext4",
+        FsType::Fat32 => "fat",
+    };
+    if !usage.fstype.starts_with(expected) {
+        bail!("verify failed: expected {expected}, found {}", usage.fstype);
     }
+    list_dir(disk, target, "/")
+        .with_context(|| format!("verify failed: new {} filesystem does not mount", usage.fstype))?;
+    println!("Verified: {} filesystem mounts", usage.fstype);
+    Ok(())
 }
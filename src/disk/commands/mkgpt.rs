@@ -1,18 +1,26 @@
 use anyhow::{anyhow, bail, Result};
-use gpt::{disk::LogicalBlockSize, partition_types, GptConfig};
+use gpt::{partition_types, GptConfig};
 use std::path::Path;
 
 use super::super::gpt::{
-    align_partition_start, clamp_size_to_lba, lb_size_bytes, parse_parameter_file,
+    align_partition_start, clamp_size_to_lba, logical_block_size, parse_layout_file,
+    parse_partition_type,
 };
 use super::super::utils::confirm_or_yes;
 
-pub fn mkgpt(disk: &Path, param_file: &Path, align_bytes: u64, yes: bool) -> Result<()> {
+pub fn mkgpt(
+    disk: &Path,
+    param_file: &Path,
+    align_bytes: u64,
+    sector_size: u64,
+    yes: bool,
+) -> Result<()> {
+    let lb_size = logical_block_size(sector_size)?;
     let disk_size = std::fs::metadata(disk)
         .map_err(|e| anyhow!("failed to stat disk {}: {e}", disk.display()))?
         .len();
 
-    if disk_size < lb_size_bytes() * 34 {
+    if disk_size < sector_size * 34 {
         bail!("disk too small for GPT");
     }
 
@@ -24,7 +32,7 @@ pub fn mkgpt(disk: &Path, param_file: &Path, align_bytes: u64, yes: bool) -> Res
         confirm_or_yes(false, &prompt)?;
     }
 
-    let specs = parse_parameter_file(param_file)?;
+    let specs = parse_layout_file(param_file)?;
 
     let file = std::fs::OpenOptions::new()
         .read(true)
@@ -34,22 +42,22 @@ pub fn mkgpt(disk: &Path, param_file: &Path, align_bytes: u64, yes: bool) -> Res
 
     let mut gdisk = GptConfig::new()
         .writable(true)
-        .logical_block_size(LogicalBlockSize::Lb512)
+        .logical_block_size(lb_size)
         .create_from_device(file, None)
         .map_err(|e| anyhow!("failed to create GPT: {e}"))?;
 
     let header = gdisk.header();
     let usable_start_lba = header.first_usable;
     let usable_last_lba = header.last_usable;
-    let usable_start_bytes = usable_start_lba * lb_size_bytes();
-    let usable_end_bytes = (usable_last_lba + 1) * lb_size_bytes();
+    let usable_start_bytes = usable_start_lba * sector_size;
+    let usable_end_bytes = (usable_last_lba + 1) * sector_size;
 
     let mut used_bytes = 0u64;
     let mut part_id: u32 = 1;
     for spec in specs {
-        let mut start = align_partition_start(spec.offset_bytes, align_bytes);
+        let mut start = align_partition_start(spec.offset_bytes, align_bytes, sector_size);
         if start < usable_start_bytes {
-            start = align_partition_start(usable_start_bytes, align_bytes);
+            start = align_partition_start(usable_start_bytes, align_bytes, sector_size);
         }
 
         let size = match spec.size_bytes {
@@ -66,9 +74,9 @@ pub fn mkgpt(disk: &Path, param_file: &Path, align_bytes: u64, yes: bool) -> Res
             }
         };
 
-        let size = clamp_size_to_lba(size);
-        let start_lba = start / lb_size_bytes();
-        let size_lba = size / lb_size_bytes();
+        let size = clamp_size_to_lba(size, sector_size);
+        let start_lba = start / sector_size;
+        let size_lba = size / sector_size;
 
         if start + size > usable_end_bytes {
             bail!("partition {} exceeds disk size", spec.name);
@@ -80,15 +88,14 @@ pub fn mkgpt(disk: &Path, param_file: &Path, align_bytes: u64, yes: bool) -> Res
             bail!("partition {} exceeds usable LBA range", spec.name);
         }
 
+        let part_type = match spec.part_type.as_deref() {
+            Some(ty) => parse_partition_type(ty)
+                .map_err(|e| anyhow!("partition {}: {e}", spec.name))?,
+            None => partition_types::LINUX_FS,
+        };
+
         gdisk
-            .add_partition_at(
-                &spec.name,
-                part_id,
-                start_lba,
-                size_lba,
-                partition_types::LINUX_FS,
-                0,
-            )
+            .add_partition_at(&spec.name, part_id, start_lba, size_lba, part_type, 0)
             .map_err(|e| anyhow!("failed to add partition {}: {e}", spec.name))?;
 
         part_id = part_id.saturating_add(1);
@@ -1,25 +1,43 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result, bail};
+use std::io::Write;
 use std::path::Path;
 
-pub fn mkimg(path: &Path, size_bytes: u64, overwrite: bool) -> Result<()> {
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Create a blank image of `size_bytes`. By default the file is sparse
+/// (extended with `ftruncate`/`SetEndOfFile`, so no blocks are allocated
+/// until written); `sparse = false` writes zeros to allocate it fully.
+pub fn mkimg(path: &Path, size_bytes: u64, overwrite: bool, sparse: bool) -> Result<()> {
     if path.exists() && !overwrite {
         bail!("image already exists, use --overwrite to replace");
     }
     if let Some(parent) = path.parent()
         && !parent.as_os_str().is_empty()
     {
-        std::fs::create_dir_all(parent).with_context(|| {
-            format!("failed to create parent directory: {}", parent.display())
-        })?;
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create parent directory: {}", parent.display()))?;
     }
-    let file = std::fs::OpenOptions::new()
+    let mut file = std::fs::OpenOptions::new()
         .create(true)
         .truncate(true)
         .read(true)
         .write(true)
         .open(path)
         .with_context(|| format!("failed to create image {}", path.display()))?;
-    file.set_len(size_bytes)
-        .with_context(|| "failed to set image size".to_string())?;
+    if sparse {
+        file.set_len(size_bytes)
+            .with_context(|| "failed to set image size".to_string())?;
+    } else {
+        let zeros = vec![0u8; CHUNK_SIZE];
+        let mut remaining = size_bytes;
+        while remaining > 0 {
+            let n = remaining.min(CHUNK_SIZE as u64) as usize;
+            file.write_all(&zeros[..n])
+                .with_context(|| "failed to allocate image".to_string())?;
+            remaining -= n as u64;
+        }
+        file.sync_all()
+            .with_context(|| "failed to flush image".to_string())?;
+    }
     Ok(())
 }
@@ -1,37 +1,81 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 use super::cli::{DiskAction, DiskCli};
+use super::fs::{ImageLock, set_locking, set_progress};
 use super::gpt::resolve_partition_target;
 use super::utils::parse_size;
 
 mod cat;
-mod cp;
+pub mod compress;
+pub mod cp;
+pub mod diff;
+mod du;
+pub mod find;
 mod info;
+mod ln;
 mod ls;
 mod mkdir;
 mod mkfs;
 pub mod mkgpt;
 pub mod mkimg;
 mod mv;
-mod rm;
+pub mod resize;
+pub mod rm;
+mod stat;
+mod sum;
+mod tree;
+mod write;
 
 pub fn run(cli: DiskCli) -> Result<()> {
+    set_locking(!cli.no_lock);
+    // Held for the whole command so multi-step operations and GPT writes
+    // cannot interleave with another xtool run; mkimg creates the image
+    let _lock = match cli.action {
+        DiskAction::Mkimg { .. } => None,
+        _ => Some(ImageLock::acquire(&cli.disk)?),
+    };
     match cli.action {
-        DiskAction::Mkimg { size, overwrite } => {
+        DiskAction::Mkimg {
+            size,
+            overwrite,
+            no_sparse,
+        } => {
             let size_bytes = parse_size(&size)?;
-            mkimg::mkimg(&cli.disk, size_bytes, overwrite)
+            mkimg::mkimg(&cli.disk, size_bytes, overwrite, !no_sparse)
         }
         DiskAction::Mkgpt { file, align, yes } => {
             let align_bytes = parse_size(&align)?;
-            mkgpt::mkgpt(&cli.disk, &file, align_bytes, yes)
+            mkgpt::mkgpt(&cli.disk, &file, align_bytes, cli.sector_size, yes)
         }
-        DiskAction::Mkfs { fstype, label, yes } => {
-            let target = resolve_partition_target(&cli.disk, cli.part.as_deref())?;
-            mkfs::mkfs(&cli.disk, &target, fstype, label.as_deref(), yes)
+        DiskAction::Resize { grow, size, yes } => {
+            let part = cli
+                .part
+                .as_deref()
+                .ok_or_else(|| anyhow!("resize requires --part"))?;
+            let size_bytes = size.as_deref().map(parse_size).transpose()?;
+            resize::resize(&cli.disk, part, cli.sector_size, grow, size_bytes, yes)
         }
-        DiskAction::Ls { path } => {
-            let target = resolve_partition_target(&cli.disk, cli.part.as_deref())?;
-            ls::ls(&cli.disk, &target, &path)
+        DiskAction::Mkfs {
+            fstype,
+            label,
+            fat_type,
+            cluster_size,
+            yes,
+            verify,
+        } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            let options = mkfs::MkfsOptions {
+                label: label.as_deref(),
+                fat_type,
+                cluster_size,
+                yes,
+                verify,
+            };
+            mkfs::mkfs(&cli.disk, &target, fstype, options)
+        }
+        DiskAction::Ls { path, long } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            ls::ls(&cli.disk, &target, &path, long)
         }
         DiskAction::Cp {
             src,
@@ -39,12 +83,26 @@ pub fn run(cli: DiskCli) -> Result<()> {
             recursive,
             force,
             preserve,
+            append,
+            verify,
+            no_progress,
         } => {
-            let target = resolve_partition_target(&cli.disk, cli.part.as_deref())?;
-            cp::cp(&cli.disk, &target, &src, &dst, recursive, force, preserve)
+            set_progress(!no_progress);
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            if append {
+                cp::append(&cli.disk, &target, &src, &dst)
+            } else {
+                let options = cp::CpOptions {
+                    recursive,
+                    force,
+                    preserve,
+                    verify,
+                };
+                cp::cp(&cli.disk, &target, &src, &dst, options)
+            }
         }
         DiskAction::Mv { src, dst, force } => {
-            let target = resolve_partition_target(&cli.disk, cli.part.as_deref())?;
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
             mv::mv(&cli.disk, &target, &src, &dst, force)
         }
         DiskAction::Rm {
@@ -53,17 +111,78 @@ pub fn run(cli: DiskCli) -> Result<()> {
             force,
             yes,
         } => {
-            let target = resolve_partition_target(&cli.disk, cli.part.as_deref())?;
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
             rm::rm(&cli.disk, &target, &path, recursive, force, yes)
         }
         DiskAction::Mkdir { path, parents } => {
-            let target = resolve_partition_target(&cli.disk, cli.part.as_deref())?;
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
             mkdir::mkdir(&cli.disk, &target, &path, parents)
         }
-        DiskAction::Cat { path, bytes, offset } => {
-            let target = resolve_partition_target(&cli.disk, cli.part.as_deref())?;
-            cat::cat(&cli.disk, &target, &path, bytes, offset)
+        DiskAction::Ln {
+            symbolic,
+            target: link_target,
+            link,
+        } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            ln::ln(&cli.disk, &target, &link_target, &link, symbolic)
+        }
+        DiskAction::Cat { path, bytes, offset, tail } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            cat::cat(&cli.disk, &target, &path, bytes, offset, tail)
+        }
+        DiskAction::Write {
+            path,
+            force,
+            append,
+        } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            write::write(&cli.disk, &target, &path, force, append)
+        }
+        DiskAction::Tree { path, depth } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            tree::tree(&cli.disk, &target, &path, depth)
+        }
+        DiskAction::Diff { a, b, recursive } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            if !diff::diff(&cli.disk, &target, &a, &b, recursive)? {
+                // Like diff(1): a difference is not an error, but scripts need to see it
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        DiskAction::Find { path, name, kind } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            let options = find::FindOptions {
+                name: name.as_deref(),
+                kind,
+            };
+            let found = find::find(&cli.disk, &target, &path, options, &mut std::io::stdout().lock())?;
+            if !found && name.is_some() {
+                // Lets scripts tell "no such file" apart from a match
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        DiskAction::Sum { path, algo } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            sum::sum(&cli.disk, &target, &path, algo)
+        }
+        DiskAction::Stat { path, json } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            stat::stat(&cli.disk, &target, &path, json)
+        }
+        DiskAction::Du { path, json } => {
+            let target = resolve_partition_target(&cli.disk, cli.part.as_deref(), cli.sector_size)?;
+            du::du(&cli.disk, &target, path.as_deref(), json)
+        }
+        DiskAction::Info { json } => info::info(&cli.disk, cli.sector_size, json),
+        DiskAction::Compress {
+            out,
+            level,
+            overwrite,
+        } => compress::compress(&cli.disk, &out, level, overwrite),
+        DiskAction::Decompress { out, overwrite } => {
+            compress::decompress(&cli.disk, &out, overwrite)
         }
-        DiskAction::Info { json } => info::info(&cli.disk, json),
     }
 }
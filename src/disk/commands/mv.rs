@@ -4,7 +4,7 @@ use std::path::Path;
 use super::super::fs::mv as fs_mv;
 use super::super::types::{PartitionTarget, PathKind};
 use super::super::utils::{confirm_or_yes, host_path, path_kind, remove_host_path};
-use super::cp::cp;
+use super::cp::{cp, CpOptions};
 use super::super::fs::is_dir as fs_is_dir;
 use super::super::utils::normalize_image_path;
 
@@ -23,7 +23,12 @@ pub fn mv(disk: &Path, target: &PartitionTarget, src: &str, dst: &str, force: bo
         (PathKind::Host, PathKind::Image) | (PathKind::Image, PathKind::Host) => {
             let prompt = "Move between host and image will copy then delete. Continue?";
             confirm_or_yes(false, prompt)?;
-            cp(disk, target, src, dst, true, force, false)?;
+            let options = CpOptions {
+                recursive: true,
+                force,
+                ..CpOptions::default()
+            };
+            cp(disk, target, src, dst, options)?;
             if src_kind == PathKind::Host {
                 remove_host_path(&host_path(src)?)
             } else {
@@ -0,0 +1,125 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::Path;
+
+use super::super::fs::probe;
+use super::super::gpt::{clamp_size_to_lba, find_partition, open_gpt};
+use super::super::types::PartitionTarget;
+use super::super::utils::{confirm_or_yes, format_mib};
+
+pub fn resize(
+    disk: &Path,
+    part: &str,
+    sector_size: u64,
+    grow: bool,
+    size_bytes: Option<u64>,
+    yes: bool,
+) -> Result<()> {
+    let mut gdisk = open_gpt(disk, true, sector_size)?;
+    let index = find_partition(&gdisk, part)?;
+
+    // Rebuild headers against the current file size so the backup header
+    // and last usable LBA follow an enlarged disk.
+    let mut parts = gdisk.partitions().clone();
+    gdisk
+        .update_partitions(parts.clone())
+        .map_err(|e| anyhow!("failed to refresh GPT headers: {e}"))?;
+    let last_usable = gdisk.header().last_usable;
+
+    let current = parts[&index].clone();
+    let next_start = parts
+        .values()
+        .filter(|p| p.is_used() && p.first_lba > current.first_lba)
+        .map(|p| p.first_lba)
+        .min();
+    let limit_lba = next_start.map_or(last_usable, |lba| lba - 1).min(last_usable);
+
+    let new_last_lba = if grow {
+        limit_lba
+    } else {
+        let size = clamp_size_to_lba(
+            size_bytes.ok_or_else(|| anyhow!("--size or --grow required"))?,
+            sector_size,
+        );
+        if size == 0 {
+            bail!("partition size must be at least one sector");
+        }
+        current.first_lba + size / sector_size - 1
+    };
+    if new_last_lba > limit_lba {
+        bail!(
+            "partition {} cannot grow past LBA {} (next partition or end of disk)",
+            current.name,
+            limit_lba
+        );
+    }
+
+    let old_size = (current.last_lba - current.first_lba + 1) * sector_size;
+    let new_size = (new_last_lba - current.first_lba + 1) * sector_size;
+    if new_size == old_size {
+        println!("Partition {} already {} M", current.name, format_mib(new_size));
+        return Ok(());
+    }
+
+    let target = PartitionTarget {
+        offset_bytes: current.first_lba * sector_size,
+        size_bytes: old_size,
+    };
+    // An unreadable filesystem must not be resized blind
+    let fs_usage = probe(disk, &target)
+        .with_context(|| format!("cannot read filesystem on {}; refusing to resize", current.name))?;
+    if new_size > old_size {
+        if let Some(fs_usage) = fs_usage.as_ref().filter(|u| u.fstype == "ext4") {
+            bail!(
+                "refusing to grow {}: in-place {} resize is not supported; \
+                 grow the partition with another tool and run resize2fs",
+                current.name,
+                fs_usage.fstype
+            );
+        }
+    } else {
+        if let Some(fs_usage) = &fs_usage {
+            // Used blocks can sit anywhere in the volume, so the whole
+            // filesystem has to fit in the new partition.
+            let fs_size = fs_usage.total_blocks * fs_usage.block_size;
+            if new_size < fs_size {
+                bail!(
+                    "refusing to shrink {} below its {} filesystem ({} M)",
+                    current.name,
+                    fs_usage.fstype,
+                    format_mib(fs_size)
+                );
+            }
+        }
+        let prompt = format!(
+            "Shrink partition {} from {} M to {} M?",
+            current.name,
+            format_mib(old_size),
+            format_mib(new_size)
+        );
+        confirm_or_yes(yes, &prompt)?;
+    }
+    if let Some(fs_usage) = &fs_usage {
+        eprintln!(
+            "{} filesystem size unchanged: in-place {} resize is not supported",
+            fs_usage.fstype, fs_usage.fstype
+        );
+    }
+
+    if let Some(entry) = parts.get_mut(&index) {
+        entry.last_lba = new_last_lba;
+    }
+    gdisk
+        .update_partitions(parts)
+        .map_err(|e| anyhow!("failed to update partition {}: {e}", current.name))?;
+    gdisk
+        .write()
+        .map_err(|e| anyhow!("failed to write GPT: {e}"))?;
+
+    println!(
+        "Partition {} resized: {} M -> {} M",
+        current.name,
+        format_mib(old_size),
+        format_mib(new_size)
+    );
+    Ok(())
+}
@@ -1,9 +1,9 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use std::path::Path;
 
-use super::super::fs::rm as fs_rm;
+use super::super::fs::{glob, rm as fs_rm};
 use super::super::types::PartitionTarget;
-use super::super::utils::normalize_image_path;
+use super::super::utils::{has_glob, normalize_image_path};
 
 pub fn rm(
     disk: &Path,
@@ -13,17 +13,27 @@ pub fn rm(
     force: bool,
     _yes: bool,
 ) -> Result<()> {
-    let image_path = normalize_image_path(path);
-    let result = fs_rm(disk, target, &image_path, recursive);
+    let paths = if has_glob(path) {
+        let matches = glob(disk, target, path)?;
+        if matches.is_empty() && !force {
+            bail!("no match for {}", path);
+        }
+        matches
+    } else {
+        vec![normalize_image_path(path)]
+    };
+
+    for image_path in paths {
+        let result = fs_rm(disk, target, &image_path, recursive);
 
-    match result {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            if force {
-                Ok(())
-            } else {
-                Err(err)
+        match result {
+            Ok(_) => {}
+            Err(err) => {
+                if !force {
+                    return Err(err);
+                }
             }
         }
     }
+    Ok(())
 }
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use std::path::Path;
+
+use super::super::fs::stat as stat_path;
+use super::super::types::{FileKind, PartitionTarget};
+use super::super::utils::format_timestamp;
+
+pub fn stat(disk: &Path, target: &PartitionTarget, path: &str, json: bool) -> Result<()> {
+    let st = stat_path(disk, target, path)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&st)?);
+        return Ok(());
+    }
+
+    match &st.symlink_target {
+        Some(link) => println!("  File: {} -> {}", st.path, link),
+        None => println!("  File: {}", st.path),
+    }
+    let kind = match st.kind {
+        FileKind::File => "regular file",
+        FileKind::Dir => "directory",
+        FileKind::Symlink => "symbolic link",
+    };
+    println!("  Type: {kind}");
+    println!("  Size: {}", st.size);
+    if let Some(inode) = st.inode {
+        println!(" Inode: {inode}");
+    }
+    if let Some(links) = st.links {
+        println!(" Links: {links}");
+    }
+    if let Some(mode) = st.mode {
+        println!(
+            "  Mode: {:04o} ({})",
+            mode & 0o7777,
+            mode_string(st.kind, mode)
+        );
+    }
+    if let (Some(uid), Some(gid)) = (st.uid, st.gid) {
+        println!("   Uid: {uid}  Gid: {gid}");
+    }
+    for (label, time) in [
+        ("Access", st.atime),
+        ("Modify", st.mtime),
+        ("Change", st.ctime),
+        (" Birth", st.btime),
+    ] {
+        println!(
+            "{label}: {}",
+            time.map_or_else(|| "-".to_string(), format_timestamp)
+        );
+    }
+    Ok(())
+}
+
+/// `ls -l` style rendering, e.g. `-rwxr-xr-x`.
+fn mode_string(kind: FileKind, mode: u32) -> String {
+    let mut out = String::with_capacity(10);
+    out.push(match kind {
+        FileKind::File => '-',
+        FileKind::Dir => 'd',
+        FileKind::Symlink => 'l',
+    });
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    out
+}
@@ -0,0 +1,43 @@
+use anyhow::{Result, bail};
+use crc::{CRC_32_ISO_HDLC, Crc};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use super::super::cli::SumAlgo;
+use super::super::fs::{is_dir, read_file_chunked};
+use super::super::types::PartitionTarget;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+pub fn sum(disk: &Path, target: &PartitionTarget, path: &str, algo: SumAlgo) -> Result<()> {
+    if is_dir(disk, target, path)? {
+        bail!("{path}: is a directory");
+    }
+
+    let hash = match algo {
+        SumAlgo::Sha256 => {
+            let mut hasher = Sha256::new();
+            read_file_chunked(disk, target, path, CHUNK_SIZE, |chunk| {
+                hasher.update(chunk);
+                Ok(())
+            })?;
+            hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>()
+        }
+        SumAlgo::Crc32 => {
+            let mut digest = CRC32.digest();
+            read_file_chunked(disk, target, path, CHUNK_SIZE, |chunk| {
+                digest.update(chunk);
+                Ok(())
+            })?;
+            format!("{:08x}", digest.finalize())
+        }
+    };
+
+    println!("{hash}  {path}");
+    Ok(())
+}
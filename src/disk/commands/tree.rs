@@ -0,0 +1,70 @@
+use anyhow::{Result, bail};
+use std::path::Path;
+
+use super::super::fs::{is_dir, list_dir};
+use super::super::types::PartitionTarget;
+
+#[derive(Default)]
+struct TreeCounts {
+    dirs: usize,
+    files: usize,
+}
+
+pub fn tree(disk: &Path, target: &PartitionTarget, path: &str, depth: Option<usize>) -> Result<()> {
+    if !is_dir(disk, target, path)? {
+        bail!("not a directory: {path}");
+    }
+
+    println!("{path}");
+    let mut counts = TreeCounts::default();
+    walk(disk, target, path, "", 1, depth, &mut counts)?;
+
+    println!();
+    println!(
+        "{} director{}, {} file{}",
+        counts.dirs,
+        if counts.dirs == 1 { "y" } else { "ies" },
+        counts.files,
+        if counts.files == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+fn walk(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    prefix: &str,
+    level: usize,
+    depth: Option<usize>,
+    counts: &mut TreeCounts,
+) -> Result<()> {
+    let mut entries = list_dir(disk, target, path)?;
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    let last = entries.len().saturating_sub(1);
+    for (i, entry) in entries.iter().enumerate() {
+        let (branch, indent) = if i == last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        if entry.is_dir {
+            counts.dirs += 1;
+            println!("{prefix}{branch}{}/", entry.name);
+            if depth.is_none_or(|max| level < max) {
+                let child = format!("{}/{}", path.trim_end_matches('/'), entry.name);
+                let child_prefix = format!("{prefix}{indent}");
+                walk(disk, target, &child, &child_prefix, level + 1, depth, counts)?;
+            }
+        } else if let Some(link) = &entry.symlink_target {
+            counts.files += 1;
+            println!("{prefix}{branch}{} -> {}", entry.name, link);
+        } else {
+            counts.files += 1;
+            println!("{prefix}{branch}{}", entry.name);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use anyhow::{Result, bail};
+use std::path::Path;
+
+use super::super::fs::{append_file_streaming, is_dir, write_file_streaming};
+use super::super::types::PartitionTarget;
+use super::super::utils::normalize_image_path;
+
+pub fn write(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    force: bool,
+    append: bool,
+) -> Result<()> {
+    let image = normalize_image_path(path);
+    if image.ends_with('/') || is_dir(disk, target, &image).unwrap_or(false) {
+        bail!("destination must be a file path");
+    }
+
+    let mut stdin = std::io::stdin().lock();
+    if append {
+        append_file_streaming(disk, target, &image, &mut stdin)?;
+    } else {
+        write_file_streaming(disk, target, &image, &mut stdin, force)?;
+    }
+    println!("{}", image);
+    Ok(())
+}
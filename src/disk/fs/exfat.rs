@@ -0,0 +1,913 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::super::io::PartitionIo;
+use super::super::types::{DirEntry, FileKind, FileStat, FsUsage, PartitionTarget};
+use super::super::utils::{iter_path_components, normalize_image_path};
+use super::FsOps;
+
+const EXFAT_SIGNATURE: &[u8; 8] = b"EXFAT   ";
+const ENTRY_SIZE: usize = 32;
+const ENTRY_IN_USE: u8 = 0x80;
+const ENTRY_BITMAP: u8 = 0x81;
+const ENTRY_UPCASE: u8 = 0x82;
+const ENTRY_FILE: u8 = 0x85;
+const ENTRY_STREAM: u8 = 0xC0;
+const ENTRY_NAME: u8 = 0xC1;
+const ATTR_DIRECTORY: u16 = 0x10;
+const ATTR_ARCHIVE: u16 = 0x20;
+const FLAG_ALLOC_POSSIBLE: u8 = 0x01;
+const FLAG_NO_FAT_CHAIN: u8 = 0x02;
+const FAT_EOC: u32 = 0xFFFF_FFFF;
+const FAT_BAD: u32 = 0xFFFF_FFF7;
+const NAME_CHARS_PER_ENTRY: usize = 15;
+const MAX_NAME_LEN: usize = 255;
+/// UTC offset field value meaning "valid, +00:00".
+const UTC_OFFSET_ZERO: u8 = 0x80;
+
+pub struct ExfatVolume {
+    io: PartitionIo,
+    cluster_size: u64,
+    fat_offset: u64,
+    heap_offset: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+    fat: Vec<u32>,
+    bitmap: Vec<u8>,
+    bitmap_clusters: Vec<u32>,
+    bitmap_dirty: bool,
+    upcase: Vec<u16>,
+}
+
+pub struct ExfatOps<'a> {
+    vol: &'a mut ExfatVolume,
+}
+
+/// A file or directory entry set as found on disk.
+#[derive(Clone)]
+struct Node {
+    name: String,
+    attrs: u16,
+    first_cluster: u32,
+    no_fat_chain: bool,
+    data_len: u64,
+    valid_len: u64,
+    modified: u32,
+    /// Partition-relative byte offsets of every entry in the set; empty for root.
+    offsets: Vec<u64>,
+    /// Raw bytes of the entry set.
+    set: Vec<u8>,
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        self.attrs & ATTR_DIRECTORY != 0
+    }
+}
+
+pub fn is_exfat_boot_sector(boot: &[u8]) -> bool {
+    boot.len() >= 512 && &boot[3..11] == EXFAT_SIGNATURE && boot[510] == 0x55 && boot[511] == 0xAA
+}
+
+pub fn with_exfat<R>(
+    disk: &Path,
+    target: &PartitionTarget,
+    f: impl for<'a> FnOnce(ExfatOps<'a>) -> Result<R>,
+) -> Result<R> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(disk)
+        .map_err(|e| anyhow!("failed to open disk {}: {e}", disk.display()))?;
+    let io = PartitionIo::new(file, target.offset_bytes, target.size_bytes);
+    let mut vol = ExfatVolume::mount(io)?;
+
+    let result = f(ExfatOps { vol: &mut vol })?;
+    vol.flush()?;
+    Ok(result)
+}
+
+impl ExfatVolume {
+    fn mount(mut io: PartitionIo) -> Result<Self> {
+        let mut boot = [0u8; 512];
+        io.seek(SeekFrom::Start(0))?;
+        io.read_exact(&mut boot)
+            .map_err(|e| anyhow!("read exfat boot sector failed: {e}"))?;
+        if !is_exfat_boot_sector(&boot) {
+            bail!("not an exfat volume");
+        }
+
+        let sector_shift = boot[108];
+        let cluster_shift = boot[109];
+        if !(9..=12).contains(&sector_shift) || sector_shift + cluster_shift > 25 {
+            bail!("invalid exfat geometry");
+        }
+        let bytes_per_sector = 1u64 << sector_shift;
+        let fat_offset = u64::from(le_u32(&boot, 80)) * bytes_per_sector;
+        let fat_length = u64::from(le_u32(&boot, 84)) * bytes_per_sector;
+        let heap_offset = u64::from(le_u32(&boot, 88)) * bytes_per_sector;
+        let cluster_count = le_u32(&boot, 92);
+        let root_cluster = le_u32(&boot, 96);
+
+        let fat_entries = (u64::from(cluster_count) + 2).min(fat_length / 4);
+        let mut raw_fat = vec![0u8; (fat_entries * 4) as usize];
+        io.seek(SeekFrom::Start(fat_offset))?;
+        io.read_exact(&mut raw_fat)
+            .map_err(|e| anyhow!("read exfat FAT failed: {e}"))?;
+        let fat = raw_fat
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+
+        let mut vol = Self {
+            io,
+            cluster_size: bytes_per_sector << cluster_shift,
+            fat_offset,
+            heap_offset,
+            cluster_count,
+            root_cluster,
+            fat,
+            bitmap: Vec::new(),
+            bitmap_clusters: Vec::new(),
+            bitmap_dirty: false,
+            upcase: Vec::new(),
+        };
+        if !vol.valid_cluster(root_cluster) {
+            bail!("invalid exfat root cluster {root_cluster}");
+        }
+        vol.load_metadata()?;
+        Ok(vol)
+    }
+
+    fn load_metadata(&mut self) -> Result<()> {
+        let (raw, _) = self.read_dir_raw(&self.root_node())?;
+        let mut bitmap = None;
+        let mut upcase = None;
+        for entry in &raw {
+            match entry[0] {
+                // Only the first allocation bitmap is used (no TexFAT).
+                ENTRY_BITMAP if entry[1] & 1 == 0 => {
+                    bitmap = Some((le_u32(entry, 20), le_u64(entry, 24)));
+                }
+                ENTRY_UPCASE => upcase = Some((le_u32(entry, 20), le_u64(entry, 24))),
+                0x00 => break,
+                _ => {}
+            }
+        }
+
+        let (first, len) = bitmap.ok_or_else(|| anyhow!("exfat allocation bitmap not found"))?;
+        self.bitmap_clusters = self.fixed_chain(first, len)?;
+        self.bitmap = self.read_clusters(&self.bitmap_clusters.clone(), len)?;
+
+        if let Some((first, len)) = upcase {
+            let clusters = self.fixed_chain(first, len)?;
+            let raw = self.read_clusters(&clusters, len)?;
+            self.upcase = decompress_upcase(&raw);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if !self.bitmap_dirty {
+            return Ok(());
+        }
+        let clusters = self.bitmap_clusters.clone();
+        let bitmap = std::mem::take(&mut self.bitmap);
+        let result = self.write_clusters(&clusters, &bitmap);
+        self.bitmap = bitmap;
+        result?;
+        self.bitmap_dirty = false;
+        self.io.flush()?;
+        Ok(())
+    }
+
+    fn root_node(&self) -> Node {
+        Node {
+            name: "/".to_string(),
+            attrs: ATTR_DIRECTORY,
+            first_cluster: self.root_cluster,
+            no_fat_chain: false,
+            data_len: 0,
+            valid_len: 0,
+            modified: 0,
+            offsets: Vec::new(),
+            set: Vec::new(),
+        }
+    }
+
+    fn valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && u64::from(cluster) < u64::from(self.cluster_count) + 2
+    }
+
+    fn cluster_pos(&self, cluster: u32) -> u64 {
+        self.heap_offset + u64::from(cluster - 2) * self.cluster_size
+    }
+
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        self.io.seek(SeekFrom::Start(pos))?;
+        self.io
+            .read_exact(buf)
+            .map_err(|e| anyhow!("exfat read at {pos} failed: {e}"))
+    }
+
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> Result<()> {
+        self.io.seek(SeekFrom::Start(pos))?;
+        self.io
+            .write_all(buf)
+            .map_err(|e| anyhow!("exfat write at {pos} failed: {e}"))
+    }
+
+    /// Cluster list of a metadata object whose FAT chain may or may not be
+    /// recorded; falls back to a contiguous run when the chain is unusable.
+    fn fixed_chain(&self, first: u32, len: u64) -> Result<Vec<u32>> {
+        let needed = len.div_ceil(self.cluster_size) as usize;
+        if let Ok(chain) = self.chain(first, false, len)
+            && chain.len() >= needed
+        {
+            return Ok(chain);
+        }
+        self.chain(first, true, len)
+    }
+
+    fn chain(&self, first: u32, no_fat_chain: bool, len: u64) -> Result<Vec<u32>> {
+        if first == 0 {
+            return Ok(Vec::new());
+        }
+        if no_fat_chain {
+            let count = len.div_ceil(self.cluster_size) as u32;
+            let last = first + count.saturating_sub(1);
+            if count > 0 && (!self.valid_cluster(first) || !self.valid_cluster(last)) {
+                bail!("exfat cluster run out of range");
+            }
+            return Ok((first..first + count).collect());
+        }
+
+        let mut out = Vec::new();
+        let mut cluster = first;
+        loop {
+            if !self.valid_cluster(cluster) {
+                bail!("exfat FAT chain references invalid cluster {cluster}");
+            }
+            out.push(cluster);
+            if out.len() > self.cluster_count as usize {
+                bail!("exfat FAT chain loops");
+            }
+            let next = self.fat.get(cluster as usize).copied().unwrap_or(FAT_EOC);
+            if next == FAT_EOC || next >= FAT_BAD {
+                return Ok(out);
+            }
+            cluster = next;
+        }
+    }
+
+    fn node_chain(&self, node: &Node) -> Result<Vec<u32>> {
+        self.chain(node.first_cluster, node.no_fat_chain, node.data_len)
+    }
+
+    fn read_clusters(&mut self, clusters: &[u32], len: u64) -> Result<Vec<u8>> {
+        let mut out = vec![0u8; len as usize];
+        for (i, chunk) in out.chunks_mut(self.cluster_size as usize).enumerate() {
+            let cluster = *clusters
+                .get(i)
+                .ok_or_else(|| anyhow!("exfat cluster chain too short"))?;
+            let pos = self.cluster_pos(cluster);
+            self.read_at(pos, chunk)?;
+        }
+        Ok(out)
+    }
+
+    fn write_clusters(&mut self, clusters: &[u32], data: &[u8]) -> Result<()> {
+        for (i, chunk) in data.chunks(self.cluster_size as usize).enumerate() {
+            let cluster = *clusters
+                .get(i)
+                .ok_or_else(|| anyhow!("exfat cluster chain too short"))?;
+            let pos = self.cluster_pos(cluster);
+            self.write_at(pos, chunk)?;
+        }
+        Ok(())
+    }
+
+    fn set_fat(&mut self, cluster: u32, value: u32) -> Result<()> {
+        if let Some(slot) = self.fat.get_mut(cluster as usize) {
+            *slot = value;
+        }
+        self.write_at(self.fat_offset + u64::from(cluster) * 4, &value.to_le_bytes())
+    }
+
+    fn is_free(&self, cluster: u32) -> bool {
+        let bit = (cluster - 2) as usize;
+        self.bitmap
+            .get(bit / 8)
+            .is_some_and(|byte| byte & (1 << (bit % 8)) == 0)
+    }
+
+    fn mark(&mut self, cluster: u32, used: bool) {
+        let bit = (cluster - 2) as usize;
+        if let Some(byte) = self.bitmap.get_mut(bit / 8) {
+            if used {
+                *byte |= 1 << (bit % 8);
+            } else {
+                *byte &= !(1 << (bit % 8));
+            }
+            self.bitmap_dirty = true;
+        }
+    }
+
+    fn free_clusters(&self) -> u64 {
+        (2..self.cluster_count + 2).filter(|c| self.is_free(*c)).count() as u64
+    }
+
+    /// Allocate `count` clusters, preferring one contiguous run so the file
+    /// can be stored without a FAT chain. Returns the clusters and whether
+    /// they are contiguous.
+    fn allocate(&mut self, count: usize) -> Result<(Vec<u32>, bool)> {
+        if count == 0 {
+            return Ok((Vec::new(), false));
+        }
+
+        let end = self.cluster_count + 2;
+        let mut run_start = 2;
+        let mut run_len = 0;
+        for cluster in 2..end {
+            if self.is_free(cluster) {
+                if run_len == 0 {
+                    run_start = cluster;
+                }
+                run_len += 1;
+                if run_len == count {
+                    let clusters: Vec<u32> = (run_start..run_start + count as u32).collect();
+                    for c in &clusters {
+                        self.mark(*c, true);
+                    }
+                    return Ok((clusters, true));
+                }
+            } else {
+                run_len = 0;
+            }
+        }
+
+        let clusters: Vec<u32> = (2..end).filter(|c| self.is_free(*c)).take(count).collect();
+        if clusters.len() < count {
+            bail!("no space left on exfat volume");
+        }
+        for (i, c) in clusters.iter().enumerate() {
+            self.mark(*c, true);
+            let next = clusters.get(i + 1).copied().unwrap_or(FAT_EOC);
+            self.set_fat(*c, next)?;
+        }
+        Ok((clusters, false))
+    }
+
+    fn release(&mut self, node: &Node) -> Result<()> {
+        for cluster in self.node_chain(node)? {
+            self.mark(cluster, false);
+        }
+        Ok(())
+    }
+
+    fn read_dir_raw(&mut self, dir: &Node) -> Result<(Vec<[u8; ENTRY_SIZE]>, Vec<u64>)> {
+        let mut raw = Vec::new();
+        let mut offsets = Vec::new();
+        let mut buf = vec![0u8; self.cluster_size as usize];
+        for cluster in self.node_chain(dir)? {
+            let base = self.cluster_pos(cluster);
+            self.read_at(base, &mut buf)?;
+            for (i, chunk) in buf.chunks_exact(ENTRY_SIZE).enumerate() {
+                let mut entry = [0u8; ENTRY_SIZE];
+                entry.copy_from_slice(chunk);
+                raw.push(entry);
+                offsets.push(base + (i * ENTRY_SIZE) as u64);
+            }
+        }
+        Ok((raw, offsets))
+    }
+
+    fn read_dir(&mut self, dir: &Node) -> Result<Vec<Node>> {
+        let (raw, offsets) = self.read_dir_raw(dir)?;
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < raw.len() {
+            let entry = &raw[i];
+            if entry[0] == 0x00 {
+                break;
+            }
+            if entry[0] != ENTRY_FILE {
+                i += 1;
+                continue;
+            }
+            let secondary = entry[1] as usize;
+            if secondary < 2 || i + secondary >= raw.len() || raw[i + 1][0] != ENTRY_STREAM {
+                i += 1;
+                continue;
+            }
+
+            let stream = &raw[i + 1];
+            let name_len = stream[3] as usize;
+            let mut name: Vec<u16> = raw[i + 2..=i + secondary]
+                .iter()
+                .filter(|e| e[0] == ENTRY_NAME)
+                .flat_map(|e| e[2..].chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])))
+                .collect();
+            name.truncate(name_len);
+
+            out.push(Node {
+                name: String::from_utf16_lossy(&name),
+                attrs: le_u16(entry, 4),
+                first_cluster: le_u32(stream, 20),
+                no_fat_chain: stream[1] & FLAG_NO_FAT_CHAIN != 0,
+                data_len: le_u64(stream, 24),
+                valid_len: le_u64(stream, 8),
+                modified: le_u32(entry, 12),
+                offsets: offsets[i..=i + secondary].to_vec(),
+                set: raw[i..=i + secondary].concat(),
+            });
+            i += secondary + 1;
+        }
+        Ok(out)
+    }
+
+    fn upcase_char(&self, c: u16) -> u16 {
+        if let Some(up) = self.upcase.get(c as usize) {
+            return *up;
+        }
+        if c < 0x80 {
+            return (c as u8).to_ascii_uppercase() as u16;
+        }
+        c
+    }
+
+    fn names_equal(&self, a: &str, b: &str) -> bool {
+        let a = a.encode_utf16().map(|c| self.upcase_char(c));
+        let b = b.encode_utf16().map(|c| self.upcase_char(c));
+        a.eq(b)
+    }
+
+    fn name_hash(&self, name: &[u16]) -> u16 {
+        let mut hash: u16 = 0;
+        for &c in name {
+            for b in self.upcase_char(c).to_le_bytes() {
+                hash = hash.rotate_right(1).wrapping_add(u16::from(b));
+            }
+        }
+        hash
+    }
+
+    fn lookup(&mut self, path: &str) -> Result<Node> {
+        let mut node = self.root_node();
+        for part in path.split('/').filter(|s| !s.is_empty()) {
+            if !node.is_dir() {
+                bail!("not a directory");
+            }
+            node = self
+                .read_dir(&node)?
+                .into_iter()
+                .find(|child| self.names_equal(&child.name, part))
+                .ok_or_else(|| anyhow!("path not found: {path}"))?;
+        }
+        Ok(node)
+    }
+
+    fn find_child(&mut self, dir: &Node, name: &str) -> Result<Option<Node>> {
+        Ok(self
+            .read_dir(dir)?
+            .into_iter()
+            .find(|child| self.names_equal(&child.name, name)))
+    }
+
+    fn lookup_parent(&mut self, path: &str) -> Result<(Node, String)> {
+        let normalized = normalize_image_path(path);
+        let trimmed = normalized.trim_end_matches('/');
+        let (parent, name) = trimmed
+            .rsplit_once('/')
+            .ok_or_else(|| anyhow!("invalid path: {path}"))?;
+        if name.is_empty() {
+            bail!("invalid path: {path}");
+        }
+        let parent = self.lookup(if parent.is_empty() { "/" } else { parent })?;
+        if !parent.is_dir() {
+            bail!("not a directory");
+        }
+        Ok((parent, name.to_string()))
+    }
+
+    fn build_set(
+        &self,
+        name: &str,
+        attrs: u16,
+        first_cluster: u32,
+        contiguous: bool,
+        data_len: u64,
+    ) -> Result<Vec<u8>> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            bail!("invalid exfat file name length");
+        }
+        let name_entries = name.len().div_ceil(NAME_CHARS_PER_ENTRY);
+        let secondary = 1 + name_entries;
+        let mut set = vec![0u8; (secondary + 1) * ENTRY_SIZE];
+
+        let (timestamp, centis) = encode_timestamp(chrono::Utc::now().naive_utc());
+        set[0] = ENTRY_FILE;
+        set[1] = secondary as u8;
+        set[4..6].copy_from_slice(&attrs.to_le_bytes());
+        set[8..12].copy_from_slice(&timestamp.to_le_bytes());
+        set[12..16].copy_from_slice(&timestamp.to_le_bytes());
+        set[16..20].copy_from_slice(&timestamp.to_le_bytes());
+        set[20] = centis;
+        set[21] = centis;
+        set[22..25].fill(UTC_OFFSET_ZERO);
+
+        let stream = &mut set[ENTRY_SIZE..2 * ENTRY_SIZE];
+        stream[0] = ENTRY_STREAM;
+        stream[1] = FLAG_ALLOC_POSSIBLE | if contiguous { FLAG_NO_FAT_CHAIN } else { 0 };
+        stream[3] = name.len() as u8;
+        stream[4..6].copy_from_slice(&self.name_hash(&name).to_le_bytes());
+        stream[8..16].copy_from_slice(&data_len.to_le_bytes());
+        stream[20..24].copy_from_slice(&first_cluster.to_le_bytes());
+        stream[24..32].copy_from_slice(&data_len.to_le_bytes());
+
+        for (i, chunk) in name.chunks(NAME_CHARS_PER_ENTRY).enumerate() {
+            let entry = &mut set[(2 + i) * ENTRY_SIZE..(3 + i) * ENTRY_SIZE];
+            entry[0] = ENTRY_NAME;
+            for (j, c) in chunk.iter().enumerate() {
+                entry[2 + j * 2..4 + j * 2].copy_from_slice(&c.to_le_bytes());
+            }
+        }
+
+        update_set_checksum(&mut set);
+        Ok(set)
+    }
+
+    /// Write an entry set into the first run of free slots in `dir`,
+    /// growing the directory by a cluster when none is large enough.
+    fn insert_set(&mut self, dir: &mut Node, set: &[u8]) -> Result<()> {
+        let count = set.len() / ENTRY_SIZE;
+        loop {
+            let (raw, offsets) = self.read_dir_raw(dir)?;
+            let mut run = 0;
+            for (i, entry) in raw.iter().enumerate() {
+                if entry[0] & ENTRY_IN_USE != 0 {
+                    run = 0;
+                    continue;
+                }
+                run += 1;
+                if run == count {
+                    let start = i + 1 - count;
+                    for (k, chunk) in set.chunks_exact(ENTRY_SIZE).enumerate() {
+                        self.write_at(offsets[start + k], chunk)?;
+                    }
+                    return Ok(());
+                }
+            }
+            self.extend_dir(dir)?;
+        }
+    }
+
+    fn extend_dir(&mut self, dir: &mut Node) -> Result<()> {
+        let mut clusters = self.node_chain(dir)?;
+        let (new, _) = self.allocate(1)?;
+        let new = new[0];
+        let zeros = vec![0u8; self.cluster_size as usize];
+        self.write_at(self.cluster_pos(new), &zeros)?;
+
+        // Record the chain in the FAT so the directory can be non-contiguous.
+        clusters.push(new);
+        for (i, c) in clusters.iter().enumerate() {
+            let next = clusters.get(i + 1).copied().unwrap_or(FAT_EOC);
+            self.set_fat(*c, next)?;
+        }
+        dir.no_fat_chain = false;
+
+        if dir.offsets.is_empty() {
+            return Ok(());
+        }
+        dir.data_len += self.cluster_size;
+        dir.valid_len = dir.data_len;
+        let stream = &mut dir.set[ENTRY_SIZE..2 * ENTRY_SIZE];
+        stream[1] &= !FLAG_NO_FAT_CHAIN;
+        stream[8..16].copy_from_slice(&dir.valid_len.to_le_bytes());
+        stream[24..32].copy_from_slice(&dir.data_len.to_le_bytes());
+        update_set_checksum(&mut dir.set);
+        self.write_set(dir)
+    }
+
+    fn write_set(&mut self, node: &Node) -> Result<()> {
+        for (offset, chunk) in node.offsets.iter().zip(node.set.chunks_exact(ENTRY_SIZE)) {
+            self.write_at(*offset, chunk)?;
+        }
+        Ok(())
+    }
+
+    fn delete_set(&mut self, node: &Node) -> Result<()> {
+        for (offset, chunk) in node.offsets.iter().zip(node.set.chunks_exact(ENTRY_SIZE)) {
+            self.write_at(*offset, &[chunk[0] & !ENTRY_IN_USE])?;
+        }
+        Ok(())
+    }
+
+    fn create_dir(&mut self, parent: &mut Node, name: &str) -> Result<()> {
+        let (clusters, contiguous) = self.allocate(1)?;
+        let zeros = vec![0u8; self.cluster_size as usize];
+        self.write_at(self.cluster_pos(clusters[0]), &zeros)?;
+        let set = self.build_set(name, ATTR_DIRECTORY, clusters[0], contiguous, self.cluster_size)?;
+        self.insert_set(parent, &set)
+    }
+
+    fn remove_node(&mut self, node: &Node, recursive: bool) -> Result<()> {
+        if node.is_dir() {
+            let children = self.read_dir(node)?;
+            if !children.is_empty() && !recursive {
+                bail!("directory not empty");
+            }
+            for child in children {
+                self.remove_node(&child, true)?;
+            }
+        }
+        self.release(node)?;
+        self.delete_set(node)
+    }
+}
+
+impl FsOps for ExfatOps<'_> {
+    fn list_dir(&mut self, path: &str) -> Result<Vec<DirEntry>> {
+        let dir = self.vol.lookup(path)?;
+        if !dir.is_dir() {
+            bail!("not a directory");
+        }
+        let mut out: Vec<DirEntry> = self
+            .vol
+            .read_dir(&dir)?
+            .into_iter()
+            .map(|node| DirEntry {
+                is_dir: node.is_dir(),
+                symlink_target: None,
+                size: Some(node.data_len),
+                mtime: decode_timestamp(node.modified),
+                name: node.name,
+            })
+            .collect();
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(out)
+    }
+
+    fn read_file(&mut self, path: &str, offset: u64, bytes: Option<usize>) -> Result<Vec<u8>> {
+        let node = self.vol.lookup(path)?;
+        if node.is_dir() {
+            bail!("is a directory");
+        }
+        if offset >= node.data_len {
+            return Ok(Vec::new());
+        }
+        let end = bytes.map_or(node.data_len, |n| (offset + n as u64).min(node.data_len));
+        let mut out = vec![0u8; (end - offset) as usize];
+
+        // Bytes past ValidDataLength read as zeros.
+        let readable_end = end.min(node.valid_len);
+        let clusters = self.vol.node_chain(&node)?;
+        let cluster_size = self.vol.cluster_size;
+        let mut pos = offset;
+        while pos < readable_end {
+            let index = (pos / cluster_size) as usize;
+            let within = pos % cluster_size;
+            let len = (cluster_size - within).min(readable_end - pos);
+            let cluster = *clusters
+                .get(index)
+                .ok_or_else(|| anyhow!("exfat cluster chain too short"))?;
+            let start = (pos - offset) as usize;
+            let disk_pos = self.vol.cluster_pos(cluster) + within;
+            self.vol.read_at(disk_pos, &mut out[start..start + len as usize])?;
+            pos += len;
+        }
+        Ok(out)
+    }
+
+    fn write_file(&mut self, path: &str, data: &[u8], force: bool) -> Result<()> {
+        let (mut parent, name) = self.vol.lookup_parent(path)?;
+        if let Some(existing) = self.vol.find_child(&parent, &name)? {
+            if existing.is_dir() {
+                bail!("destination is a directory");
+            }
+            if !force {
+                bail!("destination exists, use -f to overwrite");
+            }
+            self.vol.remove_node(&existing, false)?;
+        }
+
+        let count = (data.len() as u64).div_ceil(self.vol.cluster_size) as usize;
+        let (clusters, contiguous) = self.vol.allocate(count)?;
+        self.vol.write_clusters(&clusters, data)?;
+        let first = clusters.first().copied().unwrap_or(0);
+        let set = self
+            .vol
+            .build_set(&name, ATTR_ARCHIVE, first, contiguous, data.len() as u64)?;
+        self.vol.insert_set(&mut parent, &set)
+    }
+
+    fn write_file_streaming(&mut self, path: &str, reader: &mut dyn Read, force: bool) -> Result<u64> {
+        // The cluster run is allocated up front from the final size, so the
+        // whole input has to be known first
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| anyhow!("read failed: {e}"))?;
+        self.write_file(path, &data, force)?;
+        Ok(data.len() as u64)
+    }
+
+    fn append_file(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        // Files are written as one fresh cluster run, so append rewrites
+        let mut contents = match self.vol.lookup(path) {
+            Ok(node) if node.is_dir() => bail!("destination is a directory"),
+            Ok(_) => self.read_file(path, 0, None)?,
+            Err(_) => Vec::new(),
+        };
+        contents.extend_from_slice(data);
+        self.write_file(path, &contents, true)
+    }
+
+    fn mkdir(&mut self, path: &str, parents: bool) -> Result<()> {
+        let components = if parents {
+            iter_path_components(path)
+        } else {
+            vec![normalize_image_path(path)]
+        };
+        for component in components {
+            let (mut parent, name) = self.vol.lookup_parent(&component)?;
+            match self.vol.find_child(&parent, &name)? {
+                Some(existing) if parents && existing.is_dir() => continue,
+                Some(_) => bail!("mkdir failed: {component} exists"),
+                None => self.vol.create_dir(&mut parent, &name)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn rm(&mut self, path: &str, recursive: bool) -> Result<()> {
+        let node = self.vol.lookup(path)?;
+        if node.offsets.is_empty() {
+            bail!("cannot remove root directory");
+        }
+        if node.is_dir() && !recursive {
+            bail!("directory requires -r");
+        }
+        self.vol.remove_node(&node, recursive)
+    }
+
+    fn mv(&mut self, src: &str, dst: &str, force: bool) -> Result<()> {
+        let node = self.vol.lookup(src)?;
+        if node.offsets.is_empty() {
+            bail!("cannot move root directory");
+        }
+        let src_norm = normalize_image_path(src);
+        let dst_norm = normalize_image_path(dst);
+        if dst_norm.starts_with(&format!("{}/", src_norm.trim_end_matches('/'))) {
+            bail!("cannot move a directory into itself");
+        }
+
+        let (mut parent, name) = self.vol.lookup_parent(dst)?;
+        if let Some(existing) = self.vol.find_child(&parent, &name)? {
+            if existing.offsets == node.offsets {
+                // Case-only rename of the same entry.
+                self.vol.delete_set(&node)?;
+            } else if !force {
+                bail!("destination exists, use -f to overwrite");
+            } else {
+                self.vol.remove_node(&existing, false)?;
+            }
+        }
+
+        let mut set = self.vol.build_set(
+            &name,
+            node.attrs,
+            node.first_cluster,
+            node.no_fat_chain,
+            node.data_len,
+        )?;
+        // Keep the original timestamps and valid length.
+        set[8..ENTRY_SIZE].copy_from_slice(&node.set[8..ENTRY_SIZE]);
+        set[ENTRY_SIZE + 8..ENTRY_SIZE + 16].copy_from_slice(&node.valid_len.to_le_bytes());
+        update_set_checksum(&mut set);
+
+        // The new set may reuse the slots of the old one, so parent listings
+        // have to be re-read after the old set is released.
+        self.vol.delete_set(&node)?;
+        let parent_path = dst_norm.trim_end_matches('/');
+        let parent_path = parent_path.rsplit_once('/').map_or("/", |(p, _)| p);
+        parent = self.vol.lookup(if parent_path.is_empty() { "/" } else { parent_path })?;
+        self.vol.insert_set(&mut parent, &set)
+    }
+
+    fn symlink(&mut self, _target: &str, _link_path: &str) -> Result<()> {
+        bail!("exFAT does not support symbolic links")
+    }
+
+    fn is_dir(&mut self, path: &str) -> Result<bool> {
+        Ok(self.vol.lookup(path)?.is_dir())
+    }
+
+    fn file_size(&mut self, path: &str) -> Result<u64> {
+        Ok(self.vol.lookup(path)?.data_len)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileStat> {
+        let node = self.vol.lookup(path)?;
+        // The root has no entry set, so no timestamps either
+        let file_entry = node.set.get(..ENTRY_SIZE);
+        let timestamp = |at| file_entry.and_then(|entry| decode_timestamp(le_u32(entry, at)));
+        Ok(FileStat {
+            path: path.to_string(),
+            kind: if node.is_dir() { FileKind::Dir } else { FileKind::File },
+            size: node.data_len,
+            mode: None,
+            uid: None,
+            gid: None,
+            inode: None,
+            links: None,
+            symlink_target: None,
+            atime: timestamp(16),
+            mtime: timestamp(12),
+            ctime: None,
+            btime: timestamp(8),
+        })
+    }
+
+    fn usage(&mut self) -> Result<FsUsage> {
+        Ok(FsUsage {
+            fstype: "exfat".to_string(),
+            block_size: self.vol.cluster_size,
+            total_blocks: u64::from(self.vol.cluster_count),
+            free_blocks: self.vol.free_clusters(),
+        })
+    }
+}
+
+fn le_u16(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn le_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn le_u64(buf: &[u8], at: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[at..at + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn update_set_checksum(set: &mut [u8]) {
+    let mut sum: u16 = 0;
+    for (i, b) in set.iter().enumerate() {
+        if i == 2 || i == 3 {
+            continue;
+        }
+        sum = sum.rotate_right(1).wrapping_add(u16::from(*b));
+    }
+    set[2..4].copy_from_slice(&sum.to_le_bytes());
+}
+
+/// Expand the compressed up-case table: `0xFFFF, n` encodes `n` identity mappings.
+fn decompress_upcase(raw: &[u8]) -> Vec<u16> {
+    let words: Vec<u16> = raw
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let mut table = Vec::with_capacity(0x10000);
+    let mut i = 0;
+    while i < words.len() && table.len() < 0x10000 {
+        if words[i] == 0xFFFF && i + 1 < words.len() {
+            for _ in 0..words[i + 1] {
+                table.push(table.len() as u16);
+            }
+            i += 2;
+        } else {
+            table.push(words[i]);
+            i += 1;
+        }
+    }
+    table.truncate(0x10000);
+    table
+}
+
+fn encode_timestamp(now: NaiveDateTime) -> (u32, u8) {
+    let year = (now.year() - 1980).clamp(0, 127) as u32;
+    let timestamp = (year << 25)
+        | (now.month() << 21)
+        | (now.day() << 16)
+        | (now.hour() << 11)
+        | (now.minute() << 5)
+        | (now.second() / 2);
+    (timestamp, ((now.second() % 2) * 100) as u8)
+}
+
+fn decode_timestamp(ts: u32) -> Option<u64> {
+    let date = NaiveDate::from_ymd_opt(
+        1980 + (ts >> 25) as i32,
+        (ts >> 21) & 0x0F,
+        (ts >> 16) & 0x1F,
+    )?;
+    let time = date.and_hms_opt((ts >> 11) & 0x1F, (ts >> 5) & 0x3F, (ts & 0x1F) * 2)?;
+    u64::try_from(time.and_utc().timestamp()).ok()
+}
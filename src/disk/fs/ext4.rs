@@ -1,9 +1,11 @@
 use anyhow::{anyhow, bail, Result};
+use std::collections::VecDeque;
 use std::path::Path;
 
 use rsext4::{
-    entries::DirEntryIterator,
-    file::{delete_dir, delete_file, read_file, rename, truncate, write_file},
+    bitmap_cache::CacheKey,
+    entries::{DirEntryIterator, Ext4DirEntry2},
+    file::{delete_dir, delete_file, mkfile_with_ino, read_file, rename, truncate, write_file},
     loopfile::{get_file_inode, resolve_inode_block_allextend},
     mkfs, Ext4FileSystem, Jbd2Dev, BLOCK_SIZE,
 };
@@ -11,10 +13,16 @@ use rsext4::{
 use rsext4::disknode::Ext4Inode;
 
 use super::super::io::PartitionBlockDev;
-use super::super::types::{DirEntry, PartitionTarget};
+use super::super::types::{DirEntry, FileKind, FileStat, FsUsage, PartitionTarget};
 use super::super::utils::{iter_path_components, normalize_image_path};
 use super::FsOps;
 
+/// Longest symlink target stored inline in the inode's block pointers
+const FAST_SYMLINK_MAX: usize = 60;
+/// Same limit as Linux's MAXSYMLINKS before giving up with ELOOP
+const MAX_SYMLINK_HOPS: usize = 40;
+const ROOT_INO: u32 = 2;
+
 pub struct Ext4Ops<'a> {
     jbd: &'a mut Jbd2Dev<PartitionBlockDev>,
     fs: &'a mut Ext4FileSystem,
@@ -79,7 +87,7 @@ pub fn with_ext4<R>(
 }
 
 impl<'a> Ext4Ops<'a> {
-    fn get_dir_entries(&mut self, inode: &mut Ext4Inode) -> Result<Vec<(u32, String, bool)>> {
+    fn get_dir_entries(&mut self, inode: &mut Ext4Inode) -> Result<Vec<(u32, String, Ext4Inode)>> {
         let blocks = resolve_inode_block_allextend(self.fs, self.jbd, inode)
             .map_err(|e| anyhow!("resolve dir blocks failed: {e:?}"))?;
 
@@ -114,56 +122,114 @@ impl<'a> Ext4Ops<'a> {
                     .fs
                     .get_inode_by_num(self.jbd, inode_num)
                     .map_err(|e| anyhow!("inode read failed: {e:?}"))?;
-                entries.push((inode_num, name, child_inode.is_dir()));
+                entries.push((inode_num, name, child_inode));
             }
         }
         Ok(entries)
     }
 
+    fn root(&mut self) -> Result<Ext4Inode> {
+        let (_, root) = get_file_inode(self.fs, self.jbd, "/")
+            .map_err(|e| anyhow!("root lookup failed: {e:?}"))?
+            .ok_or_else(|| anyhow!("root not found"))?;
+        Ok(root)
+    }
+
+    /// Look up `path`, following symlinks in every component.
     fn resolve_path(&mut self, path: &str) -> Result<Ext4Inode> {
-         if path == "/" {
-             let (_, root) = get_file_inode(self.fs, self.jbd, "/")
-                 .map_err(|e| anyhow!("root lookup failed: {e:?}"))?
-                 .ok_or_else(|| anyhow!("root not found"))?;
-             return Ok(root);
-         }
-
-         let mut current_inode = {
-             let (_, root) = get_file_inode(self.fs, self.jbd, "/")
-                 .map_err(|e| anyhow!("root lookup failed: {e:?}"))?
-                 .ok_or_else(|| anyhow!("root not found"))?;
-             root
-         };
-
-         let normalized = normalize_image_path(path);
-         let parts: Vec<&str> = normalized.split('/').filter(|s| !s.is_empty()).collect();
-
-         for part in parts {
-             if !current_inode.is_dir() {
-                 bail!("not a directory");
-             }
-             
-             let entries = self.get_dir_entries(&mut current_inode)?;
-             let mut found_inode_num = None;
-             
-             for (inum, name, _) in entries {
-                 if name == part {
-                     found_inode_num = Some(inum);
-                     break;
-                 }
-             }
-             
-             match found_inode_num {
-                 Some(num) => {
-                     current_inode = self
+        Ok(self.resolve(path, true)?.1)
+    }
+
+    /// Walk `path` from the root, following symlinks in intermediate
+    /// components (and the last one if `follow_last`). Returns the path with
+    /// those links replaced by their targets, and the inode it names.
+    fn resolve(&mut self, path: &str, follow_last: bool) -> Result<(String, Ext4Inode)> {
+        let root = self.root()?;
+        // Directories walked so far, so ".." can step back
+        let mut stack: Vec<(String, Ext4Inode)> = Vec::new();
+        let mut current = root;
+        let mut pending: VecDeque<String> = normalize_image_path(path)
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        let mut hops = 0;
+
+        while let Some(part) = pending.pop_front() {
+            match part.as_str() {
+                "." => continue,
+                ".." => {
+                    stack.pop();
+                    current = stack.last().map_or(root, |(_, inode)| *inode);
+                    continue;
+                }
+                _ => {}
+            }
+            if !current.is_dir() {
+                bail!("not a directory");
+            }
+
+            let found = self
+                .get_dir_entries(&mut current)?
+                .into_iter()
+                .find(|(_, name, _)| *name == part);
+            let Some((_, _, child)) = found else {
+                bail!("path not found: {}", path);
+            };
+
+            if child.is_symlink() && (follow_last || !pending.is_empty()) {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    bail!("too many levels of symbolic links: {}", path);
+                }
+                let target = self.read_link(&child)?;
+                if target.starts_with('/') {
+                    stack.clear();
+                    current = root;
+                }
+                for component in target.split('/').filter(|s| !s.is_empty()).rev() {
+                    pending.push_front(component.to_string());
+                }
+                continue;
+            }
+            stack.push((part, child));
+            current = child;
+        }
+
+        let resolved = format!(
+            "/{}",
+            stack.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join("/")
+        );
+        Ok((resolved, current))
+    }
+
+    /// Target of a symlink inode. Short targets live in the inode itself
+    /// ("fast" symlinks); longer ones in data blocks.
+    fn read_link(&mut self, inode: &Ext4Inode) -> Result<String> {
+        let size = inode.size() as usize;
+        let mut data = Vec::with_capacity(size);
+        if size <= FAST_SYMLINK_MAX && inode.i_blocks_lo == 0 {
+            for word in inode.i_block {
+                data.extend_from_slice(&word.to_le_bytes());
+            }
+        } else {
+            let mut inode = *inode;
+            let blocks = resolve_inode_block_allextend(self.fs, self.jbd, &mut inode)
+                .map_err(|e| anyhow!("resolve symlink blocks failed: {e:?}"))?;
+            for phys in blocks.values() {
+                let cached = self
                     .fs
-                    .get_inode_by_num(self.jbd, num)
-                    .map_err(|e| anyhow!("inode read failed: {e:?}"))?;
-                 }
-                 None => bail!("path not found: {}", path),
-             }
-         }
-         Ok(current_inode)
+                    .datablock_cache
+                    .get_or_load(self.jbd, *phys)
+                    .map_err(|e| anyhow!("load block failed: {e:?}"))?;
+                data.extend_from_slice(&cached.data[..BLOCK_SIZE]);
+                if data.len() >= size {
+                    break;
+                }
+            }
+        }
+        data.truncate(size);
+        String::from_utf8(data).map_err(|_| anyhow!("symlink target is not UTF-8"))
     }
 }
 
@@ -177,17 +243,43 @@ impl FsOps for Ext4Ops<'_> {
 
         let entries = self.get_dir_entries(&mut inode)?;
         let mut res = Vec::new();
-        for (_, name, is_dir) in entries {
-            res.push(DirEntry { name, is_dir });
+        for (_, name, child) in entries {
+            let symlink_target = if child.is_symlink() {
+                Some(self.read_link(&child)?)
+            } else {
+                None
+            };
+            res.push(DirEntry {
+                name,
+                is_dir: child.is_dir(),
+                symlink_target,
+                size: Some(child.size()),
+                // rsext4 leaves timestamps zeroed on files it creates
+                mtime: (child.i_mtime != 0).then_some(u64::from(child.i_mtime)),
+            });
         }
         res.sort_by(|a, b| a.name.cmp(&b.name));
         Ok(res)
     }
 
     fn read_file(&mut self, path: &str, offset: u64, bytes: Option<usize>) -> Result<Vec<u8>> {
-        // Verify file existence via manual resolution first
-        let _ = self.resolve_path(path)?;
-        
+        // Resolve symlinks ourselves: rsext4 only follows them in the last
+        // path component
+        let (path, inode) = self.resolve(path, true)?;
+        let path = path.as_str();
+
+        // Ranged reads only touch the blocks they need; anything rsext4 can't
+        // serve that way (symlinks, non-extent inodes) falls back to a full read.
+        if let Some(len) = bytes
+            && inode.is_file()
+            && let Ok(mut file) = rsext4::open(self.jbd, self.fs, path, false)
+        {
+            rsext4::lseek(&mut file, offset);
+            if let Ok(data) = rsext4::read_at(self.jbd, self.fs, &mut file, len) {
+                return Ok(data);
+            }
+        }
+
         let data = read_file(self.jbd, self.fs, path)
             .map_err(|e| anyhow!("read failed: {e:?}"))?
             .ok_or_else(|| anyhow!("file not found (read)"))?;
@@ -201,11 +293,11 @@ impl FsOps for Ext4Ops<'_> {
     }
 
     fn write_file(&mut self, path: &str, data: &[u8], force: bool) -> Result<()> {
-        let exists = match self.resolve_path(path) {
-            Ok(_) => true,
-            Err(_) => false, // Assume not found if resolve failed
-        };
-        
+        // Writing through an existing symlink replaces its target's contents
+        let resolved = self.resolve(path, true).ok().map(|(resolved, _)| resolved);
+        let exists = resolved.is_some();
+        let path = resolved.as_deref().unwrap_or(path);
+
         if exists {
             if !force {
                 bail!("destination exists, use -f to overwrite");
@@ -220,6 +312,18 @@ impl FsOps for Ext4Ops<'_> {
         Ok(())
     }
 
+    fn append_file(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        let Ok((path, inode)) = self.resolve(path, true) else {
+            return self.write_file(path, data, false);
+        };
+        if inode.is_dir() {
+            bail!("destination is a directory");
+        }
+        write_file(self.jbd, self.fs, &path, inode.size(), data)
+            .map_err(|e| anyhow!("append failed: {e:?}"))?;
+        Ok(())
+    }
+
     fn mkdir(&mut self, path: &str, parents: bool) -> Result<()> {
         if parents {
             for p in iter_path_components(path) {
@@ -232,7 +336,9 @@ impl FsOps for Ext4Ops<'_> {
     }
 
     fn rm(&mut self, path: &str, recursive: bool) -> Result<()> {
-        let inode = self.resolve_path(path)?;
+        // Removing a symlink removes the link, not what it points to
+        let (path, inode) = self.resolve(path, false)?;
+        let path = path.as_str();
         if inode.is_dir() {
             if !recursive {
                 bail!("directory requires -r");
@@ -246,7 +352,7 @@ impl FsOps for Ext4Ops<'_> {
 
     fn mv(&mut self, src: &str, dst: &str, force: bool) -> Result<()> {
         if !force
-            && self.resolve_path(dst).is_ok()
+            && self.resolve(dst, false).is_ok()
         {
             bail!("destination exists, use -f to overwrite");
         }
@@ -254,8 +360,120 @@ impl FsOps for Ext4Ops<'_> {
         Ok(())
     }
 
+    fn symlink(&mut self, target: &str, link_path: &str) -> Result<()> {
+        if target.is_empty() {
+            bail!("symlink target must not be empty");
+        }
+        if self.resolve(link_path, false).is_ok() {
+            bail!("destination exists: {}", link_path);
+        }
+        // rsext4's own helper rejects dangling and relative targets, so
+        // create the inode directly. The target need not exist.
+        let fast = target.len() <= FAST_SYMLINK_MAX;
+        let data = (!fast).then_some(target.as_bytes());
+        let (ino, _) = mkfile_with_ino(
+            self.jbd,
+            self.fs,
+            link_path,
+            data,
+            Some(Ext4DirEntry2::EXT4_FT_SYMLINK),
+        )
+        .ok_or_else(|| anyhow!("symlink failed for path: {}", link_path))?;
+        if fast {
+            let mut raw = [0u8; FAST_SYMLINK_MAX];
+            raw[..target.len()].copy_from_slice(target.as_bytes());
+            self.fs
+                .modify_inode(self.jbd, ino, |inode| {
+                    inode.i_flags &= !Ext4Inode::EXT4_EXTENTS_FL;
+                    for (word, bytes) in inode.i_block.iter_mut().zip(raw.chunks_exact(4)) {
+                        *word = u32::from_le_bytes(bytes.try_into().expect("4-byte chunk"));
+                    }
+                    inode.i_size_lo = target.len() as u32;
+                    inode.i_size_high = 0;
+                })
+                .map_err(|e| anyhow!("symlink inode update failed: {e:?}"))?;
+        }
+        Ok(())
+    }
+
     fn is_dir(&mut self, path: &str) -> Result<bool> {
         let inode = self.resolve_path(path)?;
         Ok(inode.is_dir())
     }
+
+    fn file_size(&mut self, path: &str) -> Result<u64> {
+        let inode = self.resolve_path(path)?;
+        Ok(inode.size())
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileStat> {
+        let (resolved, inode) = self.resolve(path, false)?;
+        // The inode number lives in the parent's directory entry
+        let ino = match resolved.rsplit_once('/') {
+            Some((parent, name)) if !name.is_empty() => {
+                let mut dir = self.resolve_path(if parent.is_empty() { "/" } else { parent })?;
+                self.get_dir_entries(&mut dir)?
+                    .into_iter()
+                    .find(|(_, entry, _)| entry == name)
+                    .map(|(ino, _, _)| ino)
+            }
+            _ => Some(ROOT_INO),
+        };
+        let kind = if inode.is_symlink() {
+            FileKind::Symlink
+        } else if inode.is_dir() {
+            FileKind::Dir
+        } else {
+            FileKind::File
+        };
+        let symlink_target = if inode.is_symlink() {
+            Some(self.read_link(&inode)?)
+        } else {
+            None
+        };
+        // rsext4 leaves timestamps zeroed on files it creates
+        let time = |secs: u32| (secs != 0).then_some(u64::from(secs));
+        Ok(FileStat {
+            path: path.to_string(),
+            kind,
+            size: inode.size(),
+            mode: Some(u32::from(inode.i_mode)),
+            uid: Some(inode.uid()),
+            gid: Some(inode.gid()),
+            inode: ino,
+            links: Some(u32::from(inode.i_links_count)),
+            symlink_target,
+            atime: time(inode.i_atime),
+            mtime: time(inode.i_mtime),
+            ctime: time(inode.i_ctime),
+            btime: time(inode.i_crtime),
+        })
+    }
+
+    fn usage(&mut self) -> Result<FsUsage> {
+        let stats = self.fs.statfs();
+        // Neither the superblock nor the group descriptor free counters are
+        // kept accurate by rsext4, so count clear bits in the block bitmaps.
+        let blocks_per_group = u64::from(self.fs.superblock.blocks_per_group());
+        let mut free_blocks = 0u64;
+        for group in 0..self.fs.group_count {
+            let first = u64::from(group) * blocks_per_group;
+            let in_group = blocks_per_group.min(stats.total_blocks.saturating_sub(first));
+            let bitmap_block = self.fs.group_descs[group as usize].block_bitmap();
+            let bitmap = self
+                .fs
+                .bitmap_cache
+                .get_or_load(self.jbd, CacheKey::new_block(group), bitmap_block)
+                .map_err(|e| anyhow!("load block bitmap failed: {e:?}"))?;
+            free_blocks += (0..in_group)
+                .filter(|bit| bitmap.data[(bit / 8) as usize] & (1 << (bit % 8)) == 0)
+                .count() as u64;
+        }
+        Ok(FsUsage {
+            fstype: "ext4".to_string(),
+            block_size: stats.block_size,
+            total_blocks: stats.total_blocks,
+            free_blocks,
+        })
+    }
 }
@@ -7,7 +7,7 @@ use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
 use super::super::io::PartitionIo;
-use super::super::types::{DirEntry, PartitionTarget};
+use super::super::types::{DirEntry, FileKind, FileStat, FsUsage, PartitionTarget};
 use super::super::utils::{format_fat_label, iter_path_components, normalize_image_path};
 use super::FsOps;
 
@@ -17,14 +17,56 @@ pub struct FatOps<'a> {
     fs: &'a mut FatFs,
 }
 
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+// Partition sizes `format_volume` can lay out as each FAT type, given the
+// cluster sizes (512 B to 32 KiB) it picks and the cluster count limits of
+// the type
+const FAT12_MIN: u64 = 21 * KIB;
+const FAT12_MAX: u64 = 127 * MIB;
+const FAT16_MIN: u64 = 4118 * KIB;
+const FAT16_MAX: u64 = 2047 * MIB;
+const FAT32_MIN: u64 = 33_278 * KIB;
+/// Cluster sizes accepted by `--cluster-size`: one sector up to the 32 KiB
+/// most FAT drivers can read
+const MIN_CLUSTER_SIZE: u32 = 512;
+const MAX_CLUSTER_SIZE: u32 = 32 * 1024;
+
 pub fn mkfs_fat32(disk: &Path, target: &PartitionTarget, label: Option<&str>) -> Result<()> {
+    mkfs_fat(disk, target, label, Some(FatType::Fat32), None)
+}
+
+/// Format the partition as FAT. `fat_type` of `None` lets the size decide:
+/// FAT12 below about 4 MiB, FAT16 below 512 MiB and FAT32 above.
+/// `cluster_size` of `None` also goes by size, from 512 B on small FAT32
+/// volumes up to 32 KiB.
+pub fn mkfs_fat(
+    disk: &Path,
+    target: &PartitionTarget,
+    label: Option<&str>,
+    fat_type: Option<FatType>,
+    cluster_size: Option<u32>,
+) -> Result<()> {
+    if let Some(cluster_size) = cluster_size {
+        check_cluster_size(cluster_size)?;
+    }
+    if let Some(fat_type) = fat_type {
+        check_fat_fits(fat_type, target.size_bytes, cluster_size)?;
+    }
+
     let file = std::fs::OpenOptions::new()
         .read(true)
         .write(true)
         .open(disk)
         .map_err(|e| anyhow!("failed to open disk {}: {e}", disk.display()))?;
 
-    let mut opts = FormatVolumeOptions::new().fat_type(FatType::Fat32);
+    let mut opts = FormatVolumeOptions::new();
+    if let Some(fat_type) = fat_type {
+        opts = opts.fat_type(fat_type);
+    }
+    if let Some(cluster_size) = cluster_size {
+        opts = opts.bytes_per_cluster(cluster_size);
+    }
     if let Some(label) = label {
         opts = opts.volume_label(format_fat_label(label)?);
     }
@@ -34,10 +76,75 @@ pub fn mkfs_fat32(disk: &Path, target: &PartitionTarget, label: Option<&str>) ->
         target.offset_bytes,
         target.size_bytes,
     ));
-    fatfs::format_volume(&mut io, opts).map_err(|e| anyhow!("mkfs fat32 failed: {e}"))?;
+    let name = fat_type.map_or("fat", fat_type_name);
+    fatfs::format_volume(&mut io, opts).map_err(|e| anyhow!("mkfs {name} failed: {e}"))?;
+    Ok(())
+}
+
+fn check_cluster_size(bytes: u32) -> Result<()> {
+    if !bytes.is_power_of_two() || !(MIN_CLUSTER_SIZE..=MAX_CLUSTER_SIZE).contains(&bytes) {
+        bail!(
+            "FAT cluster size must be a power of two from {MIN_CLUSTER_SIZE} to {MAX_CLUSTER_SIZE} bytes, got {bytes}"
+        );
+    }
+    Ok(())
+}
+
+/// Refuse a FAT type the partition is too small or too large for, before
+/// anything is written. With a fixed `cluster_size` it is the cluster count
+/// that has to fit the type.
+fn check_fat_fits(fat_type: FatType, size: u64, cluster_size: Option<u32>) -> Result<()> {
+    let name = fat_type_name(fat_type).to_uppercase();
+    if let Some(cluster_size) = cluster_size {
+        let clusters = size / u64::from(cluster_size);
+        let (min, max) = (fat_type.min_clusters(), fat_type.max_clusters());
+        if clusters < u64::from(min) || clusters > u64::from(max) {
+            bail!(
+                "{name} needs {min} to {max} clusters, {cluster_size}-byte clusters give about {clusters} on this {} partition",
+                size_label(size)
+            );
+        }
+        return Ok(());
+    }
+
+    let (min, max) = match fat_type {
+        FatType::Fat12 => (FAT12_MIN, FAT12_MAX),
+        FatType::Fat16 => (FAT16_MIN, FAT16_MAX),
+        FatType::Fat32 => (FAT32_MIN, u64::MAX),
+    };
+    if size < min {
+        bail!(
+            "{name} needs a partition of at least {}, this one is {}",
+            size_label(min),
+            size_label(size)
+        );
+    }
+    if size > max {
+        bail!(
+            "{name} holds at most {}, this partition is {}; use a larger FAT type",
+            size_label(max),
+            size_label(size)
+        );
+    }
     Ok(())
 }
 
+fn size_label(bytes: u64) -> String {
+    if bytes < MIB {
+        format!("{} KiB", bytes / KIB)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / MIB as f64)
+    }
+}
+
+fn fat_type_name(fat_type: FatType) -> &'static str {
+    match fat_type {
+        FatType::Fat12 => "fat12",
+        FatType::Fat16 => "fat16",
+        FatType::Fat32 => "fat32",
+    }
+}
+
 pub fn with_fat<R>(
     disk: &Path,
     target: &PartitionTarget,
@@ -77,9 +184,13 @@ impl FsOps for FatOps<'_> {
             if name == "." || name == ".." {
                 continue;
             }
+            let modified = chrono::NaiveDateTime::from(entry.modified());
             out.push(DirEntry {
                 name,
                 is_dir: entry.is_dir(),
+                symlink_target: None,
+                size: Some(entry.len()),
+                mtime: u64::try_from(modified.and_utc().timestamp()).ok(),
             });
         }
         out.sort_by(|a, b| a.name.cmp(&b.name));
@@ -97,10 +208,11 @@ impl FsOps for FatOps<'_> {
 
         let mut data = Vec::new();
         if let Some(n) = bytes {
-            let mut buf = vec![0u8; n];
-            let read = file.read(&mut buf).map_err(|e| anyhow!("read failed: {e}"))?;
-            buf.truncate(read);
-            data.extend_from_slice(&buf);
+            // A single read stops at the end of a cluster
+            (&mut file)
+                .take(n as u64)
+                .read_to_end(&mut data)
+                .map_err(|e| anyhow!("read failed: {e}"))?;
         } else {
             file.read_to_end(&mut data)
                 .map_err(|e| anyhow!("read failed: {e}"))?;
@@ -109,6 +221,11 @@ impl FsOps for FatOps<'_> {
     }
 
     fn write_file(&mut self, path: &str, data: &[u8], force: bool) -> Result<()> {
+        self.write_file_streaming(path, &mut &data[..], force)?;
+        Ok(())
+    }
+
+    fn write_file_streaming(&mut self, path: &str, reader: &mut dyn Read, force: bool) -> Result<u64> {
         let root = self.fs.root_dir();
         let mut file = match root.open_file(path) {
             Ok(mut f) => {
@@ -122,6 +239,21 @@ impl FsOps for FatOps<'_> {
                 .create_file(path)
                 .map_err(|e| anyhow!("create file failed: {e}"))?,
         };
+        std::io::copy(reader, &mut file).map_err(|e| anyhow!("write failed: {e}"))
+    }
+
+    fn append_file(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        let root = self.fs.root_dir();
+        let mut file = match root.open_file(path) {
+            Ok(mut f) => {
+                f.seek(SeekFrom::End(0))
+                    .map_err(|e| anyhow!("seek failed: {e}"))?;
+                f
+            }
+            Err(_) => root
+                .create_file(path)
+                .map_err(|e| anyhow!("create file failed: {e}"))?,
+        };
         file.write_all(data)
             .map_err(|e| anyhow!("write failed: {e}"))?;
         Ok(())
@@ -164,11 +296,101 @@ impl FsOps for FatOps<'_> {
         Ok(())
     }
 
+    fn symlink(&mut self, _target: &str, _link_path: &str) -> Result<()> {
+        bail!("FAT does not support symbolic links")
+    }
+
     fn is_dir(&mut self, path: &str) -> Result<bool> {
         let root = self.fs.root_dir();
         let path = normalize_image_path(path);
         Ok(root.open_dir(&path).is_ok())
     }
+
+    fn file_size(&mut self, path: &str) -> Result<u64> {
+        let root = self.fs.root_dir();
+        let mut file = root
+            .open_file(path)
+            .map_err(|e| anyhow!("open file failed: {e}"))?;
+        file.seek(SeekFrom::End(0))
+            .map_err(|e| anyhow!("seek failed: {e}"))
+    }
+
+    fn stat(&mut self, path: &str) -> Result<FileStat> {
+        let path = normalize_image_path(path);
+        let trimmed = path.trim_end_matches('/');
+        let mut stat = FileStat {
+            path: path.clone(),
+            kind: FileKind::Dir,
+            size: 0,
+            mode: None,
+            uid: None,
+            gid: None,
+            inode: None,
+            links: None,
+            symlink_target: None,
+            atime: None,
+            mtime: None,
+            ctime: None,
+            btime: None,
+        };
+        // The root directory has no entry of its own
+        let Some((parent, name)) = trimmed.rsplit_once('/').filter(|_| !trimmed.is_empty()) else {
+            return Ok(stat);
+        };
+
+        let root = self.fs.root_dir();
+        let dir = if parent.is_empty() {
+            root
+        } else {
+            root.open_dir(parent).map_err(|e| anyhow!("open dir failed: {e}"))?
+        };
+        for entry in dir.iter() {
+            let entry = entry.map_err(|e| anyhow!("iter failed: {e:?}"))?;
+            if !entry.eq_name(name) {
+                continue;
+            }
+            if !entry.is_dir() {
+                stat.kind = FileKind::File;
+                stat.size = entry.len();
+            }
+            // FAT records only the date of the last access
+            let accessed = entry.accessed();
+            stat.atime = fat_timestamp(fatfs::DateTime::new(
+                accessed,
+                fatfs::Time::new(0, 0, 0, 0),
+            ));
+            stat.mtime = fat_timestamp(entry.modified());
+            stat.btime = fat_timestamp(entry.created());
+            return Ok(stat);
+        }
+        bail!("path not found: {path}")
+    }
+
+    fn usage(&mut self) -> Result<FsUsage> {
+        let stats = self.fs.stats().map_err(|e| anyhow!("fat stats failed: {e}"))?;
+        Ok(FsUsage {
+            fstype: fat_type_name(self.fs.fat_type()).to_string(),
+            block_size: u64::from(stats.cluster_size()),
+            total_blocks: u64::from(stats.total_clusters()),
+            free_blocks: u64::from(stats.free_clusters()),
+        })
+    }
+}
+
+/// Seconds since the epoch, or `None` for the zeroed fields FAT leaves
+/// unset (which are not valid dates).
+fn fat_timestamp(dt: fatfs::DateTime) -> Option<u64> {
+    let time = chrono::NaiveDate::from_ymd_opt(
+        i32::from(dt.date.year),
+        u32::from(dt.date.month),
+        u32::from(dt.date.day),
+    )?
+    .and_hms_opt(
+        u32::from(dt.time.hour),
+        u32::from(dt.time.min),
+        u32::from(dt.time.sec),
+    )?;
+    u64::try_from(time.and_utc().timestamp()).ok()
 }
 
 fn remove_fat_recursive<IO, TP, OCC>(root: &fatfs::Dir<IO, TP, OCC>, path: &str) -> Result<()>
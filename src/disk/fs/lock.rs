@@ -0,0 +1,51 @@
+use anyhow::{Result, anyhow, bail};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process to release the image
+const LOCK_TIMEOUT: Duration = Duration::from_secs(3);
+const LOCK_POLL: Duration = Duration::from_millis(100);
+
+static LOCKING: AtomicBool = AtomicBool::new(true);
+
+/// Turn image locking on or off for the rest of the process (`--no-lock`).
+pub fn set_locking(enabled: bool) {
+    LOCKING.store(enabled, Ordering::Relaxed);
+}
+
+/// Advisory exclusive lock on a disk image (flock on Unix, LockFileEx on
+/// Windows), taken once per `disk` command. Released when dropped.
+pub struct ImageLock {
+    _file: Option<File>,
+}
+
+impl ImageLock {
+    pub fn acquire(disk: &Path) -> Result<Self> {
+        if !LOCKING.load(Ordering::Relaxed) {
+            return Ok(Self { _file: None });
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .open(disk)
+            .map_err(|e| anyhow!("failed to open disk {}: {e}", disk.display()))?;
+        let start = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { _file: Some(file) }),
+                Err(TryLockError::WouldBlock) if start.elapsed() < LOCK_TIMEOUT => {
+                    thread::sleep(LOCK_POLL);
+                }
+                Err(TryLockError::WouldBlock) => bail!(
+                    "image is in use by another process: {} (use --no-lock to override)",
+                    disk.display()
+                ),
+                Err(TryLockError::Error(e)) => {
+                    bail!("failed to lock disk {}: {e}", disk.display())
+                }
+            }
+        }
+    }
+}
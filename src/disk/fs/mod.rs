@@ -1,24 +1,76 @@
 use anyhow::{Result, anyhow, bail};
+use crc::{CRC_32_ISO_HDLC, Crc};
 use std::path::Path;
-use std::{fs::OpenOptions, io::{Read, Seek, SeekFrom}};
+use std::{fs::{File, OpenOptions}, io::{Read, Seek, SeekFrom, Write}};
+use walkdir::WalkDir;
 
+mod exfat;
 mod ext4;
 mod fat;
+mod lock;
+mod progress;
 
-use super::types::{DirEntry, PartitionTarget};
+use super::types::{DirEntry, DirUsage, FileStat, FsUsage, PartitionTarget};
 use super::utils::normalize_image_path;
+use crate::utils::glob_match;
 
 pub use ext4::mkfs_ext4;
-pub use fat::mkfs_fat32;
+pub use fat::{mkfs_fat, mkfs_fat32};
+pub use lock::{ImageLock, set_locking};
+pub use progress::set_progress;
+
+use progress::CopyProgress;
 
 pub trait FsOps {
     fn list_dir(&mut self, path: &str) -> Result<Vec<DirEntry>>;
     fn read_file(&mut self, path: &str, offset: u64, bytes: Option<usize>) -> Result<Vec<u8>>;
     fn write_file(&mut self, path: &str, data: &[u8], force: bool) -> Result<()>;
+    /// Add `data` to the end of `path`, creating it if missing.
+    fn append_file(&mut self, path: &str, data: &[u8]) -> Result<()>;
+    /// Write everything `reader` yields to `path` without holding it all in
+    /// memory, returning the number of bytes written. By default the first
+    /// chunk replaces the file and the rest are appended.
+    fn write_file_streaming(&mut self, path: &str, reader: &mut dyn Read, force: bool) -> Result<u64> {
+        let mut buf = vec![0u8; STREAM_CHUNK];
+        let mut n = read_chunk(reader, &mut buf)?;
+        self.write_file(path, &buf[..n], force)?;
+        let mut total = n as u64;
+        while n == buf.len() {
+            n = read_chunk(reader, &mut buf)?;
+            if n > 0 {
+                self.append_file(path, &buf[..n])?;
+                total += n as u64;
+            }
+        }
+        Ok(total)
+    }
     fn mkdir(&mut self, path: &str, parents: bool) -> Result<()>;
     fn rm(&mut self, path: &str, recursive: bool) -> Result<()>;
     fn mv(&mut self, src: &str, dst: &str, force: bool) -> Result<()>;
+    /// Create `link_path` as a symbolic link pointing at `target`.
+    fn symlink(&mut self, target: &str, link_path: &str) -> Result<()>;
     fn is_dir(&mut self, path: &str) -> Result<bool>;
+    fn file_size(&mut self, path: &str) -> Result<u64>;
+    /// Metadata for `path` itself; a trailing symlink is not followed.
+    fn stat(&mut self, path: &str) -> Result<FileStat>;
+    fn usage(&mut self) -> Result<FsUsage>;
+}
+
+/// Block size for streamed copies between host and image
+const STREAM_CHUNK: usize = 4 * 1024 * 1024;
+
+/// Fill `buf` from `reader`, stopping early only at end of input.
+fn read_chunk(reader: &mut dyn Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(anyhow!("read failed: {e}")),
+        }
+    }
+    Ok(filled)
 }
 
 pub fn with_fs<R>(
@@ -29,6 +81,7 @@ pub fn with_fs<R>(
     if let Some(kind) = detect_fs_type(disk, target)? {
         return match kind {
             FsKind::Ext4 => ext4::with_ext4(disk, target, |mut ops| f(&mut ops)),
+            FsKind::Exfat => exfat::with_exfat(disk, target, |mut ops| f(&mut ops)),
             FsKind::Fat => fat::with_fat(disk, target, |mut ops| f(&mut ops)),
         };
     }
@@ -36,15 +89,19 @@ pub fn with_fs<R>(
         Ok(result) => Ok(result),
         Err(ext4_err) => match fat::with_fat(disk, target, |mut ops| f(&mut ops)) {
             Ok(result) => Ok(result),
-            Err(fat_err) => Err(anyhow!(
-                "mount failed: ext4: {ext4_err}; fat: {fat_err}"
-            )),
+            Err(fat_err) => match exfat::with_exfat(disk, target, |mut ops| f(&mut ops)) {
+                Ok(result) => Ok(result),
+                Err(exfat_err) => Err(anyhow!(
+                    "mount failed: ext4: {ext4_err}; fat: {fat_err}; exfat: {exfat_err}"
+                )),
+            },
         },
     }
 }
 
 enum FsKind {
     Ext4,
+    Exfat,
     Fat,
 }
 
@@ -61,9 +118,16 @@ fn detect_fs_type(disk: &Path, target: &PartitionTarget) -> Result<Option<FsKind
     }
 
     let mut boot = [0u8; 512];
-    if file.seek(SeekFrom::Start(target.offset_bytes)).is_ok()
-        && file.read(&mut boot).is_ok()
-        && boot[510] == 0x55
+    if file.seek(SeekFrom::Start(target.offset_bytes)).is_err() || file.read(&mut boot).is_err() {
+        return Ok(None);
+    }
+
+    // exFAT's boot sector also ends in 0x55AA, so it has to be ruled out first.
+    if exfat::is_exfat_boot_sector(&boot) {
+        return Ok(Some(FsKind::Exfat));
+    }
+
+    if boot[510] == 0x55
         && boot[511] == 0xAA
         && (boot.get(82..87) == Some(b"FAT32")
             || boot.get(54..59) == Some(b"FAT16")
@@ -75,6 +139,18 @@ fn detect_fs_type(disk: &Path, target: &PartitionTarget) -> Result<Option<FsKind
     Ok(None)
 }
 
+/// Type and space usage of the filesystem in `target`, or `None` when
+/// nothing recognizable is there.
+pub fn probe(disk: &Path, target: &PartitionTarget) -> Result<Option<FsUsage>> {
+    let usage = match detect_fs_type(disk, target)? {
+        Some(FsKind::Ext4) => ext4::with_ext4(disk, target, |mut ops| ops.usage())?,
+        Some(FsKind::Exfat) => exfat::with_exfat(disk, target, |mut ops| ops.usage())?,
+        Some(FsKind::Fat) => fat::with_fat(disk, target, |mut ops| ops.usage())?,
+        None => return Ok(None),
+    };
+    Ok(Some(usage))
+}
+
 pub fn list_dir(disk: &Path, target: &PartitionTarget, path: &str) -> Result<Vec<DirEntry>> {
     with_fs(disk, target, |fs| fs.list_dir(path))
 }
@@ -89,6 +165,64 @@ pub fn read_file(
     with_fs(disk, target, |fs| fs.read_file(path, offset, bytes))
 }
 
+pub fn read_file_chunked(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    chunk_size: usize,
+    mut sink: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<u64> {
+    let image_path = normalize_image_path(path);
+    with_fs(disk, target, |fs| {
+        let mut offset = 0u64;
+        loop {
+            let chunk = fs.read_file(&image_path, offset, Some(chunk_size))?;
+            if chunk.is_empty() {
+                return Ok(offset);
+            }
+            sink(&chunk)?;
+            offset += chunk.len() as u64;
+        }
+    })
+}
+
+/// Last `lines` lines of `path`. The file is read backward from the end in
+/// chunks until enough newlines are seen, so only the tail is loaded.
+pub fn read_tail(disk: &Path, target: &PartitionTarget, path: &str, lines: usize) -> Result<Vec<u8>> {
+    const CHUNK: u64 = 64 * 1024;
+    let image_path = normalize_image_path(path);
+    with_fs(disk, target, |fs| {
+        let mut pos = fs.file_size(&image_path)?;
+        let mut tail = Vec::new();
+        while pos > 0 {
+            let start = pos.saturating_sub(CHUNK);
+            let mut chunk = fs.read_file(&image_path, start, Some((pos - start) as usize))?;
+            chunk.extend_from_slice(&tail);
+            tail = chunk;
+            pos = start;
+            if let Some(cut) = tail_start(&tail, lines) {
+                return Ok(tail.split_off(cut));
+            }
+        }
+        Ok(tail)
+    })
+}
+
+/// Offset in `data` where its last `lines` lines begin, if `data` holds the
+/// newline before them. A newline ending the data does not start a line.
+fn tail_start(data: &[u8], lines: usize) -> Option<usize> {
+    if lines == 0 {
+        return Some(data.len());
+    }
+    let body = data.strip_suffix(b"\n").unwrap_or(data);
+    body.iter()
+        .enumerate()
+        .rev()
+        .filter(|&(_, &byte)| byte == b'\n')
+        .nth(lines - 1)
+        .map(|(newline, _)| newline + 1)
+}
+
 pub fn mkdir(disk: &Path, target: &PartitionTarget, path: &str, parents: bool) -> Result<()> {
     let image_path = normalize_image_path(path);
     with_fs(disk, target, |fs| fs.mkdir(&image_path, parents))
@@ -105,11 +239,117 @@ pub fn mv(disk: &Path, target: &PartitionTarget, src: &str, dst: &str, force: bo
     with_fs(disk, target, |fs| fs.mv(&src_image, &dst_image, force))
 }
 
+pub fn symlink(disk: &Path, target: &PartitionTarget, link_target: &str, link_path: &str) -> Result<()> {
+    let image_path = normalize_image_path(link_path);
+    with_fs(disk, target, |fs| fs.symlink(link_target, &image_path))
+}
+
+/// Expand a shell-style pattern into the image paths it matches, sorted.
+/// Each component may use `*`, `?` and `[...]`; a `**` component matches
+/// any number of directories, or everything below when it comes last.
+/// As in a shell, wildcards skip names starting with `.` unless the
+/// pattern component does too.
+pub fn glob(disk: &Path, target: &PartitionTarget, pattern: &str) -> Result<Vec<String>> {
+    let image_path = normalize_image_path(pattern);
+    let parts: Vec<&str> = image_path.split('/').filter(|s| !s.is_empty()).collect();
+    with_fs(disk, target, |fs| {
+        let mut matches = vec!["/".to_string()];
+        for (i, part) in parts.iter().enumerate() {
+            let last = i + 1 == parts.len();
+            let mut next = Vec::new();
+            for dir in &matches {
+                if *part == "**" {
+                    if !last {
+                        next.push(dir.clone());
+                    }
+                    collect_tree(fs, dir, !last, &mut next);
+                    continue;
+                }
+                // Unreadable or non-directory parents simply match nothing
+                let Ok(entries) = fs.list_dir(dir) else {
+                    continue;
+                };
+                for entry in entries {
+                    if glob_match(part, &entry.name)
+                        && (!entry.name.starts_with('.') || part.starts_with('.'))
+                    {
+                        next.push(join_image_path(dir, &entry.name));
+                    }
+                }
+            }
+            matches = next;
+        }
+        matches.sort();
+        matches.dedup();
+        Ok(matches)
+    })
+}
+
+fn collect_tree(fs: &mut dyn FsOps, dir: &str, dirs_only: bool, out: &mut Vec<String>) {
+    let Ok(entries) = fs.list_dir(dir) else {
+        return;
+    };
+    for entry in entries {
+        if entry.name.starts_with('.') {
+            continue;
+        }
+        let child = join_image_path(dir, &entry.name);
+        if entry.is_dir {
+            out.push(child.clone());
+            collect_tree(fs, &child, dirs_only, out);
+        } else if !dirs_only {
+            out.push(child);
+        }
+    }
+}
+
+fn join_image_path(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
 pub fn is_dir(disk: &Path, target: &PartitionTarget, path: &str) -> Result<bool> {
     let image_path = normalize_image_path(path);
     with_fs(disk, target, |fs| fs.is_dir(&image_path))
 }
 
+pub fn stat(disk: &Path, target: &PartitionTarget, path: &str) -> Result<FileStat> {
+    let image_path = normalize_image_path(path);
+    with_fs(disk, target, |fs| fs.stat(&image_path))
+}
+
+pub fn usage(disk: &Path, target: &PartitionTarget) -> Result<FsUsage> {
+    with_fs(disk, target, |fs| fs.usage())
+}
+
+pub fn dir_usage(disk: &Path, target: &PartitionTarget, path: &str) -> Result<Vec<DirUsage>> {
+    let image_path = normalize_image_path(path);
+    with_fs(disk, target, |fs| {
+        let mut out = Vec::new();
+        collect_dir_usage(fs, &image_path, &mut out)?;
+        Ok(out)
+    })
+}
+
+fn collect_dir_usage(fs: &mut dyn FsOps, path: &str, out: &mut Vec<DirUsage>) -> Result<u64> {
+    let mut total = 0u64;
+    for entry in fs.list_dir(path)? {
+        let child = format!("{}/{}", path.trim_end_matches('/'), entry.name);
+        if entry.symlink_target.is_some() {
+            // Count the link itself; its target may be missing or outside
+            total += entry.size.unwrap_or(0);
+        } else if entry.is_dir {
+            total += collect_dir_usage(fs, &child, out)?;
+        } else {
+            total += fs.file_size(&child)?;
+        }
+    }
+    out.push(DirUsage {
+        path: path.to_string(),
+        bytes: total,
+    });
+    Ok(total)
+}
+
 pub fn write_file(
     disk: &Path,
     target: &PartitionTarget,
@@ -121,23 +361,229 @@ pub fn write_file(
     with_fs(disk, target, |fs| fs.write_file(&image_path, data, force))
 }
 
-pub fn copy_host_to_image(
+pub fn write_file_streaming(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    reader: &mut dyn Read,
+    force: bool,
+) -> Result<u64> {
+    let image_path = normalize_image_path(path);
+    with_fs(disk, target, |fs| fs.write_file_streaming(&image_path, reader, force))
+}
+
+/// Append everything `reader` yields to `path` chunk by chunk, creating it
+/// if missing. Returns the number of bytes appended.
+pub fn append_file_streaming(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    reader: &mut dyn Read,
+) -> Result<u64> {
+    let image_path = normalize_image_path(path);
+    with_fs(disk, target, |fs| {
+        let mut buf = vec![0u8; STREAM_CHUNK];
+        let mut total = 0u64;
+        loop {
+            let n = read_chunk(reader, &mut buf)?;
+            // Still create the file when there is nothing to append
+            if n > 0 || total == 0 {
+                fs.append_file(&image_path, &buf[..n])?;
+            }
+            total += n as u64;
+            if n < buf.len() {
+                return Ok(total);
+            }
+        }
+    })
+}
+
+/// Append image file `src` to `dst` chunk by chunk. Each chunk is read
+/// before it is appended, so `src` and `dst` may be the same file.
+pub fn append_image_file(disk: &Path, target: &PartitionTarget, src: &str, dst: &str) -> Result<u64> {
+    let (src, dst) = (normalize_image_path(src), normalize_image_path(dst));
+    with_fs(disk, target, |fs| {
+        let len = fs.file_size(&src)?;
+        let mut offset = 0u64;
+        while offset < len {
+            let want = (len - offset).min(STREAM_CHUNK as u64) as usize;
+            let chunk = fs.read_file(&src, offset, Some(want))?;
+            if chunk.is_empty() {
+                break;
+            }
+            fs.append_file(&dst, &chunk)?;
+            offset += chunk.len() as u64;
+        }
+        if offset == 0 {
+            fs.append_file(&dst, &[])?;
+        }
+        Ok(offset)
+    })
+}
+
+pub fn append_file(disk: &Path, target: &PartitionTarget, path: &str, data: &[u8]) -> Result<()> {
+    let image_path = normalize_image_path(path);
+    with_fs(disk, target, |fs| fs.append_file(&image_path, data))
+}
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const VERIFY_CHUNK: usize = 1024 * 1024;
+
+/// Read `path` back from the image and check it matches `expected` by
+/// length and CRC32.
+pub fn verify_file(disk: &Path, target: &PartitionTarget, path: &str, expected: &[u8]) -> Result<()> {
+    verify_checksum(disk, target, path, expected.len() as u64, CRC32.checksum(expected))
+}
+
+/// Like `verify_file`, for data that is no longer in memory.
+fn verify_checksum(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    expected_len: u64,
+    want: u32,
+) -> Result<()> {
+    let mut digest = CRC32.digest();
+    let len = read_file_chunked(disk, target, path, VERIFY_CHUNK, |chunk| {
+        digest.update(chunk);
+        Ok(())
+    })?;
+    let got = digest.finalize();
+    if len != expected_len || want != got {
+        bail!(
+            "verification failed for {path}: wrote {expected_len} bytes (crc32 {want:08x}), read back {len} bytes (crc32 {got:08x})"
+        );
+    }
+    Ok(())
+}
+
+/// Passes reads through while checksumming them, so a streamed copy can be
+/// verified without keeping the source around.
+struct CrcReader<'a, R> {
+    inner: R,
+    digest: crc::Digest<'a, u32>,
+}
+
+impl<R: Read> Read for CrcReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.digest.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// `write_file_streaming`, then read the file back and check it against
+/// the CRC32 of what was streamed if `verify` is set.
+pub fn write_file_streaming_checked(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    reader: &mut dyn Read,
+    force: bool,
+    verify: bool,
+) -> Result<u64> {
+    let mut reader = CrcReader {
+        inner: reader,
+        digest: CRC32.digest(),
+    };
+    let written = write_file_streaming(disk, target, path, &mut reader, force)?;
+    if verify {
+        verify_checksum(disk, target, path, written, reader.digest.finalize())?;
+    }
+    Ok(written)
+}
+
+/// Stream a host file into the image, then `verify_checksum` if asked.
+fn copy_file_checked(
     disk: &Path,
     target: &PartitionTarget,
     src: &Path,
     dst: &str,
-    recursive: bool,
     force: bool,
+    verify: bool,
+    progress: Option<&CopyProgress>,
 ) -> Result<()> {
+    let file = File::open(src).map_err(|e| anyhow!("read host file {}: {e}", src.display()))?;
+    let bar = progress.and_then(|progress| {
+        let len = file.metadata().map_or(0, |meta| meta.len());
+        progress.file(&src.display().to_string(), len)
+    });
+    let mut reader: Box<dyn Read> = match &bar {
+        Some(bar) => Box::new(bar.wrap_read(file)),
+        None => Box::new(file),
+    };
+    let result = write_file_streaming_checked(disk, target, dst, &mut reader, force, verify);
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    result?;
+    if let Some(progress) = progress {
+        progress.file_done();
+    }
+    Ok(())
+}
+
+/// `write_file`, then `verify_file` if asked.
+fn write_checked(
+    disk: &Path,
+    target: &PartitionTarget,
+    path: &str,
+    data: &[u8],
+    force: bool,
+    verify: bool,
+) -> Result<()> {
+    write_file(disk, target, path, data, force)?;
+    if verify {
+        verify_file(disk, target, path, data)?;
+    }
+    Ok(())
+}
+
+/// Flags of a host-to-image copy
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyOptions {
+    /// Copy directories recursively
+    pub recursive: bool,
+    /// Overwrite existing files
+    pub force: bool,
+    /// Read each file back and check its CRC32
+    pub verify: bool,
+}
+
+pub fn copy_host_to_image(
+    disk: &Path,
+    target: &PartitionTarget,
+    src: &Path,
+    dst: &str,
+    options: CopyOptions,
+) -> Result<()> {
+    let CopyOptions {
+        recursive,
+        force,
+        verify,
+    } = options;
     if src.is_dir() {
         if !recursive {
             bail!("directory copy requires -r");
         }
-        return copy_host_dir_to_image(disk, target, src, dst, force);
+        let progress = CopyProgress::new().map(|progress| {
+            let files = WalkDir::new(src)
+                .follow_links(true)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|entry| !entry.file_type().is_dir())
+                .count();
+            progress.with_file_count(files as u64)
+        });
+        let result = copy_host_dir_to_image(disk, target, src, dst, force, verify, progress.as_ref());
+        if let Some(progress) = progress {
+            progress.finish();
+        }
+        return result;
     }
 
-    let data = std::fs::read(src).map_err(|e| anyhow!("read host file {}: {e}", src.display()))?;
-    write_file(disk, target, dst, &data, force)
+    let progress = CopyProgress::new();
+    copy_file_checked(disk, target, src, dst, force, verify, progress.as_ref())
 }
 
 pub fn copy_image_to_host(
@@ -149,16 +595,55 @@ pub fn copy_image_to_host(
     force: bool,
 ) -> Result<()> {
     let is_dir = with_fs(disk, target, |fs| fs.is_dir(src))?;
-    if is_dir {
-        if !recursive {
-            bail!("directory copy requires -r");
+    if is_dir && !recursive {
+        bail!("directory copy requires -r");
+    }
+    let progress = match CopyProgress::new() {
+        Some(progress) if is_dir => {
+            let files = with_fs(disk, target, |fs| count_files(fs, src))?;
+            Some(progress.with_file_count(files))
         }
+        progress => progress,
+    };
+    let result = copy_image_tree_to_host(disk, target, src, dst, is_dir, force, progress.as_ref());
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    result
+}
+
+fn copy_image_tree_to_host(
+    disk: &Path,
+    target: &PartitionTarget,
+    src: &str,
+    dst: &Path,
+    is_dir: bool,
+    force: bool,
+    progress: Option<&CopyProgress>,
+) -> Result<()> {
+    if is_dir {
         std::fs::create_dir_all(dst)?;
         let entries = list_dir(disk, target, src)?;
         for entry in entries {
             let child_src = format!("{}/{}", src.trim_end_matches('/'), entry.name);
             let child_dst = dst.join(&entry.name);
-            copy_image_to_host(disk, target, &child_src, &child_dst, recursive, force)?;
+            // Never follow links below the source: they may dangle or loop
+            if let Some(link) = &entry.symlink_target {
+                copy_link_to_host(&child_src, link, &child_dst, force)?;
+                if let Some(progress) = progress {
+                    progress.file_done();
+                }
+                continue;
+            }
+            copy_image_tree_to_host(
+                disk,
+                target,
+                &child_src,
+                &child_dst,
+                entry.is_dir,
+                force,
+                progress,
+            )?;
         }
         return Ok(());
     }
@@ -169,11 +654,59 @@ pub fn copy_image_to_host(
     if let Some(parent) = dst.parent() {
         std::fs::create_dir_all(parent)?;
     }
-    let data = read_file(disk, target, src, 0, None)?;
-    std::fs::write(dst, data)?;
+    let bar = match progress {
+        Some(progress) => progress.file(src, with_fs(disk, target, |fs| fs.file_size(src))?),
+        None => None,
+    };
+    let mut file = File::create(dst)?;
+    read_file_chunked(disk, target, src, STREAM_CHUNK, |chunk| {
+        file.write_all(chunk)?;
+        if let Some(bar) = &bar {
+            bar.inc(chunk.len() as u64);
+        }
+        Ok(())
+    })?;
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    if let Some(progress) = progress {
+        progress.file_done();
+    }
+    Ok(())
+}
+
+/// Recreate an image symlink on the host as-is.
+#[cfg(unix)]
+fn copy_link_to_host(_src: &str, link: &str, dst: &Path, force: bool) -> Result<()> {
+    if dst.symlink_metadata().is_ok() {
+        if !force {
+            bail!("destination exists, use -f to overwrite");
+        }
+        std::fs::remove_file(dst)?;
+    }
+    std::os::unix::fs::symlink(link, dst)?;
     Ok(())
 }
 
+#[cfg(not(unix))]
+fn copy_link_to_host(src: &str, link: &str, _dst: &Path, _force: bool) -> Result<()> {
+    log::warn!("skipping symlink {src} -> {link}");
+    Ok(())
+}
+
+/// Number of non-directory entries under `dir`, at any depth.
+fn count_files(fs: &mut dyn FsOps, dir: &str) -> Result<u64> {
+    let mut total = 0;
+    for entry in fs.list_dir(dir)? {
+        if entry.is_dir && entry.symlink_target.is_none() {
+            total += count_files(fs, &join_image_path(dir, &entry.name))?;
+        } else {
+            total += 1;
+        }
+    }
+    Ok(total)
+}
+
 pub fn copy_image_to_image(
     disk: &Path,
     target: &PartitionTarget,
@@ -181,6 +714,7 @@ pub fn copy_image_to_image(
     dst: &str,
     recursive: bool,
     force: bool,
+    verify: bool,
 ) -> Result<()> {
     let is_dir = with_fs(disk, target, |fs| fs.is_dir(src))?;
     if is_dir {
@@ -192,14 +726,17 @@ pub fn copy_image_to_image(
         for entry in entries {
             let child_src = format!("{}/{}", src.trim_end_matches('/'), entry.name);
             let child_dst = format!("{}/{}", dst.trim_end_matches('/'), entry.name);
-            copy_image_to_image(disk, target, &child_src, &child_dst, recursive, force)?;
+            if let Some(link) = &entry.symlink_target {
+                symlink(disk, target, link, &child_dst)?;
+                continue;
+            }
+            copy_image_to_image(disk, target, &child_src, &child_dst, recursive, force, verify)?;
         }
         return Ok(());
     }
 
     let data = read_file(disk, target, src, 0, None)?;
-    write_file(disk, target, dst, &data, force)?;
-    Ok(())
+    write_checked(disk, target, dst, &data, force, verify)
 }
 
 fn copy_host_dir_to_image(
@@ -208,6 +745,8 @@ fn copy_host_dir_to_image(
     src: &Path,
     dst: &str,
     force: bool,
+    verify: bool,
+    progress: Option<&CopyProgress>,
 ) -> Result<()> {
     mkdir(disk, target, dst, true)?;
     for entry in std::fs::read_dir(src)? {
@@ -216,10 +755,9 @@ fn copy_host_dir_to_image(
         let name = entry.file_name().to_string_lossy().to_string();
         let child = format!("{}/{}", dst.trim_end_matches('/'), name);
         if path.is_dir() {
-            copy_host_dir_to_image(disk, target, &path, &child, force)?;
+            copy_host_dir_to_image(disk, target, &path, &child, force, verify, progress)?;
         } else {
-            let data = std::fs::read(&path)?;
-            write_file(disk, target, &child, &data, force)?;
+            copy_file_checked(disk, target, &path, &child, force, verify, progress)?;
         }
     }
     Ok(())
@@ -0,0 +1,80 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Files smaller than this copy too quickly for a bar to be worth drawing
+const BAR_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+static PROGRESS: AtomicBool = AtomicBool::new(true);
+
+/// Turn copy progress bars on or off for the rest of the process (`--no-progress`).
+pub fn set_progress(enabled: bool) {
+    PROGRESS.store(enabled, Ordering::Relaxed);
+}
+
+/// Progress for one host<->image copy: a bar per large file and, for
+/// directory copies, a count of the files done so far.
+pub(super) struct CopyProgress {
+    multi: MultiProgress,
+    files: Option<ProgressBar>,
+}
+
+impl CopyProgress {
+    /// `None` when progress is turned off or stdout is not a terminal, so
+    /// piped output stays clean.
+    pub(super) fn new() -> Option<Self> {
+        if !PROGRESS.load(Ordering::Relaxed) || !std::io::stdout().is_terminal() {
+            return None;
+        }
+        Some(Self {
+            multi: MultiProgress::new(),
+            files: None,
+        })
+    }
+
+    /// Add the overall bar for a directory copy of `total` files.
+    pub(super) fn with_file_count(mut self, total: u64) -> Self {
+        let pb = self.multi.add(ProgressBar::new(total));
+        pb.set_style(
+            ProgressStyle::with_template("{spinner:.green} {pos}/{len} files [{bar:40.cyan/blue}]")
+                .unwrap()
+                .progress_chars("=>-"),
+        );
+        self.files = Some(pb);
+        self
+    }
+
+    /// Bar for copying `len` bytes of `name`, or `None` below [`BAR_THRESHOLD`].
+    pub(super) fn file(&self, name: &str, len: u64) -> Option<ProgressBar> {
+        if len < BAR_THRESHOLD {
+            return None;
+        }
+        let pb = ProgressBar::new(len);
+        let pb = match &self.files {
+            Some(files) => self.multi.insert_before(files, pb),
+            None => self.multi.add(pb),
+        };
+        pb.set_style(
+            ProgressStyle::with_template(
+                "{msg} {spinner:.green} {bytes}/{total_bytes} ({binary_bytes_per_sec}) [{bar:40.cyan/blue}] {eta}",
+            )
+            .unwrap()
+            .progress_chars("=>-"),
+        );
+        pb.set_message(name.to_string());
+        Some(pb)
+    }
+
+    /// Count one more file as copied.
+    pub(super) fn file_done(&self) {
+        if let Some(files) = &self.files {
+            files.inc(1);
+        }
+    }
+
+    pub(super) fn finish(&self) {
+        if let Some(files) = &self.files {
+            files.finish_and_clear();
+        }
+    }
+}
@@ -1,39 +1,53 @@
-use anyhow::{anyhow, Result};
-use gpt::{disk::LogicalBlockSize, GptConfig};
-use std::{fs::File, path::Path};
+use anyhow::{anyhow, bail, Result};
+use gpt::{disk::LogicalBlockSize, partition_types, GptConfig};
+use serde::Deserialize;
+use std::{fs::File, path::Path, str::FromStr};
 
 use super::types::{PartitionInfo, PartitionSpec, PartitionTarget};
-use super::utils::{align_up, parse_u64_any};
+use super::utils::{align_up, parse_size, parse_u64_any};
 
-const LB_SIZE_BYTES: u64 = 512;
+pub const DEFAULT_SECTOR_SIZE: u64 = 512;
 
-pub fn open_gpt(disk: &Path, writable: bool) -> Result<gpt::GptDisk<File>> {
+pub fn logical_block_size(sector_size: u64) -> Result<LogicalBlockSize> {
+    match sector_size {
+        512 => Ok(LogicalBlockSize::Lb512),
+        4096 => Ok(LogicalBlockSize::Lb4096),
+        other => bail!("unsupported sector size {other}, expected 512 or 4096"),
+    }
+}
+
+pub fn open_gpt(disk: &Path, writable: bool, sector_size: u64) -> Result<gpt::GptDisk<File>> {
     GptConfig::new()
         .writable(writable)
-        .logical_block_size(LogicalBlockSize::Lb512)
+        .logical_block_size(logical_block_size(sector_size)?)
         .open(disk)
         .map_err(|e| anyhow!("failed to open GPT: {e}"))
 }
 
-pub fn map_partitions(gdisk: &gpt::GptDisk<File>) -> Result<Vec<PartitionInfo>> {
+pub fn map_partitions(gdisk: &gpt::GptDisk<File>, sector_size: u64) -> Result<Vec<PartitionInfo>> {
+    let lb_size = logical_block_size(sector_size)?;
     let mut out = Vec::new();
     for (idx, part) in gdisk.partitions().iter() {
         if !part.is_used() {
             continue;
         }
         let start = part
-            .bytes_start(LogicalBlockSize::Lb512)
+            .bytes_start(lb_size)
             .map_err(|e| anyhow!("invalid partition start: {e}"))?;
         let size = part
-            .bytes_len(LogicalBlockSize::Lb512)
+            .bytes_len(lb_size)
             .map_err(|e| anyhow!("invalid partition size: {e}"))?;
         out.push(PartitionInfo {
             index: *idx,
             name: part.name.clone(),
+            uuid: part.part_guid.to_string(),
             first_lba: part.first_lba,
             last_lba: part.last_lba,
             start_bytes: start,
             size_bytes: size,
+            fs_type: None,
+            used_bytes: None,
+            free_bytes: None,
         });
     }
     out.sort_by_key(|p| p.index);
@@ -78,7 +92,16 @@ pub fn parse_parameter_file(path: &Path) -> Result<Vec<PartitionSpec>> {
             .split_once('@')
             .ok_or_else(|| anyhow!("invalid partition entry: {raw}"))?;
 
-        let grow = flags.is_some_and(|f| f.contains("grow"));
+        let mut grow = false;
+        let mut part_type = None;
+        for flag in flags.into_iter().flat_map(|f| f.split(':')).map(str::trim) {
+            if flag == "grow" {
+                grow = true;
+            } else if let Some(ty) = flag.strip_prefix("type=") {
+                parse_partition_type(ty)?;
+                part_type = Some(ty.to_string());
+            }
+        }
         let size_bytes = if size_str.trim() == "-" {
             None
         } else {
@@ -91,13 +114,128 @@ pub fn parse_parameter_file(path: &Path) -> Result<Vec<PartitionSpec>> {
             offset_bytes,
             size_bytes,
             grow,
+            part_type,
         });
     }
 
     Ok(specs)
 }
 
-pub fn resolve_partition_target(disk: &Path, part: Option<&str>) -> Result<PartitionTarget> {
+#[derive(Deserialize)]
+struct LayoutFile {
+    partitions: Vec<LayoutEntry>,
+}
+
+#[derive(Deserialize)]
+struct LayoutEntry {
+    name: String,
+    offset: LayoutNumber,
+    size: Option<LayoutNumber>,
+    #[serde(default)]
+    grow: bool,
+    #[serde(rename = "type")]
+    part_type: Option<String>,
+}
+
+/// Offsets and sizes may be plain integers or strings like "0x2000" / "64M".
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LayoutNumber {
+    Int(u64),
+    Str(String),
+}
+
+impl LayoutNumber {
+    fn bytes(&self) -> Result<u64> {
+        match self {
+            LayoutNumber::Int(v) => Ok(*v),
+            LayoutNumber::Str(s) if s.trim().starts_with("0x") => parse_u64_any(s),
+            LayoutNumber::Str(s) => parse_size(s),
+        }
+    }
+}
+
+/// Read a partition layout, picking the parser from the file extension:
+/// `.toml` and `.json` describe partitions directly, anything else is
+/// treated as a Rockchip parameter file.
+pub fn parse_layout_file(path: &Path) -> Result<Vec<PartitionSpec>> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let layout: LayoutFile = match ext.as_deref() {
+        Some("toml") | Some("json") => {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("failed to read layout file {}: {e}", path.display()))?;
+            if ext.as_deref() == Some("toml") {
+                toml::from_str(&content)
+                    .map_err(|e| anyhow!("invalid layout file {}: {e}", path.display()))?
+            } else {
+                serde_json::from_str(&content)
+                    .map_err(|e| anyhow!("invalid layout file {}: {e}", path.display()))?
+            }
+        }
+        _ => return parse_parameter_file(path),
+    };
+
+    let mut specs = Vec::new();
+    for entry in layout.partitions {
+        let size_bytes = entry.size.as_ref().map(LayoutNumber::bytes).transpose()?;
+        if size_bytes.is_none() && !entry.grow {
+            bail!("partition {} needs a size or grow = true", entry.name);
+        }
+        if let Some(ty) = &entry.part_type {
+            parse_partition_type(ty)?;
+        }
+        specs.push(PartitionSpec {
+            offset_bytes: entry.offset.bytes()?,
+            size_bytes,
+            grow: entry.grow,
+            part_type: entry.part_type,
+            name: entry.name,
+        });
+    }
+    Ok(specs)
+}
+
+const PARTITION_TYPE_NAMES: &[(&str, partition_types::Type)] = &[
+    ("esp", partition_types::EFI),
+    ("linux", partition_types::LINUX_FS),
+    ("swap", partition_types::LINUX_SWAP),
+    ("home", partition_types::LINUX_HOME),
+    ("lvm", partition_types::LINUX_LVM),
+    ("raid", partition_types::LINUX_RAID),
+    ("msdata", partition_types::BASIC),
+];
+
+/// Map a friendly partition type name or a raw type GUID to a GPT type.
+pub fn parse_partition_type(input: &str) -> Result<partition_types::Type> {
+    let s = input.trim();
+    if let Some((_, ty)) = PARTITION_TYPE_NAMES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(s))
+    {
+        return Ok(ty.clone());
+    }
+
+    let is_guid = s.len() == 36 && s.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    if is_guid && let Ok(ty) = partition_types::Type::from_str(s) {
+        return Ok(ty);
+    }
+
+    let names = PARTITION_TYPE_NAMES
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    bail!("unknown partition type '{input}', expected one of {names} or a type GUID")
+}
+
+pub fn resolve_partition_target(
+    disk: &Path,
+    part: Option<&str>,
+    sector_size: u64,
+) -> Result<PartitionTarget> {
     let disk_size = std::fs::metadata(disk)
         .map_err(|e| anyhow!("failed to stat disk {}: {e}", disk.display()))?
         .len();
@@ -109,24 +247,60 @@ pub fn resolve_partition_target(disk: &Path, part: Option<&str>) -> Result<Parti
         });
     };
 
-    let gdisk = open_gpt(disk, false).map_err(|_| anyhow!("no GPT found on disk"))?;
+    let lb_size = logical_block_size(sector_size)?;
+    let gdisk = open_gpt(disk, false, sector_size).map_err(|_| anyhow!("no GPT found on disk"))?;
+    let index = find_partition(&gdisk, part)?;
+    let part = &gdisk.partitions()[&index];
+
+    let start = part
+        .bytes_start(lb_size)
+        .map_err(|e| anyhow!("invalid partition start: {e}"))?;
+    let size = part
+        .bytes_len(lb_size)
+        .map_err(|e| anyhow!("invalid partition size: {e}"))?;
+
+    Ok(PartitionTarget {
+        offset_bytes: start,
+        size_bytes: size,
+    })
+}
+
+/// Find a partition by index, by `uuid:<GUID>` (its unique partition GUID,
+/// case-insensitive) or by name.
+pub fn find_partition(gdisk: &gpt::GptDisk<File>, part: &str) -> Result<u32> {
     let parts = gdisk.partitions();
 
-    let mut resolved: Option<(u32, gpt::partition::Partition)> = None;
+    if let Some(uuid) = part.strip_prefix("uuid:") {
+        return parts
+            .iter()
+            .find(|(_, p)| p.is_used() && p.part_guid.to_string().eq_ignore_ascii_case(uuid.trim()))
+            .map(|(idx, _)| *idx)
+            .ok_or_else(|| {
+                let list = parts
+                    .iter()
+                    .filter(|(_, p)| p.is_used())
+                    .map(|(idx, p)| format!("{}:{}", idx, p.part_guid))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                anyhow!("partition with uuid {uuid} not found. available: {list}")
+            });
+    }
+
+    let mut resolved: Option<u32> = None;
     if let Ok(idx) = part.parse::<u32>() {
-        if let Some(p) = parts.get(&idx) {
-            resolved = Some((idx, p.clone()));
+        if parts.contains_key(&idx) {
+            resolved = Some(idx);
         }
     } else {
         for (idx, p) in parts.iter() {
             if p.is_used() && p.name == part {
-                resolved = Some((*idx, p.clone()));
+                resolved = Some(*idx);
                 break;
             }
         }
     }
 
-    let (_index, part) = resolved.ok_or_else(|| {
+    resolved.ok_or_else(|| {
         let list = parts
             .iter()
             .filter(|(_, p)| p.is_used())
@@ -134,33 +308,17 @@ pub fn resolve_partition_target(disk: &Path, part: Option<&str>) -> Result<Parti
             .collect::<Vec<_>>()
             .join(", ");
         anyhow!("partition not found. available: {list}")
-    })?;
-
-    let start = part
-        .bytes_start(LogicalBlockSize::Lb512)
-        .map_err(|e| anyhow!("invalid partition start: {e}"))?;
-    let size = part
-        .bytes_len(LogicalBlockSize::Lb512)
-        .map_err(|e| anyhow!("invalid partition size: {e}"))?;
-
-    Ok(PartitionTarget {
-        offset_bytes: start,
-        size_bytes: size,
     })
 }
 
-pub fn align_partition_start(offset_bytes: u64, align_bytes: u64) -> u64 {
-    let mut start = align_up(offset_bytes, align_bytes.max(LB_SIZE_BYTES));
-    if !start.is_multiple_of(LB_SIZE_BYTES) {
-        start = align_up(start, LB_SIZE_BYTES);
+pub fn align_partition_start(offset_bytes: u64, align_bytes: u64, sector_size: u64) -> u64 {
+    let mut start = align_up(offset_bytes, align_bytes.max(sector_size));
+    if !start.is_multiple_of(sector_size) {
+        start = align_up(start, sector_size);
     }
     start
 }
 
-pub fn clamp_size_to_lba(size_bytes: u64) -> u64 {
-    size_bytes - (size_bytes % LB_SIZE_BYTES)
-}
-
-pub fn lb_size_bytes() -> u64 {
-    LB_SIZE_BYTES
+pub fn clamp_size_to_lba(size_bytes: u64, sector_size: u64) -> u64 {
+    size_bytes - (size_bytes % sector_size)
 }
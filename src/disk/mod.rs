@@ -4,7 +4,7 @@ pub mod fs;
 pub mod gpt;
 mod io;
 pub mod types;
-mod utils;
+pub(crate) mod utils;
 pub mod fatfs;
 
 pub use cli::DiskCli;
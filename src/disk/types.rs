@@ -12,22 +12,33 @@ pub struct PartitionSpec {
     pub offset_bytes: u64,
     pub size_bytes: Option<u64>,
     pub grow: bool,
+    pub part_type: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct PartitionInfo {
     pub index: u32,
     pub name: String,
+    /// Unique partition GUID, usable as `--part uuid:<GUID>`
+    pub uuid: String,
     pub first_lba: u64,
     pub last_lba: u64,
     pub start_bytes: u64,
     pub size_bytes: u64,
+    /// `None` when no filesystem is recognized
+    pub fs_type: Option<String>,
+    pub used_bytes: Option<u64>,
+    pub free_bytes: Option<u64>,
 }
 
 #[derive(Serialize)]
 pub struct DiskInfo {
     pub disk: String,
     pub size_bytes: u64,
+    /// Filesystem on the whole disk; only probed when there is no GPT
+    pub fs_type: Option<String>,
+    pub used_bytes: Option<u64>,
+    pub free_bytes: Option<u64>,
     pub partitions: Vec<PartitionInfo>,
 }
 
@@ -35,6 +46,40 @@ pub struct DiskInfo {
 pub struct DirEntry {
     pub name: String,
     pub is_dir: bool,
+    /// Where the entry points, if it is a symbolic link
+    pub symlink_target: Option<String>,
+    pub size: Option<u64>,
+    pub mtime: Option<u64>,
+}
+
+/// Metadata for a single path. Fields a filesystem does not record are
+/// `None`; FAT has no owners, permissions or inode numbers, for example.
+#[derive(Serialize, Debug, Clone)]
+pub struct FileStat {
+    pub path: String,
+    pub kind: FileKind,
+    pub size: u64,
+    /// Type and permission bits as in `st_mode`
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub inode: Option<u32>,
+    pub links: Option<u32>,
+    pub symlink_target: Option<String>,
+    /// Timestamps in seconds since the Unix epoch
+    pub atime: Option<u64>,
+    pub mtime: Option<u64>,
+    pub ctime: Option<u64>,
+    /// Creation time
+    pub btime: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -42,3 +87,23 @@ pub enum PathKind {
     Host,
     Image,
 }
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FsUsage {
+    pub fstype: String,
+    pub block_size: u64,
+    pub total_blocks: u64,
+    pub free_blocks: u64,
+}
+
+impl FsUsage {
+    pub fn used_blocks(&self) -> u64 {
+        self.total_blocks.saturating_sub(self.free_blocks)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DirUsage {
+    pub path: String,
+    pub bytes: u64,
+}
@@ -40,6 +40,23 @@ pub fn align_up(value: u64, align: u64) -> u64 {
     value.div_ceil(align) * align
 }
 
+pub fn format_mib(bytes: u64) -> String {
+    const MIB: u64 = 1024 * 1024;
+    if bytes.is_multiple_of(MIB) {
+        format!("{}", bytes / MIB)
+    } else {
+        format!("{:.1}", bytes as f64 / MIB as f64)
+    }
+}
+
+pub fn format_timestamp(secs: u64) -> String {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
 pub fn confirm_or_yes(yes: bool, prompt: &str) -> Result<()> {
     if yes {
         return Ok(());
@@ -79,6 +96,11 @@ pub fn normalize_image_path(path: &str) -> String {
     }
 }
 
+/// Whether an image path contains shell-style wildcards.
+pub fn has_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
 pub fn iter_path_components(path: &str) -> Vec<String> {
     let clean = normalize_image_path(path);
     let mut cur = String::new();
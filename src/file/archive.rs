@@ -5,16 +5,49 @@ use rand::RngCore;
 use sha2::Sha256;
 use std::{
     fs,
-    io::{self, Cursor, Write},
-    path::{Path, PathBuf},
+    io::{self, Cursor, Seek, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
 };
 use walkdir::WalkDir;
 
 pub const MAX_FILE_SIZE: u64 = 1000 * 1024 * 1024;
-const ENCRYPT_MAGIC: &[u8] = b"XTOOLENC1";
+/// Current format: magic, KDF id and parameters, salt, nonce, ciphertext
+const ENCRYPT_MAGIC: &[u8] = b"XTOOLENC2";
+/// Original format: magic, salt, nonce, ciphertext, with fixed PBKDF2 settings
+const ENCRYPT_MAGIC_V1: &[u8] = b"XTOOLENC1";
 const SALT_LEN: usize = 16;
 const NONCE_LEN: usize = 12;
-const PBKDF2_ITERS: u32 = 100_000;
+pub const DEFAULT_PBKDF2_ITERS: u32 = 100_000;
+/// Fewer rounds than this are rejected when encrypting
+pub const MIN_PBKDF2_ITERS: u32 = 1000;
+/// Upper bound on PBKDF2 rounds, so a crafted header cannot stall decryption
+pub const MAX_PBKDF2_ITERS: u32 = 10_000_000;
+pub const DEFAULT_ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+pub const DEFAULT_ARGON2_ITERS: u32 = 3;
+pub const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+/// Upper bound on Argon2 memory, so a crafted header cannot exhaust RAM
+pub const MAX_ARGON2_MEMORY_KIB: u32 = 1024 * 1024;
+/// Upper bounds on the other Argon2 costs, for the same reason
+pub const MAX_ARGON2_ITERS: u32 = 16;
+pub const MAX_ARGON2_PARALLELISM: u32 = 16;
+const KDF_PBKDF2: u8 = 1;
+const KDF_ARGON2ID: u8 = 2;
+/// Directories holding at least this much file data are compressed on all
+/// cores unless `--jobs` says otherwise
+const PARALLEL_THRESHOLD: u64 = 16 * 1024 * 1024;
+/// Files above this size are deflated straight into the archive instead of
+/// being buffered in memory by a worker
+const MAX_BUFFERED_ENTRY: u64 = 64 * 1024 * 1024;
+/// Small files handed to the workers per round, per worker
+const BATCH_PER_JOB: usize = 8;
+/// Most file data handed to the workers per round, since every compressed
+/// entry of a round is held in memory until the round is written out
+const MAX_BATCH_BYTES: u64 = 256 * 1024 * 1024;
 pub const XTOOL_FILE_SUFFIX: &str = ".xtool_file";
 pub const XTOOL_DIR_SUFFIX: &str = ".xtool_dir";
 
@@ -25,7 +58,158 @@ pub enum ArchiveHint {
     None,
 }
 
-pub fn compress_directory(dir: &Path) -> Result<(PathBuf, String, u64)> {
+/// How the archive key is derived from the passphrase. The choice and its
+/// parameters are stored in the header, so decryption never has to guess.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kdf {
+    /// PBKDF2-HMAC-SHA256
+    Pbkdf2 { iterations: u32 },
+    /// Argon2id, memory-hard and therefore much costlier to brute-force on
+    /// GPUs, at the price of `memory_kib` of RAM on both ends
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::Pbkdf2 {
+            iterations: DEFAULT_PBKDF2_ITERS,
+        }
+    }
+}
+
+impl Kdf {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Kdf::Pbkdf2 { iterations } => {
+                out.push(KDF_PBKDF2);
+                out.extend_from_slice(&iterations.to_be_bytes());
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                out.push(KDF_ARGON2ID);
+                out.extend_from_slice(&memory_kib.to_be_bytes());
+                out.extend_from_slice(&iterations.to_be_bytes());
+                out.extend_from_slice(&parallelism.to_be_bytes());
+            }
+        }
+    }
+
+    /// Parse the KDF id and parameters at the start of `bytes`, returning
+    /// them with the number of bytes consumed.
+    fn decode(bytes: &[u8]) -> Result<(Kdf, usize)> {
+        match bytes.first() {
+            Some(&KDF_PBKDF2) => {
+                let iterations = read_u32(bytes, 1)?;
+                if iterations == 0 {
+                    return Err(anyhow::anyhow!("Invalid PBKDF2 iteration count"));
+                }
+                let kdf = Kdf::Pbkdf2 { iterations };
+                kdf.check_cost()?;
+                Ok((kdf, 5))
+            }
+            Some(&KDF_ARGON2ID) => {
+                let kdf = Kdf::Argon2id {
+                    memory_kib: read_u32(bytes, 1)?,
+                    iterations: read_u32(bytes, 5)?,
+                    parallelism: read_u32(bytes, 9)?,
+                };
+                kdf.check_cost()?;
+                Ok((kdf, 13))
+            }
+            Some(id) => Err(anyhow::anyhow!("Unsupported key derivation id {}", id)),
+            None => Err(anyhow::anyhow!("Encrypted header is truncated")),
+        }
+    }
+
+    /// Reject parameters costlier than the `MAX_*` limits.
+    fn check_cost(&self) -> Result<()> {
+        match *self {
+            Kdf::Pbkdf2 { iterations } if iterations > MAX_PBKDF2_ITERS => Err(anyhow::anyhow!(
+                "PBKDF2 iteration count {} exceeds the {} limit",
+                iterations,
+                MAX_PBKDF2_ITERS
+            )),
+            Kdf::Pbkdf2 { .. } => Ok(()),
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                if memory_kib > MAX_ARGON2_MEMORY_KIB {
+                    return Err(anyhow::anyhow!(
+                        "Argon2 memory cost of {} KiB exceeds the {} KiB limit",
+                        memory_kib,
+                        MAX_ARGON2_MEMORY_KIB
+                    ));
+                }
+                if iterations > MAX_ARGON2_ITERS || parallelism > MAX_ARGON2_PARALLELISM {
+                    return Err(anyhow::anyhow!(
+                        "Argon2 cost of {} iterations on {} lanes exceeds the limit of {} on {}",
+                        iterations,
+                        parallelism,
+                        MAX_ARGON2_ITERS,
+                        MAX_ARGON2_PARALLELISM
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn derive_key(&self, key: &str, salt: &[u8]) -> Result<[u8; 32]> {
+        self.check_cost()?;
+        let mut key_bytes = [0u8; 32];
+        match self {
+            Kdf::Pbkdf2 { iterations } => {
+                pbkdf2_hmac::<Sha256>(key.as_bytes(), salt, *iterations, &mut key_bytes);
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = argon2::Params::new(*memory_kib, *iterations, *parallelism, Some(32))
+                    .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))?;
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(key.as_bytes(), salt, &mut key_bytes)
+                    .map_err(|e| anyhow::anyhow!("Argon2 key derivation failed: {}", e))?;
+            }
+        }
+        Ok(key_bytes)
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| anyhow::anyhow!("Encrypted header is truncated"))
+}
+
+type FileOptions = zip::write::FileOptions<'static, ()>;
+
+enum DirEntry {
+    Dir(String),
+    File { name: String, path: PathBuf, size: u64 },
+}
+
+/// Zip `dir` into a temp file. `jobs` is the number of compression threads;
+/// `None` picks one per core for large directories and one otherwise.
+/// Entries are sorted by name, so the result does not depend on `jobs` or on
+/// directory iteration order. The archive goes in `tmp_dir`, or the system
+/// temp directory when `None`.
+pub fn compress_directory(
+    dir: &Path,
+    jobs: Option<usize>,
+    tmp_dir: Option<&Path>,
+) -> Result<(PathBuf, String, u64)> {
     if !dir.exists() || !dir.is_dir() {
         return Err(anyhow::anyhow!("Directory not found: {}", dir.display()));
     }
@@ -36,23 +220,83 @@ pub fn compress_directory(dir: &Path) -> Result<(PathBuf, String, u64)> {
         .unwrap_or("archive");
     let zip_name = format!("{}{}", strip_xtool_suffix(base_name), XTOOL_DIR_SUFFIX);
 
-    let tmp = tempfile::Builder::new()
-        .prefix("xtool_upload_")
-        .suffix(".zip")
-        .tempfile()
-        .context("Failed to create temp file")?;
+    let tmp = temp_zip("xtool_upload_", tmp_dir)?;
     let mut writer = zip::ZipWriter::new(tmp.as_file());
-    let options = zip::write::FileOptions::<()>::default()
+    let options = FileOptions::default()
         .compression_method(zip::CompressionMethod::Deflated)
         .unix_permissions(0o644);
 
     let base = dir.canonicalize().context("Failed to canonicalize path")?;
+    let entries = collect_entries(&base)?;
+    let total: u64 = entries
+        .iter()
+        .map(|entry| match entry {
+            DirEntry::File { size, .. } => *size,
+            DirEntry::Dir(_) => 0,
+        })
+        .sum();
+    let jobs = jobs.unwrap_or_else(|| {
+        if total >= PARALLEL_THRESHOLD {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            1
+        }
+    });
+
+    let mut batch: Vec<(&str, &Path)> = Vec::new();
+    let mut batch_bytes = 0u64;
+    for entry in &entries {
+        match entry {
+            DirEntry::File { name, path, size } if jobs > 1 && *size <= MAX_BUFFERED_ENTRY => {
+                if batch_bytes + size > MAX_BATCH_BYTES {
+                    write_batch(&mut writer, &batch, jobs, options)?;
+                    batch.clear();
+                    batch_bytes = 0;
+                }
+                batch.push((name, path));
+                batch_bytes += size;
+                if batch.len() >= jobs * BATCH_PER_JOB {
+                    write_batch(&mut writer, &batch, jobs, options)?;
+                    batch.clear();
+                    batch_bytes = 0;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        // Keep archive order: everything queued so far goes in first
+        write_batch(&mut writer, &batch, jobs, options)?;
+        batch.clear();
+        batch_bytes = 0;
+        match entry {
+            DirEntry::Dir(name) => writer
+                .add_directory(name.as_str(), options)
+                .context("Failed to add directory to archive")?,
+            DirEntry::File { name, path, .. } => add_file(&mut writer, name, path, options)?,
+        }
+    }
+    write_batch(&mut writer, &batch, jobs, options)?;
+
+    writer.finish().context("Failed to finalize archive")?;
+    tmp.as_file().sync_all().ok();
+
+    let (file, path) = tmp.keep().context("Failed to keep temp file")?;
+    let size = file
+        .metadata()
+        .context("Failed to read archive metadata")?
+        .len();
+    drop(file);
 
-    for entry in WalkDir::new(&base) {
+    Ok((path, zip_name, size))
+}
+
+fn collect_entries(base: &Path) -> Result<Vec<DirEntry>> {
+    let mut entries = Vec::new();
+    for entry in WalkDir::new(base).sort_by_file_name() {
         let entry = entry.context("Failed to walk directory")?;
         let path = entry.path();
         let rel = path
-            .strip_prefix(&base)
+            .strip_prefix(base)
             .context("Failed to compute relative path")?;
         let name = rel.to_string_lossy().replace('\\', "/");
         if name.is_empty() {
@@ -60,34 +304,83 @@ pub fn compress_directory(dir: &Path) -> Result<(PathBuf, String, u64)> {
         }
 
         if path.is_dir() {
-            writer
-                .add_directory(name, options)
-                .context("Failed to add directory to archive")?;
+            entries.push(DirEntry::Dir(name));
         } else if path.is_file() {
-            writer
-                .start_file(name, options)
-                .context("Failed to add file to archive")?;
-            let mut file = fs::File::open(path)
-                .with_context(|| format!("Failed to open file: {}", path.display()))?;
-            io::copy(&mut file, &mut writer)
-                .context("Failed to write file to archive")?;
+            let size = fs::metadata(path)
+                .with_context(|| format!("Failed to read metadata: {}", path.display()))?
+                .len();
+            entries.push(DirEntry::File {
+                name,
+                path: path.to_path_buf(),
+                size,
+            });
         }
     }
+    Ok(entries)
+}
 
-    writer.finish().context("Failed to finalize archive")?;
-    tmp.as_file().sync_all().ok();
+fn add_file<W: Write + Seek>(
+    writer: &mut zip::ZipWriter<W>,
+    name: &str,
+    path: &Path,
+    options: FileOptions,
+) -> Result<()> {
+    writer
+        .start_file(name, options)
+        .context("Failed to add file to archive")?;
+    let mut file = fs::File::open(path)
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+    io::copy(&mut file, writer).context("Failed to write file to archive")?;
+    Ok(())
+}
 
-    let (file, path) = tmp.keep().context("Failed to keep temp file")?;
-    let size = file
-        .metadata()
-        .context("Failed to read archive metadata")?
-        .len();
-    drop(file);
+/// Deflate `batch` on up to `jobs` threads, each file into its own in-memory
+/// archive, then copy the compressed entries into `writer` in batch order.
+fn write_batch<W: Write + Seek>(
+    writer: &mut zip::ZipWriter<W>,
+    batch: &[(&str, &Path)],
+    jobs: usize,
+    options: FileOptions,
+) -> Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
 
-    Ok((path, zip_name, size))
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::from_iter(batch.iter().map(|_| None)));
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(batch.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(name, path)) = batch.get(index) else {
+                        break;
+                    };
+                    let mut single = zip::ZipWriter::new(Cursor::new(Vec::new()));
+                    let result = add_file(&mut single, name, path, options).and_then(|()| {
+                        single
+                            .finish_into_readable()
+                            .context("Failed to finalize archive entry")
+                    });
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    for result in results.into_inner().unwrap() {
+        let mut single = result.expect("every batch entry is compressed")?;
+        let entry = single
+            .by_index_raw(0)
+            .context("Failed to read compressed entry")?;
+        writer
+            .raw_copy_file(entry)
+            .context("Failed to add file to archive")?;
+    }
+    Ok(())
 }
 
-pub fn compress_file(file_path: &Path) -> Result<(PathBuf, String, u64)> {
+pub fn compress_file(file_path: &Path, tmp_dir: Option<&Path>) -> Result<(PathBuf, String, u64)> {
     if !file_path.exists() || !file_path.is_file() {
         return Err(anyhow::anyhow!("File not found: {}", file_path.display()));
     }
@@ -100,11 +393,7 @@ pub fn compress_file(file_path: &Path) -> Result<(PathBuf, String, u64)> {
     let clean_name = strip_xtool_suffix(&file_name);
     let zip_name = format!("{}{}", clean_name, XTOOL_FILE_SUFFIX);
 
-    let tmp = tempfile::Builder::new()
-        .prefix("xtool_upload_")
-        .suffix(".zip")
-        .tempfile()
-        .context("Failed to create temp file")?;
+    let tmp = temp_zip("xtool_upload_", tmp_dir)?;
 
     let mut writer = zip::ZipWriter::new(tmp.as_file());
     let options = zip::write::FileOptions::<()>::default()
@@ -132,26 +421,37 @@ pub fn compress_file(file_path: &Path) -> Result<(PathBuf, String, u64)> {
     Ok((path, zip_name, size))
 }
 
-pub fn compress_path(path: &Path) -> Result<(PathBuf, String, u64)> {
+pub fn compress_path(
+    path: &Path,
+    jobs: Option<usize>,
+    tmp_dir: Option<&Path>,
+) -> Result<(PathBuf, String, u64)> {
     if path.is_dir() {
-        compress_directory(path)
+        compress_directory(path, jobs, tmp_dir)
     } else {
-        compress_file(path)
+        compress_file(path, tmp_dir)
     }
 }
 
-pub fn write_temp_zip(bytes: &[u8]) -> Result<PathBuf> {
-    let mut tmp = tempfile::Builder::new()
-        .prefix("xtool_download_")
-        .suffix(".zip")
-        .tempfile()
-        .context("Failed to create temp file")?;
+pub fn write_temp_zip(bytes: &[u8], tmp_dir: Option<&Path>) -> Result<PathBuf> {
+    let mut tmp = temp_zip("xtool_download_", tmp_dir)?;
     tmp.write_all(bytes)
         .context("Failed to write temp archive")?;
     let (_file, path) = tmp.keep().context("Failed to keep temp file")?;
     Ok(path)
 }
 
+/// A `.zip` temp file in `tmp_dir`, or in the system temp directory.
+fn temp_zip(prefix: &str, tmp_dir: Option<&Path>) -> Result<tempfile::NamedTempFile> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(prefix).suffix(".zip");
+    match tmp_dir {
+        Some(dir) => builder.tempfile_in(dir),
+        None => builder.tempfile(),
+    }
+    .context("Failed to create temp file")
+}
+
 pub fn detect_archive_hint(filename: &str) -> (String, ArchiveHint) {
     if let Some(stripped) = filename.strip_suffix(XTOOL_FILE_SUFFIX) {
         return (stripped.to_string(), ArchiveHint::File);
@@ -163,13 +463,6 @@ pub fn detect_archive_hint(filename: &str) -> (String, ArchiveHint) {
 }
 
 pub fn unzip_single_from_bytes(bytes: &[u8], output_path: &Path) -> Result<()> {
-    let cursor = Cursor::new(bytes);
-    let mut archive = zip::ZipArchive::new(cursor).context("Failed to read archive")?;
-    if archive.len() == 0 {
-        return Err(anyhow::anyhow!("Archive is empty"));
-    }
-    let mut entry = archive.by_index(0).context("Failed to read archive entry")?;
-
     if let Some(parent) = output_path.parent() {
         if !parent.as_os_str().is_empty() {
             fs::create_dir_all(parent)
@@ -179,14 +472,28 @@ pub fn unzip_single_from_bytes(bytes: &[u8], output_path: &Path) -> Result<()> {
 
     let mut outfile = fs::File::create(output_path)
         .with_context(|| format!("Failed to create file: {}", output_path.display()))?;
-    io::copy(&mut entry, &mut outfile).context("Failed to extract file")?;
+    unzip_single_to_writer(bytes, &mut outfile)
+}
+
+/// Extract the first entry of a single-file archive into `writer`.
+pub fn unzip_single_to_writer(bytes: &[u8], writer: &mut impl Write) -> Result<()> {
+    let cursor = Cursor::new(bytes);
+    let mut archive = zip::ZipArchive::new(cursor).context("Failed to read archive")?;
+    if archive.len() == 0 {
+        return Err(anyhow::anyhow!("Archive is empty"));
+    }
+    let mut entry = archive.by_index(0).context("Failed to read archive entry")?;
+    // The name is not used for the output path here, but a traversal entry
+    // means the archive was crafted and should not be trusted at all
+    sanitize_entry_name(entry.name())?;
+    io::copy(&mut entry, writer).context("Failed to extract file")?;
     Ok(())
 }
 
-pub fn encrypt_zip_file(zip_path: &Path, key: &str) -> Result<u64> {
+pub fn encrypt_zip_file(zip_path: &Path, key: &str, kdf: Kdf) -> Result<u64> {
     let bytes = fs::read(zip_path)
         .with_context(|| format!("Failed to read archive: {}", zip_path.display()))?;
-    let encrypted = encrypt_zip_bytes(&bytes, key)?;
+    let encrypted = encrypt_zip_bytes(&bytes, key, kdf)?;
     fs::write(zip_path, &encrypted)
         .with_context(|| format!("Failed to write encrypted archive: {}", zip_path.display()))?;
     Ok(encrypted.len() as u64)
@@ -194,7 +501,7 @@ pub fn encrypt_zip_file(zip_path: &Path, key: &str) -> Result<u64> {
 
 pub fn is_encrypted_zip(bytes: &[u8]) -> bool {
     bytes.len() > ENCRYPT_MAGIC.len() + SALT_LEN + NONCE_LEN
-        && bytes.starts_with(ENCRYPT_MAGIC)
+        && (bytes.starts_with(ENCRYPT_MAGIC) || bytes.starts_with(ENCRYPT_MAGIC_V1))
 }
 
 pub fn decrypt_zip_bytes(bytes: &[u8], key: &str) -> Result<Vec<u8>> {
@@ -202,34 +509,43 @@ pub fn decrypt_zip_bytes(bytes: &[u8], key: &str) -> Result<Vec<u8>> {
         return Err(anyhow::anyhow!("Archive is not encrypted"));
     }
 
-    let header_len = ENCRYPT_MAGIC.len();
+    let (kdf, header_len) = if bytes.starts_with(ENCRYPT_MAGIC_V1) {
+        let kdf = Kdf::Pbkdf2 {
+            iterations: DEFAULT_PBKDF2_ITERS,
+        };
+        (kdf, ENCRYPT_MAGIC_V1.len())
+    } else {
+        let (kdf, params_len) = Kdf::decode(&bytes[ENCRYPT_MAGIC.len()..])?;
+        (kdf, ENCRYPT_MAGIC.len() + params_len)
+    };
     let salt_start = header_len;
     let salt_end = salt_start + SALT_LEN;
     let nonce_start = salt_end;
     let nonce_end = nonce_start + NONCE_LEN;
+    if bytes.len() <= nonce_end {
+        return Err(anyhow::anyhow!("Encrypted header is truncated"));
+    }
 
     let salt = &bytes[salt_start..salt_end];
     let nonce = &bytes[nonce_start..nonce_end];
     let ciphertext = &bytes[nonce_end..];
 
-    let mut key_bytes = [0u8; 32];
-    pbkdf2_hmac::<Sha256>(key.as_bytes(), salt, PBKDF2_ITERS, &mut key_bytes);
+    let key_bytes = kdf.derive_key(key, salt)?;
 
     let cipher = Aes256Gcm::new_from_slice(&key_bytes)
         .context("Failed to initialize cipher")?;
     let nonce = Nonce::from_slice(nonce);
     cipher
         .decrypt(nonce, ciphertext)
-        .map_err(|_| anyhow::anyhow!("Decrypt failed (bad key or corrupted data)"))
+        .map_err(|_| anyhow::anyhow!("Decrypt failed: authentication tag mismatch (bad key or corrupted data)"))
 }
 
-fn encrypt_zip_bytes(bytes: &[u8], key: &str) -> Result<Vec<u8>> {
+fn encrypt_zip_bytes(bytes: &[u8], key: &str, kdf: Kdf) -> Result<Vec<u8>> {
     let mut salt = [0u8; SALT_LEN];
     let mut rng = rand::rng();
     rng.fill_bytes(&mut salt);
 
-    let mut key_bytes = [0u8; 32];
-    pbkdf2_hmac::<Sha256>(key.as_bytes(), &salt, PBKDF2_ITERS, &mut key_bytes);
+    let key_bytes = kdf.derive_key(key, &salt)?;
 
     let cipher = Aes256Gcm::new_from_slice(&key_bytes)
         .context("Failed to initialize cipher")?;
@@ -238,8 +554,9 @@ fn encrypt_zip_bytes(bytes: &[u8], key: &str) -> Result<Vec<u8>> {
     rng.fill_bytes(&mut nonce_bytes);
     let nonce = Nonce::from_slice(&nonce_bytes);
 
-    let mut out = Vec::with_capacity(ENCRYPT_MAGIC.len() + SALT_LEN + NONCE_LEN + bytes.len() + 16);
+    let mut out = Vec::with_capacity(ENCRYPT_MAGIC.len() + 16 + SALT_LEN + NONCE_LEN + bytes.len() + 16);
     out.extend_from_slice(ENCRYPT_MAGIC);
+    kdf.encode(&mut out);
     out.extend_from_slice(&salt);
     out.extend_from_slice(&nonce_bytes);
 
@@ -257,19 +574,28 @@ pub fn unzip_to_dir(zip_path: &Path, output_dir: &Path) -> Result<()> {
 
     fs::create_dir_all(output_dir)
         .with_context(|| format!("Failed to create directory: {}", output_dir.display()))?;
+    let root = output_dir
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve directory: {}", output_dir.display()))?;
 
     for i in 0..archive.len() {
         let mut entry = archive.by_index(i).context("Failed to read archive entry")?;
-        let out_path = output_dir.join(entry.name());
+        let relative = sanitize_entry_name(entry.name())?;
 
         if entry.name().ends_with('/') {
-            fs::create_dir_all(&out_path)
-                .with_context(|| format!("Failed to create directory: {}", out_path.display()))?;
+            create_dirs_within(&root, &relative, entry.name())?;
         } else {
-            if let Some(parent) = out_path.parent() {
-                fs::create_dir_all(parent).with_context(|| {
-                    format!("Failed to create directory: {}", parent.display())
-                })?;
+            let parent = match relative.parent() {
+                Some(parent) => create_dirs_within(&root, parent, entry.name())?,
+                None => root.clone(),
+            };
+            let out_path = parent.join(relative.file_name().unwrap_or_default());
+            // An existing link in the output tree would redirect the write
+            if fs::symlink_metadata(&out_path).is_ok_and(|m| m.file_type().is_symlink()) {
+                return Err(anyhow::anyhow!(
+                    "Refusing to write archive entry through a symlink: {}",
+                    entry.name()
+                ));
             }
             let mut outfile = fs::File::create(&out_path)
                 .with_context(|| format!("Failed to create file: {}", out_path.display()))?;
@@ -281,6 +607,55 @@ pub fn unzip_to_dir(zip_path: &Path, output_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Turn an archive entry name into a relative path, refusing absolute paths
+/// and `..` components that would escape the extraction directory.
+fn sanitize_entry_name(name: &str) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(anyhow::anyhow!("Refusing unsafe archive entry: {}", name));
+            }
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Err(anyhow::anyhow!("Refusing unsafe archive entry: {:?}", name));
+    }
+    Ok(relative)
+}
+
+/// Create `relative` under `root` one component at a time, refusing to pass
+/// through symlinks so nothing is created outside the output directory.
+fn create_dirs_within(root: &Path, relative: &Path, name: &str) -> Result<PathBuf> {
+    let mut path = root.to_path_buf();
+    for part in relative.components() {
+        path.push(part);
+        match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                return Err(anyhow::anyhow!(
+                    "Refusing to write archive entry through a symlink: {}",
+                    name
+                ));
+            }
+            Ok(meta) if meta.is_dir() => {}
+            Ok(_) => {
+                return Err(anyhow::anyhow!("Not a directory: {}", path.display()));
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir(&path)
+                    .with_context(|| format!("Failed to create directory: {}", path.display()))?;
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to resolve path: {}", path.display()));
+            }
+        }
+    }
+    Ok(path)
+}
+
 pub fn resolve_output_path(output: Option<&Path>, filename: &str) -> PathBuf {
     match output {
         Some(path) if path.exists() && path.is_dir() => path.join(filename),
@@ -317,3 +692,224 @@ fn strip_xtool_suffix(name: &str) -> &str {
     }
     name
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAST: Kdf = Kdf::Pbkdf2 { iterations: 1000 };
+
+    #[test]
+    fn round_trips_with_stored_kdf_parameters() {
+        let encrypted = encrypt_zip_bytes(b"payload", "secret", FAST).unwrap();
+        assert!(encrypted.starts_with(ENCRYPT_MAGIC));
+        assert!(is_encrypted_zip(&encrypted));
+        let (kdf, _) = Kdf::decode(&encrypted[ENCRYPT_MAGIC.len()..]).unwrap();
+        assert_eq!(kdf, FAST);
+
+        assert_eq!(decrypt_zip_bytes(&encrypted, "secret").unwrap(), b"payload");
+        assert!(decrypt_zip_bytes(&encrypted, "wrong").is_err());
+    }
+
+    #[test]
+    fn decrypts_version_one_archives() {
+        let salt = [7u8; SALT_LEN];
+        let nonce = [9u8; NONCE_LEN];
+        let key_bytes = Kdf::default().derive_key("secret", &salt).unwrap();
+        let cipher = Aes256Gcm::new_from_slice(&key_bytes).unwrap();
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), b"legacy".as_slice())
+            .unwrap();
+        let archive = [ENCRYPT_MAGIC_V1, &salt, &nonce, &ciphertext].concat();
+
+        assert!(is_encrypted_zip(&archive));
+        assert_eq!(decrypt_zip_bytes(&archive, "secret").unwrap(), b"legacy");
+    }
+
+    #[test]
+    fn rejects_unknown_or_truncated_headers() {
+        let mut encrypted = encrypt_zip_bytes(b"payload", "secret", FAST).unwrap();
+        encrypted[ENCRYPT_MAGIC.len()] = 0xEE;
+        let err = decrypt_zip_bytes(&encrypted, "secret").unwrap_err();
+        assert!(err.to_string().contains("Unsupported key derivation"));
+
+        let zero_iters = [ENCRYPT_MAGIC, &[KDF_PBKDF2, 0, 0, 0, 0], &[0u8; 40]].concat();
+        assert!(decrypt_zip_bytes(&zero_iters, "secret").is_err());
+    }
+
+    fn archive_contents(path: &Path) -> Vec<(String, Vec<u8>)> {
+        let mut archive = zip::ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut entry = archive.by_index(i).unwrap();
+                let mut data = Vec::new();
+                io::copy(&mut entry, &mut data).unwrap();
+                (entry.name().to_string(), data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parallel_compression_matches_sequential() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("tree");
+        for i in 0..40 {
+            let sub = src.join(format!("d{}", i % 3));
+            fs::create_dir_all(&sub).unwrap();
+            fs::write(sub.join(format!("f{i:02}.txt")), format!("{i}").repeat(i * 50)).unwrap();
+        }
+        fs::create_dir_all(src.join("empty")).unwrap();
+
+        let (sequential, _, _) = compress_directory(&src, Some(1), None).unwrap();
+        let (parallel, name, _) = compress_directory(&src, Some(4), None).unwrap();
+        let expected = archive_contents(&sequential);
+        assert_eq!(archive_contents(&parallel), expected);
+        assert_eq!(name, format!("tree{}", XTOOL_DIR_SUFFIX));
+
+        let names: Vec<_> = expected.iter().map(|(name, _)| name.as_str()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert_eq!(names.len(), 40 + 4);
+
+        let out = dir.path().join("out");
+        unzip_to_dir(&parallel, &out).unwrap();
+        assert_eq!(fs::read(out.join("d1/f07.txt")).unwrap(), "7".repeat(350).as_bytes());
+        fs::remove_file(sequential).unwrap();
+        fs::remove_file(parallel).unwrap();
+    }
+
+    #[test]
+    fn temp_archives_go_in_tmp_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("notes.txt");
+        fs::write(&src, b"hello").unwrap();
+        let scratch = dir.path().join("scratch");
+        fs::create_dir(&scratch).unwrap();
+
+        let (upload, _, _) = compress_file(&src, Some(&scratch)).unwrap();
+        assert_eq!(upload.parent(), Some(scratch.as_path()));
+        let download = write_temp_zip(&fs::read(&upload).unwrap(), Some(&scratch)).unwrap();
+        assert_eq!(download.parent(), Some(scratch.as_path()));
+
+        assert!(compress_file(&src, Some(&dir.path().join("missing"))).is_err());
+    }
+
+    fn zip_with_entry(name: &str) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(name, zip::write::FileOptions::<()>::default())
+            .unwrap();
+        writer.write_all(b"pwned").unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn refuses_zip_slip_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+
+        for name in ["../evil.txt", "a/../../evil.txt", "/evil.txt"] {
+            let zip_path = dir.path().join("slip.zip");
+            fs::write(&zip_path, zip_with_entry(name)).unwrap();
+            let err = unzip_to_dir(&zip_path, &out).unwrap_err();
+            assert!(err.to_string().contains("unsafe archive entry"), "{name}: {err}");
+
+            let err = unzip_single_to_writer(&zip_with_entry(name), &mut io::sink()).unwrap_err();
+            assert!(err.to_string().contains("unsafe archive entry"), "{name}: {err}");
+        }
+        assert!(!dir.path().join("evil.txt").exists());
+
+        let zip_path = dir.path().join("ok.zip");
+        fs::write(&zip_path, zip_with_entry("./sub/ok.txt")).unwrap();
+        unzip_to_dir(&zip_path, &out).unwrap();
+        assert_eq!(fs::read(out.join("sub/ok.txt")).unwrap(), b"pwned");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_writes_through_symlinked_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(&out).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, out.join("link")).unwrap();
+
+        let zip_path = dir.path().join("slip.zip");
+        fs::write(&zip_path, zip_with_entry("link/evil.txt")).unwrap();
+        assert!(unzip_to_dir(&zip_path, &out).is_err());
+        assert!(!outside.join("evil.txt").exists());
+
+        for name in ["link/new/", "link/new/x"] {
+            fs::write(&zip_path, zip_with_entry(name)).unwrap();
+            assert!(unzip_to_dir(&zip_path, &out).is_err());
+            assert!(!outside.join("new").exists(), "{name} created a directory outside");
+        }
+
+        let elsewhere = dir.path().join("elsewhere");
+        fs::write(&elsewhere, b"keep").unwrap();
+        std::os::unix::fs::symlink(&elsewhere, out.join("x.txt")).unwrap();
+        fs::write(&zip_path, zip_with_entry("x.txt")).unwrap();
+        assert!(unzip_to_dir(&zip_path, &out).is_err());
+        assert_eq!(fs::read(&elsewhere).unwrap(), b"keep");
+    }
+
+    #[test]
+    fn round_trips_with_argon2() {
+        let kdf = Kdf::Argon2id {
+            memory_kib: 256,
+            iterations: 2,
+            parallelism: 1,
+        };
+        let encrypted = encrypt_zip_bytes(b"payload", "secret", kdf).unwrap();
+        assert_eq!(encrypted[ENCRYPT_MAGIC.len()], KDF_ARGON2ID);
+        let (stored, _) = Kdf::decode(&encrypted[ENCRYPT_MAGIC.len()..]).unwrap();
+        assert_eq!(stored, kdf);
+
+        assert_eq!(decrypt_zip_bytes(&encrypted, "secret").unwrap(), b"payload");
+        assert!(decrypt_zip_bytes(&encrypted, "wrong").is_err());
+
+        // Same passphrase and salt, different KDF: a different key
+        let salt = [1u8; SALT_LEN];
+        assert_ne!(
+            kdf.derive_key("secret", &salt).unwrap(),
+            FAST.derive_key("secret", &salt).unwrap()
+        );
+    }
+
+    #[test]
+    fn refuses_excessive_argon2_memory() {
+        let kdf = Kdf::Argon2id {
+            memory_kib: MAX_ARGON2_MEMORY_KIB + 1,
+            iterations: 1,
+            parallelism: 1,
+        };
+        assert!(kdf.derive_key("secret", &[0u8; SALT_LEN]).is_err());
+    }
+
+    #[test]
+    fn refuses_crafted_kdf_costs_in_header() {
+        let crafted = [
+            Kdf::Pbkdf2 { iterations: u32::MAX },
+            Kdf::Argon2id {
+                memory_kib: 256,
+                iterations: u32::MAX,
+                parallelism: 1,
+            },
+            Kdf::Argon2id {
+                memory_kib: 256,
+                iterations: 1,
+                parallelism: MAX_ARGON2_PARALLELISM + 1,
+            },
+        ];
+        for kdf in crafted {
+            let mut header = Vec::new();
+            kdf.encode(&mut header);
+            assert!(Kdf::decode(&header).is_err(), "{kdf:?}");
+        }
+        let mut header = Vec::new();
+        FAST.encode(&mut header);
+        assert_eq!(Kdf::decode(&header).unwrap().0, FAST);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::file::retry::{DEFAULT_RETRIES, send_with_retry};
+use anyhow::{Context, Result};
+use dialoguer::Confirm;
+use reqwest::StatusCode;
+use std::io::{self, IsTerminal};
+
+pub fn delete_file(server: &str, token: &str, yes: bool) -> Result<()> {
+    if !yes {
+        if !io::stdin().is_terminal() {
+            return Err(anyhow::anyhow!(
+                "Refusing to delete {} without confirmation; pass --yes",
+                token
+            ));
+        }
+        let confirmed = Confirm::new()
+            .with_prompt(format!("Delete {} from the server?", token))
+            .default(false)
+            .interact()
+            .context("Failed to read confirmation")?;
+        if !confirmed {
+            return Err(anyhow::anyhow!("Aborted"));
+        }
+    }
+
+    let client = reqwest::blocking::Client::new();
+    let url = format!("{}/files/{}", server.trim_end_matches('/'), token);
+    // Not idempotent: a retry after a lost response would report 404
+    let response = send_with_retry(
+        || client.delete(&url),
+        DEFAULT_RETRIES,
+        false,
+        "delete file",
+    )?;
+
+    match response.status() {
+        status if status.is_success() => {
+            println!("Deleted {}", token);
+            Ok(())
+        }
+        StatusCode::NOT_FOUND => Err(anyhow::anyhow!(
+            "No upload with id {} (already downloaded, expired or deleted)",
+            token
+        )),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(anyhow::anyhow!(
+            "Server refused to delete {} ({}); deletion may be limited to admin mode",
+            token,
+            response.status()
+        )),
+        status => Err(anyhow::anyhow!("Delete failed: {}", status)),
+    }
+}
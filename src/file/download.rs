@@ -1,22 +1,35 @@
 use crate::file::archive::{
     decrypt_zip_bytes, detect_archive_hint, is_encrypted_zip, resolve_output_dir,
-    resolve_output_path, unzip_single_from_bytes, unzip_to_dir, write_temp_zip, ArchiveHint,
-    MAX_FILE_SIZE,
+    resolve_output_path, unzip_single_from_bytes, unzip_single_to_writer, unzip_to_dir,
+    write_temp_zip, ArchiveHint, MAX_FILE_SIZE,
 };
+use crate::file::retry::send_with_retry;
+use crate::file::throttle::TokenBucket;
 use crate::file::{ContentType, DownloadResponse};
 use anyhow::{Context, Result};
-use dialoguer::Input;
+use dialoguer::Password;
 use indicatif::{ProgressBar, ProgressStyle};
-use log::info;
-use std::{fs, io::Read, path::Path};
+use log::{info, warn};
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, IsTerminal, Read, Write},
+    path::Path,
+};
 
-pub fn get_file(server: &str, token: &str, output: Option<&Path>, key: Option<&str>) -> Result<()> {
+pub fn get_file(
+    server: &str,
+    token: &str,
+    output: Option<&Path>,
+    key: Option<&str>,
+    retries: u32,
+    limit_rate: Option<u64>,
+    tmp_dir: Option<&Path>,
+) -> Result<()> {
     let client = reqwest::blocking::Client::new();
     let url = format!("{}/download/{}", normalize_server(server), token);
-    let response = client
-        .get(&url)
-        .send()
-        .context("Failed to send download request")?;
+    // Not idempotent: each successful response uses up one of the file's downloads
+    let response = send_with_retry(|| client.get(&url), retries, false, "send download request")?;
 
     if !response.status().is_success() {
         return Err(anyhow::anyhow!(
@@ -43,14 +56,22 @@ pub fn get_file(server: &str, token: &str, output: Option<&Path>, key: Option<&s
             let file_url = download_resp
                 .url
                 .context("No url in response (is this a text?)")?;
+            // Servers storing files locally hand out paths relative to themselves
+            let file_url = if file_url.starts_with('/') {
+                format!("{}{}", normalize_server(server), file_url)
+            } else {
+                file_url
+            };
             let filename = download_resp
                 .filename
                 .unwrap_or_else(|| "file.bin".to_string());
 
-            let mut file_response = client
-                .get(&file_url)
-                .send()
-                .context("Failed to download file from storage")?;
+            let mut file_response = send_with_retry(
+                || client.get(&file_url),
+                retries,
+                true,
+                "download file from storage",
+            )?;
 
             if !file_response.status().is_success() {
                 return Err(anyhow::anyhow!(
@@ -87,6 +108,7 @@ pub fn get_file(server: &str, token: &str, output: Option<&Path>, key: Option<&s
                 }
             };
 
+            let mut bucket = limit_rate.map(TokenBucket::new);
             let mut buffer = [0u8; 64 * 1024];
             loop {
                 let read = file_response
@@ -98,6 +120,9 @@ pub fn get_file(server: &str, token: &str, output: Option<&Path>, key: Option<&s
                 bytes.extend_from_slice(&buffer[..read]);
                 downloaded += read as u64;
                 progress.inc(read as u64);
+                if let Some(bucket) = bucket.as_mut() {
+                    bucket.take(read);
+                }
 
                 if downloaded > MAX_FILE_SIZE {
                     progress.finish_and_clear();
@@ -110,22 +135,30 @@ pub fn get_file(server: &str, token: &str, output: Option<&Path>, key: Option<&s
 
             progress.finish_and_clear();
 
+            if key.is_some() && !is_encrypted_zip(&bytes) {
+                warn!("Download is not encrypted; ignoring the key");
+            }
+
             let (clean_name, hint) = detect_archive_hint(&filename);
             let looks_like_zip = filename.ends_with(".zip")
                 || hint != ArchiveHint::None
                 || is_encrypted_zip(&bytes)
                 || bytes.starts_with(b"PK\x03\x04");
 
+            if output.is_some_and(is_stdout) {
+                return write_to_stdout(&bytes, key, looks_like_zip, hint);
+            }
+
             if looks_like_zip {
                 match hint {
                     ArchiveHint::File => {
                         let output_path = resolve_output_path(output, &clean_name);
-                        handle_zip_download(&bytes, key, &output_path, ArchiveHint::File)?;
+                        handle_zip_download(&bytes, key, &output_path, ArchiveHint::File, tmp_dir)?;
                         info!("Download success: {}", output_path.display());
                     }
                     ArchiveHint::Dir | ArchiveHint::None => {
                         let output_dir = resolve_output_dir(output, &clean_name)?;
-                        handle_zip_download(&bytes, key, &output_dir, ArchiveHint::Dir)?;
+                        handle_zip_download(&bytes, key, &output_dir, ArchiveHint::Dir, tmp_dir)?;
                         info!("Download success: {}", output_dir.display());
                     }
                 }
@@ -158,47 +191,87 @@ fn handle_zip_download(
     key: Option<&str>,
     output_path: &Path,
     hint: ArchiveHint,
+    tmp_dir: Option<&Path>,
 ) -> Result<()> {
-    if let Some(key) = key {
-        if key.trim().is_empty() {
+    let archive = decrypt_if_encrypted(bytes, key)?;
+    unzip_from_bytes(&archive, output_path, hint, tmp_dir)
+}
+
+/// Decrypt (and authenticate) an encrypted archive before anything tries to
+/// unzip it, so a wrong key is reported as such rather than as a zip error.
+fn decrypt_if_encrypted<'a>(bytes: &'a [u8], key: Option<&str>) -> Result<Cow<'a, [u8]>> {
+    if !is_encrypted_zip(bytes) {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    let key = match key {
+        Some(key) if key.trim().is_empty() => {
             return Err(anyhow::anyhow!("Decryption key cannot be empty"));
         }
-        let decrypted = if is_encrypted_zip(bytes) {
-            decrypt_zip_bytes(bytes, key)?
-        } else {
-            bytes.to_vec()
-        };
-        return unzip_from_bytes(&decrypted, output_path, hint);
+        Some(key) => key.to_string(),
+        None if io::stdin().is_terminal() => prompt_key()?,
+        None => {
+            return Err(anyhow::anyhow!(
+                "Download is encrypted; pass the key with -k or XTOOL_KEY"
+            ));
+        }
+    };
+    if key.is_empty() {
+        return Err(anyhow::anyhow!("Download is encrypted and no key was given"));
     }
+    decrypt_zip_bytes(bytes, &key).map(Cow::Owned)
+}
 
-    let unzip_result = unzip_from_bytes(bytes, output_path, hint);
-    match unzip_result {
-        Ok(()) => Ok(()),
-        Err(err) => {
-            if !is_encrypted_zip(bytes) {
-                return Err(err);
-            }
-            let prompt = "Enter key";
-            let input_key = Input::<String>::new()
-                .with_prompt(prompt)
-                .allow_empty(true)
-                .interact()
-                .context("Failed to read key")?;
-            let input_key = input_key.trim();
-            if input_key.is_empty() {
-                return Err(err);
-            }
-            let decrypted = decrypt_zip_bytes(bytes, input_key)?;
-            unzip_from_bytes(&decrypted, output_path, hint)
-        }
+fn prompt_key() -> Result<String> {
+    let input_key = Password::new()
+        .with_prompt("Enter key")
+        .allow_empty_password(true)
+        .interact()
+        .context("Failed to read key")?;
+    Ok(input_key.trim().to_string())
+}
+
+/// `-o -` means stdout
+fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Write the downloaded file's contents (not the transfer archive) to stdout.
+fn write_to_stdout(
+    bytes: &[u8],
+    key: Option<&str>,
+    looks_like_zip: bool,
+    hint: ArchiveHint,
+) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    if !looks_like_zip {
+        stdout.write_all(bytes).context("Failed to write to stdout")?;
+        return stdout.flush().context("Failed to write to stdout");
+    }
+    if hint == ArchiveHint::Dir {
+        return Err(anyhow::anyhow!(
+            "Download is a directory and cannot be written to stdout; use -o <dir>"
+        ));
+    }
+
+    let archive = decrypt_if_encrypted(bytes, key)?;
+    if hint == ArchiveHint::File {
+        unzip_single_to_writer(&archive, &mut stdout)?;
+    } else {
+        stdout.write_all(&archive).context("Failed to write to stdout")?;
     }
+    stdout.flush().context("Failed to write to stdout")
 }
 
-fn unzip_from_bytes(bytes: &[u8], output_path: &Path, hint: ArchiveHint) -> Result<()> {
+fn unzip_from_bytes(
+    bytes: &[u8],
+    output_path: &Path,
+    hint: ArchiveHint,
+    tmp_dir: Option<&Path>,
+) -> Result<()> {
     if hint == ArchiveHint::File {
         return unzip_single_from_bytes(bytes, output_path);
     }
-    let temp_path = write_temp_zip(bytes)?;
+    let temp_path = write_temp_zip(bytes, tmp_dir)?;
     let unzip_result = unzip_to_dir(&temp_path, output_path);
     let _ = fs::remove_file(&temp_path);
     unzip_result
@@ -0,0 +1,110 @@
+use crate::file::ContentType;
+use crate::file::retry::{DEFAULT_RETRIES, send_with_retry};
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Deserialize, Debug)]
+struct ListResponse {
+    files: Vec<FileEntry>,
+}
+
+/// The fields of a server-side record that `file list` shows
+#[derive(Deserialize, Debug)]
+struct FileEntry {
+    id: String,
+    filename: Option<String>,
+    content_type: ContentType,
+    uploaded_at: u64,
+    downloads_remaining: Option<u8>,
+}
+
+pub fn list_files(server: &str) -> Result<()> {
+    let client = reqwest::blocking::Client::new();
+    let url = format!("{}/files", server.trim_end_matches('/'));
+    let response = send_with_retry(|| client.get(&url), DEFAULT_RETRIES, true, "list files")?;
+
+    match response.status() {
+        status if status.is_success() => {}
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            return Err(anyhow::anyhow!(
+                "Server refused to list files ({}); listing may be limited to admin mode",
+                response.status()
+            ));
+        }
+        StatusCode::NOT_FOUND => {
+            return Err(anyhow::anyhow!("Server does not support listing files"));
+        }
+        status => return Err(anyhow::anyhow!("List failed: {}", status)),
+    }
+
+    let list: ListResponse = response.json().context("Failed to parse list response")?;
+    if list.files.is_empty() {
+        println!("No active uploads");
+        return Ok(());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut files = list.files;
+    files.sort_by_key(|file| std::cmp::Reverse(file.uploaded_at));
+
+    println!(
+        "{:<8} {:<6} {:>6} {:>10}  FILENAME",
+        "ID", "TYPE", "AGE", "DOWNLOADS"
+    );
+    for file in &files {
+        let kind = match file.content_type {
+            ContentType::Text => "text",
+            ContentType::File => "file",
+        };
+        let downloads = file
+            .downloads_remaining
+            .map_or_else(|| "unlimited".to_string(), |n| n.to_string());
+        println!(
+            "{:<8} {:<6} {:>6} {:>10}  {}",
+            file.id,
+            kind,
+            format_age(now.saturating_sub(file.uploaded_at)),
+            downloads,
+            file.filename.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+/// Coarse age such as `45s`, `12m` or `3h`
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_coarse_ages() {
+        assert_eq!(format_age(0), "0s");
+        assert_eq!(format_age(59), "59s");
+        assert_eq!(format_age(60), "1m");
+        assert_eq!(format_age(3 * 3600 + 59 * 60), "3h");
+        assert_eq!(format_age(2 * 86400), "2d");
+    }
+
+    #[test]
+    fn parses_server_records() {
+        let json = r#"{"files":[{"id":"081607","filename":"a.zip","content_type":"File",
+            "storage":{"Memory":"hi"},"uploaded_at":10,"expires_at":20,
+            "downloads_remaining":null}]}"#;
+        let list: ListResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(list.files[0].id, "081607");
+        assert_eq!(list.files[0].downloads_remaining, None);
+    }
+}
@@ -1,24 +1,33 @@
-use anyhow::Result;
-use clap::Subcommand;
+use anyhow::{Context, Result};
+use clap::{Subcommand, ValueEnum};
+use dialoguer::Password;
 use serde::Deserialize;
 use std::path::PathBuf;
 
 mod archive;
+mod delete;
 mod download;
+mod list;
+mod retry;
+mod throttle;
 mod upload;
 
 const DEFAULT_SERVER_URL: &str = "http://a.debin.cc:8080";
 
+/// Environment variable holding the encryption key, so it stays out of shell history
+const KEY_ENV: &str = "XTOOL_KEY";
+const TMP_DIR_ENV: &str = "XTOOL_TMPDIR";
+
 #[derive(Subcommand)]
 pub enum FileAction {
     /// Upload a file and return a token
     Send {
-        /// File or directory path to upload
+        /// File or directory path to upload (reads stdin when omitted and piped)
         #[arg(value_name = "PATH", conflicts_with_all = ["message"])]
         path: Option<PathBuf>,
 
         /// Download limit (1-10)
-        #[arg(short, long, default_value_t = 1)]
+        #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=10))]
         limit: u8,
 
         /// Send a message as a message file (no file upload)
@@ -29,9 +38,57 @@ pub enum FileAction {
         #[arg(short, long, default_value = DEFAULT_SERVER_URL)]
         server: String,
 
-        /// Encryption key for uploaded archives
+        /// Encryption key for uploaded archives (or set XTOOL_KEY)
         #[arg(short = 'k', long)]
         key: Option<String>,
+
+        /// Encrypt the upload, prompting for a key if none is given
+        #[arg(long, conflicts_with = "message")]
+        encrypt: bool,
+
+        /// Remote filename for data read from stdin (default: stdin.txt)
+        #[arg(long, conflicts_with_all = ["path", "message"])]
+        name: Option<String>,
+
+        /// How long the file stays available, e.g. 30m, 2h (server maximum: 24h)
+        #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+        expire: Option<std::time::Duration>,
+
+        /// Copy the resulting `xtool file get <id>` command to the clipboard
+        #[arg(long)]
+        copy: bool,
+
+        /// Also print the `xtool file get <id>` command as a QR code
+        #[arg(long)]
+        qr: bool,
+
+        /// Retries for transient network errors
+        #[arg(long, default_value_t = retry::DEFAULT_RETRIES)]
+        retries: u32,
+
+        /// Cap transfer speed, e.g. 500k or 2M (bytes per second)
+        #[arg(long, value_name = "SIZE/s", value_parser = throttle::parse_rate)]
+        limit_rate: Option<u64>,
+
+        /// Key derivation for encrypted uploads; stored in the archive header
+        #[arg(long, value_enum, default_value_t = KdfKind::Pbkdf2)]
+        kdf: KdfKind,
+
+        /// KDF iterations (default: 100000 for pbkdf2, 3 for argon2)
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        kdf_iters: Option<u32>,
+
+        /// Argon2 memory in MiB (default: 64)
+        #[arg(long, value_name = "MIB", value_parser = clap::value_parser!(u32).range(1..=1024))]
+        kdf_memory: Option<u32>,
+
+        /// Threads for compressing a directory (default: all cores above 16 MiB)
+        #[arg(short = 'j', long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: Option<u32>,
+
+        /// Directory for the temporary upload archive (or set XTOOL_TMPDIR)
+        #[arg(long, value_name = "PATH")]
+        tmp_dir: Option<PathBuf>,
     },
 
     /// Download a file by token
@@ -40,7 +97,7 @@ pub enum FileAction {
         #[arg(value_name = "TOKEN")]
         token: String,
 
-        /// Output file path (defaults to server filename in current directory)
+        /// Output file path (defaults to server filename in current directory; `-` for stdout)
         #[arg(short, long)]
         output: Option<PathBuf>,
 
@@ -48,9 +105,43 @@ pub enum FileAction {
         #[arg(short, long, default_value = DEFAULT_SERVER_URL)]
         server: String,
 
-        /// Decryption key for encrypted archives
+        /// Decryption key for encrypted archives (or set XTOOL_KEY; prompted on a terminal)
         #[arg(short = 'k', long)]
         key: Option<String>,
+
+        /// Retries for transient network errors
+        #[arg(long, default_value_t = retry::DEFAULT_RETRIES)]
+        retries: u32,
+
+        /// Cap transfer speed, e.g. 500k or 2M (bytes per second)
+        #[arg(long, value_name = "SIZE/s", value_parser = throttle::parse_rate)]
+        limit_rate: Option<u64>,
+
+        /// Directory for temporary files while unpacking (or set XTOOL_TMPDIR)
+        #[arg(long, value_name = "PATH")]
+        tmp_dir: Option<PathBuf>,
+    },
+
+    /// List uploads that are still available on the server
+    List {
+        /// Server URL (e.g. http://localhost:8080)
+        #[arg(short, long, default_value = DEFAULT_SERVER_URL)]
+        server: String,
+    },
+
+    /// Remove an upload from the server before it expires
+    Delete {
+        /// File token
+        #[arg(value_name = "TOKEN")]
+        token: String,
+
+        /// Server URL (e.g. http://localhost:8080)
+        #[arg(short, long, default_value = DEFAULT_SERVER_URL)]
+        server: String,
+
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
     },
 }
 
@@ -60,6 +151,8 @@ struct UploadResponse {
     id: String,
     filename: Option<String>,
     upload_token: Option<String>,
+    /// Server path to PUT the file to, for servers storing files themselves
+    upload_url: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone, Copy)]
@@ -84,18 +177,141 @@ pub fn run(action: FileAction) -> Result<()> {
             message,
             server,
             key,
-        } => upload::send_file(
-            &server,
-            path.as_deref(),
-            limit,
-            message.as_deref(),
-            key.as_deref(),
-        ),
+            encrypt,
+            name,
+            expire,
+            copy,
+            qr,
+            retries,
+            limit_rate,
+            kdf,
+            kdf_iters,
+            kdf_memory,
+            jobs,
+            tmp_dir,
+        } => {
+            let kdf = build_kdf(kdf, kdf_iters, kdf_memory)?;
+            let tmp_dir = tmp_dir_from_flag_or_env(tmp_dir)?;
+            let key = match key_from_flag_or_env(key) {
+                Some(key) => Some(key),
+                None if encrypt => Some(prompt_new_key()?),
+                None => None,
+            };
+            upload::send_file(upload::SendOptions {
+                server: &server,
+                path: path.as_deref(),
+                download_limit: limit,
+                message: message.as_deref(),
+                key: key.as_deref(),
+                name: name.as_deref(),
+                expire,
+                copy,
+                qr,
+                retries,
+                limit_rate,
+                kdf,
+                jobs: jobs.map(|n| n as usize),
+                tmp_dir: tmp_dir.as_deref(),
+            })
+        }
         FileAction::Get {
             token,
             output,
             server,
             key,
-        } => download::get_file(&server, &token, output.as_deref(), key.as_deref()),
+            retries,
+            limit_rate,
+            tmp_dir,
+        } => {
+            let key = key_from_flag_or_env(key);
+            let tmp_dir = tmp_dir_from_flag_or_env(tmp_dir)?;
+            download::get_file(
+                &server,
+                &token,
+                output.as_deref(),
+                key.as_deref(),
+                retries,
+                limit_rate,
+                tmp_dir.as_deref(),
+            )
+        }
+        FileAction::List { server } => list::list_files(&server),
+        FileAction::Delete { token, server, yes } => delete::delete_file(&server, &token, yes),
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KdfKind {
+    /// PBKDF2-HMAC-SHA256: fast, compatible with older xtool releases
+    Pbkdf2,
+    /// Argon2id: memory-hard, much stronger against GPU brute force
+    Argon2,
+}
+
+fn build_kdf(kind: KdfKind, iters: Option<u32>, memory_mib: Option<u32>) -> Result<archive::Kdf> {
+    match kind {
+        KdfKind::Pbkdf2 => {
+            if memory_mib.is_some() {
+                return Err(anyhow::anyhow!("--kdf-memory only applies to --kdf argon2"));
+            }
+            let iterations = iters.unwrap_or(archive::DEFAULT_PBKDF2_ITERS);
+            if !(archive::MIN_PBKDF2_ITERS..=archive::MAX_PBKDF2_ITERS).contains(&iterations) {
+                return Err(anyhow::anyhow!(
+                    "--kdf-iters must be between {} and {} for pbkdf2",
+                    archive::MIN_PBKDF2_ITERS,
+                    archive::MAX_PBKDF2_ITERS
+                ));
+            }
+            Ok(archive::Kdf::Pbkdf2 { iterations })
+        }
+        KdfKind::Argon2 => {
+            let iterations = iters.unwrap_or(archive::DEFAULT_ARGON2_ITERS);
+            if iterations > archive::MAX_ARGON2_ITERS {
+                return Err(anyhow::anyhow!(
+                    "--kdf-iters must be at most {} for argon2",
+                    archive::MAX_ARGON2_ITERS
+                ));
+            }
+            Ok(archive::Kdf::Argon2id {
+                memory_kib: memory_mib.map_or(archive::DEFAULT_ARGON2_MEMORY_KIB, |mib| mib * 1024),
+                iterations,
+                parallelism: archive::DEFAULT_ARGON2_PARALLELISM,
+            })
+        }
     }
 }
+
+/// `-k` wins over `XTOOL_KEY`.
+fn key_from_flag_or_env(flag: Option<String>) -> Option<String> {
+    flag.or_else(|| std::env::var(KEY_ENV).ok().filter(|k| !k.is_empty()))
+}
+
+/// `--tmp-dir`, else `XTOOL_TMPDIR`, checked up front so a bad directory is
+/// reported before any transfer starts. `None` means the system temp dir.
+fn tmp_dir_from_flag_or_env(flag: Option<PathBuf>) -> Result<Option<PathBuf>> {
+    let Some(dir) = flag.or_else(|| {
+        std::env::var_os(TMP_DIR_ENV)
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+    }) else {
+        return Ok(None);
+    };
+    if !dir.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Temp directory does not exist: {}",
+            dir.display()
+        ));
+    }
+    tempfile::tempfile_in(&dir)
+        .with_context(|| format!("Temp directory is not writable: {}", dir.display()))?;
+    Ok(Some(dir))
+}
+
+fn prompt_new_key() -> Result<String> {
+    let key = Password::new()
+        .with_prompt("Encryption key")
+        .with_confirmation("Confirm key", "Keys do not match")
+        .interact()
+        .context("Failed to read key")?;
+    Ok(key)
+}
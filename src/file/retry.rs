@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use log::warn;
+use reqwest::blocking::{RequestBuilder, Response};
+use std::{thread, time::Duration};
+
+/// Default number of retries after the first attempt
+pub const DEFAULT_RETRIES: u32 = 3;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Send a request, retrying transient failures with exponential backoff.
+///
+/// Connection errors are always retried since the server never saw the
+/// request. Timeouts and 5xx responses are only retried when `idempotent`
+/// is set, because the server may already have acted on the request.
+/// 4xx responses are returned to the caller as-is.
+pub fn send_with_retry(
+    build: impl Fn() -> RequestBuilder,
+    retries: u32,
+    idempotent: bool,
+    what: &str,
+) -> Result<Response> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let reason = match build().send() {
+            Ok(response) if response.status().is_server_error() && idempotent => {
+                format!("server returned {}", response.status())
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if e.is_connect() || (idempotent && e.is_timeout()) => e.to_string(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to {}", what)),
+        };
+
+        if attempt >= retries {
+            return Err(anyhow::anyhow!(
+                "Failed to {} after {} attempts: {}",
+                what,
+                attempt + 1,
+                reason
+            ));
+        }
+        attempt += 1;
+        warn!(
+            "Failed to {} ({}), retrying in {:.1}s ({}/{})",
+            what,
+            reason,
+            backoff.as_secs_f64(),
+            attempt,
+            retries
+        );
+        thread::sleep(backoff);
+        backoff *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serve the given status codes in order, one per request.
+    fn spawn_server(statuses: Vec<u16>) -> String {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || {
+            for status in statuses {
+                let request = server.recv().unwrap();
+                let _ = request.respond(tiny_http::Response::empty(status));
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn retries_server_errors_only_when_idempotent() {
+        let client = reqwest::blocking::Client::new();
+
+        let url = spawn_server(vec![503, 200]);
+        let response = send_with_retry(|| client.get(&url), 3, true, "fetch").unwrap();
+        assert_eq!(response.status(), 200);
+
+        let url = spawn_server(vec![503]);
+        let response = send_with_retry(|| client.get(&url), 3, false, "fetch").unwrap();
+        assert_eq!(response.status(), 503);
+
+        let url = spawn_server(vec![404]);
+        let response = send_with_retry(|| client.get(&url), 3, true, "fetch").unwrap();
+        assert_eq!(response.status(), 404);
+    }
+}
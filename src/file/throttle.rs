@@ -0,0 +1,100 @@
+use std::{
+    io::{self, Read},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Parse a `--limit-rate` value such as `500k`, `2M` or `500k/s` into bytes
+/// per second.
+pub fn parse_rate(input: &str) -> Result<u64, String> {
+    let size = input.trim();
+    let size = size.strip_suffix("/s").unwrap_or(size);
+    match crate::disk::utils::parse_size(size) {
+        Ok(0) => Err("rate must be greater than 0".to_string()),
+        Ok(rate) => Ok(rate),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Token bucket refilled at `rate` bytes per second, holding at most a
+/// quarter second's worth so an idle stream cannot burst far past the limit.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        let capacity = rate / 4.0;
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    /// Account for `bytes` just transferred, sleeping if they overdrew the bucket.
+    pub fn take(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.capacity) - bytes as f64;
+        self.last = now;
+        if self.tokens < 0.0 {
+            thread::sleep(Duration::from_secs_f64(-self.tokens / self.rate));
+        }
+    }
+}
+
+/// Reader that paces everything read through it with a [`TokenBucket`].
+#[derive(Debug)]
+pub struct Throttled<R> {
+    inner: R,
+    bucket: TokenBucket,
+}
+
+impl<R> Throttled<R> {
+    pub fn new(inner: R, rate: u64) -> Self {
+        Self {
+            inner,
+            bucket: TokenBucket::new(rate),
+        }
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bucket.take(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(parse_rate("500k").unwrap(), 500 * 1024);
+        assert_eq!(parse_rate("2M/s").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_rate("1000").unwrap(), 1000);
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn throttled_reader_holds_the_rate() {
+        let data = vec![0u8; 768 * 1024];
+        let mut reader = Throttled::new(&data[..], 1024 * 1024);
+        let started = Instant::now();
+        let copied = io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(copied, data.len() as u64);
+        // 768 KiB at 1 MiB/s, less the initial quarter-second burst
+        assert!(started.elapsed() >= Duration::from_millis(450));
+    }
+}
@@ -1,39 +1,71 @@
-use crate::file::archive::{compress_path, encrypt_zip_file, MAX_FILE_SIZE};
+use crate::file::archive::{
+    compress_file, compress_path, encrypt_zip_file, Kdf, MAX_FILE_SIZE,
+};
+use crate::file::retry::send_with_retry;
+use crate::file::throttle::Throttled;
 use crate::file::UploadResponse;
 use anyhow::{Context, Result};
-use log::info;
-use qiniu_sdk::upload::{AutoUploader, AutoUploaderObjectParams, UploadManager, UploadTokenSigner};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn};
+use qiniu_sdk::upload::{
+    AutoUploader, AutoUploaderObjectParams, UploadManager, UploadTokenSigner,
+    UploaderWithCallbacks,
+};
 use qiniu_upload_token::StaticUploadTokenProvider;
 use std::{
     fs,
+    io::{self, IsTerminal, Read},
     path::{Path, PathBuf},
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-    thread,
     time::{Duration, Instant},
 };
 
-pub fn send_file(
-    server: &str,
-    path: Option<&Path>,
-    download_limit: u8,
-    message: Option<&str>,
-    key: Option<&str>,
-) -> Result<()> {
-    let _ = download_limit;
-    let client = reqwest::blocking::Client::new();
-    let server = normalize_server(server);
+/// Remote filename used for stdin uploads without `--name`
+const DEFAULT_STDIN_NAME: &str = "stdin.txt";
 
-    if let Some(text) = message {
-        return send_message(&client, &server, text);
-    }
+/// Options for `xtool file send`
+pub struct SendOptions<'a> {
+    pub server: &'a str,
+    pub path: Option<&'a Path>,
+    pub download_limit: u8,
+    pub message: Option<&'a str>,
+    pub key: Option<&'a str>,
+    /// Remote filename for data piped on stdin
+    pub name: Option<&'a str>,
+    /// Requested lifetime; the server clamps it to its own maximum
+    pub expire: Option<Duration>,
+    /// Copy the `xtool file get` command to the clipboard
+    pub copy: bool,
+    /// Print the `xtool file get` command as a QR code
+    pub qr: bool,
+    /// Retries for transient network errors
+    pub retries: u32,
+    /// Upload speed cap in bytes per second
+    pub limit_rate: Option<u64>,
+    /// Key derivation for encrypted archives
+    pub kdf: Kdf,
+    /// Compression threads for directories; `None` decides by size
+    pub jobs: Option<usize>,
+    /// Where to build the upload archive; `None` uses the system temp directory
+    pub tmp_dir: Option<&'a Path>,
+}
+
+pub fn send_file(options: SendOptions<'_>) -> Result<()> {
+    let client = reqwest::blocking::Client::new();
+    let server = normalize_server(options.server);
 
-    send_archive(&client, &server, path, key)
+    let id = match options.message {
+        Some(text) => send_message(&client, &server, text, &options)?,
+        None => send_archive(&client, &server, &options)?,
+    };
+    print_get_command(&id, options.copy, options.qr)
 }
 
-fn send_message(client: &reqwest::blocking::Client, server: &str, text: &str) -> Result<()> {
+fn send_message(
+    client: &reqwest::blocking::Client,
+    server: &str,
+    text: &str,
+    options: &SendOptions<'_>,
+) -> Result<String> {
     let trimmed = text.trim();
     if trimmed.is_empty() {
         return Err(anyhow::anyhow!("Message cannot be empty"));
@@ -44,20 +76,24 @@ fn send_message(client: &reqwest::blocking::Client, server: &str, text: &str) ->
     }
 
     let url = format!("{}/upload", server);
-    let response = client
-        .post(&url)
-        .header("x-upload-type", "text")
-        .body(trimmed.to_string())
-        .send()
-        .context("Failed to send text upload request")?;
+    // Each text POST creates a new record, so only retry if it never reached the server
+    let response = send_with_retry(
+        || {
+            upload_headers(client.post(&url), options)
+                .header("x-upload-type", "text")
+                .body(trimmed.to_string())
+        },
+        options.retries,
+        false,
+        "send text upload request",
+    )?;
 
     if response.status().is_success() {
         let upload_resp: UploadResponse = response
             .json()
             .context("Failed to parse upload response")?;
         info!("Upload success: id={}", upload_resp.id);
-        println!("xtool file get {}", upload_resp.id);
-        return Ok(());
+        return Ok(upload_resp.id);
     }
 
     Err(anyhow::anyhow!("Upload text failed: {}", response.status()))
@@ -66,17 +102,26 @@ fn send_message(client: &reqwest::blocking::Client, server: &str, text: &str) ->
 fn send_archive(
     client: &reqwest::blocking::Client,
     server: &str,
-    path: Option<&Path>,
-    key: Option<&str>,
-) -> Result<()> {
-    let (file_path, filename, temp_path) = resolve_upload_target(path)?;
+    options: &SendOptions<'_>,
+) -> Result<String> {
+    let (file_path, filename, temp_path) = resolve_upload_target(options.path, options.name, options.jobs, options.tmp_dir)?;
     let result = (|| {
-        maybe_encrypt(&file_path, key)?;
-        let (upload_token, id) = request_file_upload(client, server, &filename)?;
-        upload_to_qiniu(&file_path, &filename, &upload_token)?;
+        maybe_encrypt(&file_path, options.key, options.kdf)?;
+        let (target, id) = request_file_upload(client, server, &filename, options)?;
+        match target {
+            UploadTarget::Qiniu(token) => {
+                upload_to_qiniu(&file_path, &filename, &token, options.limit_rate)?
+            }
+            UploadTarget::Server(path) => upload_to_server(
+                client,
+                &format!("{}{}", server, path),
+                &file_path,
+                &filename,
+                options.limit_rate,
+            )?,
+        }
         info!("Upload success: id={}, name={}", id, filename);
-        println!("xtool file get {}", id);
-        Ok(())
+        Ok(id)
     })();
 
     if let Some(path) = temp_path {
@@ -86,12 +131,56 @@ fn send_archive(
     result
 }
 
-fn maybe_encrypt(file_path: &Path, key: Option<&str>) -> Result<()> {
+/// Headers shared by text and file upload requests.
+fn upload_headers(
+    request: reqwest::blocking::RequestBuilder,
+    options: &SendOptions<'_>,
+) -> reqwest::blocking::RequestBuilder {
+    let request = request.header("x-download-limit", options.download_limit.to_string());
+    match options.expire {
+        Some(ttl) => request.header("x-expire-secs", ttl.as_secs().to_string()),
+        None => request,
+    }
+}
+
+/// Print the command the recipient runs, optionally as a QR code and/or
+/// copied to the clipboard.
+fn print_get_command(id: &str, copy: bool, qr: bool) -> Result<()> {
+    let command = format!("xtool file get {}", id);
+    if qr {
+        println!("{}", render_qr(&command)?);
+    }
+    println!("{}", command);
+    if copy {
+        // Clipboard access fails on headless machines; the command is printed anyway
+        match arboard::Clipboard::new().and_then(|mut c| c.set_text(command)) {
+            Ok(()) => info!("Copied to clipboard"),
+            Err(e) => warn!("Failed to copy to clipboard: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Render `text` as a QR code using Unicode half-blocks (two modules per
+/// character cell). Colors are inverted so it scans on dark terminals.
+fn render_qr(text: &str) -> Result<String> {
+    use qrcode::render::unicode::Dense1x2;
+
+    let code = qrcode::QrCode::new(text.as_bytes()).context("Failed to encode QR code")?;
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
+
+fn maybe_encrypt(file_path: &Path, key: Option<&str>, kdf: Kdf) -> Result<()> {
     let Some(key) = key else { return Ok(()); };
     if key.trim().is_empty() {
         return Err(anyhow::anyhow!("Encryption key cannot be empty"));
     }
-    let encrypted_size = encrypt_zip_file(file_path, key)?;
+    let encrypted_size = encrypt_zip_file(file_path, key, kdf)?;
     if encrypted_size > MAX_FILE_SIZE {
         return Err(anyhow::anyhow!(
             "Encrypted file exceeds {}MB limit",
@@ -101,18 +190,28 @@ fn maybe_encrypt(file_path: &Path, key: Option<&str>) -> Result<()> {
     Ok(())
 }
 
-fn resolve_upload_target(path: Option<&Path>) -> Result<(PathBuf, String, Option<PathBuf>)> {
-    let path = path.ok_or_else(|| {
-        anyhow::anyhow!("Please provide a file/dir path or -m <message>")
-    })?;
+fn resolve_upload_target(
+    path: Option<&Path>,
+    name: Option<&str>,
+    jobs: Option<usize>,
+    tmp_dir: Option<&Path>,
+) -> Result<(PathBuf, String, Option<PathBuf>)> {
+    let Some(path) = path else {
+        if io::stdin().is_terminal() {
+            return Err(anyhow::anyhow!(
+                "Please provide a file/dir path, -m <message>, or pipe data on stdin"
+            ));
+        }
+        return stdin_upload_target(name.unwrap_or(DEFAULT_STDIN_NAME), tmp_dir);
+    };
 
     if path.is_dir() {
-        eprintln!("Compressing directory: {}", path.display());
+        info!("Compressing directory: {}", path.display());
     } else {
-        eprintln!("Compressing file: {}", path.display());
+        info!("Compressing file: {}", path.display());
     }
 
-    let (zip_path, zip_name, size) = compress_path(path)?;
+    let (zip_path, zip_name, size) = compress_path(path, jobs, tmp_dir)?;
 
     if size > MAX_FILE_SIZE {
         let _ = fs::remove_file(&zip_path);
@@ -126,18 +225,68 @@ fn resolve_upload_target(path: Option<&Path>) -> Result<(PathBuf, String, Option
     Ok((zip_path.clone(), zip_name, Some(zip_path)))
 }
 
+/// Buffer stdin into a temporary file named `name` and compress it like a
+/// regular file upload.
+fn stdin_upload_target(
+    name: &str,
+    tmp_dir: Option<&Path>,
+) -> Result<(PathBuf, String, Option<PathBuf>)> {
+    let name = Path::new(name)
+        .file_name()
+        .context("Invalid --name for stdin upload")?;
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("xtool_stdin_");
+    let dir = match tmp_dir {
+        Some(tmp_dir) => builder.tempdir_in(tmp_dir),
+        None => builder.tempdir(),
+    }
+    .context("Failed to create temp directory")?;
+    let path = dir.path().join(name);
+
+    info!("Reading from stdin...");
+    let mut file = fs::File::create(&path).context("Failed to create temp file")?;
+    // Read one byte past the limit so oversized input is detected without
+    // buffering all of it
+    let copied = io::copy(&mut io::stdin().lock().take(MAX_FILE_SIZE + 1), &mut file)
+        .context("Failed to read stdin")?;
+    if copied > MAX_FILE_SIZE {
+        return Err(anyhow::anyhow!(
+            "Input exceeds {}MB limit",
+            MAX_FILE_SIZE / 1024 / 1024
+        ));
+    }
+    drop(file);
+
+    let (zip_path, zip_name, _size) = compress_file(&path, tmp_dir)?;
+    Ok((zip_path.clone(), zip_name, Some(zip_path)))
+}
+
+/// Where the server wants the file bytes sent
+enum UploadTarget {
+    /// Upload to Qiniu with this token
+    Qiniu(String),
+    /// PUT to this path on the transfer server
+    Server(String),
+}
+
 fn request_file_upload(
     client: &reqwest::blocking::Client,
     server: &str,
     filename: &str,
-) -> Result<(String, String)> {
+    options: &SendOptions<'_>,
+) -> Result<(UploadTarget, String)> {
     let url = format!("{}/upload", server);
-    let response = client
-        .post(&url)
-        .header("x-upload-type", "file")
-        .header("x-filename", filename)
-        .send()
-        .context("Failed to request upload token")?;
+    // Only issues an upload token; repeating it is harmless
+    let response = send_with_retry(
+        || {
+            upload_headers(client.post(&url), options)
+                .header("x-upload-type", "file")
+                .header("x-filename", filename)
+        },
+        options.retries,
+        true,
+        "request upload token",
+    )?;
 
     if !response.status().is_success() {
         return Err(anyhow::anyhow!(
@@ -149,24 +298,60 @@ fn request_file_upload(
     let upload_resp: UploadResponse = response
         .json()
         .context("Failed to parse upload response")?;
-    let token = upload_resp
-        .upload_token
-        .context("Missing upload token")?;
-    Ok((token, upload_resp.id))
+    let target = match (upload_resp.upload_token, upload_resp.upload_url) {
+        (Some(token), _) => UploadTarget::Qiniu(token),
+        (None, Some(path)) => UploadTarget::Server(path),
+        (None, None) => return Err(anyhow::anyhow!("Missing upload token")),
+    };
+    Ok((target, upload_resp.id))
 }
 
-fn upload_to_qiniu(file_path: &Path, filename: &str, token: &str) -> Result<()> {
-    let running = Arc::new(AtomicBool::new(true));
-    let timer_flag = Arc::clone(&running);
+fn upload_to_server(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    file_path: &Path,
+    filename: &str,
+    limit_rate: Option<u64>,
+) -> Result<()> {
     let start = Instant::now();
-    let timer_handle = thread::spawn(move || {
-        let mut seconds = 0u64;
-        while timer_flag.load(Ordering::Relaxed) {
-            thread::sleep(Duration::from_secs(1));
-            seconds += 1;
-            eprintln!("Uploading... elapsed {}s", seconds);
-        }
-    });
+    let file = fs::File::open(file_path).context("Failed to open upload file")?;
+    let total_size = file.metadata().map(|m| m.len()).ok();
+    let progress = upload_progress_bar(total_size, filename);
+
+    let reader: Box<dyn Read + Send> = match limit_rate {
+        Some(rate) => Box::new(Throttled::new(file, rate)),
+        None => Box::new(file),
+    };
+    // Each upload URL is single use, so this is not retried
+    let body = match total_size {
+        Some(size) => reqwest::blocking::Body::sized(progress.wrap_read(reader), size),
+        None => reqwest::blocking::Body::new(progress.wrap_read(reader)),
+    };
+    let result = client
+        .put(url)
+        .header("x-filename", filename)
+        .body(body)
+        .send()
+        .context("Failed to upload file");
+    progress.finish_and_clear();
+    let response = result?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Upload failed: {}", response.status()));
+    }
+
+    info!("Upload finished in {:.2}s", start.elapsed().as_secs_f64());
+    Ok(())
+}
+
+fn upload_to_qiniu(
+    file_path: &Path,
+    filename: &str,
+    token: &str,
+    limit_rate: Option<u64>,
+) -> Result<()> {
+    let start = Instant::now();
+    let total_size = fs::metadata(file_path).map(|m| m.len()).ok();
+    let progress = upload_progress_bar(total_size, filename);
 
     let token_provider: StaticUploadTokenProvider = token
         .parse()
@@ -175,22 +360,77 @@ fn upload_to_qiniu(file_path: &Path, filename: &str, token: &str) -> Result<()>
         token_provider,
     ))
     .build();
-    let uploader: AutoUploader = upload_manager.auto_uploader();
+    let mut uploader: AutoUploader = upload_manager.auto_uploader();
+    let pb = progress.clone();
+    uploader.on_upload_progress(move |info| {
+        if let Some(total) = info.total_bytes()
+            && pb.length() != Some(total)
+        {
+            pb.set_length(total);
+        }
+        pb.set_position(info.transferred_bytes());
+        Ok(())
+    });
 
     let params = AutoUploaderObjectParams::builder()
         .file_name(filename)
         .build();
 
-    uploader
-        .upload_path(file_path, params)
-        .context("Qiniu upload failed")?;
+    // The SDK reads the file itself; pacing needs it to go through our reader
+    let result = match limit_rate {
+        Some(rate) => {
+            let file = fs::File::open(file_path).context("Failed to open upload file")?;
+            uploader.upload_reader(Throttled::new(file, rate), params)
+        }
+        None => uploader.upload_path(file_path, params),
+    }
+    .context("Qiniu upload failed");
+    progress.finish_and_clear();
+    result?;
 
-    running.store(false, Ordering::Relaxed);
-    let _ = timer_handle.join();
-    eprintln!("Upload finished in {:.2}s", start.elapsed().as_secs_f64());
+    info!("Upload finished in {:.2}s", start.elapsed().as_secs_f64());
     Ok(())
 }
 
+fn upload_progress_bar(total_size: Option<u64>, filename: &str) -> ProgressBar {
+    let pb = match total_size {
+        Some(total) if total > 0 => {
+            let pb = ProgressBar::new(total);
+            let style = ProgressStyle::with_template(
+                "{msg} {spinner:.green} {bytes}/{total_bytes} ({percent}%) [{bar:40.cyan/blue}] {eta}",
+            )
+            .unwrap()
+            .progress_chars("=>-");
+            pb.set_style(style);
+            pb
+        }
+        _ => {
+            let pb = ProgressBar::new_spinner();
+            pb.set_style(
+                ProgressStyle::with_template("{msg} {spinner:.green} {bytes} uploaded").unwrap(),
+            );
+            pb.enable_steady_tick(Duration::from_millis(120));
+            pb
+        }
+    };
+    pb.set_message(filename.to_string());
+    pb
+}
+
 fn normalize_server(server: &str) -> String {
     server.trim_end_matches('/').to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_qr_with_half_blocks() {
+        let qr = render_qr("xtool file get 123456").unwrap();
+        let lines: Vec<&str> = qr.lines().collect();
+        // Version 2 (25 modules) + 4-module quiet zone each side, two rows per line
+        assert_eq!(lines.len(), (25usize + 8).div_ceil(2));
+        assert!(lines.iter().all(|l| l.chars().count() == 25 + 8));
+    }
+}
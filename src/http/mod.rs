@@ -1,26 +1,309 @@
 use anyhow::{anyhow, Result};
-use log::{error, info};
-use std::path::{Path, PathBuf};
-use tiny_http::{Header, Method, Response, Server, StatusCode};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use log::{error, info, warn};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::panic::AssertUnwindSafe;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use tiny_http::{Header, Method, Response, Server, SslConfig, StatusCode};
+use walkdir::WalkDir;
 
-pub fn run(port: u16, path: PathBuf) -> Result<()> {
+use crate::utils::{format_size, glob_match};
+
+pub struct HttpOptions {
+    /// Address to listen on; `0.0.0.0` for all interfaces
+    pub bind: IpAddr,
+    pub port: u16,
+    pub path: PathBuf,
+    /// `user:pass` required via HTTP Basic auth
+    pub auth: Option<String>,
+    /// Worker threads; defaults to the number of CPUs
+    pub threads: Option<usize>,
+    /// File to append Combined Log Format lines to
+    pub access_log: Option<PathBuf>,
+    /// PEM certificate chain; with `tls_key`, serve HTTPS
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key for `tls_cert`
+    pub tls_key: Option<PathBuf>,
+    /// Open the server URL in the default browser once listening
+    pub open: bool,
+    /// Accept `PUT` and multipart `POST` uploads into the served directory
+    pub allow_upload: bool,
+    /// File names served for directory requests, in order of preference
+    pub index: Vec<String>,
+    /// File under the root served with 200 in place of any 404
+    pub spa: Option<PathBuf>,
+    /// Globs for paths treated as missing: left out of listings and zips, 404 when requested
+    pub hide: Vec<String>,
+    /// Serve dotfiles instead of hiding them
+    pub show_hidden: bool,
+}
+
+struct ServeConfig {
+    /// Canonical directory to serve, or the one file served in single-file mode.
+    root: PathBuf,
+    /// The root is a regular file, answered at `/` and `/<name>` only.
+    single_file: bool,
+    /// Expected `user:pass` for HTTP Basic auth; `None` leaves the server open.
+    auth: Option<String>,
+    access_log: Option<Mutex<std::fs::File>>,
+    /// Accept uploads; the server is read-only otherwise.
+    allow_upload: bool,
+    /// Index file names probed for directory requests, in order.
+    index: Vec<String>,
+    /// Canonical path of the single-page app entrypoint served for misses.
+    spa: Option<PathBuf>,
+    /// Paths served as if they did not exist.
+    hidden: HiddenPaths,
+}
+
+/// Paths under the root that are never listed or served.
+#[derive(Clone)]
+struct HiddenPaths {
+    /// `--hide` patterns split into components, matched like `disk` globs
+    globs: Vec<Vec<String>>,
+    dotfiles: bool,
+}
+
+impl HiddenPaths {
+    fn new(patterns: &[String], show_hidden: bool) -> Result<Self> {
+        let globs = patterns
+            .iter()
+            .map(|pattern| {
+                let parts: Vec<String> = pattern
+                    .split('/')
+                    .filter(|part| !part.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if parts.is_empty() {
+                    return Err(anyhow!("Invalid --hide pattern {:?}: empty", pattern));
+                }
+                Ok(parts)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            globs,
+            dotfiles: !show_hidden,
+        })
+    }
+
+    /// Whether `relative` (to the root) or a directory above it is hidden.
+    /// Patterns are matched against both the path from the root and each
+    /// name on it, so `--hide secret` hides every `secret` at any depth.
+    fn hides(&self, relative: &Path) -> bool {
+        let mut prefix = Vec::new();
+        for component in relative.components() {
+            let Component::Normal(name) = component else {
+                continue;
+            };
+            let name = name.to_string_lossy();
+            if self.dotfiles && name.starts_with('.') {
+                return true;
+            }
+            prefix.push(name);
+            let matched = self.globs.iter().any(|glob| match glob.as_slice() {
+                [single] => glob_match(single, prefix.last().unwrap()),
+                parts => {
+                    parts.len() == prefix.len()
+                        && parts.iter().zip(&prefix).all(|(part, name)| glob_match(part, name))
+                }
+            });
+            if matched {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// [`Self::hides`] for a path under `root`.
+    fn hides_under(&self, root: &Path, path: &Path) -> bool {
+        path.strip_prefix(root).is_ok_and(|relative| self.hides(relative))
+    }
+}
+
+/// Status and body size of a response, for access logging.
+struct Sent {
+    status: u16,
+    bytes: Option<usize>,
+}
+
+pub fn run(options: HttpOptions) -> Result<()> {
+    let HttpOptions {
+        bind,
+        port,
+        path,
+        auth,
+        threads,
+        access_log,
+        tls_cert,
+        tls_key,
+        open,
+        allow_upload,
+        index,
+        spa,
+        hide,
+        show_hidden,
+    } = options;
     let root = resolve_root(path)?;
+    let single_file = root.is_file();
+    if single_file && (allow_upload || spa.is_some()) {
+        return Err(anyhow!("--allow-upload and --spa need a directory to serve"));
+    }
+    if let Some(auth) = &auth
+        && !auth.contains(':')
+    {
+        return Err(anyhow!("--auth must be in the form user:pass"));
+    }
+    let access_log = access_log
+        .map(|path| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map(Mutex::new)
+                .map_err(|e| anyhow!("Failed to open access log {}: {}", path.display(), e))
+        })
+        .transpose()?;
+
+    if let Some(name) = index.iter().find(|name| !is_plain_file_name(name)) {
+        return Err(anyhow!("--index must be a file name, got {:?}", name));
+    }
+    let spa = spa
+        .map(|spa| {
+            root.join(&spa)
+                .canonicalize()
+                .ok()
+                .filter(|path| path.starts_with(&root) && path.is_file())
+                .ok_or_else(|| anyhow!("--spa must name a file under the root: {}", spa.display()))
+        })
+        .transpose()?;
+    let hidden = HiddenPaths::new(&hide, show_hidden)?;
+
+    let tls = match (tls_cert, tls_key) {
+        (Some(cert), Some(key)) => Some(load_tls_config(&cert, &key)?),
+        (None, None) => None,
+        _ => return Err(anyhow!("--tls-cert and --tls-key must be given together")),
+    };
+
+    let addr = SocketAddr::new(bind, port);
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let server = match tls {
+        Some(tls) => Server::https(addr, tls),
+        None => Server::http(addr),
+    }
+    .map_err(|e| anyhow!("Failed to bind {}: {}", addr, e))?;
+
+    info!("HTTP server listening on {}://{}", scheme, addr);
+    if single_file {
+        info!("Serving file: {}", root.display());
+    } else {
+        info!("Serving directory: {}", root.display());
+    }
+    if auth.is_some() {
+        info!("Basic authentication enabled");
+    }
+    if allow_upload {
+        info!("Uploads enabled");
+    }
 
-    let addr = format!("0.0.0.0:{}", port);
-    let server = Server::http(&addr).map_err(|e| anyhow!("Failed to bind {}: {}", addr, e))?;
+    let threads = threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()))
+        .max(1);
+    info!("Using {} worker threads", threads);
 
-    info!("HTTP server listening on http://{}", addr);
-    info!("Serving directory: {}", root.display());
+    if open && let Some(addr) = server.server_addr().to_ip() {
+        open_browser(&reachable_url(scheme, addr));
+    }
+
+    let config = Arc::new(ServeConfig {
+        root,
+        single_file,
+        auth,
+        access_log,
+        allow_upload,
+        index,
+        spa,
+        hidden,
+    });
+    let (tx, rx) = mpsc::channel::<tiny_http::Request>();
+    let rx = Arc::new(Mutex::new(rx));
+    for _ in 0..threads {
+        let rx = Arc::clone(&rx);
+        let config = Arc::clone(&config);
+        std::thread::spawn(move || worker(&rx, &config));
+    }
 
     for request in server.incoming_requests() {
-        if let Err(err) = handle_request(request, &root) {
-            error!("Request handling error: {}", err);
+        if tx.send(request).is_err() {
+            break;
         }
     }
 
     Ok(())
 }
 
+fn worker(rx: &Mutex<mpsc::Receiver<tiny_http::Request>>, config: &ServeConfig) {
+    loop {
+        let request = match rx.lock() {
+            Ok(rx) => rx.recv(),
+            Err(_) => return,
+        };
+        let Ok(request) = request else {
+            return;
+        };
+
+        // A panic while serving one request must not take the worker down.
+        match std::panic::catch_unwind(AssertUnwindSafe(|| handle_request(request, config))) {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("Request handling error: {}", err),
+            Err(_) => error!("Request handler panicked"),
+        }
+    }
+}
+
+/// URL a local browser can use to reach a server bound to `addr`.
+fn reachable_url(scheme: &str, addr: SocketAddr) -> String {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    format!("{}://{}/", scheme, SocketAddr::new(ip, addr.port()))
+}
+
+/// Launch the platform's default browser; failure only costs a warning.
+fn open_browser(url: &str) {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command
+        .arg(url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    match command.spawn() {
+        Ok(_) => info!("Opening {} in the browser", url),
+        Err(e) => warn!("Failed to open browser for {}: {}", url, e),
+    }
+}
+
+fn load_tls_config(cert: &Path, key: &Path) -> Result<SslConfig> {
+    let read = |path: &Path| {
+        std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))
+    };
+    Ok(SslConfig {
+        certificate: read(cert)?,
+        private_key: read(key)?,
+    })
+}
+
 fn resolve_root(path: PathBuf) -> Result<PathBuf> {
     let root = if path.as_os_str().is_empty() {
         PathBuf::from(".")
@@ -36,56 +319,358 @@ fn resolve_root(path: PathBuf) -> Result<PathBuf> {
     Ok(canonical)
 }
 
-fn handle_request(request: tiny_http::Request, root: &Path) -> Result<()> {
-    if request.method() != &Method::Get {
+fn handle_request(request: tiny_http::Request, config: &ServeConfig) -> Result<()> {
+    let remote = request
+        .remote_addr()
+        .map_or_else(|| "-".to_string(), |addr| addr.ip().to_string());
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let combined = config.access_log.as_ref().map(|_| CombinedFields::new(&request));
+
+    let sent = serve(request, config).map_err(|err| anyhow!("{} {} {}: {}", remote, method, url, err))?;
+    let bytes = sent.bytes.map_or_else(|| "-".to_string(), |n| n.to_string());
+    info!("{} {} {} {} {}", remote, method, url, sent.status, bytes);
+
+    if let (Some(log), Some(fields)) = (&config.access_log, combined) {
+        let line = format!(
+            "{} - {} [{}] \"{} {} HTTP/{}\" {} {} \"{}\" \"{}\"\n",
+            remote,
+            fields.user,
+            chrono::Local::now().format("%d/%b/%Y:%H:%M:%S %z"),
+            method,
+            url,
+            fields.version,
+            sent.status,
+            bytes,
+            fields.referer,
+            fields.user_agent
+        );
+        if let Ok(mut file) = log.lock()
+            && let Err(err) = file.write_all(line.as_bytes())
+        {
+            error!("Failed to write access log: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Request fields only needed for Combined Log Format lines.
+struct CombinedFields {
+    user: String,
+    version: String,
+    referer: String,
+    user_agent: String,
+}
+
+impl CombinedFields {
+    fn new(request: &tiny_http::Request) -> Self {
+        let user = header_value(request, "Authorization")
+            .and_then(|value| value.trim().strip_prefix("Basic "))
+            .and_then(|encoded| BASE64_STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| {
+                let decoded = String::from_utf8_lossy(&decoded).into_owned();
+                decoded.split_once(':').map(|(user, _)| user.to_string())
+            })
+            .filter(|user| !user.is_empty())
+            .unwrap_or_else(|| "-".to_string());
+        let version = request.http_version();
+        Self {
+            user,
+            version: format!("{}.{}", version.0, version.1),
+            referer: header_value(request, "Referer").unwrap_or("-").replace('"', "\\\""),
+            user_agent: header_value(request, "User-Agent")
+                .unwrap_or("-")
+                .replace('"', "\\\""),
+        }
+    }
+}
+
+fn respond<R: Read>(request: tiny_http::Request, response: Response<R>) -> Result<Sent> {
+    let status = response.status_code().0;
+    let bytes = if request.method() == &Method::Head {
+        Some(0)
+    } else {
+        response.data_length()
+    };
+    request.respond(response)?;
+    Ok(Sent { status, bytes })
+}
+
+fn serve(request: tiny_http::Request, config: &ServeConfig) -> Result<Sent> {
+    if let Some(expected) = &config.auth
+        && !is_authorized(&request, expected)
+    {
+        let mut response = Response::empty(StatusCode(401));
+        add_header(&mut response, "WWW-Authenticate", "Basic realm=\"xtool\"")?;
+        return respond(request, response);
+    }
+
+    let root = config.root.as_path();
+    if config.allow_upload && matches!(request.method(), Method::Put | Method::Post) {
+        return receive_upload(request, root, &config.hidden);
+    }
+
+    // HEAD goes through the same path as GET; tiny_http keeps the headers
+    // (including Content-Length) and drops the body for HEAD requests.
+    if !matches!(request.method(), Method::Get | Method::Head) {
         let response = Response::empty(StatusCode(405));
-        request.respond(response)?;
-        return Ok(());
+        return respond(request, response);
     }
 
     let url_path = request.url();
-    let target_path = match resolve_target_path(root, url_path) {
+    if config.single_file {
+        let target_path = single_file_target(root, url_path);
+        return serve_file(request, target_path);
+    }
+
+    if wants_zip(url_path)
+        && let Some(dir) = resolve_request_path(root, url_path, &config.hidden).filter(|p| p.is_dir())
+    {
+        return serve_zip(request, root, &dir, &config.hidden);
+    }
+
+    let target_path = match resolve_target_path(root, url_path, &config.index, &config.hidden)
+        .filter(|path| path.exists())
+        .or_else(|| config.spa.clone())
+    {
         Some(path) => path,
         None => {
             let response = Response::empty(StatusCode(404));
-            request.respond(response)?;
-            return Ok(());
+            return respond(request, response);
         }
     };
 
-    if !target_path.exists() {
-        let response = Response::empty(StatusCode(404));
-        request.respond(response)?;
-        return Ok(());
-    }
-
     if target_path.is_dir() {
-        let listing = build_directory_listing(root, &target_path, url_path)?;
+        let listing = build_directory_listing(root, &target_path, url_path, &config.hidden)?;
         let mut response = Response::from_string(listing);
         let header = Header::from_bytes("Content-Type", "text/html; charset=utf-8")
             .map_err(|_| anyhow!("Invalid Content-Type header value"))?;
         response.add_header(header);
-        request.respond(response)?;
-        return Ok(());
+        return respond(request, response);
     }
 
-    let file = std::fs::File::open(&target_path)?;
-    let mut response = Response::from_file(file);
+    serve_file(request, Some(target_path))
+}
 
-    if let Some(mime) = mime_guess::from_path(&target_path).first() {
-        let header = Header::from_bytes("Content-Type", mime.as_ref())
-            .map_err(|_| anyhow!("Invalid Content-Type header value"))?;
-        response.add_header(header);
+/// Send the file at `target_path` (honouring conditional and `Range`
+/// requests), or `404` when there is none.
+fn serve_file(request: tiny_http::Request, target_path: Option<PathBuf>) -> Result<Sent> {
+    let Some(target_path) = target_path else {
+        let response = Response::empty(StatusCode(404));
+        return respond(request, response);
+    };
+
+    let mut file = std::fs::File::open(&target_path)?;
+    let metadata = file.metadata()?;
+    let file_len = metadata.len();
+    let validators = Validators::new(file_len, metadata.modified().ok());
+    if validators.not_modified(&request) {
+        let mut response = Response::empty(StatusCode(304));
+        validators.add_headers(&mut response)?;
+        return respond(request, response);
     }
 
-    request.respond(response)?;
+    let range = header_value(&request, "Range")
+        .map(|value| parse_range(value, file_len))
+        .unwrap_or(ByteRange::Full);
+
+    match range {
+        ByteRange::Full => {
+            let mut response = Response::from_file(file);
+            add_file_headers(&mut response, &target_path)?;
+            validators.add_headers(&mut response)?;
+            respond(request, response)
+        }
+        ByteRange::Partial(start, end) => {
+            let len = end - start + 1;
+            file.seek(SeekFrom::Start(start))?;
+            let mut response =
+                Response::new(StatusCode(206), Vec::new(), file.take(len), Some(len as usize), None);
+            add_file_headers(&mut response, &target_path)?;
+            validators.add_headers(&mut response)?;
+            add_header(
+                &mut response,
+                "Content-Range",
+                &format!("bytes {start}-{end}/{file_len}"),
+            )?;
+            respond(request, response)
+        }
+        ByteRange::Unsatisfiable => {
+            let mut response = Response::empty(StatusCode(416));
+            add_header(&mut response, "Content-Range", &format!("bytes */{file_len}"))?;
+            respond(request, response)
+        }
+    }
+}
+
+fn is_authorized(request: &tiny_http::Request, expected: &str) -> bool {
+    let Some(header) = header_value(request, "Authorization") else {
+        return false;
+    };
+    let Some(encoded) = header.trim().strip_prefix("Basic ") else {
+        return false;
+    };
+    match BASE64_STANDARD.decode(encoded.trim()) {
+        Ok(decoded) => constant_time_eq(&decoded, expected.as_bytes()),
+        Err(_) => false,
+    }
+}
+
+/// Compare without short-circuiting so timing doesn't reveal the matching prefix.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = a.len() ^ b.len();
+    for i in 0..a.len().max(b.len()) {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        diff |= usize::from(x ^ y);
+    }
+    diff == 0
+}
+
+/// Cache validators for a file: a weak ETag built from size and mtime, and
+/// the mtime itself for `Last-Modified`.
+struct Validators {
+    etag: String,
+    modified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Validators {
+    fn new(len: u64, modified: Option<std::time::SystemTime>) -> Self {
+        let modified = modified.map(chrono::DateTime::<chrono::Utc>::from);
+        let secs = modified.map_or(0, |m| m.timestamp());
+        Self {
+            etag: format!("W/\"{:x}-{:x}\"", len, secs),
+            modified,
+        }
+    }
+
+    /// `If-None-Match` takes precedence; `If-Modified-Since` is only
+    /// consulted when the client sent no ETag (RFC 9110 section 13.2.2).
+    fn not_modified(&self, request: &tiny_http::Request) -> bool {
+        if let Some(tags) = header_value(request, "If-None-Match") {
+            let ours = self.etag.trim_start_matches("W/");
+            return tags
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == ours);
+        }
+
+        match (header_value(request, "If-Modified-Since"), self.modified) {
+            (Some(since), Some(modified)) => chrono::DateTime::parse_from_rfc2822(since)
+                .is_ok_and(|since| modified.timestamp() <= since.timestamp()),
+            _ => false,
+        }
+    }
+
+    fn add_headers<R: Read>(&self, response: &mut Response<R>) -> Result<()> {
+        add_header(response, "ETag", &self.etag)?;
+        if let Some(modified) = self.modified {
+            let value = modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            add_header(response, "Last-Modified", &value)?;
+        }
+        Ok(())
+    }
+}
+
+fn header_value<'a>(request: &'a tiny_http::Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Full,
+    /// Inclusive start and end offsets.
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parse a single `bytes=` range. Malformed or multi-range headers fall back
+/// to serving the whole file, which RFC 9110 permits.
+fn parse_range(value: &str, len: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return ByteRange::Full,
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(n) => (len.saturating_sub(n), len.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, len.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            _ => return ByteRange::Full,
+        },
+    };
+
+    if len == 0 || start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start, end)
+}
+
+fn add_file_headers<R: Read>(response: &mut Response<R>, path: &Path) -> Result<()> {
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        add_header(response, "Content-Type", mime.as_ref())?;
+    }
+    add_header(response, "Accept-Ranges", "bytes")
+}
+
+fn add_header<R: Read>(response: &mut Response<R>, name: &str, value: &str) -> Result<()> {
+    let header = Header::from_bytes(name, value)
+        .map_err(|_| anyhow!("Invalid {} header value", name))?;
+    response.add_header(header);
     Ok(())
 }
 
-fn resolve_target_path(root: &Path, url: &str) -> Option<PathBuf> {
+fn resolve_target_path(
+    root: &Path,
+    url: &str,
+    index: &[String],
+    hidden: &HiddenPaths,
+) -> Option<PathBuf> {
+    let canonical = resolve_request_path(root, url, hidden)?;
+    if canonical.is_dir()
+        && let Some(index) = index
+            .iter()
+            .map(|name| canonical.join(name))
+            .find(|path| path.is_file() && !hidden.hides_under(root, path))
+    {
+        return Some(index);
+    }
+
+    Some(canonical)
+}
+
+fn is_plain_file_name(name: &str) -> bool {
+    !name.is_empty() && Path::new(name).file_name() == Some(std::ffi::OsStr::new(name))
+}
+
+/// Map a URL onto a canonical path under `root`, rejecting anything that
+/// escapes it (`..`, symlinks pointing outside) or is hidden, whether by
+/// the requested name or by where a symlink leads.
+fn resolve_request_path(root: &Path, url: &str, hidden: &HiddenPaths) -> Option<PathBuf> {
     let path_part = url.split('?').next().unwrap_or("");
     let trimmed = path_part.trim_start_matches('/');
     let decoded = urlencoding::decode(trimmed).ok()?.into_owned();
+    if hidden.hides(Path::new(&decoded)) {
+        return None;
+    }
 
     let joined = if decoded.is_empty() {
         root.to_path_buf()
@@ -94,23 +679,390 @@ fn resolve_target_path(root: &Path, url: &str) -> Option<PathBuf> {
     };
 
     let canonical = joined.canonicalize().ok()?;
-    if !canonical.starts_with(root) {
+    if !canonical.starts_with(root) || hidden.hides_under(root, &canonical) {
+        return None;
+    }
+    Some(canonical)
+}
+
+/// In single-file mode only `/` and `/<file name>` lead to the file.
+fn single_file_target(file: &Path, url: &str) -> Option<PathBuf> {
+    let path_part = url.split('?').next().unwrap_or("");
+    let decoded = urlencoding::decode(path_part.trim_start_matches('/')).ok()?;
+    let name = file.file_name()?.to_str()?;
+    (decoded.is_empty() || decoded == name).then(|| file.to_path_buf())
+}
+
+fn wants_zip(url: &str) -> bool {
+    has_query(url, "download=zip")
+}
+
+fn has_query(url: &str, pair: &str) -> bool {
+    url.split_once('?')
+        .is_some_and(|(_, query)| query.split('&').any(|kv| kv == pair))
+}
+
+/// Store a `PUT` body at its URL, or the files of a `multipart/form-data`
+/// `POST` in the directory it targets. Existing files are only replaced
+/// when the query has `overwrite=1`.
+fn receive_upload(
+    mut request: tiny_http::Request,
+    root: &Path,
+    hidden: &HiddenPaths,
+) -> Result<Sent> {
+    let url = request.url().to_string();
+    let overwrite = has_query(&url, "overwrite=1");
+    let status = if request.method() == &Method::Put {
+        match resolve_upload_path(root, &url, hidden) {
+            Some(path) if path.is_dir() => 409,
+            Some(path) => store_upload(request.as_reader(), &path, overwrite)?,
+            None => 404,
+        }
+    } else {
+        let boundary = header_value(&request, "Content-Type").and_then(multipart_boundary);
+        let dir = resolve_request_path(root, &url, hidden).filter(|p| p.is_dir());
+        match (boundary, dir) {
+            (None, _) => 400,
+            (_, None) => 404,
+            (Some(boundary), Some(dir)) => {
+                store_multipart(request.as_reader(), &boundary, &dir, overwrite, hidden)?
+            }
+        }
+    };
+    respond(request, Response::empty(StatusCode(status)))
+}
+
+/// Where an upload to `url` goes. The parent directory must already exist
+/// under `root`, and an existing entry (e.g. a symlink) must not lead out of it.
+/// Hidden paths cannot be written either.
+fn resolve_upload_path(root: &Path, url: &str, hidden: &HiddenPaths) -> Option<PathBuf> {
+    let path_part = url.split('?').next().unwrap_or("");
+    let decoded = urlencoding::decode(path_part.trim_start_matches('/')).ok()?.into_owned();
+    let relative = Path::new(&decoded);
+    if hidden.hides(relative) {
+        return None;
+    }
+    let name = relative.file_name()?;
+    let parent = resolve_request_path(root, relative.parent()?.to_str()?, hidden)?;
+    if !parent.is_dir() {
         return None;
     }
+    let target = parent.join(name);
+    if target.symlink_metadata().is_ok() {
+        let real = target.canonicalize().ok()?;
+        if !real.starts_with(root) || hidden.hides_under(root, &real) {
+            return None;
+        }
+    }
+    Some(target)
+}
+
+/// Write `reader` to `path` through a temporary file in the same directory,
+/// so a failed upload never leaves a truncated file behind. Returns the
+/// response status.
+fn store_upload(mut reader: impl Read, path: &Path, overwrite: bool) -> Result<u16> {
+    if path.exists() && !overwrite {
+        return Ok(409);
+    }
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("Upload path has no parent: {}", path.display()))?;
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    std::io::copy(&mut reader, &mut temp)?;
+    persist_upload(temp, path, overwrite)
+}
+
+fn persist_upload(temp: tempfile::NamedTempFile, path: &Path, overwrite: bool) -> Result<u16> {
+    let stored = if overwrite {
+        temp.persist(path)
+    } else {
+        // Another upload may have created it meanwhile
+        temp.persist_noclobber(path)
+    };
+    match stored {
+        Ok(_) => Ok(201),
+        Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => Ok(409),
+        Err(e) => Err(e.error.into()),
+    }
+}
+
+/// Store the file parts of a `multipart/form-data` body in `dir`. Parts are
+/// streamed to temporary files as they arrive and only moved into place once
+/// the whole body has been read, so memory use does not grow with the upload.
+fn store_multipart(
+    body: impl Read,
+    boundary: &str,
+    dir: &Path,
+    overwrite: bool,
+    hidden: &HiddenPaths,
+) -> Result<u16> {
+    let mut body = MultipartReader::new(body, boundary);
+    let mut files = Vec::new();
+    if !body.skip_preamble()? {
+        return Ok(400);
+    }
+    while body.next_part()? {
+        let Some(headers) = body.part_headers()? else {
+            return Ok(400);
+        };
+        let Some(name) = headers.lines().find_map(disposition_filename) else {
+            if !body.copy_part(&mut std::io::sink())? {
+                return Ok(400);
+            }
+            continue;
+        };
+        if hidden.hides(Path::new(&name)) {
+            return Ok(403);
+        }
+        let path = dir.join(&name);
+        if !overwrite && path.exists() {
+            return Ok(409);
+        }
+        let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+        if !body.copy_part(&mut temp)? {
+            return Ok(400);
+        }
+        files.push((path, temp));
+    }
+    if files.is_empty() {
+        return Ok(400);
+    }
+    for (path, temp) in files {
+        let status = persist_upload(temp, &path, overwrite)?;
+        if status != 201 {
+            return Ok(status);
+        }
+    }
+    Ok(201)
+}
+
+/// Longest header block accepted for one multipart part
+const MAX_PART_HEADERS: usize = 16 * 1024;
+
+/// Incremental reader over a `multipart/form-data` body. Only a buffer of
+/// about one read plus one delimiter is held in memory.
+struct MultipartReader<R> {
+    reader: R,
+    /// `\r\n--<boundary>`, the delimiter that ends each part
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> MultipartReader<R> {
+    fn new(reader: R, boundary: &str) -> Self {
+        Self {
+            reader,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            buf: Vec::new(),
+            eof: false,
+        }
+    }
 
-    if canonical.is_dir() {
-        let index = canonical.join("index.html");
-        if index.exists() {
-            return Some(index);
+    /// Read more of the body into `buf`; false once the body is exhausted.
+    fn fill(&mut self) -> Result<bool> {
+        if self.eof {
+            return Ok(false);
         }
+        let mut chunk = [0u8; 64 * 1024];
+        let read = self.reader.read(&mut chunk)?;
+        self.buf.extend_from_slice(&chunk[..read]);
+        self.eof = read == 0;
+        Ok(read > 0)
     }
 
-    Some(canonical)
+    /// Make sure at least `len` bytes are buffered, unless the body ends first.
+    fn want(&mut self, len: usize) -> Result<bool> {
+        while self.buf.len() < len {
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Consume everything up to and including the first boundary, which
+    /// has no leading line break.
+    fn skip_preamble(&mut self) -> Result<bool> {
+        let first = self.delimiter[2..].to_vec();
+        if !self.want(first.len())? || !self.buf.starts_with(&first) {
+            return Ok(false);
+        }
+        self.buf.drain(..first.len());
+        Ok(true)
+    }
+
+    /// After a boundary: true if a part follows, false at the closing `--`.
+    fn next_part(&mut self) -> Result<bool> {
+        if !self.want(2)? {
+            return Ok(false);
+        }
+        let more = self.buf.starts_with(b"\r\n");
+        if more {
+            self.buf.drain(..2);
+        }
+        Ok(more)
+    }
+
+    /// The header block of the current part, or `None` if it is malformed.
+    fn part_headers(&mut self) -> Result<Option<String>> {
+        loop {
+            if let Some(end) = find_bytes(&self.buf, b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+                self.buf.drain(..end + 4);
+                return Ok(Some(headers));
+            }
+            if self.buf.len() > MAX_PART_HEADERS || !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Copy the current part's contents to `out`, consuming the delimiter
+    /// after it. False if the body ends before the delimiter.
+    fn copy_part(&mut self, out: &mut impl Write) -> Result<bool> {
+        loop {
+            if let Some(end) = find_bytes(&self.buf, &self.delimiter) {
+                out.write_all(&self.buf[..end])?;
+                self.buf.drain(..end + self.delimiter.len());
+                return Ok(true);
+            }
+            // Keep a tail that could be the start of a split delimiter
+            let keep = self.delimiter.len() - 1;
+            if self.buf.len() > keep {
+                let flush = self.buf.len() - keep;
+                out.write_all(&self.buf[..flush])?;
+                self.buf.drain(..flush);
+            }
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Base name from a `Content-Disposition` header line's `filename="..."`.
+fn disposition_filename(line: &str) -> Option<String> {
+    let (field, value) = line.split_once(':')?;
+    if !field.trim().eq_ignore_ascii_case("content-disposition") {
+        return None;
+    }
+    let start = value.find("filename=\"")? + "filename=\"".len();
+    let name = &value[start..];
+    let name = &name[..name.find('"')?];
+    // Some browsers send the client-side path
+    let name = name.rsplit(['/', '\\']).next()?;
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Stream `dir` as a zip archive. The archive is produced on a separate thread
+/// into a pipe so large trees never have to be buffered in memory or on disk.
+fn serve_zip(
+    request: tiny_http::Request,
+    root: &Path,
+    dir: &Path,
+    hidden: &HiddenPaths,
+) -> Result<Sent> {
+    let name = dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("archive")
+        .replace('"', "_");
+    let disposition = format!("attachment; filename=\"{}.zip\"", name);
+
+    if request.method() == &Method::Head {
+        let mut response = Response::empty(StatusCode(200));
+        add_header(&mut response, "Content-Type", "application/zip")?;
+        add_header(&mut response, "Content-Disposition", &disposition)?;
+        return respond(request, response);
+    }
+
+    let (reader, writer) = std::io::pipe()?;
+    let root = root.to_path_buf();
+    let base = dir.to_path_buf();
+    let hidden = hidden.clone();
+    std::thread::spawn(move || {
+        if let Err(err) = write_zip(writer, &root, &base, &hidden) {
+            error!("Zip stream for {} failed: {}", base.display(), err);
+        }
+    });
+
+    let mut response = Response::new(StatusCode(200), Vec::new(), reader, None, None);
+    add_header(&mut response, "Content-Type", "application/zip")?;
+    add_header(&mut response, "Content-Disposition", &disposition)?;
+    respond(request, response)
+}
+
+fn write_zip<W: Write>(out: W, root: &Path, base: &Path, hidden: &HiddenPaths) -> Result<()> {
+    let mut writer = zip::ZipWriter::new_stream(out);
+    let options = zip::write::FileOptions::<()>::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(0o644);
+
+    let entries = WalkDir::new(base)
+        .into_iter()
+        .filter_entry(|entry| !hidden.hides_under(root, entry.path()));
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let name = path
+            .strip_prefix(base)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if name.is_empty() {
+            continue;
+        }
+
+        // Same guard as resolve_request_path: never follow links out of root
+        // or into hidden paths.
+        match path.canonicalize() {
+            Ok(real) if real.starts_with(root) && !hidden.hides_under(root, &real) => {}
+            _ => continue,
+        }
+
+        if entry.file_type().is_dir() {
+            writer.add_directory(name, options)?;
+        } else if path.is_file() {
+            writer.start_file(name, options)?;
+            let mut file = std::fs::File::open(path)?;
+            std::io::copy(&mut file, &mut writer)?;
+        }
+    }
+
+    writer.finish()?;
+    Ok(())
 }
 
-fn build_directory_listing(root: &Path, dir: &Path, url: &str) -> Result<String> {
+fn build_directory_listing(
+    root: &Path,
+    dir: &Path,
+    url: &str,
+    hidden: &HiddenPaths,
+) -> Result<String> {
     let mut entries: Vec<_> = std::fs::read_dir(dir)?
         .filter_map(Result::ok)
+        .filter(|entry| {
+            let path = entry.path();
+            !hidden.hides_under(root, &path)
+                && !path.canonicalize().is_ok_and(|real| hidden.hides_under(root, &real))
+        })
         .collect();
 
     entries.sort_by_key(|entry| entry.file_name().to_string_lossy().to_lowercase());
@@ -131,10 +1083,13 @@ fn build_directory_listing(root: &Path, dir: &Path, url: &str) -> Result<String>
     body.push_str("<!doctype html><html><head><meta charset=\"utf-8\">");
     body.push_str(&format!("<title>Index of {}</title>", html_escape(&title)));
     body.push_str("</head><body>");
-    body.push_str(&format!("<h1>Index of {}</h1><hr><ul>", html_escape(&title)));
+    body.push_str(&format!("<h1>Index of {}</h1>", html_escape(&title)));
+    body.push_str("<p><a href=\"?download=zip\">Download as zip</a></p><hr>");
+    body.push_str("<table><thead><tr><th align=\"left\">Name</th><th align=\"right\">Size</th>");
+    body.push_str("<th align=\"left\">Modified</th></tr></thead><tbody>");
 
     if !rel_dir.as_os_str().is_empty() {
-        body.push_str("<li><a href=\"../\">../</a></li>");
+        body.push_str("<tr><td><a href=\"../\">../</a></td><td align=\"right\">-</td><td>-</td></tr>");
     }
 
     for entry in entries {
@@ -154,14 +1109,30 @@ fn build_directory_listing(root: &Path, dir: &Path, url: &str) -> Result<String>
             format!("{}{}", base_path, encoded_name)
         };
 
+        let metadata = entry.metadata().ok();
+        let size = match &metadata {
+            Some(meta) if !is_dir => format_size(meta.len()),
+            _ => "-".to_string(),
+        };
+        let modified = metadata
+            .and_then(|meta| meta.modified().ok())
+            .map(|time| {
+                chrono::DateTime::<chrono::Local>::from(time)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "-".to_string());
+
         body.push_str(&format!(
-            "<li><a href=\"{}\">{}</a></li>",
+            "<tr><td><a href=\"{}\">{}</a></td><td align=\"right\">{}</td><td>{}</td></tr>",
             html_escape(&href),
-            html_escape(&display)
+            html_escape(&display),
+            size,
+            modified
         ));
     }
 
-    body.push_str("</ul><hr></body></html>");
+    body.push_str("</tbody></table><hr></body></html>");
     Ok(body)
 }
 
@@ -172,3 +1143,457 @@ fn html_escape(input: &str) -> String {
         .replace('>', "&gt;")
         .replace('"', "&quot;")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl ServeConfig {
+        /// A read-only, open server for `root` with the default index name.
+        fn for_test(root: &Path) -> Self {
+            Self {
+                root: root.canonicalize().unwrap(),
+                single_file: false,
+                auth: None,
+                access_log: None,
+                allow_upload: false,
+                index: vec!["index.html".to_string()],
+                spa: None,
+                hidden: HiddenPaths::new(&[], true).unwrap(),
+            }
+        }
+    }
+
+    fn spawn_server(
+        root: &Path,
+        auth: Option<&str>,
+        requests: usize,
+    ) -> (u16, std::thread::JoinHandle<()>) {
+        let config = ServeConfig {
+            auth: auth.map(str::to_string),
+            ..ServeConfig::for_test(root)
+        };
+        spawn_with_config(config, requests)
+    }
+
+    fn spawn_with_config(
+        config: ServeConfig,
+        requests: usize,
+    ) -> (u16, std::thread::JoinHandle<()>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            for request in server.incoming_requests().take(requests) {
+                handle_request(request, &config).unwrap();
+            }
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn browser_url_avoids_unspecified_address() {
+        assert_eq!(
+            reachable_url("http", "0.0.0.0:8000".parse().unwrap()),
+            "http://127.0.0.1:8000/"
+        );
+        assert_eq!(
+            reachable_url("https", "192.168.1.5:443".parse().unwrap()),
+            "https://192.168.1.5:443/"
+        );
+        assert_eq!(
+            reachable_url("http", "[::]:8080".parse().unwrap()),
+            "http://[::1]:8080/"
+        );
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(parse_range("bytes=5-9", 20), ByteRange::Partial(5, 9));
+        assert_eq!(parse_range("bytes=5-", 20), ByteRange::Partial(5, 19));
+        assert_eq!(parse_range("bytes=-4", 20), ByteRange::Partial(16, 19));
+        assert_eq!(parse_range("bytes=15-100", 20), ByteRange::Partial(15, 19));
+        assert_eq!(parse_range("bytes=20-25", 20), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-1,4-5", 20), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 20), ByteRange::Full);
+    }
+
+    #[test]
+    fn lists_sizes_and_dates() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a&b.bin"), vec![0u8; 2048]).unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let hidden = HiddenPaths::new(&[], true).unwrap();
+
+        let listing = build_directory_listing(&root, &root, "/", &hidden).unwrap();
+        assert!(listing.contains(">a&amp;b.bin</a></td><td align=\"right\">2.0 KiB</td>"));
+        assert!(listing.contains(">sub/</a></td><td align=\"right\">-</td>"));
+        assert!(!listing.contains("../"));
+
+        let listing = build_directory_listing(&root, &root.join("sub"), "/sub/", &hidden).unwrap();
+        assert!(listing.contains("<a href=\"../\">../</a>"));
+    }
+
+    #[test]
+    fn serves_ranges_and_head() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("data.txt"), b"0123456789abcdef").unwrap();
+        let (port, handle) = spawn_server(dir.path(), None, 4);
+
+        let url = format!("http://127.0.0.1:{port}/data.txt");
+        let client = reqwest::blocking::Client::new();
+        let response = client.get(&url).header("Range", "bytes=5-9").send().unwrap();
+        assert_eq!(response.status().as_u16(), 206);
+        assert_eq!(
+            response.headers()["Content-Range"].to_str().unwrap(),
+            "bytes 5-9/16"
+        );
+        assert_eq!(response.bytes().unwrap().as_ref(), b"56789");
+
+        let response = client.get(&url).header("Range", "bytes=16-").send().unwrap();
+        assert_eq!(response.status().as_u16(), 416);
+
+        let response = client.head(&url).send().unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Content-Length"].to_str().unwrap(), "16");
+        assert_eq!(response.headers()["Content-Type"].to_str().unwrap(), "text/plain");
+        assert!(response.bytes().unwrap().is_empty());
+
+        let response = client.delete(&url).send().unwrap();
+        assert_eq!(response.status().as_u16(), 405);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn requires_basic_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("secret.txt"), b"ok").unwrap();
+        let (port, handle) = spawn_server(dir.path(), Some("user:pass"), 3);
+
+        let url = format!("http://127.0.0.1:{port}/secret.txt");
+        let client = reqwest::blocking::Client::new();
+        let response = client.get(&url).send().unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"].to_str().unwrap(),
+            "Basic realm=\"xtool\""
+        );
+
+        let response = client.get(&url).basic_auth("user", Some("nope")).send().unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+
+        let response = client.get(&url).basic_auth("user", Some("pass")).send().unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.bytes().unwrap().as_ref(), b"ok");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn downloads_directory_as_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let share = dir.path().join("share");
+        std::fs::create_dir_all(share.join("nested")).unwrap();
+        std::fs::write(share.join("a.txt"), b"alpha").unwrap();
+        std::fs::write(share.join("nested/b.txt"), b"beta").unwrap();
+        let (port, handle) = spawn_server(dir.path(), None, 2);
+
+        let client = reqwest::blocking::Client::new();
+        let url = format!("http://127.0.0.1:{port}/share/?download=zip");
+        let response = client.get(&url).send().unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(
+            response.headers()["Content-Disposition"].to_str().unwrap(),
+            "attachment; filename=\"share.zip\""
+        );
+        let bytes = response.bytes().unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes.to_vec())).unwrap();
+        let mut contents = String::new();
+        archive
+            .by_name("nested/b.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "beta");
+        assert!(archive.by_name("a.txt").is_ok());
+
+        let url = format!("http://127.0.0.1:{port}/..%2F?download=zip");
+        assert_eq!(client.get(&url).send().unwrap().status().as_u16(), 404);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn answers_conditional_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("cached.txt"), b"cache me").unwrap();
+        let (port, handle) = spawn_server(dir.path(), None, 3);
+
+        let url = format!("http://127.0.0.1:{port}/cached.txt");
+        let client = reqwest::blocking::Client::new();
+        let first = client.get(&url).send().unwrap();
+        assert_eq!(first.status().as_u16(), 200);
+        let etag = first.headers()["ETag"].to_str().unwrap().to_string();
+        let modified = first.headers()["Last-Modified"].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\""));
+
+        let second = client.get(&url).header("If-None-Match", &etag).send().unwrap();
+        assert_eq!(second.status().as_u16(), 304);
+        assert!(second.bytes().unwrap().is_empty());
+
+        let third = client.get(&url).header("If-Modified-Since", &modified).send().unwrap();
+        assert_eq!(third.status().as_u16(), 304);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn writes_combined_access_log() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("log.txt"), b"logged").unwrap();
+        let log_path = dir.path().join("access.log");
+        let config = ServeConfig {
+            access_log: Some(Mutex::new(std::fs::File::create(&log_path).unwrap())),
+            ..ServeConfig::for_test(dir.path())
+        };
+        let (port, handle) = spawn_with_config(config, 2);
+
+        let client = reqwest::blocking::Client::new();
+        let base = format!("http://127.0.0.1:{port}");
+        client
+            .get(format!("{base}/log.txt"))
+            .header("User-Agent", "xtool-test")
+            .send()
+            .unwrap();
+        client.get(format!("{base}/missing")).send().unwrap();
+        handle.join().unwrap();
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("127.0.0.1 - - ["));
+        assert!(lines[0].ends_with("\"GET /log.txt HTTP/1.1\" 200 6 \"-\" \"xtool-test\""));
+        assert!(lines[1].contains("\"GET /missing HTTP/1.1\" 404 0"));
+    }
+
+    #[test]
+    fn accepts_uploads_only_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let client = reqwest::blocking::Client::new();
+
+        let (port, handle) = spawn_server(dir.path(), None, 1);
+        let response = client
+            .put(format!("http://127.0.0.1:{port}/new.txt"))
+            .body("data")
+            .send()
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 405);
+        handle.join().unwrap();
+        assert!(!dir.path().join("new.txt").exists());
+
+        let config = ServeConfig {
+            allow_upload: true,
+            ..ServeConfig::for_test(dir.path())
+        };
+        let (port, handle) = spawn_with_config(config, 6);
+        let base = format!("http://127.0.0.1:{port}");
+        let put = |path: &str, body: &'static str| {
+            client
+                .put(format!("{base}{path}"))
+                .body(body)
+                .send()
+                .unwrap()
+                .status()
+                .as_u16()
+        };
+        assert_eq!(put("/sub/new.txt", "first"), 201);
+        assert_eq!(put("/sub/new.txt", "second"), 409);
+        assert_eq!(put("/sub/new.txt?overwrite=1", "second"), 201);
+        assert_eq!(put("/..%2fescape.txt", "x"), 404);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("sub/new.txt")).unwrap(),
+            "second"
+        );
+        assert!(!dir.path().parent().unwrap().join("escape.txt").exists());
+
+        let body = "--XB\r\nContent-Disposition: form-data; name=\"file\"; filename=\"up.bin\"\r\n\
+                    Content-Type: application/octet-stream\r\n\r\nline1\r\nline2\r\n--XB--\r\n";
+        let response = client
+            .post(format!("{base}/sub/"))
+            .header("Content-Type", "multipart/form-data; boundary=XB")
+            .body(body)
+            .send()
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 201);
+        assert_eq!(
+            std::fs::read(dir.path().join("sub/up.bin")).unwrap(),
+            b"line1\r\nline2"
+        );
+
+        let response = client
+            .post(format!("{base}/sub/"))
+            .header("Content-Type", "text/plain")
+            .body("x")
+            .send()
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn streams_multipart_parts_across_reads() {
+        /// Hands out the body a few bytes at a time
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(self.0.len()).min(7);
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let big: Vec<u8> = (0..200_000u32).map(|i| b"\r\n--X"[i as usize % 5]).collect();
+        let mut body = b"--XB\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhi\r\n--XB\r\n\
+                         Content-Disposition: form-data; name=\"a\"; filename=\"big.bin\"\r\n\r\n"
+            .to_vec();
+        body.extend_from_slice(&big);
+        body.extend_from_slice(
+            b"\r\n--XB\r\nContent-Disposition: form-data; name=\"b\"; filename=\"small.txt\"\r\n\r\n\
+              small\r\n--XB--\r\n",
+        );
+        let hidden = HiddenPaths::new(&[], true).unwrap();
+        let status = store_multipart(Trickle(&body), "XB", dir.path(), false, &hidden).unwrap();
+        assert_eq!(status, 201);
+        assert_eq!(std::fs::read(dir.path().join("big.bin")).unwrap(), big);
+        assert_eq!(std::fs::read(dir.path().join("small.txt")).unwrap(), b"small");
+
+        // A body cut off mid-part stores nothing
+        let status = store_multipart(&body[..body.len() - 20], "XB", dir.path(), true, &hidden).unwrap();
+        assert_eq!(status, 400);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn probes_index_names_and_falls_back_to_spa() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/index.htm"), b"docs").unwrap();
+        std::fs::write(dir.path().join("app.html"), b"app").unwrap();
+        let root = dir.path().canonicalize().unwrap();
+
+        let config = ServeConfig {
+            index: vec!["index.html".to_string(), "index.htm".to_string()],
+            spa: Some(root.join("app.html")),
+            ..ServeConfig::for_test(&root)
+        };
+        let (port, handle) = spawn_with_config(config, 3);
+        let get = |path: &str| {
+            let response =
+                reqwest::blocking::get(format!("http://127.0.0.1:{port}{path}")).unwrap();
+            (response.status().as_u16(), response.text().unwrap())
+        };
+        assert_eq!(get("/docs/"), (200, "docs".to_string()));
+        assert_eq!(get("/users/42"), (200, "app".to_string()));
+        assert_eq!(get("/app.html"), (200, "app".to_string()));
+        handle.join().unwrap();
+
+        assert!(is_plain_file_name("index.htm"));
+        assert!(!is_plain_file_name("../index.html"));
+        assert!(!is_plain_file_name(""));
+    }
+
+    #[test]
+    fn hides_matching_paths_and_dotfiles() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("private/deep")).unwrap();
+        std::fs::create_dir(dir.path().join("public")).unwrap();
+        std::fs::write(dir.path().join("private/deep/key.pem"), b"key").unwrap();
+        std::fs::write(dir.path().join("public/notes.txt"), b"notes").unwrap();
+        std::fs::write(dir.path().join("public/build.log"), b"log").unwrap();
+        std::fs::write(dir.path().join(".env"), b"SECRET=1").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path().join("private"), dir.path().join("public/link"))
+            .unwrap();
+        let root = dir.path().canonicalize().unwrap();
+
+        let hidden = HiddenPaths::new(&["private".to_string(), "*.log".to_string()], false)
+            .unwrap();
+        assert!(hidden.hides(Path::new("private/deep/key.pem")));
+        assert!(hidden.hides(Path::new("public/build.log")));
+        assert!(hidden.hides(Path::new("public/.cache/x")));
+        assert!(!hidden.hides(Path::new("public/notes.txt")));
+        assert!(HiddenPaths::new(&["/".to_string()], false).is_err());
+
+        // Same rules as `disk` globs: an unclosed `[` is literal, and a
+        // pattern with a `/` is anchored at the root
+        let nested = HiddenPaths::new(&["a[".to_string(), "public/*.txt".to_string()], true)
+            .unwrap();
+        assert!(nested.hides(Path::new("a[")));
+        assert!(nested.hides(Path::new("public/notes.txt")));
+        assert!(!nested.hides(Path::new("other/public/notes.txt")));
+
+        let listing = build_directory_listing(&root, &root, "/", &hidden).unwrap();
+        assert!(listing.contains(">public/</a>"));
+        assert!(!listing.contains("private"));
+        assert!(!listing.contains(".env"));
+        let listing = build_directory_listing(&root, &root.join("public"), "/public/", &hidden)
+            .unwrap();
+        assert!(listing.contains("notes.txt"));
+        assert!(!listing.contains("build.log"));
+        assert!(!listing.contains("link"));
+
+        let config = ServeConfig {
+            hidden,
+            ..ServeConfig::for_test(&root)
+        };
+        let (port, handle) = spawn_with_config(config, 7);
+        let status = |path: &str| {
+            reqwest::blocking::get(format!("http://127.0.0.1:{port}{path}"))
+                .unwrap()
+                .status()
+                .as_u16()
+        };
+        assert_eq!(status("/public/notes.txt"), 200);
+        assert_eq!(status("/private/deep/key.pem"), 404);
+        assert_eq!(status("/private/"), 404);
+        assert_eq!(status("/public/build.log"), 404);
+        assert_eq!(status("/%2Eenv"), 404);
+        assert_eq!(status("/public/link/deep/key.pem"), 404);
+
+        let bytes = reqwest::blocking::get(format!("http://127.0.0.1:{port}/?download=zip"))
+            .unwrap()
+            .bytes()
+            .unwrap();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bytes.to_vec())).unwrap();
+        let names: Vec<&str> = archive.file_names().collect();
+        assert!(names.contains(&"public/notes.txt"));
+        assert!(!names.iter().any(|name| name.contains("private")
+            || name.contains("link/")
+            || name.ends_with(".log")
+            || name.contains(".env")));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn serves_a_single_file_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("report.pdf"), b"%PDF-1.7").unwrap();
+        std::fs::write(dir.path().join("other.txt"), b"other").unwrap();
+        let config = ServeConfig {
+            single_file: true,
+            ..ServeConfig::for_test(&dir.path().join("report.pdf"))
+        };
+        let (port, handle) = spawn_with_config(config, 4);
+        let get = |path: &str| reqwest::blocking::get(format!("http://127.0.0.1:{port}{path}")).unwrap();
+
+        for path in ["/", "/report.pdf"] {
+            let response = get(path);
+            assert_eq!(response.status().as_u16(), 200);
+            assert_eq!(response.headers()["content-type"], "application/pdf");
+            assert_eq!(response.bytes().unwrap().as_ref(), b"%PDF-1.7");
+        }
+        assert_eq!(get("/other.txt").status().as_u16(), 404);
+        assert_eq!(get("/../other.txt").status().as_u16(), 404);
+        handle.join().unwrap();
+    }
+}
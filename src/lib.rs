@@ -4,6 +4,7 @@ pub mod file;
 pub mod http;
 pub mod serial;
 pub mod tftp;
+pub(crate) mod utils;
 
 #[macro_use]
 extern crate log;
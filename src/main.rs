@@ -1,6 +1,5 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use log::{error, info};
+use clap::{ArgAction, Parser, Subcommand};
 use std::path::PathBuf;
 
 use xtool::{config, disk, file, http, serial, tftp};
@@ -9,16 +8,44 @@ use xtool::{config, disk, file, http, serial, tftp};
 #[command(name = "xtool")]
 #[command(version, about = "Amazing Tools", long_about = None)]
 struct Cli {
+    /// Configuration file (default: $XTOOL_CONFIG, then ./.xtool.toml)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Only log errors; regular output on stdout is unaffected
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log more: -v for debug, -vv for trace
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Print a failure as {"error": ..., "code": N} on stderr instead of plain text
+    #[arg(long, global = true)]
+    json_errors: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+impl Cli {
+    /// Level chosen by `-q`/`-v`, if any; otherwise `RUST_LOG` or `info` applies
+    fn log_level(&self) -> Option<log::LevelFilter> {
+        match (self.quiet, self.verbose) {
+            (true, _) => Some(log::LevelFilter::Error),
+            (false, 0) => None,
+            (false, 1) => Some(log::LevelFilter::Debug),
+            (false, _) => Some(log::LevelFilter::Trace),
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Start a TFTP server
     Tftpd {
-        /// IP address to listen on
-        #[arg(short, long, default_value = "0.0.0.0")]
+        /// IP address to listen on; transfers reply from the same address
+        #[arg(short, long, visible_alias = "bind", default_value = "0.0.0.0")]
         ip: String,
 
         /// Port to listen on
@@ -60,6 +87,46 @@ enum Commands {
         #[arg(short, long)]
         baud: Option<u32>,
 
+        /// Append received data to a log file
+        #[arg(long, value_name = "PATH")]
+        log: Option<PathBuf>,
+
+        /// Prefix each received line with a timestamp
+        #[arg(long)]
+        timestamp: bool,
+
+        /// Bytes sent for Enter [default: cr]
+        #[arg(long, value_enum)]
+        eol: Option<serial::monitor::LineEnding>,
+
+        /// Byte sent for Backspace [default: del]
+        #[arg(long, value_enum)]
+        backspace: Option<serial::monitor::BackspaceKey>,
+
+        /// Wait for the port to reappear if the device disconnects
+        #[arg(long)]
+        reconnect: bool,
+
+        /// Set DTR on startup
+        #[arg(long, value_enum)]
+        dtr: Option<serial::monitor::LineLevel>,
+
+        /// Set RTS on startup
+        #[arg(long, value_enum)]
+        rts: Option<serial::monitor::LineLevel>,
+
+        /// Send a BREAK right after connecting
+        #[arg(long = "break")]
+        send_break: bool,
+
+        /// Print typed characters locally (toggle with Ctrl+E)
+        #[arg(long)]
+        local_echo: bool,
+
+        /// How long a BREAK (startup or Ctrl+B) is held, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = serial::monitor::DEFAULT_BREAK_MS)]
+        break_ms: u64,
+
         #[command(subcommand)]
         subcommand: Option<serial::SerialSubcommand>,
     },
@@ -73,22 +140,99 @@ enum Commands {
 
     /// Start a HTTP static file server
     Http {
+        /// Address to listen on (e.g. 127.0.0.1 to stay local)
+        #[arg(short, long, value_name = "ADDR", default_value = "0.0.0.0")]
+        bind: std::net::IpAddr,
+
         /// Port to listen on
         #[arg(short, long, default_value = "80")]
         port: u16,
 
-        /// Root directory to serve
+        /// Root directory to serve, or a single file to serve at `/`
         #[arg(short = 'd', long, default_value = ".")]
         path: PathBuf,
+
+        /// Require HTTP Basic authentication
+        #[arg(long, value_name = "USER:PASS")]
+        auth: Option<String>,
+
+        /// Number of worker threads (default: number of CPUs)
+        #[arg(long, value_name = "N")]
+        threads: Option<usize>,
+
+        /// Append requests to this file in Combined Log Format
+        #[arg(long, value_name = "PATH")]
+        access_log: Option<PathBuf>,
+
+        /// Serve HTTPS using this PEM certificate (requires --tls-key)
+        #[arg(long, value_name = "PEM", requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+
+        /// PEM private key for --tls-cert
+        #[arg(long, value_name = "PEM", requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
+
+        /// Open the server in the default browser once it is listening
+        #[arg(long)]
+        open: bool,
+
+        /// Accept PUT and multipart POST uploads (add ?overwrite=1 to replace files)
+        #[arg(long)]
+        allow_upload: bool,
+
+        /// Index file served for directory requests; repeat to probe several in order
+        #[arg(long, value_name = "NAME", default_value = "index.html")]
+        index: Vec<String>,
+
+        /// Serve this file (relative to the root) instead of 404, for single-page apps
+        #[arg(long, value_name = "FILE")]
+        spa: Option<PathBuf>,
+
+        /// Hide paths matching this glob (`*`, `?`, `[...]`, as in `disk`) from listings and requests; repeatable
+        #[arg(long, value_name = "GLOB")]
+        hide: Vec<String>,
+
+        /// Serve dotfiles, which are hidden by default
+        #[arg(long)]
+        show_hidden: bool,
     },
 
     /// Disk image utilities
     Disk(disk::DiskCli),
 }
 
-fn main() -> Result<()> {
+/// Exit status for a command that failed with an error.
+const ERROR_EXIT_CODE: i32 = 1;
+
+fn main() {
+    let cli = Cli::parse();
+    let json_errors = cli.json_errors;
+    if let Err(err) = run(cli) {
+        if json_errors {
+            let report = serde_json::json!({
+                "error": format!("{:#}", err),
+                "code": ERROR_EXIT_CODE,
+            });
+            eprintln!("{}", report);
+        } else {
+            eprintln!("Error: {:?}", err);
+        }
+        std::process::exit(ERROR_EXIT_CODE);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
     // Initialize logger, default info level, display file line number and time
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+    // -q/-v replace RUST_LOG entirely rather than adding to it
+    let mut logger = match cli.log_level() {
+        Some(level) => {
+            let mut builder = env_logger::Builder::new();
+            builder.filter_level(level);
+            builder
+        }
+        None => env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")),
+    };
+    logger
         .format(|buf, record| {
             use std::io::Write;
             let level_style = buf.default_level_style(record.level());
@@ -104,26 +248,7 @@ fn main() -> Result<()> {
         })
         .init();
 
-    let cli = Cli::parse();
-
-    // Try to load configuration file
-    let config_path = ".xtool.toml";
-    let app_config = if std::path::Path::new(config_path).exists() {
-        match config::AppConfig::load_from_file(config_path) {
-            Ok(cfg) => {
-                let abs_path = std::fs::canonicalize(config_path)
-                    .unwrap_or_else(|_| std::path::PathBuf::from(config_path));
-                info!("Using configuration file: {}", abs_path.display());
-                Some(cfg)
-            }
-            Err(e) => {
-                error!("Failed to load configuration file: {}, using defaults", e);
-                None
-            }
-        }
-    } else {
-        None
-    };
+    let app_config = config::AppConfig::load(cli.config.as_deref())?;
 
     match cli.command {
         Commands::Tftpd {
@@ -158,25 +283,80 @@ fn main() -> Result<()> {
         Commands::Serial {
             uart,
             baud,
+            log,
+            timestamp,
+            eol,
+            backspace,
+            reconnect,
+            dtr,
+            rts,
+            send_break,
+            local_echo,
+            break_ms,
             subcommand,
         } => {
+            let serial_config = app_config.as_ref().and_then(|c| c.serial.clone());
+            let monitor_options = serial::monitor::MonitorOptions {
+                log,
+                timestamp,
+                eol: eol
+                    .or(serial_config.as_ref().and_then(|c| c.eol))
+                    .unwrap_or_default(),
+                backspace: backspace
+                    .or(serial_config.as_ref().and_then(|c| c.backspace))
+                    .unwrap_or_default(),
+                reconnect,
+                dtr,
+                rts,
+                break_duration: std::time::Duration::from_millis(break_ms),
+                send_break,
+                local_echo,
+            };
             serial::run(
                 subcommand,
                 uart,
                 baud,
-                app_config.as_ref().and_then(|c| c.serial.clone()),
+                monitor_options,
+                serial_config,
             )?;
         }
 
         Commands::Genconfig { force } => {
-            if let Err(e) = config::AppConfig::generate_config_file(force) {
-                error!("Error: {}", e);
-                std::process::exit(1);
-            }
+            config::AppConfig::generate_config_file(force)?;
         }
 
-        Commands::Http { port, path } => {
-            http::run(port, path)?;
+        Commands::Http {
+            bind,
+            port,
+            path,
+            auth,
+            threads,
+            access_log,
+            tls_cert,
+            tls_key,
+            open,
+            allow_upload,
+            index,
+            spa,
+            hide,
+            show_hidden,
+        } => {
+            http::run(http::HttpOptions {
+                bind,
+                port,
+                path,
+                auth,
+                threads,
+                access_log,
+                tls_cert,
+                tls_key,
+                open,
+                allow_upload,
+                index,
+                spa,
+                hide,
+                show_hidden,
+            })?;
         }
 
         Commands::Disk(cmd) => {
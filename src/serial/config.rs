@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
+use super::monitor::{BackspaceKey, LineEnding};
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 pub struct SerialConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub uart: Option<String>,
@@ -10,4 +13,30 @@ pub struct SerialConfig {
     pub net_port: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub net_bind: Option<String>,
+    /// Shared secret required by `netd` and sent by `netc`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net_token: Option<String>,
+    /// Bytes sent for Enter in the monitor: "cr", "lf" or "crlf"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eol: Option<LineEnding>,
+    /// Byte sent for Backspace in the monitor: "del" or "bs"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backspace: Option<BackspaceKey>,
+}
+
+impl SerialConfig {
+    /// Append a message for each invalid value to `problems`.
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if self.uart.as_deref().is_some_and(|u| u.trim().is_empty()) {
+            problems.push("serial.uart: must not be empty".to_string());
+        }
+        if self.baud == Some(0) {
+            problems.push("serial.baud: must be greater than 0".to_string());
+        }
+        if let Some(bind) = &self.net_bind
+            && bind.parse::<std::net::IpAddr>().is_err()
+        {
+            problems.push(format!("serial.net_bind: {:?} is not an IP address", bind));
+        }
+    }
 }
@@ -1,8 +1,83 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use serde::Serialize;
 use serialport::SerialPortType;
 
-pub fn run() -> Result<()> {
+/// One enumerated serial port
+#[derive(Debug, Clone, Serialize)]
+pub struct PortInfo {
+    pub port_name: String,
+    /// "usb", "pci", "bluetooth" or "unknown"
+    #[serde(rename = "type")]
+    pub port_type: &'static str,
+    /// USB vendor ID as 4 hex digits (e.g. "10c4")
+    pub vid: Option<String>,
+    /// USB product ID as 4 hex digits (e.g. "ea60")
+    pub pid: Option<String>,
+    pub serial_number: Option<String>,
+    pub product: Option<String>,
+    pub manufacturer: Option<String>,
+}
+
+/// Parse a USB ID given as hex, with or without a `0x` prefix.
+pub fn parse_usb_id(input: &str) -> Result<u16> {
+    let hex = input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+        .unwrap_or(input);
+    u16::from_str_radix(hex, 16).map_err(|_| anyhow!("invalid USB ID: {input}"))
+}
+
+/// Enumerate serial ports, keeping only USB devices matching `vid`/`pid` when given.
+pub fn list_ports(vid: Option<u16>, pid: Option<u16>) -> Result<Vec<PortInfo>> {
     let ports = serialport::available_ports()?;
+    let filtered = vid.is_some() || pid.is_some();
+
+    let mut result = Vec::new();
+    for p in ports {
+        let info = match p.port_type {
+            SerialPortType::UsbPort(usb) => {
+                if vid.is_some_and(|v| v != usb.vid) || pid.is_some_and(|v| v != usb.pid) {
+                    continue;
+                }
+                PortInfo {
+                    port_name: p.port_name,
+                    port_type: "usb",
+                    vid: Some(format!("{:04x}", usb.vid)),
+                    pid: Some(format!("{:04x}", usb.pid)),
+                    serial_number: usb.serial_number,
+                    product: usb.product,
+                    manufacturer: usb.manufacturer,
+                }
+            }
+            // VID/PID filters only ever match USB devices
+            _ if filtered => continue,
+            other => PortInfo {
+                port_name: p.port_name,
+                port_type: match other {
+                    SerialPortType::PciPort => "pci",
+                    SerialPortType::BluetoothPort => "bluetooth",
+                    _ => "unknown",
+                },
+                vid: None,
+                pid: None,
+                serial_number: None,
+                product: None,
+                manufacturer: None,
+            },
+        };
+        result.push(info);
+    }
+    Ok(result)
+}
+
+pub fn run(json: bool, vid: Option<u16>, pid: Option<u16>) -> Result<()> {
+    let ports = list_ports(vid, pid)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&ports)?);
+        return Ok(());
+    }
+
     if ports.is_empty() {
         println!("No serial ports found.");
         return Ok(());
@@ -12,24 +87,37 @@ pub fn run() -> Result<()> {
     for p in ports {
         println!("  {}", p.port_name);
         match p.port_type {
-            SerialPortType::UsbPort(info) => {
-                if let Some(product) = info.product {
+            "usb" => {
+                if let (Some(vid), Some(pid)) = (&p.vid, &p.pid) {
+                    println!("    USB ID: {}:{}", vid, pid);
+                }
+                if let Some(product) = p.product {
                     println!("    Product: {}", product);
                 }
-                if let Some(manufacturer) = info.manufacturer {
+                if let Some(manufacturer) = p.manufacturer {
                     println!("    Manufacturer: {}", manufacturer);
                 }
+                if let Some(serial) = p.serial_number {
+                    println!("    Serial: {}", serial);
+                }
             }
-            SerialPortType::PciPort => {
-                println!("    Type: PCI");
-            }
-            SerialPortType::BluetoothPort => {
-                println!("    Type: Bluetooth");
-            }
-            SerialPortType::Unknown => {
-                println!("    Type: Unknown");
-            }
+            "pci" => println!("    Type: PCI"),
+            "bluetooth" => println!("    Type: Bluetooth"),
+            _ => println!("    Type: Unknown"),
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_usb_ids() {
+        assert_eq!(parse_usb_id("10c4").unwrap(), 0x10c4);
+        assert_eq!(parse_usb_id("0xEA60").unwrap(), 0xea60);
+        assert!(parse_usb_id("xyz").is_err());
+        assert!(parse_usb_id("12345").is_err());
+    }
+}
@@ -1,19 +1,32 @@
 use anyhow::Result;
-use clap::Subcommand;
+use clap::{Subcommand, ValueEnum};
 use dialoguer::{theme::ColorfulTheme, Select};
 use serialport::SerialPortType;
+use std::path::PathBuf;
 
 pub mod config;
 pub mod list;
 pub mod monitor;
 pub mod net;
+pub mod xmodem;
 
 use config::SerialConfig;
+use monitor::MonitorOptions;
 
 #[derive(Subcommand)]
 pub enum SerialSubcommand {
     /// List available serial ports
-    List,
+    List {
+        /// Print the ports as a JSON array
+        #[arg(long)]
+        json: bool,
+        /// Only show USB devices with this vendor ID (hex, e.g. 10c4)
+        #[arg(long, value_parser = list::parse_usb_id)]
+        vid: Option<u16>,
+        /// Only show USB devices with this product ID (hex, e.g. ea60)
+        #[arg(long, value_parser = list::parse_usb_id)]
+        pid: Option<u16>,
+    },
     /// Network setup server (Forward network to serial)
     Netd {
         /// Serial port name
@@ -28,42 +41,118 @@ pub enum SerialSubcommand {
         /// Listen IP
         #[arg(short = 's', long)]
         bind: Option<String>,
+        /// Only the first connected client may write; later clients are read-only viewers
+        #[arg(long)]
+        read_only_clients: bool,
+        /// Require clients to present this shared secret
+        #[arg(long, value_name = "SECRET")]
+        token: Option<String>,
+        /// Drop a client after this many seconds without traffic either way
+        #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+        idle_timeout: Option<u64>,
     },
     /// Network connect client (Connect to serial server)
     Netc {
         /// Server IP
         #[arg(short, long)]
         server: String,
-        /// Server Port
-        #[arg(short, long, default_value = "5432")]
-        port: u16,
-    }
+        /// Server Port (default: net_port from config, then 5432)
+        #[arg(short, long)]
+        port: Option<u16>,
+        /// Shared secret expected by the server
+        #[arg(long, value_name = "SECRET")]
+        token: Option<String>,
+        /// Keep reconnecting with backoff when the connection drops
+        #[arg(long)]
+        reconnect: bool,
+    },
+    /// Send a file to the device (e.g. to a bootloader waiting for XMODEM)
+    Send {
+        /// Serial port name
+        #[arg(value_name = "UART")]
+        uart: Option<String>,
+        /// Baud rate
+        #[arg(short = 'b', long)]
+        baud: Option<u32>,
+        /// File to send
+        #[arg(short, long)]
+        file: PathBuf,
+        /// Transfer protocol
+        #[arg(long, value_enum, default_value_t = Protocol::Xmodem)]
+        protocol: Protocol,
+    },
+    /// Receive a file from the device
+    Receive {
+        /// Serial port name
+        #[arg(value_name = "UART")]
+        uart: Option<String>,
+        /// Baud rate
+        #[arg(short = 'b', long)]
+        baud: Option<u32>,
+        /// Where to save the received file
+        #[arg(short, long)]
+        file: PathBuf,
+        /// Transfer protocol
+        #[arg(long, value_enum, default_value_t = Protocol::Xmodem)]
+        protocol: Protocol,
+    },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Protocol {
+    /// XMODEM-CRC, 128-byte blocks
+    Xmodem,
 }
 
 pub fn run(
     subcommand: Option<SerialSubcommand>,
     uart: Option<String>,
     baud: Option<u32>,
+    monitor_options: MonitorOptions,
     config: Option<SerialConfig>,
 ) -> Result<()> {
     match subcommand {
-        Some(SerialSubcommand::List) => return list::run(),
-        Some(SerialSubcommand::Netd { uart, baud, port, bind }) => {
+        Some(SerialSubcommand::List { json, vid, pid }) => return list::run(json, vid, pid),
+        Some(SerialSubcommand::Netd { uart, baud, port, bind, read_only_clients, token, idle_timeout }) => {
+            let idle_timeout = idle_timeout.map(std::time::Duration::from_secs);
             let rt = tokio::runtime::Runtime::new()?;
-            return rt.block_on(net::server::run(uart, baud, port, bind, config));
+            return rt.block_on(net::server::run(uart, baud, port, bind, read_only_clients, token, idle_timeout, config));
         },
-        Some(SerialSubcommand::Netc { server, port }) => {
+        Some(SerialSubcommand::Netc { server, port, token, reconnect }) => {
+            let port = port.or(config.as_ref().and_then(|c| c.net_port)).unwrap_or(5432);
+            let token = token.or(config.as_ref().and_then(|c| c.net_token.clone()));
             let rt = tokio::runtime::Runtime::new()?;
-            return rt.block_on(net::client::run(server, port));
+            return rt.block_on(net::client::run(server, port, token, reconnect));
         },
+        Some(SerialSubcommand::Send { uart, baud, file, protocol }) => {
+            let (uart_name, baud) = resolve_port(uart, baud, config.as_ref())?;
+            return match protocol {
+                Protocol::Xmodem => xmodem::send_file(&uart_name, baud, &file),
+            };
+        }
+        Some(SerialSubcommand::Receive { uart, baud, file, protocol }) => {
+            let (uart_name, baud) = resolve_port(uart, baud, config.as_ref())?;
+            return match protocol {
+                Protocol::Xmodem => xmodem::receive_file(&uart_name, baud, &file),
+            };
+        }
         _ => {}
     }
 
     // Default action: Monitor
-    let final_uart = uart.or(config.as_ref().and_then(|c| c.uart.clone()));
-    let final_baud = baud
-        .or(config.as_ref().and_then(|c| c.baud))
-        .unwrap_or(115200);
+    let (uart_name, final_baud) = resolve_port(uart, baud, config.as_ref())?;
+    monitor::run(&uart_name, final_baud, monitor_options)
+}
+
+/// Pick the port and baud rate from the command line, then the config file,
+/// prompting for the port if neither names one.
+fn resolve_port(
+    uart: Option<String>,
+    baud: Option<u32>,
+    config: Option<&SerialConfig>,
+) -> Result<(String, u32)> {
+    let final_uart = uart.or(config.and_then(|c| c.uart.clone()));
+    let final_baud = baud.or(config.and_then(|c| c.baud)).unwrap_or(115200);
 
     let uart_name = match final_uart {
         Some(p) => p,
@@ -96,5 +185,5 @@ pub fn run(
         }
     };
 
-    monitor::run(&uart_name, final_baud)
+    Ok((uart_name, final_baud))
 }
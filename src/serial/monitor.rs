@@ -1,28 +1,304 @@
+use std::fs::{File, OpenOptions};
 use std::io::{self, Write};
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Duration;
 
+use clap::ValueEnum;
 use crossterm::{
     event::{self, Event, KeyCode, KeyModifiers},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+
+/// Options for the interactive monitor
+#[derive(Debug, Clone, Default)]
+pub struct MonitorOptions {
+    /// Append everything received from the device to this file
+    pub log: Option<PathBuf>,
+    /// Prefix each received line with a local ISO-8601 timestamp
+    pub timestamp: bool,
+    /// Bytes sent when Enter is pressed
+    pub eol: LineEnding,
+    /// Byte sent when Backspace is pressed
+    pub backspace: BackspaceKey,
+    /// Wait for the port to come back instead of stopping when it disappears
+    pub reconnect: bool,
+    /// Initial DTR state (left as the OS opened it when unset)
+    pub dtr: Option<LineLevel>,
+    /// Initial RTS state (left as the OS opened it when unset)
+    pub rts: Option<LineLevel>,
+    /// How long a BREAK is held, from Ctrl+B or `send_break`
+    pub break_duration: Duration,
+    /// Send one BREAK right after connecting
+    pub send_break: bool,
+    /// Print typed characters locally, for devices that do not echo
+    pub local_echo: bool,
+}
+
+/// State of a modem control line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LineLevel {
+    On,
+    Off,
+}
+
+impl LineLevel {
+    fn is_on(self) -> bool {
+        self == LineLevel::On
+    }
+}
+
+/// How long a line is held in the opposite state when pulsed
+const PULSE_DURATION: Duration = Duration::from_millis(100);
+
+/// Default `--break-ms`
+pub const DEFAULT_BREAK_MS: u64 = 250;
+
+/// What the Enter key sends to the device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// Carriage return (\r)
+    #[default]
+    Cr,
+    /// Line feed (\n)
+    Lf,
+    /// Carriage return + line feed (\r\n)
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Cr => b"\r",
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+}
+
+/// What the Backspace key sends to the device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackspaceKey {
+    /// ASCII DEL (0x7F)
+    #[default]
+    Del,
+    /// ASCII BS (0x08)
+    Bs,
+}
+
+impl BackspaceKey {
+    pub fn bytes(self) -> &'static [u8] {
+        match self {
+            BackspaceKey::Del => b"\x7F",
+            BackspaceKey::Bs => b"\x08",
+        }
+    }
+}
+
+/// Inserts a timestamp at the start of every received line.
+///
+/// Line state is carried across reads, so a line split over several reads
+/// gets exactly one prefix. Bytes are passed through as soon as they arrive
+/// rather than held back until the newline, so prompts without a trailing
+/// newline still show up immediately.
+struct LineStamper {
+    at_line_start: bool,
+}
+
+impl LineStamper {
+    fn new() -> Self {
+        Self {
+            at_line_start: true,
+        }
+    }
+
+    fn process(&mut self, data: &[u8], now: impl Fn() -> String) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 32);
+        for &byte in data {
+            if self.at_line_start {
+                out.extend_from_slice(format!("[{}] ", now()).as_bytes());
+                self.at_line_start = false;
+            }
+            out.push(byte);
+            if byte == b'\n' {
+                self.at_line_start = true;
+            }
+        }
+        out
+    }
+}
+
+/// Port used by the keyboard loop. `None` while waiting for a reconnect.
+type SharedPort = Arc<Mutex<Option<Box<dyn SerialPort>>>>;
+
+fn open_port(port_name: &str, baud_rate: u32) -> serialport::Result<Box<dyn SerialPort>> {
+    serialport::new(port_name, baud_rate)
+        .timeout(Duration::from_millis(10))
+        .open()
+}
+
+/// Apply the `--dtr`/`--rts` startup states.
+fn set_initial_lines(
+    port: &mut dyn SerialPort,
+    dtr: Option<LineLevel>,
+    rts: Option<LineLevel>,
+) -> serialport::Result<()> {
+    if let Some(level) = dtr {
+        port.write_data_terminal_ready(level.is_on())?;
+    }
+    if let Some(level) = rts {
+        port.write_request_to_send(level.is_on())?;
+    }
+    Ok(())
+}
+
+/// Modem control line that can be pulsed from the keyboard
+#[derive(Clone, Copy)]
+enum ControlLine {
+    Dtr,
+    Rts,
+}
+
+/// Flip a control line away from `idle` for a moment and back,
+/// e.g. DTR low then high to reset an ESP32.
+fn pulse_line(port: &SharedPort, line: ControlLine, idle: bool) -> anyhow::Result<()> {
+    let mut guard = port.lock().unwrap();
+    let Some(port) = guard.as_mut() else {
+        return Ok(());
+    };
+    let mut set = |level: bool| match line {
+        ControlLine::Dtr => port.write_data_terminal_ready(level),
+        ControlLine::Rts => port.write_request_to_send(level),
+    };
+    set(!idle)?;
+    thread::sleep(PULSE_DURATION);
+    set(idle)?;
+    Ok(())
+}
+
+/// Hold the TX line in the BREAK condition for `duration`, e.g. to drop an
+/// MCU into its bootloader. Nothing is printed, so raw mode is left alone.
+fn send_break(port: &SharedPort, duration: Duration) -> anyhow::Result<()> {
+    let mut guard = port.lock().unwrap();
+    let Some(port) = guard.as_mut() else {
+        return Ok(());
+    };
+    // Release the line even if setting it failed part way
+    let set = port.set_break();
+    if set.is_ok() {
+        thread::sleep(duration);
+    }
+    let clear = port.clear_break();
+    set?;
+    clear?;
+    Ok(())
+}
+
+/// Poll until `port_name` shows up again and can be opened.
+///
+/// Returns `None` if the monitor is shut down while waiting.
+fn wait_for_port(
+    port_name: &str,
+    baud_rate: u32,
+    running: &AtomicBool,
+) -> Option<Box<dyn SerialPort>> {
+    while running.load(Ordering::Relaxed) {
+        let present = serialport::available_ports()
+            .map(|ports| ports.iter().any(|p| p.port_name == port_name))
+            .unwrap_or(false);
+        if present && let Ok(port) = open_port(port_name, baud_rate) {
+            return Some(port);
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+    None
+}
+
+/// Write keystrokes to the device. While reconnecting, input is dropped.
+fn write_port(port: &SharedPort, data: &[u8], reconnect: bool) -> anyhow::Result<()> {
+    let mut guard = port.lock().unwrap();
+    if let Some(tx) = guard.as_mut()
+        && let Err(e) = tx.write_all(data)
+    {
+        if !reconnect {
+            return Err(e.into());
+        }
+        // The reader thread notices the failure and reopens the port
+        *guard = None;
+    }
+    Ok(())
+}
+
+/// Send a keystroke and, with local echo on, show it as the device would.
+fn send_key(port: &SharedPort, data: &[u8], reconnect: bool, echo: bool) -> anyhow::Result<()> {
+    write_port(port, data, reconnect)?;
+    if echo {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&echo_bytes(data));
+        let _ = stdout.flush();
+    }
+    Ok(())
+}
+
+/// How sent bytes look on a raw-mode terminal: any line ending starts a new
+/// line, DEL/BS erase the previous character, other control bytes are hidden.
+fn echo_bytes(data: &[u8]) -> Vec<u8> {
+    match data {
+        b"\r" | b"\n" | b"\r\n" => b"\r\n".to_vec(),
+        b"\x7F" | b"\x08" => b"\x08 \x08".to_vec(),
+        _ => data.iter().copied().filter(|&b| b >= 0x20).collect(),
+    }
+}
+
+fn local_timestamp() -> String {
+    chrono::Local::now()
+        .format("%Y-%m-%dT%H:%M:%S%.3f%:z")
+        .to_string()
+}
+
+pub fn run(port_name: &str, baud_rate: u32, options: MonitorOptions) -> anyhow::Result<()> {
+    let mut log_file: Option<File> = options
+        .log
+        .as_ref()
+        .map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| anyhow::anyhow!("Failed to open log file {}: {}", path.display(), e))
+        })
+        .transpose()?;
 
-pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
     println!(
         "Connected to {} at {} baud. Press 'Ctrl + ]' to exit.",
         port_name, baud_rate
     );
+    println!("Press 'Ctrl + T' to pulse DTR, 'Ctrl + R' to pulse RTS, 'Ctrl + B' to send BREAK.");
+    println!(
+        "Press 'Ctrl + E' to toggle local echo (currently {}).",
+        if options.local_echo { "on" } else { "off" }
+    );
+    if let Some(path) = &options.log {
+        println!("Logging received data to {}", path.display());
+    }
     println!("---------------------------------------------------------------");
 
     // 1. Open Serial Port
-    let mut serial_tx = serialport::new(port_name, baud_rate)
-        .timeout(Duration::from_millis(10))
-        .open()?;
+    let mut port = open_port(port_name, baud_rate)?;
+    set_initial_lines(port.as_mut(), options.dtr, options.rts)?;
 
     // Clone the port for the reading thread (serialport supports cloning)
-    let mut serial_rx = serial_tx.try_clone()?;
+    let mut serial_rx = port.try_clone()?;
+    let serial_tx: SharedPort = Arc::new(Mutex::new(Some(port)));
+    if options.send_break {
+        send_break(&serial_tx, options.break_duration)?;
+    }
 
     // 2. Enable Raw Mode
     enable_raw_mode()?;
@@ -33,9 +309,15 @@ pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
 
     // 3. Spawn Thread: Serial -> Stdout
     // This thread reads bytes from the device and prints them to the terminal
+    let timestamp = options.timestamp;
+    let reconnect = options.reconnect;
+    let port_name_rx = port_name.to_string();
+    let serial_tx_rx = serial_tx.clone();
+    let (dtr, rts) = (options.dtr, options.rts);
     let rx_thread = thread::spawn(move || {
         let mut buffer = [0; 1024];
         let mut stdout = io::stdout();
+        let mut stamper = LineStamper::new();
 
         while running_rx.load(Ordering::Relaxed) {
             match serial_rx.read(&mut buffer) {
@@ -45,8 +327,21 @@ pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
                     // If the device sends just \n, we might need to fix it,
                     // but usually, we just pass through what we get.
                     // For a robust monitor, we often just write raw bytes.
-                    let _ = stdout.write_all(&buffer[..n]);
+                    let stamped;
+                    let data = if timestamp {
+                        stamped = stamper.process(&buffer[..n], local_timestamp);
+                        &stamped[..]
+                    } else {
+                        &buffer[..n]
+                    };
+                    let _ = stdout.write_all(data);
                     let _ = stdout.flush();
+                    if let Some(file) = log_file.as_mut()
+                        && let Err(e) = file.write_all(data)
+                    {
+                        let _ = write!(stdout, "\r\nError writing log file: {}\r\n", e);
+                        log_file = None;
+                    }
                 }
                 Ok(_) => {} // Zero bytes read
                 Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
@@ -58,13 +353,41 @@ pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
                     // We can't easily print to stderr without messing up the terminal state
                     // so we just break.
                     let _ = write!(stdout, "\r\nError reading from serial: {}\r\n", e);
-                    break;
+                    if !reconnect {
+                        break;
+                    }
+
+                    // The device went away (e.g. USB adapter reset on reboot).
+                    // Reopen it from this same thread so only one reader exists.
+                    *serial_tx_rx.lock().unwrap() = None;
+                    let _ = disable_raw_mode();
+                    println!("waiting for {}...", port_name_rx);
+                    let Some(mut port) = wait_for_port(&port_name_rx, baud_rate, &running_rx)
+                    else {
+                        break;
+                    };
+                    let _ = set_initial_lines(port.as_mut(), dtr, rts);
+                    let tx = match port.try_clone() {
+                        Ok(tx) => tx,
+                        Err(e) => {
+                            println!("Failed to reopen {}: {}", port_name_rx, e);
+                            break;
+                        }
+                    };
+                    serial_rx = port;
+                    *serial_tx_rx.lock().unwrap() = Some(tx);
+                    if running_rx.load(Ordering::Relaxed) {
+                        let _ = enable_raw_mode();
+                    }
+                    let _ = write!(stdout, "Reconnected to {}\r\n", port_name_rx);
+                    let _ = stdout.flush();
                 }
             }
         }
     });
 
     // 4. Main Loop: Stdin (Keyboard) -> Serial
+    let mut echo = options.local_echo;
 
     while running.load(Ordering::Relaxed) {
         // Poll for events to avoid blocking forever so we can check 'running'
@@ -82,12 +405,49 @@ pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
                         break;
                     }
 
-                    // Handle Enter key
-
                     // Handle Enter key
                     KeyCode::Enter => {
-                        // Most serial shells expect \r (Carriage Return)
-                        serial_tx.write_all(b"\r")?;
+                        send_key(&serial_tx, options.eol.bytes(), reconnect, echo)?;
+                    }
+
+                    // Pulse modem control lines (board reset)
+                    KeyCode::Char('t') | KeyCode::Char('T')
+                        if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        let idle = options.dtr.is_none_or(LineLevel::is_on);
+                        if let Err(e) = pulse_line(&serial_tx, ControlLine::Dtr, idle) {
+                            let mut stdout = io::stdout();
+                            let _ = write!(stdout, "\r\nFailed to pulse DTR: {}\r\n", e);
+                            let _ = stdout.flush();
+                        }
+                    }
+                    KeyCode::Char('r') | KeyCode::Char('R')
+                        if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        let idle = options.rts.is_none_or(LineLevel::is_on);
+                        if let Err(e) = pulse_line(&serial_tx, ControlLine::Rts, idle) {
+                            let mut stdout = io::stdout();
+                            let _ = write!(stdout, "\r\nFailed to pulse RTS: {}\r\n", e);
+                            let _ = stdout.flush();
+                        }
+                    }
+                    KeyCode::Char('b') | KeyCode::Char('B')
+                        if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        if let Err(e) = send_break(&serial_tx, options.break_duration) {
+                            let mut stdout = io::stdout();
+                            let _ = write!(stdout, "\r\nFailed to send BREAK: {}\r\n", e);
+                            let _ = stdout.flush();
+                        }
+                    }
+                    KeyCode::Char('e') | KeyCode::Char('E')
+                        if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        echo = !echo;
+                        let mut stdout = io::stdout();
+                        let state = if echo { "on" } else { "off" };
+                        let _ = write!(stdout, "\r\n[local echo {}]\r\n", state);
+                        let _ = stdout.flush();
                     }
 
                     // Handle other Control characters
@@ -98,9 +458,9 @@ pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
                         // instead of sending literal "c"
                         let byte = c as u8;
                         if (b'a'..=b'z').contains(&byte) {
-                            serial_tx.write_all(&[byte - b'a' + 1])?;
+                            send_key(&serial_tx, &[byte - b'a' + 1], reconnect, echo)?;
                         } else if (b'A'..=b'Z').contains(&byte) {
-                            serial_tx.write_all(&[byte - b'A' + 1])?;
+                            send_key(&serial_tx, &[byte - b'A' + 1], reconnect, echo)?;
                         } else {
                             // Verify specific cases like Ctrl+\, etc if needed.
                             // For now, fallback to raw char if we can't map simply,
@@ -109,7 +469,7 @@ pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
                             // Let's at least try to send what they typed if it's not simple alpha
                             let mut buf = [0; 4];
                             let s = c.encode_utf8(&mut buf);
-                            serial_tx.write_all(s.as_bytes())?;
+                            send_key(&serial_tx, s.as_bytes(), reconnect, echo)?;
                         }
                     }
 
@@ -117,15 +477,12 @@ pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
                     KeyCode::Char(c) => {
                         let mut buf = [0; 4];
                         let s = c.encode_utf8(&mut buf);
-                        serial_tx.write_all(s.as_bytes())?;
+                        send_key(&serial_tx, s.as_bytes(), reconnect, echo)?;
                     }
 
-                    // Handle Backspace (often tricky)
+                    // Handle Backspace: DEL by default, BS for devices that want it
                     KeyCode::Backspace => {
-                        // Send ASCII DEL (0x7F) or BS (0x08) depending on device
-                        // Usually 0x08 (BS) or 0x7F (DEL). Let's try 0x08 first or 0x7F.
-                        // Many terminals send 0x7F for backspace.
-                        serial_tx.write_all(b"\x7F")?;
+                        send_key(&serial_tx, options.backspace.bytes(), reconnect, echo)?;
                     }
 
                     // You might need to handle arrows/special keys here if needed
@@ -135,12 +492,40 @@ pub fn run(port_name: &str, baud_rate: u32) -> anyhow::Result<()> {
     }
 
     // 5. Cleanup
+    // Wait for the RX thread first so a reconnect in progress cannot turn
+    // raw mode back on after we leave it. We set running to false, so it
+    // exits on the next timeout or poll.
+    let _ = rx_thread.join();
+
     disable_raw_mode()?;
     println!("\nDisconnected.");
 
-    // Wait for RX thread to finish (optional, or just let it die with the process)
-    // We set running to false, so it should exit on next timeout or read.
-    let _ = rx_thread.join();
-
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_lines_split_across_reads() {
+        let mut stamper = LineStamper::new();
+        let now = || "T".to_string();
+        let mut out = stamper.process(b"boot", now);
+        out.extend(stamper.process(b"ing...\r\nlog", now));
+        out.extend(stamper.process(b"in: ", now));
+        assert_eq!(out, b"[T] booting...\r\n[T] login: ");
+    }
+
+    #[test]
+    fn echoes_what_the_keys_send() {
+        for eol in [LineEnding::Cr, LineEnding::Lf, LineEnding::Crlf] {
+            assert_eq!(echo_bytes(eol.bytes()), b"\r\n");
+        }
+        for backspace in [BackspaceKey::Del, BackspaceKey::Bs] {
+            assert_eq!(echo_bytes(backspace.bytes()), b"\x08 \x08");
+        }
+        assert_eq!(echo_bytes("é".as_bytes()), "é".as_bytes());
+        assert_eq!(echo_bytes(&[0x03]), b"");
+    }
+}
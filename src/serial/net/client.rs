@@ -3,8 +3,10 @@ use anyhow::{Result, Context};
 use tokio::net::TcpStream;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Duration, Instant};
 use crossterm::terminal::{enable_raw_mode, disable_raw_mode};
 use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use super::AUTH_PREFIX;
 
 struct RawModeGuard;
 impl Drop for RawModeGuard {
@@ -14,21 +16,83 @@ impl Drop for RawModeGuard {
     }
 }
 
-pub async fn run(server: String, port: u16) -> Result<()> {
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Why a bridged session stopped
+enum SessionEnd {
+    /// The user pressed Ctrl + ]
+    Quit,
+    /// The server closed the connection or it failed
+    Disconnected,
+}
+
+pub async fn run(server: String, port: u16, token: Option<String>, reconnect: bool) -> Result<()> {
     let addr = format!("{}:{}", server, port);
     info!("Connecting to {}...", addr);
-    
-    let mut stream = TcpStream::connect(&addr).await.with_context(|| format!("Failed to connect to {}", addr))?;
-    let (mut ri, mut wi) = stream.split();
-    
-    info!("Connected. Press 'Ctrl + ]' to exit.");
-    
-    // Enable raw mode
-    enable_raw_mode()?;
-    let _guard = RawModeGuard;
+    let mut stream = connect(&addr, token.as_deref()).await?;
 
+    // One input thread serves every connection; it ends when the user quits,
+    // which closes the channel
     let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+    spawn_input_thread(tx);
+
+    loop {
+        info!("Connected. Press 'Ctrl + ]' to exit.");
+        if let SessionEnd::Quit = bridge(&mut stream, &mut rx).await? {
+            return Ok(());
+        }
+        if !reconnect {
+            return Ok(());
+        }
+
+        // Raw mode is off again here, so plain output lines up
+        let mut backoff = INITIAL_BACKOFF;
+        stream = loop {
+            println!(
+                "Disconnected from {}, reconnecting in {:.1}s...",
+                addr,
+                backoff.as_secs_f64()
+            );
+            if !wait_unless_quit(&mut rx, backoff).await {
+                return Ok(());
+            }
+            match connect(&addr, token.as_deref()).await {
+                Ok(stream) => break stream,
+                Err(e) => println!("{:#}", e),
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        };
+    }
+}
+
+async fn connect(addr: &str, token: Option<&str>) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .with_context(|| format!("Failed to connect to {}", addr))?;
+    if let Some(token) = token {
+        stream.write_all(format!("{}{}\n", AUTH_PREFIX, token).as_bytes()).await?;
+    }
+    Ok(stream)
+}
+
+/// Sleep for `delay`, dropping keystrokes typed meanwhile. Returns false if
+/// the user quit.
+async fn wait_unless_quit(rx: &mut mpsc::UnboundedReceiver<Vec<u8>>, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        tokio::select! {
+            _ = sleep_until(deadline) => return true,
+            msg = rx.recv() => {
+                if msg.is_none() {
+                    return false;
+                }
+            }
+        }
+    }
+}
 
+fn spawn_input_thread(tx: mpsc::UnboundedSender<Vec<u8>>) {
     // Input thread (Blocking, for crossterm)
     std::thread::spawn(move || {
         loop {
@@ -75,6 +139,19 @@ pub async fn run(server: String, port: u16) -> Result<()> {
              }
         }
     });
+}
+
+/// Relay between the terminal and `stream` in raw mode until either side
+/// ends. Raw mode is restored before returning.
+async fn bridge(
+    stream: &mut TcpStream,
+    rx: &mut mpsc::UnboundedReceiver<Vec<u8>>,
+) -> Result<SessionEnd> {
+    let (mut ri, mut wi) = stream.split();
+
+    // Enable raw mode
+    enable_raw_mode()?;
+    let _guard = RawModeGuard;
 
     let mut buf = [0u8; 2048];
     let mut stdout = tokio::io::stdout();
@@ -90,10 +167,10 @@ pub async fn run(server: String, port: u16) -> Result<()> {
                     }
                     Ok(_) => {
                         // EOF
-                        break;
+                        return Ok(SessionEnd::Disconnected);
                     }
                     Err(_) => {
-                        break;
+                        return Ok(SessionEnd::Disconnected);
                     }
                 }
             }
@@ -103,20 +180,18 @@ pub async fn run(server: String, port: u16) -> Result<()> {
                 match msg {
                     Some(data) => {
                         if wi.write_all(&data).await.is_err() {
-                            break;
+                            return Ok(SessionEnd::Disconnected);
                         }
                         if wi.flush().await.is_err() { // Important for TCP immediateness
-                            break;
+                            return Ok(SessionEnd::Disconnected);
                         }
                     }
                     None => {
                         // User requested exit
-                        break;
+                        return Ok(SessionEnd::Quit);
                     }
                 }
             }
         }
     }
-
-    Ok(())
 }
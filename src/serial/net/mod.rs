@@ -1,2 +1,6 @@
 pub mod client;
-pub mod server;
\ No newline at end of file
+pub mod server;
+
+/// First line a client sends when the server requires a token:
+/// `XTOOL-AUTH <token>\n`
+pub const AUTH_PREFIX: &str = "XTOOL-AUTH ";
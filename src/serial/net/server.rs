@@ -1,12 +1,19 @@
 use anyhow::{Result, Context};
 use crate::serial::config::SerialConfig;
+use super::AUTH_PREFIX;
 use tokio::net::TcpListener;
 use tokio::sync::{broadcast, mpsc};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_serial::SerialPortBuilderExt;
-// Removed std::sync::Arc
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-pub async fn run(uart: Option<String>, baud: Option<u32>, port: Option<u16>, bind: Option<String>, config: Option<SerialConfig>) -> Result<()> {
+/// Idle time before the OS starts probing a silent client
+const KEEPALIVE_TIME: Duration = Duration::from_secs(60);
+
+#[allow(clippy::too_many_arguments)]
+pub async fn run(uart: Option<String>, baud: Option<u32>, port: Option<u16>, bind: Option<String>, read_only_clients: bool, token: Option<String>, idle_timeout: Option<Duration>, config: Option<SerialConfig>) -> Result<()> {
     // Resolve UART and Baud
     let final_uart = uart.or(config.as_ref().and_then(|c| c.uart.clone()));
     let final_baud = baud.or(config.as_ref().and_then(|c| c.baud)).unwrap_or(115200);
@@ -15,6 +22,8 @@ pub async fn run(uart: Option<String>, baud: Option<u32>, port: Option<u16>, bin
     let final_port = port.or(config.as_ref().and_then(|c| c.net_port)).unwrap_or(5432);
     let final_bind = bind.or(config.as_ref().and_then(|c| c.net_bind.clone())).unwrap_or_else(|| "0.0.0.0".to_string());
 
+    let token = token.or(config.as_ref().and_then(|c| c.net_token.clone()));
+
     let uart_name = final_uart.ok_or_else(|| anyhow::anyhow!("Serial port not specified. Please use UART argument or config file."))?;
 
     info!("Starting Netd: Serial <-> TCP Server (Multi-client broadcast)");
@@ -86,18 +95,67 @@ pub async fn run(uart: Option<String>, baud: Option<u32>, port: Option<u16>, bin
     let listener = TcpListener::bind(&addr).await.with_context(|| format!("Failed to bind to {}", addr))?;
     
     info!("Listening on {}", addr);
+    if token.is_some() {
+        info!("Clients must authenticate with a token");
+    }
+    if read_only_clients {
+        info!("Only the first connected client may write; others are read-only viewers");
+    }
+    if let Some(idle) = idle_timeout {
+        info!("Sessions idle for {}s are dropped", idle.as_secs());
+    }
     info!("Ready to accept connections...");
 
+    // Set while a client holds write access (only used with read_only_clients)
+    let writer_taken = Arc::new(AtomicBool::new(false));
+
     loop {
         match listener.accept().await {
-            Ok((socket, peer_addr)) => {
+            Ok((mut socket, peer_addr)) => {
                 info!("Client connected from {}", peer_addr);
-                
+                // Lets the OS notice peers that vanished without closing
+                let keepalive = socket2::TcpKeepalive::new().with_time(KEEPALIVE_TIME);
+                if let Err(e) = socket2::SockRef::from(&socket).set_tcp_keepalive(&keepalive) {
+                    warn!("Failed to enable keepalive for {}: {}", peer_addr, e);
+                }
+
                 let client_b_rx = broadcast_tx.subscribe();
                 let client_m_tx = mpsc_tx.clone();
-                
+                let token = token.clone();
+                let writer_taken = writer_taken.clone();
+
                 tokio::spawn(async move {
-                    handle_client(socket, client_b_rx, client_m_tx, peer_addr).await;
+                    // Authenticate inside the task so a slow client cannot stall accept()
+                    if let Some(expected) = &token {
+                        match read_handshake(&mut socket).await {
+                            Some(line) if token_matches(&line, expected) => {}
+                            _ => {
+                                warn!("Rejected {}: missing or invalid token", peer_addr);
+                                return; // Dropping the socket closes the connection
+                            }
+                        }
+                    }
+
+                    // Without --read-only-clients every client may write.
+                    // With it, the first client to claim the slot is the writer.
+                    let is_writer = read_only_clients
+                        && writer_taken
+                            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                            .is_ok();
+                    if read_only_clients {
+                        if is_writer {
+                            info!("{} is the writer", peer_addr);
+                        } else {
+                            info!("{} joined as read-only viewer", peer_addr);
+                        }
+                    }
+                    let can_write = !read_only_clients || is_writer;
+
+                    handle_client(socket, client_b_rx, client_m_tx, can_write, idle_timeout, peer_addr).await;
+                    // Release write access for the next client
+                    if is_writer {
+                        writer_taken.store(false, Ordering::Release);
+                    }
                 });
             }
             Err(e) => {
@@ -107,13 +165,57 @@ pub async fn run(uart: Option<String>, baud: Option<u32>, port: Option<u16>, bin
     }
 }
 
+/// Read the client's handshake line. Returns `None` on timeout, EOF or a
+/// line longer than any sensible token.
+async fn read_handshake(socket: &mut tokio::net::TcpStream) -> Option<String> {
+    let read_line = async {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while line.len() < 1024 {
+            match socket.read(&mut byte).await {
+                Ok(1) if byte[0] == b'\n' => return String::from_utf8(line).ok(),
+                Ok(1) => line.push(byte[0]),
+                _ => return None,
+            }
+        }
+        None
+    };
+    tokio::time::timeout(Duration::from_secs(5), read_line).await.ok().flatten()
+}
+
+fn token_matches(line: &str, expected: &str) -> bool {
+    let Some(given) = line.trim_end_matches('\r').strip_prefix(AUTH_PREFIX) else {
+        return false;
+    };
+    // Constant-time comparison so the token cannot be guessed byte by byte
+    let (a, b) = (given.as_bytes(), expected.as_bytes());
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 async fn handle_client(
     socket: tokio::net::TcpStream, 
     mut broadcast_rx: broadcast::Receiver<Vec<u8>>, 
     mpsc_tx: mpsc::Sender<Vec<u8>>,
+    can_write: bool,
+    idle_timeout: Option<Duration>,
     peer_addr: std::net::SocketAddr
 ) {
     let (mut socket_read, mut socket_write) = socket.into_split();
+
+    // Milliseconds after `started` at which bytes last moved either way
+    let started = Instant::now();
+    let last_activity = Arc::new(AtomicU64::new(0));
+    let touch = {
+        let last_activity = last_activity.clone();
+        move || last_activity.store(started.elapsed().as_millis() as u64, Ordering::Relaxed)
+    };
+    let touch_read = touch.clone();
+
+    if !can_write {
+        let _ = socket_write
+            .write_all(b"[xtool] read-only: another client has write access\r\n")
+            .await;
+    }
     
     // Client specific tasks container
     let mut handle_read = tokio::task::spawn(async move {
@@ -121,6 +223,10 @@ async fn handle_client(
         loop {
             match socket_read.read(&mut buf).await {
                 Ok(n) if n > 0 => {
+                    touch_read();
+                    if !can_write {
+                        continue; // Viewer input is discarded
+                    }
                     let data = buf[..n].to_vec();
                     if mpsc_tx.send(data).await.is_err() {
                         break; // Serial writer task died?
@@ -133,13 +239,38 @@ async fn handle_client(
     });
 
     let mut handle_write = tokio::task::spawn(async move {
-        while let Ok(data) = broadcast_rx.recv().await {
-            if socket_write.write_all(&data).await.is_err() {
-                break;
+        loop {
+            match broadcast_rx.recv().await {
+                Ok(data) => {
+                    if socket_write.write_all(&data).await.is_err() {
+                        break;
+                    }
+                    touch();
+                }
+                // A slow client missed some output; keep going rather than drop it
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("{} lagged behind, skipped {} chunks", peer_addr, n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
             }
         }
     });
 
+    // Resolves once nothing has moved for `idle_timeout`
+    let idle = async {
+        let Some(limit) = idle_timeout else {
+            return std::future::pending().await;
+        };
+        loop {
+            let last = Duration::from_millis(last_activity.load(Ordering::Relaxed));
+            let idle_for = started.elapsed().saturating_sub(last);
+            if idle_for >= limit {
+                return;
+            }
+            tokio::time::sleep(limit - idle_for).await;
+        }
+    };
+
     // Wait for either direction to fail/finish
     tokio::select! {
         _ = &mut handle_read => {
@@ -148,6 +279,9 @@ async fn handle_client(
         _ = &mut handle_write => {
             // Write loop finished
         }
+        _ = idle => {
+            warn!("Dropping idle session from {}", peer_addr);
+        }
     }
     
     // Cleanup
@@ -0,0 +1,388 @@
+//! XMODEM file transfer (128-byte blocks, CRC-16 with checksum fallback).
+//!
+//! Both directions are generic over `Read + Write` so they work on an opened
+//! `serialport` as well as any other byte stream.
+
+use anyhow::{Context, Result, anyhow, bail};
+use crc::{CRC_16_XMODEM, Crc};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_REQUEST: u8 = b'C';
+const PAD: u8 = 0x1A;
+
+const BLOCK_SIZE: usize = 128;
+const MAX_RETRIES: usize = 10;
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+const BYTE_TIMEOUT: Duration = Duration::from_secs(1);
+const POLL_INTERVAL: Duration = if cfg!(test) {
+    Duration::from_millis(100)
+} else {
+    Duration::from_secs(3)
+};
+/// Unanswered 'C' requests before falling back to checksum mode with NAK
+const CRC_POLLS: usize = 3;
+
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_XMODEM);
+
+/// Send a file to the device on `port_name`.
+pub fn send_file(port_name: &str, baud_rate: u32, path: &Path) -> Result<()> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut port = open_port(port_name, baud_rate)?;
+
+    println!(
+        "Sending {} ({} bytes) via XMODEM, waiting for receiver...",
+        path.display(),
+        data.len()
+    );
+    let pb = progress_bar(Some(data.len() as u64));
+    send(&mut port, &data, |n| pb.set_position(n as u64))?;
+    pb.finish_with_message("Done");
+    Ok(())
+}
+
+/// Receive a file from the device on `port_name` and write it to `path`.
+pub fn receive_file(port_name: &str, baud_rate: u32, path: &Path) -> Result<()> {
+    let mut port = open_port(port_name, baud_rate)?;
+
+    println!("Waiting for XMODEM sender on {}...", port_name);
+    let pb = progress_bar(None);
+    let data = receive(&mut port, |n| pb.set_position(n as u64))?;
+    pb.finish_with_message("Done");
+
+    std::fs::write(path, &data).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Saved {} bytes to {}", data.len(), path.display());
+    Ok(())
+}
+
+fn open_port(port_name: &str, baud_rate: u32) -> Result<Box<dyn serialport::SerialPort>> {
+    serialport::new(port_name, baud_rate)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .with_context(|| format!("Failed to open {}", port_name))
+}
+
+fn progress_bar(total: Option<u64>) -> ProgressBar {
+    match total {
+        Some(total) => {
+            let pb = ProgressBar::new(total);
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template("{msg} {spinner:.green} {bytes}/{total_bytes} ({percent}%) [{bar:40.cyan/blue}] {eta}")
+                    .unwrap()
+                    .progress_chars("=>-"),
+            );
+            pb
+        }
+        None => {
+            let pb = ProgressBar::new_spinner();
+            pb.set_style(
+                ProgressStyle::default_spinner()
+                    .template("{msg} {spinner:.green} {bytes} ({bytes_per_sec})")
+                    .unwrap(),
+            );
+            pb.enable_steady_tick(Duration::from_millis(100));
+            pb
+        }
+    }
+}
+
+/// Send `data`, calling `progress` with the number of bytes acknowledged so far.
+pub fn send<P: Read + Write>(
+    port: &mut P,
+    data: &[u8],
+    mut progress: impl FnMut(usize),
+) -> Result<()> {
+    let use_crc = wait_for_receiver(port)?;
+
+    let blocks = data.chunks(BLOCK_SIZE);
+    for (index, chunk) in blocks.enumerate() {
+        let number = ((index + 1) % 256) as u8;
+        let packet = build_packet(number, chunk, use_crc);
+        send_with_retry(port, &packet, number)?;
+        progress((index * BLOCK_SIZE + chunk.len()).min(data.len()));
+    }
+
+    for _ in 0..MAX_RETRIES {
+        port.write_all(&[EOT])?;
+        port.flush()?;
+        match read_byte(port, ACK_TIMEOUT)? {
+            Some(ACK) => return Ok(()),
+            Some(CAN) => bail!("transfer cancelled by receiver"),
+            _ => continue,
+        }
+    }
+    bail!("receiver did not acknowledge end of transfer")
+}
+
+/// Receive a file, calling `progress` with the number of bytes received so far.
+///
+/// CRC-16 is requested first; senders that only do the 8-bit checksum are
+/// served after `CRC_POLLS` unanswered requests. XMODEM has no length field,
+/// so trailing SUB (0x1A) padding of the final block is stripped.
+pub fn receive<P: Read + Write>(port: &mut P, mut progress: impl FnMut(usize)) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut expected: u8 = 1;
+    let mut started = false;
+    let mut use_crc = true;
+    let mut polls = 0;
+    let mut errors = 0;
+    let start = Instant::now();
+
+    loop {
+        if !started {
+            if start.elapsed() > START_TIMEOUT {
+                bail!("timed out waiting for sender");
+            }
+            use_crc = polls < CRC_POLLS;
+            polls += 1;
+            port.write_all(&[if use_crc { CRC_REQUEST } else { NAK }])?;
+            port.flush()?;
+        }
+
+        let timeout = if started { ACK_TIMEOUT } else { POLL_INTERVAL };
+        let header = match read_byte(port, timeout)? {
+            Some(byte) => byte,
+            None if started => {
+                errors += 1;
+                if errors > MAX_RETRIES {
+                    bail!("too many timeouts waiting for the next block");
+                }
+                nak(port)?;
+                continue;
+            }
+            None => continue,
+        };
+
+        let size = match header {
+            SOH => BLOCK_SIZE,
+            STX => 1024,
+            EOT => {
+                port.write_all(&[ACK])?;
+                port.flush()?;
+                while data.last() == Some(&PAD) {
+                    data.pop();
+                }
+                return Ok(data);
+            }
+            CAN => {
+                if read_byte(port, BYTE_TIMEOUT)? == Some(CAN) {
+                    bail!("transfer cancelled by sender");
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        started = true;
+
+        let check_len = if use_crc { 2 } else { 1 };
+        let mut packet = vec![0u8; size + 2 + check_len];
+        if !read_exact_timeout(port, &mut packet)? {
+            errors += 1;
+            if errors > MAX_RETRIES {
+                cancel(port)?;
+                bail!("too many errors receiving block {expected}");
+            }
+            nak(port)?;
+            continue;
+        }
+
+        let (number, complement) = (packet[0], packet[1]);
+        let payload = &packet[2..2 + size];
+        let valid = if use_crc {
+            u16::from_be_bytes([packet[2 + size], packet[3 + size]]) == CRC16.checksum(payload)
+        } else {
+            packet[2 + size] == payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+        };
+        if number != !complement || !valid {
+            errors += 1;
+            if errors > MAX_RETRIES {
+                cancel(port)?;
+                bail!("too many errors receiving block {expected}");
+            }
+            nak(port)?;
+            continue;
+        }
+
+        if number == expected {
+            data.extend_from_slice(payload);
+            expected = expected.wrapping_add(1);
+            errors = 0;
+            progress(data.len());
+        } else if number != expected.wrapping_sub(1) {
+            cancel(port)?;
+            bail!("block sequence error: expected {expected}, got {number}");
+        }
+        // Duplicates of the previous block are acknowledged and dropped.
+        port.write_all(&[ACK])?;
+        port.flush()?;
+    }
+}
+
+/// Wait for the receiver's start request. Returns true for CRC mode.
+fn wait_for_receiver<P: Read + Write>(port: &mut P) -> Result<bool> {
+    let start = Instant::now();
+    while start.elapsed() < START_TIMEOUT {
+        match read_byte(port, POLL_INTERVAL)? {
+            Some(CRC_REQUEST) => return Ok(true),
+            Some(NAK) => return Ok(false),
+            Some(CAN) => bail!("transfer cancelled by receiver"),
+            _ => {}
+        }
+    }
+    bail!("timed out waiting for receiver to start")
+}
+
+fn send_with_retry<P: Read + Write>(port: &mut P, packet: &[u8], number: u8) -> Result<()> {
+    for _ in 0..MAX_RETRIES {
+        port.write_all(packet)?;
+        port.flush()?;
+        // Ignore line noise (e.g. late 'C' start requests); only NAK or
+        // silence triggers a resend.
+        loop {
+            match read_byte(port, ACK_TIMEOUT)? {
+                Some(ACK) => return Ok(()),
+                Some(CAN) => bail!("transfer cancelled by receiver"),
+                Some(NAK) | None => break,
+                Some(_) => {}
+            }
+        }
+    }
+    cancel(port)?;
+    Err(anyhow!(
+        "block {number} not acknowledged after {MAX_RETRIES} attempts"
+    ))
+}
+
+fn build_packet(number: u8, chunk: &[u8], use_crc: bool) -> Vec<u8> {
+    let mut payload = [PAD; BLOCK_SIZE];
+    payload[..chunk.len()].copy_from_slice(chunk);
+
+    let mut packet = Vec::with_capacity(BLOCK_SIZE + 5);
+    packet.extend_from_slice(&[SOH, number, !number]);
+    packet.extend_from_slice(&payload);
+    if use_crc {
+        packet.extend_from_slice(&CRC16.checksum(&payload).to_be_bytes());
+    } else {
+        packet.push(payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+    }
+    packet
+}
+
+fn nak<P: Write>(port: &mut P) -> Result<()> {
+    port.write_all(&[NAK])?;
+    port.flush()?;
+    Ok(())
+}
+
+fn cancel<P: Write>(port: &mut P) -> Result<()> {
+    port.write_all(&[CAN, CAN])?;
+    port.flush()?;
+    Ok(())
+}
+
+/// Read one byte, returning `None` if nothing arrives within `timeout`.
+fn read_byte<P: Read>(port: &mut P, timeout: Duration) -> Result<Option<u8>> {
+    let deadline = Instant::now() + timeout;
+    let mut byte = [0u8; 1];
+    while Instant::now() < deadline {
+        match port.read(&mut byte) {
+            Ok(1) => return Ok(Some(byte[0])),
+            Ok(_) => {}
+            Err(e) if is_timeout(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(None)
+}
+
+/// Fill `buf`, giving up if the line goes quiet for longer than a byte timeout.
+fn read_exact_timeout<P: Read>(port: &mut P, buf: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    let mut last = Instant::now();
+    while filled < buf.len() {
+        match port.read(&mut buf[filled..]) {
+            Ok(0) => {}
+            Ok(n) => {
+                filled += n;
+                last = Instant::now();
+                continue;
+            }
+            Err(e) if is_timeout(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        if last.elapsed() > BYTE_TIMEOUT {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn transfers_file_over_stream() {
+        use std::os::unix::net::UnixStream;
+
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        for s in [&a, &b] {
+            s.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        }
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 200) as u8).collect();
+        let expected = data.clone();
+
+        let sender = std::thread::spawn(move || send(&mut a, &data, |_| {}));
+        let received = receive(&mut b, |_| {}).unwrap();
+        sender.join().unwrap().unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn falls_back_to_checksum_for_plain_senders() {
+        use std::os::unix::net::UnixStream;
+
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        for s in [&a, &b] {
+            s.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        }
+        // A sender that ignores 'C' and only starts on NAK
+        let sender = std::thread::spawn(move || {
+            while read_byte(&mut a, START_TIMEOUT).unwrap() != Some(NAK) {}
+            a.write_all(&build_packet(1, b"plain", false)).unwrap();
+            assert_eq!(read_byte(&mut a, ACK_TIMEOUT).unwrap(), Some(ACK));
+            a.write_all(&[EOT]).unwrap();
+            assert_eq!(read_byte(&mut a, ACK_TIMEOUT).unwrap(), Some(ACK));
+        });
+        let received = receive(&mut b, |_| {}).unwrap();
+        sender.join().unwrap();
+        assert_eq!(received, b"plain");
+    }
+
+    #[test]
+    fn builds_crc_packet() {
+        let packet = build_packet(1, b"hi", true);
+        assert_eq!(&packet[..3], &[SOH, 1, 0xFE]);
+        assert_eq!(packet.len(), 3 + BLOCK_SIZE + 2);
+        assert_eq!(packet[5], PAD);
+    }
+}
@@ -1,11 +1,118 @@
+use std::collections::VecDeque;
+use std::fmt;
 use std::fs::File;
-use std::io::{Read, Seek, Write};
+use std::io::{self, Read, Write};
 use std::net::{IpAddr, SocketAddr, UdpSocket};
 use std::path::Path;
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
 
 use super::config::ClientConfig;
-use crate::tftp::core::{OptionType, Packet, TransferOption};
+use crate::tftp::core::options::{
+    DEFAULT_BLOCK_SIZE, DEFAULT_WINDOW_SIZE, OptionsProtocol, RequestType, Rollover,
+};
+use crate::tftp::core::{ErrorCode, OptionType, Packet, TransferOption};
+use crate::utils::format_size;
+
+/// Suffix of the companion file holding a download's SHA-256
+const HASH_SUFFIX: &str = ".sha256";
+
+/// Transfer parameters the server agreed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiatedOptions {
+    pub block_size: u16,
+    pub window_size: u16,
+    pub timeout: Duration,
+    /// File size reported by the server, if it acknowledged `tsize`
+    pub transfer_size: Option<u64>,
+}
+
+impl NegotiatedOptions {
+    /// What applies when the server ignores every option (RFC 1350 behavior)
+    fn defaults(timeout: Duration) -> Self {
+        Self {
+            block_size: DEFAULT_BLOCK_SIZE,
+            window_size: DEFAULT_WINDOW_SIZE,
+            timeout,
+            transfer_size: None,
+        }
+    }
+
+    /// Read the options acknowledged in an OACK. Options the server left
+    /// out fall back to their defaults; an unacknowledged timeout keeps
+    /// the client's own.
+    fn from_oack(mut options: Vec<TransferOption>, timeout: Duration) -> anyhow::Result<Self> {
+        let timeout_acked = options
+            .iter()
+            .any(|o| matches!(o.option, OptionType::Timeout | OptionType::TimeoutMs));
+        let parsed = OptionsProtocol::parse(&mut options, RequestType::Write)?;
+        Ok(Self {
+            block_size: parsed.block_size,
+            window_size: parsed.window_size,
+            timeout: if timeout_acked {
+                parsed.timeout
+            } else {
+                timeout
+            },
+            transfer_size: parsed.transfer_size,
+        })
+    }
+}
+
+/// An ERROR packet from the server, kept as its own type so a missing file
+/// can be told apart from other failures
+#[derive(Debug)]
+struct ServerError {
+    code: ErrorCode,
+    msg: String,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TFTP Error {:?}: {}", self.code, self.msg)
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// Block number that follows `block` when sending, or `None` if `rollover`
+/// forbids going past 65535.
+fn next_block_tx(block: u16, rollover: Rollover) -> Option<u16> {
+    match (block.checked_add(1), rollover) {
+        (Some(next), _) => Some(next),
+        (None, Rollover::None) => None,
+        (None, Rollover::Enforce1) => Some(1),
+        (None, Rollover::Enforce0 | Rollover::DontCare) => Some(0),
+    }
+}
+
+/// Whether `received` is the block after `last` under `rollover`. After
+/// 65535 `dont_care` takes either 0 or 1, whichever the server sent, so
+/// with windows it cannot tell a lost block 0 from the server wrapping to 1.
+fn is_next_block_rx(last: u16, received: u16, rollover: Rollover) -> bool {
+    match (last.checked_add(1), rollover) {
+        (Some(next), _) => received == next,
+        (None, Rollover::None) => false,
+        (None, Rollover::Enforce0) => received == 0,
+        (None, Rollover::Enforce1) => received == 1,
+        (None, Rollover::DontCare) => received <= 1,
+    }
+}
+
+/// One-line summary such as `Downloaded 4.2 MiB in 1.3s (3.2 MiB/s)`
+fn transfer_summary(verb: &str, bytes: u64, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { bytes as f64 / secs } else { 0.0 };
+    format!(
+        "{} {} in {:.1}s ({}/s)",
+        verb,
+        format_size(bytes),
+        secs,
+        format_size(rate as u64)
+    )
+}
 
 /// TFTP client
 ///
@@ -15,8 +122,13 @@ pub struct Client {
     server_port: u16,
     block_size: u16,
     timeout: Duration,
+    initial_timeout: Duration,
+    max_retries: u32,
     window_size: u16,
     mode: String,
+    rollover: Rollover,
+    verify_hash: bool,
+    last_negotiated: Mutex<Option<NegotiatedOptions>>,
 }
 
 impl Client {
@@ -29,54 +141,125 @@ impl Client {
             .parse()
             .map_err(|e| anyhow::anyhow!("Invalid server address '{}': {}", server_str, e))?;
 
+        let timeout = config.timeout.unwrap_or(Duration::from_secs(5));
         Ok(Self {
             server_ip,
             server_port: config.port.unwrap_or(69),
             block_size: config.block_size.unwrap_or(512),
-            timeout: config.timeout.unwrap_or(Duration::from_secs(5)),
+            timeout,
+            initial_timeout: config.initial_timeout.unwrap_or(timeout),
+            max_retries: config.max_retries.unwrap_or(5),
             window_size: config.window_size.unwrap_or(1),
             mode: config.mode.unwrap_or_else(|| "octet".to_string()),
+            rollover: config.rollover.unwrap_or(Rollover::Enforce0),
+            verify_hash: config.verify_hash.unwrap_or(false),
+            last_negotiated: Mutex::new(None),
         })
     }
 
-    fn build_options(&self, transfer_size: u64) -> Vec<TransferOption> {
-        let mut options = Vec::new();
-
-        options.push(TransferOption {
-            option: OptionType::BlockSize,
-            value: self.block_size as u64,
-        });
-
-        options.push(TransferOption {
-            option: OptionType::Timeout,
-            value: self.timeout.as_secs(),
-        });
+    /// Options in effect for the most recent successful `get` or `put`.
+    pub fn last_negotiated(&self) -> Option<NegotiatedOptions> {
+        *self
+            .last_negotiated
+            .lock()
+            .expect("negotiated options lock poisoned")
+    }
 
-        options.push(TransferOption {
-            option: OptionType::WindowSize,
-            value: self.window_size as u64,
-        });
+    fn set_last_negotiated(&self, negotiated: Option<NegotiatedOptions>) {
+        *self
+            .last_negotiated
+            .lock()
+            .expect("negotiated options lock poisoned") = negotiated;
+    }
 
-        if transfer_size > 0 {
-            options.push(TransferOption {
+    fn build_options(&self, transfer_size: u64) -> Vec<TransferOption> {
+        vec![
+            TransferOption {
+                option: OptionType::BlockSize,
+                value: self.block_size as u64,
+            },
+            TransferOption {
+                option: OptionType::Timeout,
+                value: self.timeout.as_secs(),
+            },
+            TransferOption {
+                option: OptionType::WindowSize,
+                value: self.window_size as u64,
+            },
+            // On a read, tsize 0 asks the server to report the file size
+            TransferOption {
                 option: OptionType::TransferSize,
                 value: transfer_size,
-            });
-        }
-
-        options
+            },
+        ]
     }
 
-    /// Download a file from the server (RRQ - Read Request)
+    /// Download a file from the server (RRQ - Read Request). With
+    /// `verify_hash` set, the download is then checked against
+    /// `<remote_file>.sha256` if the server has one.
     pub fn get(&self, remote_file: &str, local_file: &Path) -> anyhow::Result<()> {
         log::info!("Downloading {} to {}", remote_file, local_file.display());
+        let mut file = File::create(local_file)?;
+        self.receive(remote_file, &mut file)?;
+        drop(file);
+
+        if self.verify_hash {
+            self.verify_download(remote_file, local_file)?;
+        }
+        Ok(())
+    }
+
+    /// Fetch `<remote_file>.sha256` and compare it with the SHA-256 of
+    /// `local_file`. A server without the manifest only earns a warning.
+    fn verify_download(&self, remote_file: &str, local_file: &Path) -> anyhow::Result<()> {
+        let manifest_name = format!("{remote_file}{HASH_SUFFIX}");
+        let negotiated = self.last_negotiated();
+        let mut manifest = Vec::new();
+        let fetched = self.receive(&manifest_name, &mut manifest);
+        // Report the options of the file itself, not of its manifest
+        self.set_last_negotiated(negotiated);
+        match fetched {
+            Ok(()) => {}
+            Err(err)
+                if err
+                    .downcast_ref::<ServerError>()
+                    .is_some_and(|e| e.code == ErrorCode::FileNotFound) =>
+            {
+                log::warn!("{manifest_name} not found on server, skipping hash check");
+                return Ok(());
+            }
+            Err(err) => return Err(err.context(format!("Failed to fetch {manifest_name}"))),
+        }
+
+        let expected = parse_hash_manifest(&manifest)
+            .ok_or_else(|| anyhow::anyhow!("{manifest_name} does not start with a SHA-256 hash"))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(local_file)?, &mut hasher)?;
+        let actual = hex(&hasher.finalize());
+        if actual != expected {
+            return Err(anyhow::anyhow!(
+                "SHA-256 mismatch for {}: expected {}, got {}",
+                local_file.display(),
+                expected,
+                actual
+            ));
+        }
+        log::info!("SHA-256 verified: {}", actual);
+        Ok(())
+    }
+
+    /// Run one RRQ for `remote_file`, writing the data to `out`.
+    fn receive(&self, remote_file: &str, out: &mut impl Write) -> anyhow::Result<()> {
+        let started = Instant::now();
 
         // Create local socket
         let socket = UdpSocket::bind("0.0.0.0:0")?;
         let mut server_addr = SocketAddr::new(self.server_ip, self.server_port);
         let mut tid_set = false;
 
-        socket.set_read_timeout(Some(self.timeout))?;
+        // The first reply may take longer than later blocks (e.g. the server
+        // opening a large file), so it gets its own deadline
+        socket.set_read_timeout(Some(self.initial_timeout))?;
         socket.set_write_timeout(Some(self.timeout))?;
 
         // Build options
@@ -92,20 +275,32 @@ impl Client {
         socket.send_to(&bytes, server_addr)?;
 
         // Receive file
-        let mut file = File::create(local_file)?;
-        let mut block_num: u16 = 1;
+        // Wire number of the last block written, and how many were written
+        let mut last_block: u16 = 0;
+        let mut blocks: u64 = 0;
+        // Blocks received since the last ACK went out
+        let mut unacked: u16 = 0;
+        let mut received: u64 = 0;
         let mut retries = 0;
-        let max_retries = 5;
+        let max_retries = self.max_retries;
+        let mut negotiated: Option<NegotiatedOptions> = None;
+        self.set_last_negotiated(None);
 
         loop {
-            let mut buf = vec![0; self.block_size as usize + 4];
+            // The server may grant less than requested, never more
+            let mut buf = vec![0; self.block_size.max(DEFAULT_BLOCK_SIZE) as usize + 4];
             match socket.recv_from(&mut buf) {
                 Ok((amt, src)) => {
                     if !tid_set {
                         if src.ip() == self.server_ip {
                             server_addr = src;
                             tid_set = true;
+                            socket.set_read_timeout(Some(self.timeout))?;
                         } else {
+                            log::warn!(
+                                "Ignoring reply from {src}: expected {}; is the server bound to a specific address?",
+                                self.server_ip
+                            );
                             continue;
                         }
                     } else if src != server_addr {
@@ -118,27 +313,44 @@ impl Client {
                             block_num: block,
                             data,
                         } => {
-                            if block == block_num {
-                                file.write_all(&data)?;
-
-                                // Send ACK
-                                let ack = Packet::Ack(block);
-                                socket.send_to(&ack.serialize()?, server_addr)?;
-
-                                block_num = block_num.wrapping_add(1);
+                            // Data without an OACK means the server ignored our options
+                            let agreed = *negotiated
+                                .get_or_insert_with(|| NegotiatedOptions::defaults(self.timeout));
+                            if is_next_block_rx(last_block, block, self.rollover) {
+                                out.write_all(&data)?;
+                                received += data.len() as u64;
+
+                                last_block = block;
+                                blocks += 1;
+                                unacked += 1;
                                 retries = 0;
 
-                                if data.len() < self.block_size as usize {
+                                // Only the last block of each window, or of
+                                // the file, is acknowledged (RFC 7440)
+                                let last = data.len() < agreed.block_size as usize;
+                                if last || unacked == agreed.window_size {
+                                    let ack = Packet::Ack(block);
+                                    socket.send_to(&ack.serialize()?, server_addr)?;
+                                    unacked = 0;
+                                }
+
+                                if last {
                                     break; // End of file
                                 }
+                            } else if last_block == u16::MAX && self.rollover == Rollover::None {
+                                return Err(anyhow::anyhow!(
+                                    "File needs more than 65535 blocks, but block rollover is disabled"
+                                ));
                             }
                         }
                         Packet::Error { code, msg } => {
-                            return Err(anyhow::anyhow!("TFTP Error {:?}: {}", code, msg));
+                            return Err(ServerError { code, msg }.into());
                         }
-                        Packet::Oack(_) => {
+                        Packet::Oack(options) => {
                             // Handle option negotiation
-                            if block_num == 1 {
+                            if blocks == 0 {
+                                negotiated =
+                                    Some(NegotiatedOptions::from_oack(options, self.timeout)?);
                                 // Send ACK 0 to confirm options
                                 let ack = Packet::Ack(0);
                                 socket.send_to(&ack.serialize()?, server_addr)?;
@@ -157,20 +369,33 @@ impl Client {
                     retries += 1;
                     log::warn!("Timeout, retrying... ({}/{})", retries, max_retries);
 
-                    // Resend last ACK
-                    let ack = Packet::Ack(block_num.wrapping_sub(1));
+                    // Resend last ACK; the server starts a new window after it
+                    let ack = Packet::Ack(last_block);
                     socket.send_to(&ack.serialize()?, server_addr)?;
+                    unacked = 0;
                 }
                 Err(e) => return Err(e.into()),
             }
         }
 
+        if let Some(expected) = negotiated.and_then(|n| n.transfer_size)
+            && expected != received
+        {
+            log::warn!(
+                "Server announced {} bytes but sent {} bytes",
+                expected,
+                received
+            );
+        }
+        log::info!("{}", transfer_summary("Downloaded", received, started.elapsed()));
+        self.set_last_negotiated(negotiated);
         Ok(())
     }
 
     /// Upload a file to the server (WRQ - Write Request)
     pub fn put(&self, local_file: &Path, remote_file: &str) -> anyhow::Result<()> {
         log::info!("Uploading {} to {}", local_file.display(), remote_file);
+        let started = Instant::now();
 
         let mut file = File::open(local_file)?;
         let file_size = file.metadata()?.len();
@@ -180,7 +405,9 @@ impl Client {
         let mut server_addr = SocketAddr::new(self.server_ip, self.server_port);
         let mut tid_set = false;
 
-        socket.set_read_timeout(Some(self.timeout))?;
+        // The first reply may take longer than later blocks (e.g. the server
+        // opening a large file), so it gets its own deadline
+        socket.set_read_timeout(Some(self.initial_timeout))?;
         socket.set_write_timeout(Some(self.timeout))?;
 
         // Build options
@@ -195,20 +422,31 @@ impl Client {
         let bytes = wrq.serialize()?;
         socket.send_to(&bytes, server_addr)?;
 
+        // DATA packets sent but not yet acknowledged, oldest first, with their
+        // wire block numbers; never more than one window of them
+        let mut pending: VecDeque<(u16, Vec<u8>)> = VecDeque::new();
         let mut block_num: u16 = 0;
+        let mut sent: u64 = 0;
         let mut retries = 0;
-        let max_retries = 5;
+        let max_retries = self.max_retries;
         let mut finished = false;
+        let mut negotiated: Option<NegotiatedOptions> = None;
+        self.set_last_negotiated(None);
 
         loop {
-            let mut buf = vec![0; self.block_size as usize + 4];
+            let mut buf = vec![0; self.block_size.max(DEFAULT_BLOCK_SIZE) as usize + 4];
             match socket.recv_from(&mut buf) {
                 Ok((amt, src)) => {
                     if !tid_set {
                         if src.ip() == self.server_ip {
                             server_addr = src;
                             tid_set = true;
+                            socket.set_read_timeout(Some(self.timeout))?;
                         } else {
+                            log::warn!(
+                                "Ignoring reply from {src}: expected {}; is the server bound to a specific address?",
+                                self.server_ip
+                            );
                             continue;
                         }
                     } else if src != server_addr {
@@ -216,54 +454,51 @@ impl Client {
                     }
 
                     let packet = Packet::deserialize(&buf[..amt])?;
-                    match packet {
-                        Packet::Ack(block) => {
-                            if block == block_num {
-                                if finished {
-                                    break;
-                                }
-
-                                block_num = block_num.wrapping_add(1);
-
-                                // Read next block
-                                let mut data = vec![0; self.block_size as usize];
-                                let n = file.read(&mut data)?;
-                                data.truncate(n);
-
-                                if n < self.block_size as usize {
-                                    finished = true;
-                                }
-
-                                // Send Data
-                                let data_packet = Packet::Data { block_num, data };
-                                socket.send_to(&data_packet.serialize()?, server_addr)?;
-
-                                retries = 0;
-                            }
+                    let agreed = match packet {
+                        // ACK 0 instead of an OACK: the server ignored our options
+                        Packet::Ack(0) if negotiated.is_none() => {
+                            *negotiated.insert(NegotiatedOptions::defaults(self.timeout))
                         }
-                        Packet::Oack(_) => {
-                            if block_num == 0 {
-                                // OACK received, start sending data (block 1)
-                                block_num = 1;
-
-                                let mut data = vec![0; self.block_size as usize];
-                                let n = file.read(&mut data)?;
-                                data.truncate(n);
-
-                                if n < self.block_size as usize {
-                                    finished = true;
-                                }
-
-                                let data_packet = Packet::Data { block_num, data };
-                                socket.send_to(&data_packet.serialize()?, server_addr)?;
-
-                                retries = 0;
-                            }
+                        Packet::Oack(options) if negotiated.is_none() => {
+                            *negotiated.insert(NegotiatedOptions::from_oack(options, self.timeout)?)
+                        }
+                        Packet::Ack(block) => {
+                            // Stale or duplicate ACKs match nothing still pending
+                            let Some(index) = pending.iter().position(|(b, _)| *b == block) else {
+                                continue;
+                            };
+                            pending.drain(..=index);
+                            negotiated.expect("data is only sent after negotiation")
                         }
                         Packet::Error { code, msg } => {
-                            return Err(anyhow::anyhow!("TFTP Error {:?}: {}", code, msg));
+                            return Err(ServerError { code, msg }.into());
                         }
-                        _ => {}
+                        _ => continue,
+                    };
+                    retries = 0;
+                    if finished && pending.is_empty() {
+                        break;
+                    }
+
+                    // An ACK short of the window's end means the rest was
+                    // lost: go back and resend it, then top the window up
+                    for (_, bytes) in &pending {
+                        socket.send_to(bytes, server_addr)?;
+                    }
+                    while !finished && pending.len() < agreed.window_size as usize {
+                        block_num = next_block_tx(block_num, self.rollover).ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "File needs more than 65535 blocks, but block rollover is disabled"
+                            )
+                        })?;
+
+                        let data = read_block(&mut file, agreed.block_size)?;
+                        sent += data.len() as u64;
+                        finished = data.len() < agreed.block_size as usize;
+
+                        let bytes = Packet::Data { block_num, data }.serialize()?;
+                        socket.send_to(&bytes, server_addr)?;
+                        pending.push_back((block_num, bytes));
                     }
                 }
                 Err(e)
@@ -276,39 +511,94 @@ impl Client {
                     retries += 1;
                     log::warn!("Timeout, retrying... ({}/{})", retries, max_retries);
 
-                    // Resend last packet (WRQ or Data)
-                    if block_num == 0 {
-                        // Resend WRQ
+                    // Resend the unacknowledged window, or the WRQ if nothing
+                    // has been agreed yet
+                    if negotiated.is_some() {
+                        for (_, bytes) in &pending {
+                            socket.send_to(bytes, server_addr)?;
+                        }
+                    } else {
                         let wrq = Packet::Wrq {
                             filename: remote_file.to_string(),
                             mode: self.mode.clone(),
                             options: self.build_options(file_size),
                         };
                         socket.send_to(&wrq.serialize()?, server_addr)?;
-                    } else {
-                        // Resend Data
-                        // We need to seek back in file?
-                        // For simplicity in this refactor, we just error or warn.
-                        // Proper retry for data requires caching the last data packet or seeking.
-                        // Since we don't have the last data packet easily available here without restructuring,
-                        // we will just log a warning that retry might fail if we don't resend data.
-                        // Actually, we can seek back.
-
-                        let offset = (block_num as u64 - 1) * (self.block_size as u64);
-                        file.seek(std::io::SeekFrom::Start(offset))?;
-
-                        let mut data = vec![0; self.block_size as usize];
-                        let n = file.read(&mut data)?;
-                        data.truncate(n);
-
-                        let data_packet = Packet::Data { block_num, data };
-                        socket.send_to(&data_packet.serialize()?, server_addr)?;
                     }
                 }
                 Err(e) => return Err(e.into()),
             }
         }
 
+        log::info!("{}", transfer_summary("Uploaded", sent, started.elapsed()));
+        self.set_last_negotiated(negotiated);
         Ok(())
     }
 }
+
+/// The hash from a `sha256sum`-style manifest (`<hex>  <name>`, or just the
+/// hex digest), in lower case.
+fn parse_hash_manifest(manifest: &[u8]) -> Option<String> {
+    let hash = std::str::from_utf8(manifest).ok()?.split_whitespace().next()?;
+    (hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| hash.to_ascii_lowercase())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Read up to `block_size` bytes; fewer only at the end of the file.
+fn read_block(file: &mut File, block_size: u16) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(block_size as usize);
+    file.take(block_size as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_transfer_speed() {
+        assert_eq!(
+            transfer_summary("Downloaded", 4_404_019, Duration::from_millis(1300)),
+            "Downloaded 4.2 MiB in 1.3s (3.2 MiB/s)"
+        );
+        assert_eq!(
+            transfer_summary("Uploaded", 100, Duration::ZERO),
+            "Uploaded 100 B in 0.0s (0 B/s)"
+        );
+    }
+
+    #[test]
+    fn wraps_block_numbers_by_policy() {
+        assert_eq!(next_block_tx(1, Rollover::None), Some(2));
+        assert_eq!(next_block_tx(u16::MAX, Rollover::None), None);
+        assert_eq!(next_block_tx(u16::MAX, Rollover::Enforce0), Some(0));
+        assert_eq!(next_block_tx(u16::MAX, Rollover::Enforce1), Some(1));
+        assert_eq!(next_block_tx(u16::MAX, Rollover::DontCare), Some(0));
+
+        assert!(is_next_block_rx(0, 1, Rollover::None));
+        assert!(!is_next_block_rx(5, 5, Rollover::DontCare));
+        assert!(!is_next_block_rx(u16::MAX, 0, Rollover::None));
+        assert!(is_next_block_rx(u16::MAX, 0, Rollover::Enforce0));
+        assert!(!is_next_block_rx(u16::MAX, 1, Rollover::Enforce0));
+        assert!(is_next_block_rx(u16::MAX, 1, Rollover::Enforce1));
+        assert!(!is_next_block_rx(u16::MAX, 0, Rollover::Enforce1));
+        assert!(is_next_block_rx(u16::MAX, 0, Rollover::DontCare));
+        assert!(is_next_block_rx(u16::MAX, 1, Rollover::DontCare));
+        assert!(!is_next_block_rx(u16::MAX, 2, Rollover::DontCare));
+    }
+
+    #[test]
+    fn parses_sha256sum_manifests() {
+        let hash = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+        let lower = hash.to_ascii_lowercase();
+        assert_eq!(parse_hash_manifest(hash.as_bytes()), Some(lower.clone()));
+        let line = format!("{lower}  firmware.bin\n");
+        assert_eq!(parse_hash_manifest(line.as_bytes()), Some(lower));
+        assert_eq!(parse_hash_manifest(b"abc123  firmware.bin"), None);
+        assert_eq!(parse_hash_manifest(b""), None);
+    }
+}
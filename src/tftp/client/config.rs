@@ -1,7 +1,13 @@
+use crate::tftp::core::options::Rollover;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+/// Block sizes allowed by RFC 2348
+const MIN_BLOCK_SIZE: u16 = 8;
+const MAX_BLOCK_SIZE: u16 = 65464;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 pub struct TftpcConfigFile {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub get: Option<ClientConfig>,
@@ -10,6 +16,7 @@ pub struct TftpcConfigFile {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 pub struct ClientConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub server: Option<String>,
@@ -17,23 +24,87 @@ pub struct ClientConfig {
     pub port: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub block_size: Option<u16>,
-    #[serde(skip_serializing_if = "Option::is_none", with = "humantime_serde")]
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "humantime_serde")]
     pub timeout: Option<Duration>,
+    /// How long to wait for the server's first reply (OACK, DATA or ACK);
+    /// defaults to `timeout`
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "humantime_serde")]
+    pub initial_timeout: Option<Duration>,
+    /// Consecutive timeouts tolerated before the transfer is abandoned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub window_size: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mode: Option<String>,
+    /// Block counter policy past block 65535; must match the server's.
+    /// Defaults to `enforce0`, like the server. `dont_care` accepts either
+    /// 0 or 1 after 65535 when receiving, which is only safe with a window
+    /// of 1: a lost block 0 followed by block 1 would go unnoticed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollover: Option<Rollover>,
+    /// After a download, fetch `<file>.sha256` from the server (if it has
+    /// one) and check the download against it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_hash: Option<bool>,
+}
+
+impl TftpcConfigFile {
+    /// Append a message for each invalid value to `problems`.
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if let Some(get) = &self.get {
+            get.validate("tftpc.get", problems);
+        }
+        if let Some(put) = &self.put {
+            put.validate("tftpc.put", problems);
+        }
+    }
 }
 
 impl ClientConfig {
+    pub fn validate(&self, section: &str, problems: &mut Vec<String>) {
+        if self.server.as_deref().is_some_and(|s| s.trim().is_empty()) {
+            problems.push(format!("{section}.server: must not be empty"));
+        }
+        if self.port == Some(0) {
+            problems.push(format!("{section}.port: must not be 0"));
+        }
+        if let Some(size) = self.block_size
+            && !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&size)
+        {
+            problems.push(format!(
+                "{section}.block_size: {size} is outside {MIN_BLOCK_SIZE}-{MAX_BLOCK_SIZE}"
+            ));
+        }
+        if self.timeout.is_some_and(|t| t.is_zero()) {
+            problems.push(format!("{section}.timeout: must be greater than 0"));
+        }
+        if self.initial_timeout.is_some_and(|t| t.is_zero()) {
+            problems.push(format!("{section}.initial_timeout: must be greater than 0"));
+        }
+        if self.window_size == Some(0) {
+            problems.push(format!("{section}.window_size: must be at least 1"));
+        }
+        if let Some(mode) = &self.mode
+            && !mode.eq_ignore_ascii_case("octet")
+            && !mode.eq_ignore_ascii_case("netascii")
+        {
+            problems.push(format!("{section}.mode: {mode:?} is not \"octet\" or \"netascii\""));
+        }
+    }
+
     pub fn new(server: String, port: u16) -> Self {
         Self {
             server: Some(server),
             port: Some(port),
             block_size: Some(512),
             timeout: Some(Duration::from_secs(5)),
+            initial_timeout: None,
+            max_retries: Some(5),
             window_size: Some(1),
             mode: Some("octet".to_string()),
+            rollover: None,
+            verify_hash: None,
         }
     }
 
@@ -43,6 +114,8 @@ impl ClientConfig {
         cli_port: u16,
         cli_block_size: u16,
         cli_timeout: u64,
+        cli_retries: u32,
+        cli_initial_timeout: Option<u64>,
     ) -> Self {
         // CLI args are used if config file doesn't specify them
         // (Matching previous behavior: File > CLI)
@@ -58,6 +131,12 @@ impl ClientConfig {
         if self.timeout.is_none() {
             self.timeout = Some(Duration::from_secs(cli_timeout));
         }
+        if self.initial_timeout.is_none() {
+            self.initial_timeout = cli_initial_timeout.map(Duration::from_secs);
+        }
+        if self.max_retries.is_none() {
+            self.max_retries = Some(cli_retries);
+        }
         if self.window_size.is_none() {
             self.window_size = Some(1);
         }
@@ -79,9 +158,39 @@ impl ClientConfig {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn with_initial_timeout(mut self, timeout: Duration) -> Self {
+        self.initial_timeout = Some(timeout);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
     #[allow(dead_code)]
     pub fn with_window_size(mut self, window_size: u16) -> Self {
         self.window_size = Some(window_size);
         self
     }
+
+    #[allow(dead_code)]
+    pub fn with_rollover(mut self, rollover: Rollover) -> Self {
+        self.rollover = Some(rollover);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_mode(mut self, mode: &str) -> Self {
+        self.mode = Some(mode.to_string());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_verify_hash(mut self, verify_hash: bool) -> Self {
+        self.verify_hash = Some(verify_hash);
+        self
+    }
 }
@@ -39,16 +39,20 @@
 //!
 //! # Upload file
 //! xtool tftpc put 192.168.1.100 local.txt [remote.txt]
+//!
+//! # Interactive session (get/put/blksize/timeout/mode/verbose)
+//! xtool tftpc shell 192.168.1.100
 //! ```
 
 mod client_impl;
 pub mod config;
+mod shell;
 
 use anyhow::Result;
 use clap::Subcommand;
 use std::path::PathBuf;
 
-pub use client_impl::Client;
+pub use client_impl::{Client, NegotiatedOptions};
 
 #[derive(Subcommand)]
 pub enum TftpcAction {
@@ -75,6 +79,18 @@ pub enum TftpcAction {
         /// Timeout in seconds
         #[arg(short, long, default_value = "5")]
         timeout: u64,
+
+        /// Seconds to wait for the server's first reply (defaults to --timeout)
+        #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+        initial_timeout: Option<u64>,
+
+        /// Consecutive timeouts tolerated before giving up
+        #[arg(long, value_name = "N", default_value = "5")]
+        retries: u32,
+
+        /// Check the download against `<REMOTE_FILE>.sha256` if the server has one
+        #[arg(long)]
+        verify_hash: bool,
     },
 
     /// Upload a file to TFTP server (WRQ)
@@ -100,6 +116,24 @@ pub enum TftpcAction {
         /// Timeout in seconds
         #[arg(short, long, default_value = "5")]
         timeout: u64,
+
+        /// Seconds to wait for the server's first reply (defaults to --timeout)
+        #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+        initial_timeout: Option<u64>,
+
+        /// Consecutive timeouts tolerated before giving up
+        #[arg(long, value_name = "N", default_value = "5")]
+        retries: u32,
+    },
+
+    /// Interactive session for several transfers against one server
+    Shell {
+        /// Server IP address or hostname
+        server: String,
+
+        /// Server port
+        #[arg(short, long, default_value = "69")]
+        port: u16,
     },
 }
 
@@ -116,9 +150,22 @@ pub fn run_with_config(
             port,
             block_size,
             timeout,
+            initial_timeout,
+            retries,
+            verify_hash,
         } => {
             let client_config = config.and_then(|c| c.get.clone()).unwrap_or_default();
-            let cfg = client_config.merge_cli(server.clone(), port, block_size, timeout);
+            let mut cfg = client_config.merge_cli(
+                server.clone(),
+                port,
+                block_size,
+                timeout,
+                retries,
+                initial_timeout,
+            );
+            if verify_hash {
+                cfg.verify_hash = Some(true);
+            }
 
             let local_path = local_file.unwrap_or_else(|| PathBuf::from(&remote_file));
 
@@ -147,9 +194,18 @@ pub fn run_with_config(
             port,
             block_size,
             timeout,
+            initial_timeout,
+            retries,
         } => {
             let client_config = config.and_then(|c| c.put.clone()).unwrap_or_default();
-            let cfg = client_config.merge_cli(server.clone(), port, block_size, timeout);
+            let cfg = client_config.merge_cli(
+                server.clone(),
+                port,
+                block_size,
+                timeout,
+                retries,
+                initial_timeout,
+            );
 
             if !local_file.exists() {
                 log::error!("Local file does not exist: {}", local_file.display());
@@ -180,6 +236,11 @@ pub fn run_with_config(
 
             log::info!("Upload completed successfully");
         }
+
+        TftpcAction::Shell { server, port } => {
+            let client_config = config.and_then(|c| c.get.clone()).unwrap_or_default();
+            shell::run(client_config.merge_cli(server, port, 512, 5, 5, None))?;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,232 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use dialoguer::{BasicHistory, Input};
+
+use super::Client;
+use super::config::ClientConfig;
+
+const HELP: &str = "\
+Commands:
+  get <remote> [local]     download a file
+  put <local> [remote]     upload a file
+  connect <server> [port]  switch to another server
+  blksize <n>              block size for the next transfers
+  timeout <secs>           per-packet timeout
+  mode <octet|netascii>    transfer mode
+  verbose [on|off]         show negotiated options after each transfer
+  status                   show the current settings
+  help                     show this text
+  quit                     leave the shell";
+
+/// Whether the shell keeps reading commands
+#[derive(Debug, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Quit,
+}
+
+/// Settings shared by every transfer started from `xtool tftpc shell`
+struct Shell {
+    config: ClientConfig,
+    verbose: bool,
+}
+
+impl Shell {
+    fn execute(&mut self, line: &str) -> Result<Flow> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(Flow::Continue);
+        };
+
+        match (command, args) {
+            ("get", [remote]) => self.get(remote, Path::new(remote))?,
+            ("get", [remote, local]) => self.get(remote, Path::new(local))?,
+            ("put", [local]) => {
+                let remote = Path::new(local)
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .ok_or_else(|| anyhow!("Cannot derive a remote name from {local}"))?;
+                self.put(Path::new(local), remote)?
+            }
+            ("put", [local, remote]) => self.put(Path::new(local), remote)?,
+            ("connect", [server]) => self.update(|c| c.server = Some(server.to_string()))?,
+            ("connect", [server, port]) => {
+                let port = port.parse().map_err(|_| anyhow!("Invalid port: {port}"))?;
+                self.update(|c| {
+                    c.server = Some(server.to_string());
+                    c.port = Some(port);
+                })?
+            }
+            ("blksize", [size]) => {
+                let size = size
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid block size: {size}"))?;
+                self.update(|c| c.block_size = Some(size))?
+            }
+            ("timeout", [secs]) => {
+                let secs: u64 = secs
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid timeout: {secs}"))?;
+                self.update(|c| c.timeout = Some(Duration::from_secs(secs)))?
+            }
+            ("mode", [mode]) => self.update(|c| c.mode = Some(mode.to_ascii_lowercase()))?,
+            ("verbose", []) => self.verbose = !self.verbose,
+            ("verbose", ["on"]) => self.verbose = true,
+            ("verbose", ["off"]) => self.verbose = false,
+            ("status", []) => println!("{}", self.status()),
+            ("help" | "?", _) => println!("{HELP}"),
+            ("quit" | "exit" | "q", _) => return Ok(Flow::Quit),
+            _ => return Err(anyhow!("Unknown or malformed command: {line} (try `help`)")),
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Apply `change` only if the result is still a valid configuration.
+    fn update(&mut self, change: impl FnOnce(&mut ClientConfig)) -> Result<()> {
+        let mut config = self.config.clone();
+        change(&mut config);
+        let mut problems = Vec::new();
+        config.validate("shell", &mut problems);
+        if let Some(problem) = problems.first() {
+            return Err(anyhow!("{}", problem.trim_start_matches("shell.")));
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    fn get(&self, remote: &str, local: &Path) -> Result<()> {
+        let client = Client::new(self.config.clone())?;
+        client.get(remote, local)?;
+        self.report(&client);
+        Ok(())
+    }
+
+    fn put(&self, local: &Path, remote: &str) -> Result<()> {
+        if !local.is_file() {
+            return Err(anyhow!("Local file does not exist: {}", local.display()));
+        }
+        let client = Client::new(self.config.clone())?;
+        client.put(local, remote)?;
+        self.report(&client);
+        Ok(())
+    }
+
+    fn report(&self, client: &Client) {
+        if let (true, Some(negotiated)) = (self.verbose, client.last_negotiated()) {
+            println!(
+                "blksize {} windowsize {} timeout {}s tsize {}",
+                negotiated.block_size,
+                negotiated.window_size,
+                negotiated.timeout.as_secs(),
+                negotiated
+                    .transfer_size
+                    .map_or_else(|| "-".to_string(), |size| size.to_string())
+            );
+        }
+    }
+
+    fn status(&self) -> String {
+        let c = &self.config;
+        format!(
+            "server {}:{} blksize {} timeout {}s mode {} verbose {}",
+            c.server.as_deref().unwrap_or("-"),
+            c.port.unwrap_or(69),
+            c.block_size.unwrap_or(512),
+            c.timeout.unwrap_or(Duration::from_secs(5)).as_secs(),
+            c.mode.as_deref().unwrap_or("octet"),
+            if self.verbose { "on" } else { "off" }
+        )
+    }
+}
+
+/// Read commands until `quit` or end of input. On a terminal lines are
+/// edited with history; otherwise commands are read from stdin one per line,
+/// so the shell can be scripted.
+pub fn run(config: ClientConfig) -> Result<()> {
+    let mut shell = Shell {
+        config,
+        verbose: false,
+    };
+
+    if !io::stdin().is_terminal() {
+        for line in io::stdin().lock().lines() {
+            if run_line(&mut shell, &line?) == Flow::Quit {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    println!("{}\nType `help` for commands.", shell.status());
+    let mut history = BasicHistory::new().max_entries(100).no_duplicates(true);
+    // Ctrl-C / Ctrl-D end the prompt with an error, which ends the session
+    while let Ok(line) = Input::<String>::new()
+        .with_prompt("tftp")
+        .allow_empty(true)
+        .history_with(&mut history)
+        .interact_text()
+    {
+        if run_line(&mut shell, &line) == Flow::Quit {
+            break;
+        }
+        io::stdout().flush()?;
+    }
+    Ok(())
+}
+
+/// Failed commands are reported and the shell carries on.
+fn run_line(shell: &mut Shell, line: &str) -> Flow {
+    match shell.execute(line) {
+        Ok(flow) => flow,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            Flow::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell() -> Shell {
+        Shell {
+            config: ClientConfig::new("127.0.0.1".to_string(), 69),
+            verbose: false,
+        }
+    }
+
+    #[test]
+    fn updates_settings() {
+        let mut shell = shell();
+        assert_eq!(shell.execute("blksize 1024").unwrap(), Flow::Continue);
+        assert_eq!(shell.execute("  timeout 9 ").unwrap(), Flow::Continue);
+        shell.execute("mode NETASCII").unwrap();
+        shell.execute("verbose").unwrap();
+        shell.execute("connect 10.0.0.2 1069").unwrap();
+        shell.execute("").unwrap();
+        assert_eq!(
+            shell.status(),
+            "server 10.0.0.2:1069 blksize 1024 timeout 9s mode netascii verbose on"
+        );
+        assert_eq!(shell.execute("quit").unwrap(), Flow::Quit);
+    }
+
+    #[test]
+    fn rejects_bad_commands_without_changing_settings() {
+        let mut shell = shell();
+        let before = shell.status();
+        assert!(shell.execute("blksize 4").is_err());
+        assert!(shell.execute("blksize big").is_err());
+        assert!(shell.execute("timeout 0").is_err());
+        assert!(shell.execute("mode mail").is_err());
+        assert!(shell.execute("connect 10.0.0.2 http").is_err());
+        assert!(shell.execute("get").is_err());
+        assert!(shell.execute("frobnicate").is_err());
+        assert!(shell.execute("put /definitely/missing.bin").is_err());
+        assert_eq!(shell.status(), before);
+    }
+}
@@ -26,7 +26,10 @@ impl Convert {
     /// Converts a zero-terminated [`u8`] slice to a [`String`], and returns the
     /// size of the [`String`]. Useful for TFTP packet conversions.
     pub fn to_string(buf: &[u8], start: usize) -> anyhow::Result<(String, usize)> {
-        match buf[start..].iter().position(|&b| b == 0x00) {
+        let Some(rest) = buf.get(start..) else {
+            return Err(anyhow::anyhow!("Invalid string"));
+        };
+        match rest.iter().position(|&b| b == 0x00) {
             Some(index) => Ok((
                 String::from_utf8(buf[start..start + index].to_vec())?,
                 index + start,
@@ -82,4 +85,11 @@ mod tests {
         assert_eq!(result, "world");
         assert_eq!(index, 11);
     }
+
+    #[test]
+    fn returns_error_on_unterminated_or_out_of_range_string() {
+        assert!(Convert::to_string(b"hello", 0).is_err());
+        assert!(Convert::to_string(b"hello\0", 6).is_err());
+        assert!(Convert::to_string(b"hello\0", 60).is_err());
+    }
 }
@@ -6,8 +6,10 @@
 //! - `options`: Protocol options and parameters
 //! - `window`: Windowed transfer management
 //! - `convert`: Data conversion utilities
+//! - `netascii`: Line-ending translation for netascii transfers
 
 mod convert;
+pub mod netascii;
 pub mod options;
 mod packet;
 mod socket;
@@ -0,0 +1,202 @@
+use std::io::{self, Read};
+
+const CR: u8 = b'\r';
+const LF: u8 = b'\n';
+const NUL: u8 = 0;
+
+/// Returns `true` if a request `mode` asks for netascii (case-insensitive,
+/// as RFC 1350 requires).
+pub fn is_netascii(mode: &str) -> bool {
+    mode.eq_ignore_ascii_case("netascii")
+}
+
+/// Translates file contents into netascii as defined by RFC 764: every line
+/// ends in CR LF and a CR on its own is sent as CR NUL. Lines that already
+/// end in CR LF pass through unchanged. Data may arrive in arbitrary chunks;
+/// a CR at the end of one chunk is resolved by the next.
+///
+/// # Example
+///
+/// ```rust
+/// use xtool::tftp::core::netascii::Encoder;
+///
+/// let mut encoder = Encoder::default();
+/// let mut out = Vec::new();
+/// encoder.encode(b"one\ntwo\r", &mut out);
+/// encoder.encode(b"\nend\r", &mut out);
+/// encoder.finish(&mut out);
+/// assert_eq!(out, b"one\r\ntwo\r\nend\r\0");
+/// ```
+#[derive(Default)]
+pub struct Encoder {
+    pending_cr: bool,
+}
+
+impl Encoder {
+    /// Appends the netascii form of `data` to `out`.
+    pub fn encode(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        for &byte in data {
+            if std::mem::take(&mut self.pending_cr) {
+                if byte == LF {
+                    out.push(LF);
+                    continue;
+                }
+                out.push(NUL);
+            }
+            match byte {
+                CR => {
+                    out.push(CR);
+                    self.pending_cr = true;
+                }
+                LF => out.extend_from_slice(&[CR, LF]),
+                _ => out.push(byte),
+            }
+        }
+    }
+
+    /// Completes a CR left at the very end of the data.
+    pub fn finish(&mut self, out: &mut Vec<u8>) {
+        if std::mem::take(&mut self.pending_cr) {
+            out.push(NUL);
+        }
+    }
+}
+
+/// Translates received netascii back into file contents: CR NUL becomes a
+/// lone CR and lines keep their CR LF ending. A bare LF, as sent by clients
+/// that skip the translation, is stored as CR LF too, so text written in
+/// netascii mode always lands on disk with CR LF line endings.
+///
+/// # Example
+///
+/// ```rust
+/// use xtool::tftp::core::netascii::Decoder;
+///
+/// let mut decoder = Decoder::default();
+/// let mut out = Vec::new();
+/// decoder.decode(b"one\r\ntwo\nCR\r", &mut out);
+/// decoder.decode(b"\0", &mut out);
+/// assert_eq!(out, b"one\r\ntwo\r\nCR\r");
+/// ```
+#[derive(Default)]
+pub struct Decoder {
+    pending_cr: bool,
+}
+
+impl Decoder {
+    /// Appends the file form of the netascii `data` to `out`.
+    pub fn decode(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        for &byte in data {
+            if std::mem::take(&mut self.pending_cr) {
+                match byte {
+                    NUL => continue,
+                    LF => {
+                        out.push(LF);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            match byte {
+                CR => {
+                    out.push(CR);
+                    self.pending_cr = true;
+                }
+                LF => out.extend_from_slice(&[CR, LF]),
+                _ => out.push(byte),
+            }
+        }
+    }
+}
+
+/// A [`Read`] adapter producing the netascii form of `R`'s contents.
+pub struct EncodingReader<R> {
+    inner: R,
+    encoder: Encoder,
+    buffer: Vec<u8>,
+    offset: usize,
+    done: bool,
+}
+
+impl<R: Read> EncodingReader<R> {
+    /// Wraps `inner`, encoding as it is read.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            encoder: Encoder::default(),
+            buffer: Vec::new(),
+            offset: 0,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Read for EncodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.buffer.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.buffer.clear();
+            self.offset = 0;
+
+            let mut raw = [0u8; 4096];
+            let read = self.inner.read(&mut raw)?;
+            if read == 0 {
+                self.encoder.finish(&mut self.buffer);
+                self.done = true;
+            } else {
+                self.encoder.encode(&raw[..read], &mut self.buffer);
+            }
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.offset);
+        buf[..len].copy_from_slice(&self.buffer[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_line_endings_and_lone_cr() {
+        let mut out = Vec::new();
+        let mut encoder = Encoder::default();
+        encoder.encode(b"a\nb\r\nc\rd", &mut out);
+        encoder.finish(&mut out);
+        assert_eq!(out, b"a\r\nb\r\nc\r\0d");
+    }
+
+    #[test]
+    fn decodes_across_chunk_boundaries() {
+        let mut out = Vec::new();
+        let mut decoder = Decoder::default();
+        decoder.decode(b"a\r", &mut out);
+        decoder.decode(b"\nb\r", &mut out);
+        decoder.decode(b"\0c\n", &mut out);
+        assert_eq!(out, b"a\r\nb\rc\r\n");
+    }
+
+    #[test]
+    fn reader_round_trips_through_decoder() {
+        let text = b"line one\nline two\r\nbare\r".repeat(500);
+        let mut wire = Vec::new();
+        let mut reader = EncodingReader::new(&text[..]);
+        let mut chunk = [0u8; 7];
+        loop {
+            let read = reader.read(&mut chunk).unwrap();
+            if read == 0 {
+                break;
+            }
+            wire.extend_from_slice(&chunk[..read]);
+        }
+        assert!(!wire.windows(2).any(|w| w[1] == LF && w[0] != CR));
+
+        let mut decoded = Vec::new();
+        Decoder::default().decode(&wire, &mut decoded);
+        assert_eq!(decoded, b"line one\r\nline two\r\nbare\r".repeat(500));
+    }
+}
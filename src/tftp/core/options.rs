@@ -44,6 +44,8 @@ pub struct OptionsPrivate {
     pub max_retries: usize,
     /// Block counter roll-over policy  (default: Enforce0)
     pub rollover: Rollover,
+    /// Cap on DATA bytes sent per second, per transfer (default: unlimited)
+    pub max_bps: Option<u64>,
 }
 
 impl Default for OptionsPrivate {
@@ -53,6 +55,7 @@ impl Default for OptionsPrivate {
             clean_on_error: true,
             max_retries: DEFAULT_MAX_RETRIES,
             rollover: DEFAULT_ROLLOVER,
+            max_bps: None,
         }
     }
 }
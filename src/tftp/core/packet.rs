@@ -226,22 +226,12 @@ fn parse_rq(buf: &[u8], opcode: Opcode) -> anyhow::Result<Packet> {
     let mode: String;
     let mut zero_index: usize;
 
-    (filename, zero_index) = Convert::to_string(buf, 2)?;
-    (mode, zero_index) = Convert::to_string(buf, zero_index + 1)?;
+    (filename, zero_index) = Convert::to_string(buf, 2)
+        .map_err(|_| anyhow::anyhow!("Request has no terminated filename"))?;
+    (mode, zero_index) = Convert::to_string(buf, zero_index + 1)
+        .map_err(|_| anyhow::anyhow!("Request has no terminated mode"))?;
 
-    let mut value: String;
-    let mut option;
-    while zero_index < buf.len() - 1 {
-        (option, zero_index) = Convert::to_string(buf, zero_index + 1)?;
-        (value, zero_index) = Convert::to_string(buf, zero_index + 1)?;
-
-        if let Ok(option) = OptionType::from_str(option.to_lowercase().as_str()) {
-            options.push(TransferOption {
-                option,
-                value: value.parse()?,
-            });
-        }
-    }
+    parse_options(buf, zero_index, &mut options)?;
 
     match opcode {
         Opcode::Rrq => Ok(Packet::Rrq {
@@ -259,6 +249,7 @@ fn parse_rq(buf: &[u8], opcode: Opcode) -> anyhow::Result<Packet> {
 }
 
 fn parse_data(buf: &[u8]) -> anyhow::Result<Packet> {
+    check_header(buf, "Data")?;
     Ok(Packet::Data {
         block_num: Convert::to_u16(&buf[2..])?,
         data: buf[4..].to_vec(),
@@ -266,30 +257,60 @@ fn parse_data(buf: &[u8]) -> anyhow::Result<Packet> {
 }
 
 fn parse_ack(buf: &[u8]) -> anyhow::Result<Packet> {
+    check_header(buf, "Ack")?;
     Ok(Packet::Ack(Convert::to_u16(&buf[2..])?))
 }
 
 fn parse_oack(buf: &[u8]) -> anyhow::Result<Packet> {
     let mut options = vec![];
+    parse_options(buf, 1, &mut options)?;
+
+    Ok(Packet::Oack(options))
+}
+
+/// Parses the `name\0value\0` pairs that follow the terminator at
+/// `zero_index`. Unknown options are skipped, but every pair must be complete.
+fn parse_options(
+    buf: &[u8],
+    mut zero_index: usize,
+    options: &mut Vec<TransferOption>,
+) -> anyhow::Result<()> {
     let mut value: String;
-    let mut option;
-    let mut zero_index = 1usize;
+    let mut option: String;
+
+    // Each pass consumes at least two bytes, so the loop always terminates
+    while zero_index + 1 < buf.len() {
+        (option, zero_index) = Convert::to_string(buf, zero_index + 1)
+            .map_err(|_| anyhow::anyhow!("Option name is not terminated"))?;
+        (value, zero_index) = Convert::to_string(buf, zero_index + 1)
+            .map_err(|_| anyhow::anyhow!("Option {option:?} has no value"))?;
 
-    while zero_index < buf.len() - 1 {
-        (option, zero_index) = Convert::to_string(buf, zero_index + 1)?;
-        (value, zero_index) = Convert::to_string(buf, zero_index + 1)?;
-        if let Ok(option) = OptionType::from_str(option.to_lowercase().as_str()) {
+        if let Ok(option_type) = OptionType::from_str(option.to_lowercase().as_str()) {
             options.push(TransferOption {
-                option,
-                value: value.parse()?,
+                option: option_type,
+                value: value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid value {value:?} for option {option}"))?,
             });
         }
     }
 
-    Ok(Packet::Oack(options))
+    Ok(())
+}
+
+/// Makes sure a fixed-size header (opcode plus one 16-bit field) is present.
+fn check_header(buf: &[u8], kind: &str) -> anyhow::Result<()> {
+    if buf.len() < 4 {
+        return Err(anyhow::anyhow!(
+            "{kind} packet too short: {} bytes",
+            buf.len()
+        ));
+    }
+    Ok(())
 }
 
 fn parse_error(buf: &[u8]) -> anyhow::Result<Packet> {
+    check_header(buf, "Error")?;
     let code = ErrorCode::from_u16(Convert::to_u16(&buf[2..])?)?;
     if let Ok((msg, _)) = Convert::to_string(buf, 4) {
         Ok(Packet::Error { code, msg })
@@ -642,6 +663,98 @@ mod tests {
         }
     }
 
+    #[test]
+    fn rejects_malformed_packets() {
+        let cases: [&[u8]; 9] = [
+            &[],
+            &[0x00],
+            &[0x00, 0x09],
+            &[0x00, 0x01, b'a'],
+            &[0x00, 0x01, b'a', 0x00, b'o'],
+            b"\x00\x01a\x00octet\x00blksize",
+            b"\x00\x01a\x00octet\x00blksize\x00",
+            b"\x00\x01a\x00octet\x00blksize\x00big\x00",
+            &[0x00, 0x03, 0x00],
+        ];
+
+        for buf in cases {
+            assert!(Packet::deserialize(buf).is_err(), "accepted {buf:?}");
+        }
+        assert!(Packet::deserialize(&[0x00, 0x04, 0x01]).is_err());
+        assert!(Packet::deserialize(&[0x00, 0x05, 0x00]).is_err());
+        assert!(Packet::deserialize(b"\x00\x06blksize\x00").is_err());
+    }
+
+    #[test]
+    fn never_panics_on_truncated_packets() {
+        let packets = [
+            Packet::Rrq {
+                filename: "boot/kernel.img".to_string(),
+                mode: "octet".to_string(),
+                options: vec![
+                    TransferOption {
+                        option: OptionType::BlockSize,
+                        value: 1468,
+                    },
+                    TransferOption {
+                        option: OptionType::TransferSize,
+                        value: 0,
+                    },
+                ],
+            },
+            Packet::Data {
+                block_num: 7,
+                data: vec![0xAA; 16],
+            },
+            Packet::Ack(42),
+            Packet::Error {
+                code: ErrorCode::DiskFull,
+                msg: "disk full".to_string(),
+            },
+            Packet::Oack(vec![TransferOption {
+                option: OptionType::WindowSize,
+                value: 8,
+            }]),
+        ];
+
+        for packet in packets {
+            let bytes = packet.serialize().unwrap();
+            for len in 0..bytes.len() {
+                let _ = Packet::deserialize(&bytes[..len]);
+            }
+            assert_eq!(Packet::deserialize(&bytes).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn never_panics_on_random_input() {
+        // xorshift keeps the inputs reproducible without pulling in a crate
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..20_000 {
+            let len = (next() % 48) as usize;
+            let mut buf: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            // Bias towards valid opcodes and frequent terminators so the
+            // string and option parsers get exercised, not just the opcode check
+            if len >= 2 {
+                buf[0] = 0;
+                buf[1] = (next() % 7) as u8;
+            }
+            for byte in buf.iter_mut().skip(2) {
+                if next() % 4 == 0 {
+                    *byte = 0;
+                }
+            }
+            let _ = Packet::deserialize(&buf);
+        }
+    }
+
     #[test]
     fn serializes_rrq() {
         let serialized_data = vec![
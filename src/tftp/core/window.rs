@@ -7,6 +7,8 @@ use std::{
 /// Window `struct` is used to store chunks of data from a file. It is
 /// used to help store the data that is being sent or received for the
 /// [RFC 7440](https://www.rfc-editor.org/rfc/rfc7440) Windowsize option.
+/// Chunks can also be read from any other [`Read`] source, such as a
+/// [`netascii::EncodingReader`](super::netascii::EncodingReader).
 ///
 /// # Example
 /// ```rust
@@ -22,16 +24,16 @@ use std::{
 /// window.fill().unwrap();
 /// fs::remove_file("test.txt").unwrap();
 /// ```
-pub struct Window {
+pub struct Window<F = File> {
     elements: VecDeque<Vec<u8>>,
     size: u16,
     chunk_size: u16,
-    file: File,
+    file: F,
 }
 
-impl Window {
+impl<F> Window<F> {
     /// Creates a new `Window` with the supplied size and chunk size.
-    pub fn new(size: u16, chunk_size: u16, file: File) -> Window {
+    pub fn new(size: u16, chunk_size: u16, file: F) -> Window<F> {
         Window {
             elements: VecDeque::new(),
             size,
@@ -40,36 +42,6 @@ impl Window {
         }
     }
 
-    /// Fills the `Window` with chunks of data from the file.
-    /// Returns `true` if the `Window` is full.
-    pub fn fill(&mut self) -> anyhow::Result<bool> {
-        for _ in self.len()..self.size {
-            let mut chunk = vec![0; self.chunk_size as usize];
-            let size = self.file.read(&mut chunk)?;
-
-            if size != self.chunk_size as usize {
-                chunk.truncate(size);
-                self.elements.push_back(chunk);
-                return Ok(false);
-            }
-
-            self.elements.push_back(chunk);
-        }
-
-        Ok(true)
-    }
-
-    /// Empties the `Window` by writing the data to the file.
-    pub fn empty(&mut self) -> anyhow::Result<()> {
-        for data in &self.elements {
-            self.file.write_all(data)?;
-        }
-
-        self.elements.clear();
-
-        Ok(())
-    }
-
     /// Removes the first `amount` of elements from the `Window`.
     pub fn remove(&mut self, amount: u16) -> anyhow::Result<()> {
         if amount > self.len() {
@@ -119,13 +91,64 @@ impl Window {
     pub fn is_full(&self) -> bool {
         self.elements.len() as u16 == self.size
     }
+}
 
+impl<F: Read> Window<F> {
+    /// Fills the `Window` with chunks of data from the file.
+    /// Returns `true` if the `Window` is full.
+    pub fn fill(&mut self) -> anyhow::Result<bool> {
+        for _ in self.len()..self.size {
+            let mut chunk = vec![0; self.chunk_size as usize];
+            let size = read_chunk(&mut self.file, &mut chunk)?;
+
+            if size != self.chunk_size as usize {
+                chunk.truncate(size);
+                self.elements.push_back(chunk);
+                return Ok(false);
+            }
+
+            self.elements.push_back(chunk);
+        }
+
+        Ok(true)
+    }
+}
+
+impl<F: Write> Window<F> {
+    /// Empties the `Window` by writing the data to the file.
+    pub fn empty(&mut self) -> anyhow::Result<()> {
+        for data in &self.elements {
+            self.file.write_all(data)?;
+        }
+
+        self.elements.clear();
+
+        Ok(())
+    }
+}
+
+impl Window<File> {
     /// Returns the length of the file
     pub fn file_len(&self) -> anyhow::Result<u64> {
         Ok(self.file.metadata()?.len())
     }
 }
 
+/// Reads until `chunk` is full or the source ends, since a short read only
+/// marks the last block of a transfer.
+fn read_chunk(source: &mut impl Read, chunk: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < chunk.len() {
+        match source.read(&mut chunk[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -16,7 +16,8 @@
 //! │   ├── socket      # Socket abstraction layer
 //! │   ├── options     # Protocol options
 //! │   ├── window      # Windowed transfer
-//! │   └── convert     # Data conversion utilities
+//! │   ├── convert     # Data conversion utilities
+//! │   └── netascii    # netascii line-ending translation
 //! │
 //! ├── server/         # TFTP server
 //! │   ├── server      # Main server logic
@@ -4,6 +4,7 @@ use std::path::PathBuf;
 
 /// TFTP server configuration
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ip: Option<String>,
@@ -27,6 +28,9 @@ pub struct Config {
     pub max_retries: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rollover: Option<Rollover>,
+    /// Per-transfer send rate cap in bytes per second; unset means unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bps: Option<u64>,
 }
 
 impl Config {
@@ -42,6 +46,25 @@ impl Config {
             clean_on_error: Some(true),
             max_retries: Some(6),
             rollover: Some(Rollover::Enforce0),
+            max_bps: None,
+        }
+    }
+
+    /// Append a message for each invalid value to `problems`.
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if let Some(ip) = &self.ip
+            && ip.parse::<std::net::IpAddr>().is_err()
+        {
+            problems.push(format!("tftpd.ip: {:?} is not an IP address", ip));
+        }
+        if self.directory.as_ref().is_some_and(|d| d.as_os_str().is_empty()) {
+            problems.push("tftpd.directory: must not be empty".to_string());
+        }
+        if self.repeat_count == Some(0) {
+            problems.push("tftpd.repeat_count: must be at least 1".to_string());
+        }
+        if self.max_bps == Some(0) {
+            problems.push("tftpd.max_bps: must be at least 1 (omit it for no limit)".to_string());
         }
     }
 
@@ -95,6 +118,7 @@ impl Config {
             clean_on_error: self.clean_on_error.unwrap_or(true),
             max_retries: self.max_retries.unwrap_or(6),
             rollover: self.rollover.unwrap_or(Rollover::Enforce0),
+            max_bps: self.max_bps,
         }
     }
 }
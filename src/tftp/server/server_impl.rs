@@ -1,10 +1,11 @@
 use std::cmp::max;
 use std::collections::HashMap;
-use std::net::{SocketAddr, UdpSocket};
-use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::{Component, MAIN_SEPARATOR, Path, PathBuf};
 use std::sync::mpsc::Sender;
 use std::time::Duration;
 
+use crate::tftp::core::netascii;
 use crate::tftp::core::options::{
     DEFAULT_BLOCK_SIZE, OptionFmt, OptionsPrivate, OptionsProtocol, RequestType,
 };
@@ -12,6 +13,9 @@ use crate::tftp::core::{ErrorCode, Packet, ServerSocket, Socket, TransferOption}
 
 use super::{Config, Worker};
 
+/// Receive buffer for requests, matching [`Socket::recv_from`]
+const MAX_REQUEST_PACKET_SIZE: u16 = 512;
+
 #[cfg(test)]
 use crate::tftp::core::OptionType;
 
@@ -37,6 +41,9 @@ use crate::tftp::core::OptionType;
 /// ```
 pub struct Server {
     socket: UdpSocket,
+    /// Address transfer sockets bind to, so replies leave from the interface
+    /// the client addressed
+    bind_ip: IpAddr,
     directory: PathBuf,
     single_port: bool,
     read_only: bool,
@@ -50,7 +57,7 @@ impl Server {
     /// Creates the TFTP Server with the supplied [`Config`].
     pub fn new(config: &Config) -> anyhow::Result<Server> {
         let ip_str = config.ip.as_deref().unwrap_or("0.0.0.0");
-        let ip_addr: std::net::IpAddr = ip_str.parse()?;
+        let ip_addr: IpAddr = ip_str.parse()?;
         let port = config.port.unwrap_or(69);
 
         let socket = UdpSocket::bind(SocketAddr::from((ip_addr, port))).map_err(|e| {
@@ -69,6 +76,13 @@ impl Server {
             }
         })?;
 
+        if ip_addr.is_unspecified() && !config.single_port.unwrap_or(false) {
+            log::warn!(
+                "Listening on {ip_addr}: on hosts with several addresses, transfers may reply \
+                 from a different one than the client used; pass --bind <IP> to pin it"
+            );
+        }
+
         let directory = config
             .directory
             .clone()
@@ -78,6 +92,7 @@ impl Server {
 
         let server = Server {
             socket,
+            bind_ip: ip_addr,
             directory,
             single_port: config.single_port.unwrap_or(false),
             read_only: config.read_only.unwrap_or(false),
@@ -93,48 +108,27 @@ impl Server {
     /// Starts listening for connections. Note that this function does not finish running until termination.
     pub fn listen(&mut self) {
         loop {
-            let received = if self.single_port {
-                self.socket
-                    .recv_from_with_size(self.largest_block_size as usize)
-            } else {
-                Socket::recv_from(&self.socket)
-            };
-
-            if let Ok((packet, from)) = received {
+            if let Some((packet, from)) = self.recv_request() {
                 match packet {
                     Packet::Rrq {
                         filename,
+                        mode,
                         mut options,
-                        ..
                     } => {
                         log::info!("Received Read request from {from}: {filename}");
-                        if let Err(err) = self.handle_rrq(filename.clone(), &mut options, &from) {
+                        if let Err(err) =
+                            self.handle_rrq(filename.clone(), &mode, &mut options, &from)
+                        {
                             log::error!("Error while sending file: {err}")
                         }
                     }
                     Packet::Wrq {
                         filename,
+                        mode,
                         mut options,
-                        ..
                     } => {
-                        if self.read_only {
-                            if Socket::send_to(
-                                &self.socket,
-                                &Packet::Error {
-                                    code: ErrorCode::AccessViolation,
-                                    msg: "server is read-only".to_string(),
-                                },
-                                &from,
-                            )
-                            .is_err()
-                            {
-                                log::error!("Could not send error packet");
-                            };
-                            log::warn!("Received write request while in read-only mode");
-                            continue;
-                        }
                         log::info!("Received Write request from {from}: {filename}");
-                        if let Err(err) = self.handle_wrq(filename, &mut options, &from) {
+                        if let Err(err) = self.handle_wrq(filename, &mode, &mut options, &from) {
                             log::error!("Error while receiving file: {err}")
                         }
                     }
@@ -160,9 +154,46 @@ impl Server {
         }
     }
 
+    /// Receives the next packet, answering anything that does not parse with
+    /// an illegal operation error instead of dropping it silently.
+    fn recv_request(&self) -> Option<(Packet, SocketAddr)> {
+        let size = if self.single_port {
+            self.largest_block_size.max(MAX_REQUEST_PACKET_SIZE) as usize
+        } else {
+            MAX_REQUEST_PACKET_SIZE as usize
+        };
+        let mut buf = vec![0; size + 4];
+        let (amt, from) = self.socket.recv_from(&mut buf).ok()?;
+
+        match Packet::deserialize(&buf[..amt]) {
+            Ok(packet) => Some((packet, from)),
+            Err(err) => {
+                log::warn!("Malformed packet from {from}: {err}");
+                // Never answer an error with an error, or two peers could ping-pong
+                if buf[..amt].starts_with(&[0x00, 0x05]) {
+                    return None;
+                }
+                if Socket::send_to(
+                    &self.socket,
+                    &Packet::Error {
+                        code: ErrorCode::IllegalOperation,
+                        msg: "malformed packet".to_string(),
+                    },
+                    &from,
+                )
+                .is_err()
+                {
+                    log::error!("Could not send error packet");
+                }
+                None
+            }
+        }
+    }
+
     fn handle_rrq(
         &mut self,
         filename: String,
+        mode: &str,
         options: &mut [TransferOption],
         to: &SocketAddr,
     ) -> anyhow::Result<()> {
@@ -207,7 +238,7 @@ impl Server {
 
                     socket = Box::new(single_socket);
                 } else {
-                    socket = Box::new(create_multi_socket(&self.socket.local_addr()?, to)?);
+                    socket = Box::new(create_multi_socket(self.bind_ip, to)?);
                 }
 
                 socket.set_read_timeout(worker_options.timeout)?;
@@ -226,7 +257,8 @@ impl Server {
                     file_path.clone(),
                     self.opt_local.clone(),
                     worker_options.clone(),
-                );
+                )
+                .with_netascii(netascii::is_netascii(mode));
                 worker.send(!options.is_empty())?;
                 Ok(())
             }
@@ -237,9 +269,23 @@ impl Server {
     fn handle_wrq(
         &mut self,
         filename: String,
+        mode: &str,
         options: &mut [TransferOption],
         to: &SocketAddr,
     ) -> anyhow::Result<()> {
+        // Refuse before touching the filesystem, so nothing is ever created
+        if self.read_only {
+            log::warn!("Refused write of {filename} from {to}: server is read-only");
+            return Socket::send_to(
+                &self.socket,
+                &Packet::Error {
+                    code: ErrorCode::AccessViolation,
+                    msg: "server is read-only".to_string(),
+                },
+                to,
+            );
+        }
+
         let file_path = convert_file_path(&filename);
         let file_path = &self.directory.join(file_path);
         let initialize_write = &mut || -> anyhow::Result<()> {
@@ -253,7 +299,7 @@ impl Server {
 
                 socket = Box::new(single_socket);
             } else {
-                socket = Box::new(create_multi_socket(&self.socket.local_addr()?, to)?);
+                socket = Box::new(create_multi_socket(self.bind_ip, to)?);
             }
 
             socket.set_read_timeout(worker_options.timeout)?;
@@ -267,7 +313,8 @@ impl Server {
                 file_path.clone(),
                 self.opt_local.clone(),
                 worker_options.clone(),
-            );
+            )
+            .with_netascii(netascii::is_netascii(mode));
             worker.receive()?;
             Ok(())
         };
@@ -342,8 +389,8 @@ fn create_single_socket(
     Ok(socket)
 }
 
-fn create_multi_socket(addr: &SocketAddr, remote: &SocketAddr) -> anyhow::Result<UdpSocket> {
-    let socket = UdpSocket::bind(SocketAddr::from((addr.ip(), 0)))?;
+fn create_multi_socket(ip: IpAddr, remote: &SocketAddr) -> anyhow::Result<UdpSocket> {
+    let socket = UdpSocket::bind(SocketAddr::from((ip, 0)))?;
     socket.connect(remote)?;
 
     Ok(socket)
@@ -375,8 +422,31 @@ fn check_file_exists(file: &Path, directory: &PathBuf) -> ErrorCode {
     ErrorCode::FileExists
 }
 
+/// Whether `file`, a request path joined onto `directory`, stays inside it.
+/// `..` components are refused outright. Symlinks are then resolved on the
+/// deepest part of the path that exists, so a link inside the directory
+/// cannot send a read or write somewhere else.
 fn validate_file_path(file: &Path, directory: &PathBuf) -> bool {
-    !file.to_str().unwrap().contains("..") && file.ancestors().any(|a| a == directory)
+    let Ok(relative) = file.strip_prefix(directory) else {
+        return false;
+    };
+    if relative.as_os_str().is_empty()
+        || !relative.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return false;
+    }
+
+    // Without the directory on disk there is nothing a link could escape through
+    let Ok(root) = directory.canonicalize() else {
+        return true;
+    };
+    let Some(existing) = file.ancestors().find(|a| a.symlink_metadata().is_ok()) else {
+        return false;
+    };
+    // A dangling link fails to resolve and is refused with the rest
+    existing
+        .canonicalize()
+        .is_ok_and(|resolved| resolved.starts_with(&root))
 }
 
 #[cfg(test)]
@@ -440,6 +510,16 @@ mod tests {
             &PathBuf::from("/dir/test/../file"),
             &PathBuf::from("/dir/test")
         ));
+
+        assert!(validate_file_path(
+            &PathBuf::from("/dir/test/v1..v2.bin"),
+            &PathBuf::from("/dir/test")
+        ));
+
+        assert!(!validate_file_path(
+            &PathBuf::from("/dir/test"),
+            &PathBuf::from("/dir/test")
+        ));
     }
 
     #[test]
@@ -1,15 +1,59 @@
 use std::{
+    collections::VecDeque,
     fs::{self, File},
-    io::ErrorKind,
+    io::{ErrorKind, Read},
     path::PathBuf,
     thread,
     time::{Duration, Instant},
 };
 
+use crate::tftp::core::netascii::{Decoder, EncodingReader};
 use crate::tftp::core::options::{OptionsPrivate, OptionsProtocol, Rollover};
 use crate::tftp::core::{ErrorCode, Packet, Socket, Window};
 
 const DEFAULT_DUPLICATE_DELAY: Duration = Duration::from_millis(1);
+/// Span over which `max_bps` is enforced; short enough to smooth out bursts
+const PACING_WINDOW: Duration = Duration::from_millis(200);
+
+/// Holds DATA packets back so that no more than `max_bps` worth of bytes
+/// go out within any [`PACING_WINDOW`].
+struct Pacer {
+    budget: u64,
+    sent: VecDeque<(Instant, u64)>,
+    in_window: u64,
+}
+
+impl Pacer {
+    fn new(max_bps: u64) -> Self {
+        Self {
+            budget: max_bps * PACING_WINDOW.as_millis() as u64 / 1000,
+            sent: VecDeque::new(),
+            in_window: 0,
+        }
+    }
+
+    /// Sleep until `len` more bytes fit in the window, then account for them.
+    fn wait(&mut self, len: u64) {
+        loop {
+            let now = Instant::now();
+            while let Some(&(at, bytes)) = self.sent.front() {
+                if now.duration_since(at) < PACING_WINDOW {
+                    break;
+                }
+                self.sent.pop_front();
+                self.in_window -= bytes;
+            }
+            // A packet larger than the whole budget still goes out, alone
+            if self.sent.is_empty() || self.in_window + len <= self.budget {
+                self.sent.push_back((now, len));
+                self.in_window += len;
+                return;
+            }
+            let (oldest, _) = self.sent[0];
+            thread::sleep((oldest + PACING_WINDOW).saturating_duration_since(now));
+        }
+    }
+}
 
 /// Worker `struct` is used for multithreaded file sending and receiving.
 /// It creates a new socket using the Server's IP and a random port
@@ -44,6 +88,7 @@ pub struct Worker<T: Socket + ?Sized> {
     file_path: PathBuf,
     opt_local: OptionsPrivate,
     opt_common: OptionsProtocol,
+    netascii: bool,
 }
 
 impl<T: Socket + ?Sized> Worker<T> {
@@ -59,9 +104,17 @@ impl<T: Socket + ?Sized> Worker<T> {
             file_path,
             opt_local,
             opt_common,
+            netascii: false,
         }
     }
 
+    /// Translates line endings as the `netascii` mode requires, instead of
+    /// transferring the file byte for byte. See [`crate::tftp::core::netascii`].
+    pub fn with_netascii(mut self, netascii: bool) -> Worker<T> {
+        self.netascii = netascii;
+        self
+    }
+
     /// Sends a file to the remote [`SocketAddr`] that has sent a read request using
     /// a random port, asynchronously.
     pub fn send(self, check_response: bool) -> anyhow::Result<thread::JoinHandle<bool>> {
@@ -69,15 +122,30 @@ impl<T: Socket + ?Sized> Worker<T> {
         let remote_addr = self.socket.remote_addr().unwrap();
 
         let handle = thread::spawn(move || {
-            let handle_send = || -> anyhow::Result<()> {
-                self.send_file(File::open(&file_path)?, check_response)
+            let started = Instant::now();
+            let handle_send = || -> anyhow::Result<u64> {
+                let file = File::open(&file_path)?;
+                let size = file.metadata()?.len();
+                if self.netascii {
+                    self.send_file(EncodingReader::new(file), check_response)?;
+                } else {
+                    self.send_file(file, check_response)?;
+                }
+                Ok(size)
             };
 
             match handle_send() {
-                Ok(_) => {
+                Ok(size) => {
+                    let secs = started.elapsed().as_secs_f64();
                     log::info!(
-                        "Sent {} to {}",
+                        "Sent {} ({} bytes, {:.1} KiB/s) to {}",
                         &file_path.file_name().unwrap().to_string_lossy(),
+                        size,
+                        if secs > 0.0 {
+                            size as f64 / 1024.0 / secs
+                        } else {
+                            0.0
+                        },
                         &remote_addr
                     );
                     true
@@ -102,18 +170,26 @@ impl<T: Socket + ?Sized> Worker<T> {
         let clean_on_error = self.opt_local.clean_on_error;
         let file_path = self.file_path.clone();
         let remote_addr = self.socket.remote_addr().unwrap();
-        let opt_tsize = self.opt_common.transfer_size;
+        // The size on disk only matches the negotiated one byte for byte
+        let opt_tsize = self.opt_common.transfer_size.filter(|_| !self.netascii);
 
         let handle = thread::spawn(move || {
             let handle_receive =
                 || -> anyhow::Result<u64> { self.receive_file(File::create(&file_path)?) };
 
+            let clean = || {
+                if clean_on_error && fs::remove_file(&file_path).is_err() {
+                    log::error!("Error while cleaning {}", &file_path.to_str().unwrap());
+                }
+            };
+
             match handle_receive() {
                 Ok(size) => {
                     if let Some(tsize) = opt_tsize
                         && tsize != size
                     {
                         log::error!("Size mismatch, negotiated: {tsize}, transferred: {size}");
+                        clean();
                         return false;
                     }
 
@@ -131,9 +207,7 @@ impl<T: Socket + ?Sized> Worker<T> {
                         &file_path.file_name().unwrap().to_string_lossy(),
                         remote_addr
                     );
-                    if clean_on_error && fs::remove_file(&file_path).is_err() {
-                        log::error!("Error while cleaning {}", &file_path.to_str().unwrap());
-                    }
+                    clean();
                     false
                 }
             }
@@ -142,7 +216,7 @@ impl<T: Socket + ?Sized> Worker<T> {
         Ok(handle)
     }
 
-    fn send_file(mut self, file: File, check_response: bool) -> anyhow::Result<()> {
+    fn send_file(mut self, file: impl Read, check_response: bool) -> anyhow::Result<()> {
         let mut block_seq_win: u16 = 0;
         let mut win_idx: u16 = 0;
         let mut window = Window::new(
@@ -154,6 +228,7 @@ impl<T: Socket + ?Sized> Worker<T> {
 
         let mut timeout_end = Instant::now() + self.opt_common.timeout;
         let mut retry_cnt = 0;
+        let mut pacer = self.opt_local.max_bps.map(Pacer::new);
 
         if cfg!(windows) {
             // On Windows, recv can return up to 15ms before timeout
@@ -180,6 +255,9 @@ impl<T: Socket + ?Sized> Worker<T> {
                     }
                 }
 
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.wait(frame.len() as u64);
+                }
                 self.send_packet(&Packet::Data {
                     block_num: block_seq_tx,
                     data: frame.to_vec(),
@@ -295,6 +373,7 @@ impl<T: Socket + ?Sized> Worker<T> {
             file,
         );
         let mut retry_cnt = 0;
+        let mut decoder = self.netascii.then(Decoder::default);
 
         let mut last = false;
         let mut listen_all = false;
@@ -340,6 +419,14 @@ impl<T: Socket + ?Sized> Worker<T> {
                         if received_block_number == new_block_number {
                             block_number = received_block_number;
                             last = data.len() < self.opt_common.block_size as usize;
+                            let data = match decoder.as_mut() {
+                                Some(decoder) => {
+                                    let mut decoded = Vec::with_capacity(data.len());
+                                    decoder.decode(&data, &mut decoded);
+                                    decoded
+                                }
+                                None => data,
+                            };
                             window.add(data)?;
                             send_ack = window.is_full() || last;
                         } else {
@@ -0,0 +1,83 @@
+//! Helpers shared by more than one subcommand.
+
+/// Byte count in binary units, e.g. `512 B` or `4.2 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Match one path component against a pattern using `*`, `?` and `[...]`
+/// (with `!` or `^` negation and `a-z` ranges). An unclosed `[` is literal.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    // Position after the last `*`, and the name position it is retrying from
+    let mut star: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        if pi < p.len() {
+            if p[pi] == '*' {
+                star = Some((pi + 1, ni));
+                pi += 1;
+                continue;
+            }
+            let step = match p[pi] {
+                '?' => Some(1),
+                '[' => match match_class(&p[pi..], n[ni]) {
+                    Some((true, len)) => Some(len),
+                    Some((false, _)) => None,
+                    None => (n[ni] == '[').then_some(1),
+                },
+                c => (c == n[ni]).then_some(1),
+            };
+            if let Some(len) = step {
+                pi += len;
+                ni += 1;
+                continue;
+            }
+        }
+        match star.as_mut() {
+            Some((star_pi, star_ni)) => {
+                *star_ni += 1;
+                pi = *star_pi;
+                ni = *star_ni;
+            }
+            None => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Match `c` against the bracket expression at the start of `p`, returning
+/// whether it matched and the expression's length.
+fn match_class(p: &[char], c: char) -> Option<(bool, usize)> {
+    let negate = matches!(p.get(1), Some('!' | '^'));
+    let start = if negate { 2 } else { 1 };
+    let mut i = start;
+    let mut matched = false;
+    while i < p.len() {
+        // A `]` right after the opening bracket is a literal member
+        if p[i] == ']' && i > start {
+            return Some((matched != negate, i + 1));
+        }
+        if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            matched |= (p[i]..=p[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= p[i] == c;
+            i += 1;
+        }
+    }
+    None
+}
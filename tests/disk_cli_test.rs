@@ -1,8 +1,15 @@
 use std::fs;
 
+use clap::Parser;
 use tempfile::TempDir;
+use xtool::disk::commands::cp::CpOptions;
+use xtool::disk::fs::CopyOptions;
+use xtool::disk::fatfs::FatType;
+use xtool::disk::types::FileKind;
 use xtool::disk::{commands, fs as disk_fs, gpt as disk_gpt};
 
+const SECTOR: u64 = disk_gpt::DEFAULT_SECTOR_SIZE;
+
 #[test]
 fn disk_ext4_workflow() {
     let temp = TempDir::new().expect("temp dir");
@@ -10,17 +17,17 @@ fn disk_ext4_workflow() {
     let hello = temp.path().join("hello.txt");
     fs::write(&hello, b"hello ext4").expect("write host file");
 
-    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false).expect("mkimg");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
 
     let meta = fs::metadata(&disk).expect("disk exists");
     assert_eq!(meta.len(), 32 * 1024 * 1024);
 
-    let target = disk_gpt::resolve_partition_target(&disk, None).expect("target");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
     disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
 
     disk_fs::mkdir(&disk, &target, "/etc", true).expect("mkdir");
 
-    disk_fs::copy_host_to_image(&disk, &target, &hello, "/etc/hello.txt", false, false)
+    disk_fs::copy_host_to_image(&disk, &target, &hello, "/etc/hello.txt", CopyOptions::default())
         .expect("copy host->image");
 
     let entries = disk_fs::list_dir(&disk, &target, "/etc").expect("ls");
@@ -37,6 +44,519 @@ fn disk_ext4_workflow() {
     assert!(!entries.iter().any(|e| e.name == "hi.txt"));
 }
 
+#[test]
+fn disk_cat_ranges() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+    disk_fs::write_file(&disk, &target, "/hello.txt", b"hello ext4", false).expect("write");
+
+    let entries = disk_fs::list_dir(&disk, &target, "/").expect("ls");
+    let hello_entry = entries.iter().find(|e| e.name == "hello.txt").expect("hello entry");
+    assert_eq!(hello_entry.size, Some(10));
+
+    let data = disk_fs::read_file(&disk, &target, "/hello.txt", 6, Some(8)).expect("cat range");
+    assert_eq!(data, b"ext4");
+
+    let mut chunks = Vec::new();
+    let total = disk_fs::read_file_chunked(&disk, &target, "/hello.txt", 3, |chunk| {
+        chunks.push(chunk.to_vec());
+        Ok(())
+    })
+    .expect("chunked read");
+    assert_eq!(total, 10);
+    assert_eq!(chunks.concat(), b"hello ext4");
+}
+
+#[test]
+fn disk_cat_tail() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+
+    // Long enough that the tail is found a few chunks back from the end
+    let lines: String = (0..20000).map(|i| format!("line {i}\n")).collect();
+    disk_fs::write_file(&disk, &target, "/var.log", lines.as_bytes(), false).expect("write lines");
+    let tail = disk_fs::read_tail(&disk, &target, "/var.log", 3).expect("tail");
+    assert_eq!(tail, b"line 19997\nline 19998\nline 19999\n");
+    let tail = disk_fs::read_tail(&disk, &target, "/var.log", 12000).expect("long tail");
+    assert_eq!(tail, lines.as_bytes()[lines.find("line 8000\n").unwrap()..]);
+
+    disk_fs::write_file(&disk, &target, "/short.txt", b"hello ext4", false).expect("write");
+    let tail = disk_fs::read_tail(&disk, &target, "/short.txt", 50).expect("short file");
+    assert_eq!(tail, b"hello ext4");
+}
+
+#[test]
+fn disk_write_stdin() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+
+    // What `disk write` does with stdin
+    disk_fs::write_file_streaming(&disk, &target, "/piped.txt", &mut &b"first"[..], false)
+        .expect("write stream");
+    assert!(disk_fs::write_file_streaming(&disk, &target, "/piped.txt", &mut &b"x"[..], false).is_err());
+    let appended = disk_fs::append_file_streaming(&disk, &target, "/piped.txt", &mut &b" second"[..])
+        .expect("append stream");
+    assert_eq!(appended, 7);
+    let data = disk_fs::read_file(&disk, &target, "/piped.txt", 0, None).expect("cat piped");
+    assert_eq!(data, b"first second");
+}
+
+#[test]
+fn disk_append() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+
+    // Crosses a block boundary on the second append
+    let line = vec![b'x'; 3000];
+    for _ in 0..2 {
+        disk_fs::append_file(&disk, &target, "/log.txt", &line).expect("append");
+    }
+    let data = disk_fs::read_file(&disk, &target, "/log.txt", 0, None).expect("cat log");
+    assert_eq!(data, [line.as_slice(), line.as_slice()].concat());
+
+    let host = temp.path().join("more.txt");
+    fs::write(&host, b"more").expect("write host file");
+    commands::cp::append(&disk, &target, &format!("host:{}", host.display()), "/short.txt")
+        .expect("append creates");
+    commands::cp::append(&disk, &target, "/short.txt", "/short.txt").expect("append to itself");
+    let data = disk_fs::read_file(&disk, &target, "/short.txt", 0, None).expect("cat short");
+    assert_eq!(data, b"moremore");
+}
+
+#[test]
+fn disk_du() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+    disk_fs::write_file(&disk, &target, "/etc/hello.txt", b"hello ext4", false).expect("write");
+
+    let usage = disk_fs::usage(&disk, &target).expect("du");
+    assert_eq!(usage.fstype, "ext4");
+    assert!(usage.used_blocks() > 0 && usage.free_blocks < usage.total_blocks);
+
+    let dirs = disk_fs::dir_usage(&disk, &target, "/etc").expect("du /etc");
+    assert_eq!(dirs.last().map(|d| d.bytes), Some(10));
+}
+
+#[test]
+fn disk_export_root() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+    disk_fs::write_file(&disk, &target, "/etc/hello.txt", b"hello ext4", false).expect("write");
+    disk_fs::mkdir(&disk, &target, "/var/empty", true).expect("mkdir empty");
+
+    let export = temp.path().join("export");
+    let export_arg = format!("host:{}", export.display());
+    let recursive = CpOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    commands::cp::cp(&disk, &target, "/", &export_arg, recursive).expect("export root");
+    assert_eq!(fs::read(export.join("etc/hello.txt")).expect("exported file"), b"hello ext4");
+    assert!(export.join("var/empty").is_dir());
+}
+
+#[test]
+fn disk_find_by_name_and_type() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+    disk_fs::mkdir(&disk, &target, "/usr/lib/x.so.d", true).expect("mkdir");
+    disk_fs::write_file(&disk, &target, "/usr/lib/libc.so", b"c", false).expect("write");
+    disk_fs::write_file(&disk, &target, "/usr/lib/libc.a", b"a", false).expect("write");
+    disk_fs::write_file(&disk, &target, "/init.so", b"i", false).expect("write");
+    disk_fs::symlink(&disk, &target, "usr/lib", "/lib").expect("ln -s");
+
+    let find = |path: &str, name: Option<&str>, kind: Option<char>| {
+        let mut out = Vec::new();
+        let options = commands::find::FindOptions { name, kind };
+        let found = commands::find::find(&disk, &target, path, options, &mut out).expect("find");
+        (found, String::from_utf8(out).expect("utf8"))
+    };
+
+    // The /lib link is not followed, so nothing is reported twice
+    let (found, out) = find("/", Some("*.so"), None);
+    assert!(found);
+    assert_eq!(out, "/init.so\n/usr/lib/libc.so\n");
+    let (_, out) = find("/usr", Some("*.so*"), Some('f'));
+    assert_eq!(out, "/usr/lib/libc.so\n");
+    let (_, out) = find("/usr", None, Some('d'));
+    assert_eq!(out, "/usr\n/usr/lib\n/usr/lib/x.so.d\n");
+    let (found, out) = find("/usr", Some("*.ko"), None);
+    assert!(!found && out.is_empty());
+}
+
+#[test]
+fn disk_ext4_symlinks() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    let hello = temp.path().join("hello.txt");
+    fs::write(&hello, b"hello link").expect("write host file");
+
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+    disk_fs::mkdir(&disk, &target, "/usr/bin", true).expect("mkdir");
+    disk_fs::copy_host_to_image(&disk, &target, &hello, "/usr/bin/hello", CopyOptions::default())
+        .expect("copy host->image");
+
+    disk_fs::symlink(&disk, &target, "usr/bin", "/bin").expect("ln -s dir");
+    let long_target = format!("/usr/bin/{}/../hello", "x".repeat(64));
+    disk_fs::symlink(&disk, &target, &long_target, "/hello").expect("ln -s long");
+    disk_fs::symlink(&disk, &target, "missing", "/dangling").expect("ln -s dangling");
+    assert!(disk_fs::symlink(&disk, &target, "usr", "/bin").is_err());
+
+    let entries = disk_fs::list_dir(&disk, &target, "/").expect("ls");
+    let link = entries.iter().find(|e| e.name == "bin").expect("bin entry");
+    assert_eq!(link.symlink_target.as_deref(), Some("usr/bin"));
+    let link = entries.iter().find(|e| e.name == "hello").expect("hello entry");
+    assert_eq!(link.symlink_target.as_deref(), Some(long_target.as_str()));
+
+    let data = disk_fs::read_file(&disk, &target, "/bin/hello", 0, None).expect("cat via dir link");
+    assert_eq!(data, b"hello link");
+    assert!(disk_fs::is_dir(&disk, &target, "/bin").expect("is_dir"));
+    assert!(disk_fs::read_file(&disk, &target, "/dangling", 0, None).is_err());
+
+    let st = disk_fs::stat(&disk, &target, "/bin").expect("stat link");
+    assert_eq!(st.kind, FileKind::Symlink);
+    assert_eq!(st.symlink_target.as_deref(), Some("usr/bin"));
+    let st = disk_fs::stat(&disk, &target, "/bin/hello").expect("stat via link");
+    assert_eq!((st.kind, st.size), (FileKind::File, 10));
+    assert_eq!(st.mode.map(|m| m & 0o170000), Some(0o100000));
+    assert!(st.inode.is_some_and(|ino| ino > 2) && st.uid.is_some());
+    let st = disk_fs::stat(&disk, &target, "/").expect("stat root");
+    assert_eq!((st.kind, st.inode), (FileKind::Dir, Some(2)));
+    assert!(disk_fs::stat(&disk, &target, "/missing").is_err());
+
+    disk_fs::rm(&disk, &target, "/bin", false).expect("rm link");
+    let entries = disk_fs::list_dir(&disk, &target, "/usr/bin").expect("ls target");
+    assert!(entries.iter().any(|e| e.name == "hello"), "rm removes the link only");
+}
+
+#[cfg(unix)]
+#[test]
+fn disk_walks_do_not_follow_symlinks() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+    disk_fs::write_file(&disk, &target, "/etc/hostname", b"xtool", false).expect("write");
+    disk_fs::symlink(&disk, &target, "/proc/self/mounts", "/mtab").expect("ln -s dangling");
+    disk_fs::symlink(&disk, &target, "loop", "/loop").expect("ln -s loop");
+
+    let out = temp.path().join("out");
+    disk_fs::copy_image_to_host(&disk, &target, "/", &out, true, false).expect("cp -r /");
+    assert_eq!(fs::read(out.join("etc/hostname")).expect("read copy"), b"xtool");
+    assert_eq!(fs::read_link(out.join("mtab")).expect("mtab link"), std::path::Path::new("/proc/self/mounts"));
+    assert_eq!(fs::read_link(out.join("loop")).expect("loop link"), std::path::Path::new("loop"));
+
+    let dirs = disk_fs::dir_usage(&disk, &target, "/").expect("du /");
+    let root = dirs.iter().find(|d| d.path == "/").expect("root usage");
+    assert!(root.bytes >= 5 + "/proc/self/mounts".len() as u64 + "loop".len() as u64);
+
+    let out_arg = format!("host:{}", out.display());
+    assert!(commands::diff::diff(&disk, &target, "/", &out_arg, true).expect("diff"));
+    fs::remove_file(out.join("loop")).expect("rm host link");
+    std::os::unix::fs::symlink("elsewhere", out.join("loop")).expect("relink");
+    assert!(!commands::diff::diff(&disk, &target, "/", &out_arg, true).expect("diff"));
+}
+
+#[test]
+fn disk_streams_large_copies() {
+    let temp = TempDir::new().expect("temp dir");
+    let big = temp.path().join("big.bin");
+    // Not a multiple of the 4 MiB copy chunk, so the last one is partial
+    let data: Vec<u8> = (0..64 * 1024 * 1024 + 123).map(|i: u32| (i % 251) as u8).collect();
+    fs::write(&big, &data).expect("write host file");
+
+    for fstype in ["ext4", "fat32"] {
+        let disk = temp.path().join(format!("{fstype}.img"));
+        commands::mkimg::mkimg(&disk, 128 * 1024 * 1024, false, true).expect("mkimg");
+        let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+        match fstype {
+            "ext4" => disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4"),
+            _ => disk_fs::mkfs_fat32(&disk, &target, None).expect("mkfs fat32"),
+        }
+
+        let verify = CopyOptions {
+            verify: true,
+            ..Default::default()
+        };
+        disk_fs::copy_host_to_image(&disk, &target, &big, "/big.bin", verify)
+            .expect("stream host->image");
+        assert_eq!(
+            disk_fs::stat(&disk, &target, "/big.bin").expect("stat").size,
+            data.len() as u64
+        );
+
+        let back = temp.path().join(format!("{fstype}-back.bin"));
+        disk_fs::copy_image_to_host(&disk, &target, "/big.bin", &back, false, false)
+            .expect("stream image->host");
+        assert!(fs::read(&back).expect("read back") == data, "{fstype} round trip differs");
+    }
+}
+
+#[test]
+fn disk_glob_rm_and_cp() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 64 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_fat32(&disk, &target, None).expect("mkfs fat32");
+
+    disk_fs::mkdir(&disk, &target, "/logs/old", true).expect("mkdir");
+    for path in ["/logs/a.txt", "/logs/b.txt", "/logs/c.log", "/logs/old/d.txt"] {
+        disk_fs::write_file(&disk, &target, path, path.as_bytes(), false).expect("write");
+    }
+
+    let matches = disk_fs::glob(&disk, &target, "/logs/[ab].txt").expect("glob");
+    assert_eq!(matches, ["/logs/a.txt", "/logs/b.txt"]);
+    let matches = disk_fs::glob(&disk, &target, "/**/*.txt").expect("glob **");
+    assert_eq!(matches, ["/logs/a.txt", "/logs/b.txt", "/logs/old/d.txt"]);
+    assert_eq!(disk_fs::glob(&disk, &target, "/logs/?.log").expect("glob ?"), ["/logs/c.log"]);
+
+    let export = temp.path().join("export");
+    fs::create_dir(&export).expect("export dir");
+    let export_arg = format!("host:{}", export.display());
+    commands::cp::cp(&disk, &target, "/logs/*.txt", &export_arg, CpOptions::default())
+        .expect("cp glob");
+    assert_eq!(fs::read(export.join("b.txt")).expect("copied"), b"/logs/b.txt");
+    assert!(!export.join("c.log").exists());
+    commands::cp::cp(&disk, &target, "/logs/*.bin", &export_arg, CpOptions::default())
+        .expect_err("empty match");
+    let force = CpOptions {
+        force: true,
+        ..Default::default()
+    };
+    commands::cp::cp(&disk, &target, "/logs/*.bin", &export_arg, force).expect("empty match with -f");
+
+    commands::rm::rm(&disk, &target, "/logs/*.txt", false, false, true).expect("rm glob");
+    let names: Vec<String> = disk_fs::list_dir(&disk, &target, "/logs")
+        .expect("ls")
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert!(names.contains(&"c.log".to_string()) && names.contains(&"old".to_string()));
+    assert!(!names.iter().any(|n| n.ends_with(".txt")));
+    commands::rm::rm(&disk, &target, "/logs/*.txt", false, false, true).expect_err("no match");
+    commands::rm::rm(&disk, &target, "/logs/*.txt", false, true, true).expect("no match with -f");
+}
+
+#[test]
+fn disk_cp_verify() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 64 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+
+    let tree = temp.path().join("tree");
+    fs::create_dir_all(tree.join("sub")).expect("host dirs");
+    fs::write(tree.join("a.txt"), b"alpha").expect("write a");
+    let blob: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(tree.join("sub/b.bin"), &blob).expect("write b");
+
+    let verify = CopyOptions {
+        verify: true,
+        ..Default::default()
+    };
+    disk_fs::copy_host_to_image(&disk, &target, &tree.join("a.txt"), "/a.txt", verify)
+        .expect("verified file copy");
+    disk_fs::verify_file(&disk, &target, "/a.txt", b"alpha").expect("matches");
+    disk_fs::verify_file(&disk, &target, "/a.txt", b"something else")
+        .expect_err("verify catches a mismatch");
+
+    disk_fs::copy_host_to_image(&disk, &target, &tree, "/tree", verify)
+        .expect_err("directory copy requires -r");
+    let options = CpOptions {
+        recursive: true,
+        verify: true,
+        ..Default::default()
+    };
+    let tree_arg = format!("host:{}", tree.display());
+    commands::cp::cp(&disk, &target, &tree_arg, "/tree", options).expect("verified directory copy");
+    assert_eq!(disk_fs::read_file(&disk, &target, "/tree/a.txt", 0, None).expect("cat a"), b"alpha");
+    assert_eq!(disk_fs::read_file(&disk, &target, "/tree/sub/b.bin", 0, None).expect("cat b"), blob);
+
+    let export_arg = format!("host:{}", temp.path().join("export").display());
+    commands::cp::cp(&disk, &target, "/a.txt", &export_arg, options)
+        .expect_err("--verify only applies to copies into the image");
+}
+
+#[test]
+fn disk_diff_image_and_host() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+
+    // Spans several compare chunks
+    let data: Vec<u8> = (0..3 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+    let host = temp.path().join("app");
+    fs::create_dir_all(host.join("lib")).expect("host dir");
+    fs::write(host.join("lib/data.bin"), &data).expect("write host file");
+    fs::write(host.join("readme"), b"same").expect("write host file");
+    disk_fs::mkdir(&disk, &target, "/app/lib", true).expect("mkdir");
+    disk_fs::write_file(&disk, &target, "/app/lib/data.bin", &data, false).expect("write");
+    disk_fs::write_file(&disk, &target, "/app/readme", b"same", false).expect("write");
+
+    let host_arg = |p: &str| format!("host:{}", host.join(p).display());
+    let diff = |a: &str, b: &str, recursive| {
+        commands::diff::diff(&disk, &target, a, b, recursive).expect("diff")
+    };
+    assert!(diff("/app/lib/data.bin", &host_arg("lib/data.bin"), false));
+    assert!(diff("/app", &host_arg(""), true));
+    assert!(diff("/app/readme", "/app/readme", false));
+    commands::diff::diff(&disk, &target, "/app", &host_arg(""), false).expect_err("needs -r");
+
+    let mut changed = data.clone();
+    changed[2 * 1024 * 1024 + 5] ^= 0xff;
+    fs::write(host.join("lib/data.bin"), &changed).expect("modify host file");
+    fs::write(host.join("extra"), b"new").expect("add host file");
+    assert!(!diff("/app/lib/data.bin", &host_arg("lib/data.bin"), false));
+    assert!(!diff("/app", &host_arg(""), true));
+    assert!(!diff("/app/readme", &host_arg("extra"), false));
+}
+
+#[test]
+fn disk_image_lock_blocks_concurrent_access() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+
+    let disk_arg = disk.to_str().expect("utf-8 path");
+    let run = |args: &[&str]| {
+        let argv = ["disk", "--disk", disk_arg].into_iter().chain(args.iter().copied());
+        xtool::disk::run(xtool::disk::DiskCli::try_parse_from(argv).expect("parse args"))
+    };
+    let lock = disk_fs::ImageLock::acquire(&disk).expect("lock");
+    for args in [&["ls", "/"][..], &["mkdir", "/locked"], &["resize", "--grow"]] {
+        let err = run(args).expect_err("image is locked");
+        assert!(err.to_string().contains("in use by another process"), "{err}");
+    }
+
+    drop(lock);
+    run(&["mkdir", "/unlocked"]).expect("lock released");
+    let names: Vec<_> = disk_fs::list_dir(&disk, &target, "/")
+        .expect("list root")
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert!(names.contains(&"unlocked".to_string()));
+    assert!(!names.contains(&"locked".to_string()));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn mkimg_sparse_and_full() {
+    use std::os::unix::fs::MetadataExt;
+
+    const SIZE: u64 = 64 * 1024 * 1024;
+    let temp = TempDir::new().expect("temp dir");
+
+    let sparse = temp.path().join("sparse.img");
+    commands::mkimg::mkimg(&sparse, SIZE, false, true).expect("mkimg");
+    let meta = fs::metadata(&sparse).expect("stat sparse");
+    assert_eq!(meta.len(), SIZE);
+    assert!(meta.blocks() * 512 < SIZE / 16, "allocated {} blocks", meta.blocks());
+
+    let full = temp.path().join("full.img");
+    commands::mkimg::mkimg(&full, SIZE, false, false).expect("mkimg --no-sparse");
+    let meta = fs::metadata(&full).expect("stat full");
+    assert_eq!(meta.len(), SIZE);
+    assert!(meta.blocks() * 512 >= SIZE);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn disk_compress_round_trip() {
+    use std::os::unix::fs::MetadataExt;
+
+    const SIZE: u64 = 32 * 1024 * 1024;
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    let packed = temp.path().join("disk.img.zst");
+    let unpacked = temp.path().join("copy.img");
+
+    commands::mkimg::mkimg(&disk, SIZE, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    disk_fs::mkfs_ext4(&disk, &target, None).expect("mkfs ext4");
+
+    commands::compress::compress(&disk, &packed, 3, false).expect("compress");
+    commands::compress::decompress(&packed, &unpacked, false).expect("decompress");
+    assert_eq!(fs::read(&unpacked).expect("read copy"), fs::read(&disk).expect("read disk"));
+    let meta = fs::metadata(&unpacked).expect("stat copy");
+    assert_eq!(meta.len(), SIZE);
+    assert!(meta.blocks() * 512 < SIZE / 4, "allocated {} blocks", meta.blocks());
+
+    commands::compress::compress(&disk, &disk, 3, true).expect_err("output is the input");
+    commands::compress::decompress(&packed, &temp.path().join("./disk.img.zst"), true)
+        .expect_err("output is the input");
+    assert_eq!(fs::metadata(&disk).expect("stat disk").len(), SIZE);
+}
+
+#[test]
+fn disk_mkfs_fat_types() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    commands::mkimg::mkimg(&disk, 64 * 1024 * 1024, false, true).expect("mkimg");
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+
+    for (fat_type, expected) in [
+        (Some(FatType::Fat12), "fat12"),
+        (Some(FatType::Fat16), "fat16"),
+        (Some(FatType::Fat32), "fat32"),
+        (None, "fat16"),
+    ] {
+        disk_fs::mkfs_fat(&disk, &target, None, fat_type, None).expect("mkfs fat");
+        let probed = disk_fs::probe(&disk, &target).expect("probe").expect("fat detected");
+        assert_eq!(probed.fstype, expected);
+    }
+
+    disk_fs::mkfs_fat(&disk, &target, None, Some(FatType::Fat16), Some(8192)).expect("mkfs 8K");
+    assert_eq!(disk_fs::usage(&disk, &target).expect("du").block_size, 8192);
+    let err = disk_fs::mkfs_fat(&disk, &target, None, None, Some(3000)).unwrap_err();
+    assert!(err.to_string().contains("power of two"), "{err}");
+    let err = disk_fs::mkfs_fat(&disk, &target, None, Some(FatType::Fat32), Some(4096)).unwrap_err();
+    assert!(err.to_string().contains("FAT32 needs 65525 to"), "{err}");
+
+    let small = temp.path().join("small.img");
+    commands::mkimg::mkimg(&small, 16 * 1024 * 1024, false, true).expect("mkimg small");
+    let target = disk_gpt::resolve_partition_target(&small, None, SECTOR).expect("target");
+    let err = disk_fs::mkfs_fat32(&small, &target, None).unwrap_err();
+    assert!(err.to_string().contains("FAT32 needs a partition of at least"), "{err}");
+
+    let large = temp.path().join("large.img");
+    commands::mkimg::mkimg(&large, 2048 * 1024 * 1024, false, true).expect("mkimg large");
+    let target = disk_gpt::resolve_partition_target(&large, None, SECTOR).expect("target");
+    let err = disk_fs::mkfs_fat(&large, &target, None, Some(FatType::Fat12), None).unwrap_err();
+    assert!(err.to_string().contains("FAT12 holds at most 127.0 MiB"), "{err}");
+}
+
 #[test]
 fn disk_gpt_fat32_workflow() {
     let temp = TempDir::new().expect("temp dir");
@@ -51,29 +571,338 @@ fn disk_gpt_fat32_workflow() {
     )
     .expect("write parameter file");
 
-    commands::mkimg::mkimg(&disk, 256 * 1024 * 1024, false).expect("mkimg");
+    commands::mkimg::mkimg(&disk, 256 * 1024 * 1024, false, true).expect("mkimg");
 
-    commands::mkgpt::mkgpt(&disk, &param, 1024 * 1024, true).expect("mkgpt");
+    commands::mkgpt::mkgpt(&disk, &param, 1024 * 1024, SECTOR, true).expect("mkgpt");
 
-    let gdisk = disk_gpt::open_gpt(&disk, false).expect("open gpt");
-    let parts = disk_gpt::map_partitions(&gdisk).expect("map partitions");
+    let gdisk = disk_gpt::open_gpt(&disk, false, SECTOR).expect("open gpt");
+    let parts = disk_gpt::map_partitions(&gdisk, SECTOR).expect("map partitions");
     assert_eq!(parts.len(), 2);
 
-    let boot = disk_gpt::resolve_partition_target(&disk, Some("boot")).expect("part boot");
+    let boot = disk_gpt::resolve_partition_target(&disk, Some("boot"), SECTOR).expect("part boot");
     disk_fs::mkfs_fat32(&disk, &boot, None).expect("mkfs fat32");
 
+    let boot_uuid = gdisk.partitions()[&1].part_guid.to_string();
+    assert_eq!(parts[0].uuid, boot_uuid);
+    let by_uuid = format!("uuid:{}", boot_uuid.to_uppercase());
+    let target = disk_gpt::resolve_partition_target(&disk, Some(&by_uuid), SECTOR).expect("part uuid");
+    assert_eq!(target.offset_bytes, boot.offset_bytes);
+    let err = disk_gpt::resolve_partition_target(&disk, Some("uuid:0-0"), SECTOR).unwrap_err();
+    assert!(err.to_string().contains(&format!("1:{boot_uuid}")));
+
     disk_fs::mkdir(&disk, &boot, "/foo", false).expect("mkdir");
 
-    disk_fs::copy_host_to_image(&disk, &boot, &hello, "/foo/hello.txt", false, false)
+    disk_fs::copy_host_to_image(&disk, &boot, &hello, "/foo/hello.txt", CopyOptions::default())
         .expect("copy host->image");
 
     let data = disk_fs::read_file(&disk, &boot, "/foo/hello.txt", 0, None).expect("cat");
     assert_eq!(data, b"hello fat");
 
+    let entries = disk_fs::list_dir(&disk, &boot, "/foo").expect("ls");
+    let hello_entry = entries.iter().find(|e| e.name == "hello.txt").expect("hello entry");
+    assert_eq!(hello_entry.size, Some(9));
+    assert!(hello_entry.mtime.is_some());
+
+    let st = disk_fs::stat(&disk, &boot, "/foo/hello.txt").expect("stat");
+    assert_eq!((st.kind, st.size), (FileKind::File, 9));
+    assert_eq!(st.mtime, hello_entry.mtime);
+    assert!(st.mode.is_none() && st.uid.is_none() && st.inode.is_none());
+    assert_eq!(disk_fs::stat(&disk, &boot, "/foo").expect("stat dir").kind, FileKind::Dir);
+    assert!(disk_fs::stat(&disk, &boot, "/foo/missing").is_err());
+
+    let usage = disk_fs::usage(&disk, &boot).expect("du");
+    assert_eq!(usage.fstype, "fat32");
+    assert!(usage.used_blocks() > 0);
+
+    let probed = disk_fs::probe(&disk, &boot).expect("probe boot").expect("fat32 detected");
+    assert_eq!(probed.fstype, "fat32");
+    let root = disk_gpt::resolve_partition_target(&disk, Some("root"), SECTOR).expect("part root");
+    assert!(disk_fs::probe(&disk, &root).expect("probe root").is_none());
+
+    let dirs = disk_fs::dir_usage(&disk, &boot, "/").expect("du /");
+    assert_eq!(dirs.last().map(|d| (d.path.as_str(), d.bytes)), Some(("/", 9)));
+
+    commands::cp::append(&disk, &boot, &format!("host:{}", hello.display()), "/foo/log.txt")
+        .expect("append creates");
+    commands::cp::append(&disk, &boot, "/foo/hello.txt", "/foo/log.txt").expect("append");
+    let data = disk_fs::read_file(&disk, &boot, "/foo/log.txt", 0, None).expect("cat log");
+    assert_eq!(data, b"hello fathello fat");
+
     disk_fs::mv(&disk, &boot, "/foo/hello.txt", "/foo/hi.txt", false).expect("mv");
 
     disk_fs::rm(&disk, &boot, "/foo/hi.txt", false).expect("rm");
 
     let entries = disk_fs::list_dir(&disk, &boot, "/foo").expect("ls");
     assert!(!entries.iter().any(|e| e.name == "hi.txt"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn disk_gpt_partition_types() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    let param = temp.path().join("parameter.txt");
+
+    fs::write(
+        &param,
+        "CMDLINE: mtdparts=rk:0x00800000@0x00002000(boot:type=esp),\
+         0x00800000@0x00802000(data:type=EBD0A0A2-B9E5-4433-87C0-68B6B72699C7),\
+         -@0x01002000(root:grow)\n",
+    )
+    .expect("write parameter file");
+
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    commands::mkgpt::mkgpt(&disk, &param, 1024 * 1024, SECTOR, true).expect("mkgpt");
+
+    let gdisk = disk_gpt::open_gpt(&disk, false, SECTOR).expect("open gpt");
+    assert_eq!(gdisk.partitions()[&1].part_type_guid, gpt::partition_types::EFI);
+    assert_eq!(gdisk.partitions()[&2].part_type_guid, gpt::partition_types::BASIC);
+    assert_eq!(gdisk.partitions()[&3].part_type_guid, gpt::partition_types::LINUX_FS);
+
+    assert_eq!(
+        disk_gpt::parse_partition_type("ESP").expect("names are case-insensitive"),
+        gpt::partition_types::EFI
+    );
+    let err = disk_gpt::parse_partition_type("bogus").unwrap_err();
+    assert!(err.to_string().contains("unknown partition type 'bogus'"), "{err}");
+}
+
+#[test]
+fn disk_gpt_toml_layout() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    let layout = temp.path().join("layout.toml");
+
+    fs::write(
+        &layout,
+        r#"
+[[partitions]]
+name = "efi"
+offset = "1M"
+size = "8M"
+type = "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"
+
+[[partitions]]
+name = "rootfs"
+offset = 0x900000
+grow = true
+"#,
+    )
+    .expect("write layout file");
+
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    commands::mkgpt::mkgpt(&disk, &layout, 1024 * 1024, SECTOR, true).expect("mkgpt");
+
+    let gdisk = disk_gpt::open_gpt(&disk, false, SECTOR).expect("open gpt");
+    let parts = disk_gpt::map_partitions(&gdisk, SECTOR).expect("map partitions");
+    assert_eq!(parts.len(), 2);
+    assert_eq!((parts[0].name.as_str(), parts[0].start_bytes), ("efi", 1024 * 1024));
+    assert_eq!(parts[0].size_bytes, 8 * 1024 * 1024);
+    assert_eq!(parts[1].start_bytes, 9 * 1024 * 1024);
+    assert_eq!(
+        gdisk.partitions()[&1].part_type_guid,
+        gpt::partition_types::EFI
+    );
+    assert_eq!(
+        gdisk.partitions()[&2].part_type_guid,
+        gpt::partition_types::LINUX_FS
+    );
+}
+
+#[test]
+fn disk_gpt_resize_grow() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    let param = temp.path().join("parameter.txt");
+
+    fs::write(
+        &param,
+        "CMDLINE: mtdparts=rk:0x00800000@0x00002000(boot),-@0x00802000(root:grow)\n",
+    )
+    .expect("write parameter file");
+
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    commands::mkgpt::mkgpt(&disk, &param, 1024 * 1024, SECTOR, true).expect("mkgpt");
+    let before = disk_gpt::resolve_partition_target(&disk, Some("root"), SECTOR).expect("part root");
+
+    // Simulate writing the image to a larger card.
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&disk)
+        .and_then(|f| f.set_len(64 * 1024 * 1024))
+        .expect("extend disk");
+
+    let boot_before = disk_gpt::resolve_partition_target(&disk, Some("boot"), SECTOR).expect("part boot");
+    commands::resize::resize(&disk, "boot", SECTOR, true, None, true).expect("boot is bounded by root");
+    commands::resize::resize(&disk, "boot", SECTOR, false, Some(16 * 1024 * 1024), true)
+        .expect_err("boot cannot overlap root");
+    commands::resize::resize(&disk, "root", SECTOR, true, None, true).expect("resize --grow");
+
+    let after = disk_gpt::resolve_partition_target(&disk, Some("root"), SECTOR).expect("part root");
+    assert_eq!(after.offset_bytes, before.offset_bytes);
+    assert!(after.size_bytes >= before.size_bytes + 31 * 1024 * 1024);
+
+    let boot_after = disk_gpt::resolve_partition_target(&disk, Some("boot"), SECTOR).expect("part boot");
+    assert_eq!(boot_after.size_bytes, boot_before.size_bytes);
+}
+
+#[test]
+fn disk_gpt_resize_keeps_filesystem_intact() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    let param = temp.path().join("parameter.txt");
+
+    fs::write(
+        &param,
+        "CMDLINE: mtdparts=rk:0x00800000@0x00002000(boot),-@0x00802000(root:grow)\n",
+    )
+    .expect("write parameter file");
+
+    commands::mkimg::mkimg(&disk, 32 * 1024 * 1024, false, true).expect("mkimg");
+    commands::mkgpt::mkgpt(&disk, &param, 1024 * 1024, SECTOR, true).expect("mkgpt");
+    let root = disk_gpt::resolve_partition_target(&disk, Some("root"), SECTOR).expect("part root");
+    disk_fs::mkfs_ext4(&disk, &root, None).expect("mkfs ext4");
+
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&disk)
+        .and_then(|f| f.set_len(64 * 1024 * 1024))
+        .expect("extend disk");
+
+    commands::resize::resize(&disk, "root", SECTOR, true, None, true)
+        .expect_err("ext4 cannot be grown in place");
+    commands::resize::resize(&disk, "root", SECTOR, false, Some(root.size_bytes - 1024 * 1024), true)
+        .expect_err("shrinking below the filesystem size");
+
+    let after = disk_gpt::resolve_partition_target(&disk, Some("root"), SECTOR).expect("part root");
+    assert_eq!(after.size_bytes, root.size_bytes);
+}
+
+#[test]
+fn disk_gpt_4k_sectors() {
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    let param = temp.path().join("parameter.txt");
+    let hello = temp.path().join("hello.txt");
+    fs::write(&hello, b"hello 4k").expect("write host file");
+
+    fs::write(
+        &param,
+        "CMDLINE: mtdparts=rk:0x00800000@0x00002000(boot),-@0x00802000(root:grow)\n",
+    )
+    .expect("write parameter file");
+
+    commands::mkimg::mkimg(&disk, 64 * 1024 * 1024, false, true).expect("mkimg");
+    commands::mkgpt::mkgpt(&disk, &param, 1024 * 1024, 4096, true).expect("mkgpt 4k");
+
+    let gdisk = disk_gpt::open_gpt(&disk, false, 4096).expect("open gpt 4k");
+    let parts = disk_gpt::map_partitions(&gdisk, 4096).expect("map partitions");
+    assert_eq!(parts.len(), 2);
+    for p in &parts {
+        assert_eq!(p.start_bytes, p.first_lba * 4096);
+        assert_eq!(p.size_bytes, (p.last_lba - p.first_lba + 1) * 4096);
+    }
+    assert_eq!(parts[0].start_bytes, 1024 * 1024);
+    assert_eq!(parts[0].size_bytes, 8 * 1024 * 1024);
+
+    disk_gpt::resolve_partition_target(&disk, Some("boot"), SECTOR)
+        .expect_err("4K GPT is not visible with 512-byte sectors");
+
+    let root = disk_gpt::resolve_partition_target(&disk, Some("root"), 4096).expect("part root");
+    assert_eq!(root.offset_bytes, parts[1].start_bytes);
+    disk_fs::mkfs_ext4(&disk, &root, None).expect("mkfs ext4");
+    disk_fs::copy_host_to_image(&disk, &root, &hello, "/hello.txt", CopyOptions::default())
+        .expect("copy host->image");
+    let data = disk_fs::read_file(&disk, &root, "/hello.txt", 0, None).expect("cat");
+    assert_eq!(data, b"hello 4k");
+}
+
+#[test]
+fn disk_exfat_workflow() {
+    use exfat_fs::dir::{entry::fs::FsElement, Root};
+    use exfat_fs::format::{Exfat, FormatVolumeOptionsBuilder};
+    use std::io::Read;
+    use std::time::SystemTime;
+
+    let temp = TempDir::new().expect("temp dir");
+    let disk = temp.path().join("disk.img");
+    let hello = temp.path().join("hello.txt");
+    let big = temp.path().join("big.bin");
+    let big_data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(&hello, b"hello exfat").expect("write host file");
+    fs::write(&big, &big_data).expect("write big host file");
+
+    let size = 32 * 1024 * 1024;
+    commands::mkimg::mkimg(&disk, size, false, true).expect("mkimg");
+    let opts = FormatVolumeOptionsBuilder::default()
+        .pack_bitmap(false)
+        .full_format(false)
+        .dev_size(size)
+        .bytes_per_sector(512)
+        .build()
+        .expect("format options");
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(&disk).expect("open disk");
+    Exfat::try_from::<SystemTime>(opts)
+        .expect("exfat formatter")
+        .write::<SystemTime, _>(&mut file)
+        .expect("format exfat");
+    drop(file);
+
+    let target = disk_gpt::resolve_partition_target(&disk, None, SECTOR).expect("target");
+    let initial = disk_fs::usage(&disk, &target).expect("du empty");
+    disk_fs::mkdir(&disk, &target, "/docs/deep", true).expect("mkdir");
+    disk_fs::copy_host_to_image(&disk, &target, &hello, "/docs/hello.txt", CopyOptions::default())
+        .expect("copy host->image");
+    disk_fs::copy_host_to_image(&disk, &target, &big, "/docs/deep/big.bin", CopyOptions::default())
+        .expect("copy big host->image");
+    // Enough entries to spill the directory into a second cluster.
+    for i in 0..60 {
+        let name = format!("/docs/deep/file-{i:02}.txt");
+        disk_fs::write_file(&disk, &target, &name, name.as_bytes(), false).expect("write many");
+    }
+
+    let entries = disk_fs::list_dir(&disk, &target, "/docs").expect("ls");
+    let hello_entry = entries.iter().find(|e| e.name == "hello.txt").expect("hello entry");
+    assert_eq!(hello_entry.size, Some(11));
+    assert!(hello_entry.mtime.is_some());
+    assert_eq!(disk_fs::list_dir(&disk, &target, "/docs/deep").expect("ls deep").len(), 61);
+
+    let data = disk_fs::read_file(&disk, &target, "/DOCS/Hello.TXT", 0, None).expect("cat");
+    assert_eq!(data, b"hello exfat");
+    let data = disk_fs::read_file(&disk, &target, "/docs/deep/big.bin", 0, None).expect("cat big");
+    assert_eq!(data, big_data);
+    let data = disk_fs::read_file(&disk, &target, "/docs/deep/file-59.txt", 0, None).expect("cat last");
+    assert_eq!(data, b"/docs/deep/file-59.txt");
+
+    let usage = disk_fs::usage(&disk, &target).expect("du");
+    assert_eq!(usage.fstype, "exfat");
+    assert!(usage.used_blocks() > 0);
+
+    // Cross-check the on-disk structures with an independent reader.
+    let mut root = Root::open(fs::File::open(&disk).expect("open disk")).expect("open exfat root");
+    let docs = root
+        .items()
+        .iter()
+        .find_map(|item| match item {
+            FsElement::D(dir) if dir.name() == "docs" => Some(dir.open().expect("open docs")),
+            _ => None,
+        })
+        .expect("docs directory");
+    let mut contents = String::new();
+    for mut item in docs {
+        if let FsElement::F(ref mut file) = item
+            && file.name() == "hello.txt"
+        {
+            file.read_to_string(&mut contents).expect("read hello");
+        }
+    }
+    assert_eq!(contents, "hello exfat");
+
+    disk_fs::mv(&disk, &target, "/docs/hello.txt", "/docs/hi.txt", false).expect("mv");
+    assert_eq!(
+        disk_fs::read_file(&disk, &target, "/docs/hi.txt", 0, None).expect("cat moved"),
+        b"hello exfat"
+    );
+    disk_fs::rm(&disk, &target, "/docs", true).expect("rm -r");
+    assert!(disk_fs::list_dir(&disk, &target, "/").expect("ls root").is_empty());
+    assert_eq!(disk_fs::usage(&disk, &target).expect("du").free_blocks, initial.free_blocks);
+}
@@ -5,6 +5,7 @@ use std::thread;
 use std::time::Duration;
 use xtool::tftp::client::Client;
 use xtool::tftp::client::config::ClientConfig;
+use xtool::tftp::core::options::Rollover;
 use xtool::tftp::server::{Config, Server};
 
 // Use serial_test to prevent port conflicts
@@ -136,6 +137,9 @@ fn test_large_file_transfer() {
     // Upload
     let result = client.put(&client_file, "large.dat");
     assert!(result.is_ok(), "Upload failed: {:?}", result.err());
+    let negotiated = client.last_negotiated().expect("options negotiated");
+    assert_eq!(negotiated.block_size, 8192);
+    assert_eq!(negotiated.timeout, Duration::from_secs(10));
 
     thread::sleep(Duration::from_millis(200));
 
@@ -158,11 +162,12 @@ fn test_multiple_block_sizes() {
     let (server_dir, client_dir) = setup_test_env();
     let test_dir = server_dir.parent().unwrap().to_path_buf();
 
-    // Create test file
-    let test_content = b"Block Size Test Content";
+    // Several blocks even at the largest size, so a silent fallback to 512
+    // byte blocks would still move the data but fail the assertions below
+    let test_content: Vec<u8> = (0..40_000u32).map(|i| (i % 251) as u8).collect();
     let server_file = server_dir.join("blocksize.txt");
     let mut file = File::create(&server_file).unwrap();
-    file.write_all(test_content).unwrap();
+    file.write_all(&test_content).unwrap();
     drop(file);
 
     // Start server
@@ -170,10 +175,11 @@ fn test_multiple_block_sizes() {
     let _server_handle = start_test_server(port, server_dir.clone());
     thread::sleep(Duration::from_millis(500));
 
-    // Test different block sizes
-    for block_size in [512, 1024, 4096, 8192] {
+    // Test different block and window sizes
+    for (block_size, window_size) in [(512, 1), (1024, 2), (4096, 4), (8192, 8)] {
         let config = ClientConfig::new("127.0.0.1".parse().unwrap(), port)
             .with_block_size(block_size)
+            .with_window_size(window_size)
             .with_timeout(Duration::from_secs(5));
 
         let client = Client::new(config).unwrap();
@@ -189,6 +195,19 @@ fn test_multiple_block_sizes() {
 
         let downloaded_content = fs::read(&local_file).unwrap();
         assert_eq!(downloaded_content, test_content);
+
+        let negotiated = client.last_negotiated().expect("options negotiated");
+        assert_eq!(negotiated.block_size, block_size);
+        assert_eq!(negotiated.window_size, window_size);
+        assert_eq!(negotiated.transfer_size, Some(test_content.len() as u64));
+
+        let remote_name = format!("blocksize_up_{}.txt", block_size);
+        client.put(&local_file, &remote_name).unwrap();
+        assert_eq!(fs::read(server_dir.join(&remote_name)).unwrap(), test_content);
+
+        let negotiated = client.last_negotiated().expect("options negotiated");
+        assert_eq!(negotiated.block_size, block_size);
+        assert_eq!(negotiated.window_size, window_size);
     }
 
     cleanup_test_env(&test_dir);
@@ -221,3 +240,456 @@ fn test_nonexistent_file() {
 
     cleanup_test_env(&test_dir);
 }
+
+#[test]
+#[serial]
+fn test_server_rate_limit() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    let test_content = vec![0x5au8; 64 * 1024];
+    fs::write(server_dir.join("paced.bin"), &test_content).unwrap();
+
+    let port = 7005;
+    let root = server_dir.clone();
+    let _server_handle = thread::spawn(move || {
+        let mut config =
+            Config::default().merge_cli("127.0.0.1".to_string(), port, root, false, false);
+        config.max_bps = Some(128 * 1024);
+        let mut server = Server::new(&config).unwrap();
+        server.listen();
+    });
+    thread::sleep(Duration::from_millis(500));
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), port)
+        .with_block_size(1024)
+        .with_timeout(Duration::from_secs(5));
+    let client = Client::new(config).unwrap();
+    let local_file = client_dir.join("paced.bin");
+
+    let started = std::time::Instant::now();
+    let result = client.get("paced.bin", &local_file);
+    let elapsed = started.elapsed();
+
+    assert!(result.is_ok(), "Download failed: {:?}", result.err());
+    assert_eq!(fs::read(&local_file).unwrap(), test_content);
+    // 64 KiB at 128 KiB/s, less the first window's burst
+    assert!(
+        elapsed >= Duration::from_millis(300),
+        "transfer was not paced: {:?}",
+        elapsed
+    );
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_client_retries_unanswered_request() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    // A socket that swallows requests without ever answering
+    let silent = std::net::UdpSocket::bind("127.0.0.1:7006").unwrap();
+    silent
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), 7006)
+        .with_timeout(Duration::from_millis(100))
+        .with_initial_timeout(Duration::from_millis(300))
+        .with_max_retries(2);
+    let client = Client::new(config).unwrap();
+
+    let started = std::time::Instant::now();
+    let err = client
+        .get("missing.bin", &client_dir.join("missing.bin"))
+        .unwrap_err();
+    let elapsed = started.elapsed();
+    assert!(err.to_string().contains("timed out"), "{err}");
+
+    // The initial wait applies to every attempt before the first reply
+    assert!(elapsed >= Duration::from_millis(800), "gave up after {elapsed:?}");
+
+    // The RRQ plus one resend per retry
+    let mut buf = [0u8; 1024];
+    let mut received = 0;
+    while silent.recv_from(&mut buf).is_ok() {
+        received += 1;
+    }
+    assert_eq!(received, 3);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_server_rejects_malformed_request() {
+    let (server_dir, _) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    let port = 7007;
+    let _server_handle = start_test_server(port, server_dir.clone());
+    thread::sleep(Duration::from_millis(500));
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // An RRQ whose blksize option is missing its value
+    socket
+        .send_to(b"\x00\x01test.txt\x00octet\x00blksize", ("127.0.0.1", port))
+        .unwrap();
+
+    let mut buf = [0u8; 516];
+    let (amt, _) = socket.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..4], &[0x00, 0x05, 0x00, 0x04], "{:?}", &buf[..amt]);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_block_number_rollover() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    // More than 65535 blocks of 512 bytes, so block numbers wrap
+    let content: Vec<u8> = (0..65_536 * 512 + 1000u32)
+        .map(|i| ((i / 512) ^ i) as u8)
+        .collect();
+    fs::write(server_dir.join("huge.bin"), &content).unwrap();
+    fs::write(client_dir.join("huge.bin"), &content).unwrap();
+
+    // Servers wrapping to 1 and to 0
+    for (port, rollover) in [(7008, Rollover::Enforce1), (7009, Rollover::Enforce0)] {
+        let dir = server_dir.clone();
+        thread::spawn(move || {
+            let mut config =
+                Config::default().merge_cli("127.0.0.1".to_string(), port, dir, false, false);
+            config.rollover = Some(rollover);
+            Server::new(&config).unwrap().listen();
+        });
+    }
+    thread::sleep(Duration::from_millis(500));
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), 7008)
+        .with_block_size(512)
+        .with_rollover(Rollover::Enforce1);
+    let client = Client::new(config).unwrap();
+    let local_file = client_dir.join("downloaded.bin");
+    client.get("huge.bin", &local_file).unwrap();
+    assert!(fs::read(&local_file).unwrap() == content, "download differs");
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), 7009).with_block_size(512);
+    let client = Client::new(config).unwrap();
+    client
+        .put(&client_dir.join("huge.bin"), "uploaded.bin")
+        .unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert!(
+        fs::read(server_dir.join("uploaded.bin")).unwrap() == content,
+        "upload differs"
+    );
+
+    // A client that forbids rollover gives up instead of corrupting the file
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), 7009)
+        .with_block_size(512)
+        .with_rollover(Rollover::None);
+    let client = Client::new(config).unwrap();
+    let err = client
+        .put(&client_dir.join("huge.bin"), "refused.bin")
+        .unwrap_err();
+    assert!(err.to_string().contains("rollover"), "{err}");
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_windowed_rollover_survives_lost_block_zero() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    // 65538 full blocks of 8 bytes, then a short one. In windows of 3,
+    // block 0 opens a window and block 1 follows it
+    let blocks = 65_539u32;
+    let content: Vec<u8> = (0..(blocks - 1) * 8 + 5).map(|i| ((i / 8) ^ i) as u8).collect();
+
+    // A server that loses block 0 (the one after 65535) the first time
+    let listener = std::net::UdpSocket::bind("127.0.0.1:7016").unwrap();
+    let data = content.clone();
+    let server = thread::spawn(move || {
+        let mut buf = [0u8; 516];
+        let (_, client) = listener.recv_from(&mut buf).unwrap();
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        socket
+            .send_to(b"\x00\x06blksize\x008\x00windowsize\x003\x00", client)
+            .unwrap();
+        let mut dropped = false;
+        let mut acked = 0u32;
+        while acked < blocks {
+            let (amt, _) = socket.recv_from(&mut buf).unwrap();
+            assert_eq!(&buf[..2], &[0x00, 0x04], "{:?}", &buf[..amt]);
+            let wire = u16::from_be_bytes([buf[2], buf[3]]);
+            // ACKs name the last block received, modulo 65536
+            while acked < blocks && (acked as u16) != wire {
+                acked += 1;
+            }
+            let window = acked + 1..=(acked + 3).min(blocks);
+            for index in window {
+                if index == 65_536 && !dropped {
+                    dropped = true;
+                    continue;
+                }
+                let start = (index as usize - 1) * 8;
+                let chunk = &data[start..(start + 8).min(data.len())];
+                let mut packet = vec![0x00, 0x03];
+                packet.extend_from_slice(&(index as u16).to_be_bytes());
+                packet.extend_from_slice(chunk);
+                socket.send_to(&packet, client).unwrap();
+            }
+        }
+        dropped
+    });
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), 7016)
+        .with_block_size(8)
+        .with_window_size(3)
+        .with_timeout(Duration::from_millis(200));
+    let local_file = client_dir.join("windowed.bin");
+    Client::new(config)
+        .unwrap()
+        .get("windowed.bin", &local_file)
+        .unwrap();
+    assert!(server.join().unwrap(), "block 0 was never dropped");
+    assert!(fs::read(&local_file).unwrap() == content, "download differs");
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_read_only_server_refuses_writes() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    let client_file = client_dir.join("upload.txt");
+    fs::write(&client_file, b"should never arrive").unwrap();
+
+    let port = 7010;
+    let dir = server_dir.clone();
+    thread::spawn(move || {
+        let config = Config::default().merge_cli("127.0.0.1".to_string(), port, dir, true, false);
+        Server::new(&config).unwrap().listen();
+    });
+    thread::sleep(Duration::from_millis(500));
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), port)
+        .with_timeout(Duration::from_secs(5))
+        .with_max_retries(0);
+    let client = Client::new(config).unwrap();
+
+    let started = std::time::Instant::now();
+    let err = client.put(&client_file, "upload.txt").unwrap_err();
+    assert!(err.to_string().contains("AccessViolation"), "{err}");
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "refusal took {:?}",
+        started.elapsed()
+    );
+    assert!(!server_dir.join("upload.txt").exists());
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_server_rejects_path_traversal() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    let client_file = client_dir.join("upload.txt");
+    fs::write(&client_file, b"escape attempt").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&client_dir, server_dir.join("link")).unwrap();
+
+    let port = 7011;
+    let _server_handle = start_test_server(port, server_dir.clone());
+    thread::sleep(Duration::from_millis(500));
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), port).with_max_retries(0);
+    let client = Client::new(config).unwrap();
+
+    let mut names = vec!["../escape.txt", "sub/../../escape.txt", "..\\escape.txt"];
+    if cfg!(unix) {
+        names.push("link/escape.txt");
+    }
+    for name in names {
+        let err = client.put(&client_file, name).unwrap_err();
+        assert!(err.to_string().contains("AccessViolation"), "{name}: {err}");
+    }
+    assert!(!test_dir.join("escape.txt").exists());
+    assert!(!client_dir.join("escape.txt").exists());
+
+    // Reads through the link are refused as well
+    if cfg!(unix) {
+        let err = client
+            .get("link/upload.txt", &client_dir.join("copy.txt"))
+            .unwrap_err();
+        assert!(err.to_string().contains("AccessViolation"), "{err}");
+    }
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_transfers_reply_from_bound_address() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+    fs::write(server_dir.join("bound.txt"), b"bound").unwrap();
+
+    // 127.0.0.2 stands in for a second address on a multi-homed host
+    let port = 7012;
+    let root = server_dir.clone();
+    thread::spawn(move || {
+        let config = Config::default().merge_cli("127.0.0.2".to_string(), port, root, false, false);
+        Server::new(&config).unwrap().listen();
+    });
+    thread::sleep(Duration::from_millis(500));
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    socket
+        .send_to(b"\x00\x01bound.txt\x00octet\x00", ("127.0.0.2", port))
+        .unwrap();
+    let mut buf = [0u8; 516];
+    let (amt, src) = socket.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..amt], b"\x00\x03\x00\x01bound");
+    assert_eq!(src.ip().to_string(), "127.0.0.2");
+    assert_ne!(src.port(), port);
+
+    let config = ClientConfig::new("127.0.0.2".parse().unwrap(), port);
+    let local_file = client_dir.join("bound.txt");
+    Client::new(config)
+        .unwrap()
+        .get("bound.txt", &local_file)
+        .unwrap();
+    assert_eq!(fs::read(&local_file).unwrap(), b"bound");
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_client_ignores_reply_from_unexpected_address() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    // Answers requests from another local address, as a server bound to
+    // 0.0.0.0 might on a multi-homed host
+    let listener = std::net::UdpSocket::bind("127.0.0.1:7013").unwrap();
+    let stray = std::net::UdpSocket::bind("127.0.0.2:0").unwrap();
+    let responder = thread::spawn(move || {
+        listener
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let mut buf = [0u8; 516];
+        let mut answered = 0;
+        while let Ok((_, client)) = listener.recv_from(&mut buf) {
+            stray.send_to(b"\x00\x03\x00\x01stray", client).unwrap();
+            answered += 1;
+        }
+        answered
+    });
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), 7013)
+        .with_timeout(Duration::from_millis(200))
+        .with_max_retries(1);
+    let local_file = client_dir.join("stray.txt");
+    let err = Client::new(config)
+        .unwrap()
+        .get("stray.txt", &local_file)
+        .unwrap_err();
+    assert!(err.to_string().contains("timed out"), "{err}");
+    assert!(responder.join().unwrap() > 0);
+    assert!(!fs::read(&local_file).is_ok_and(|data| data == b"stray"));
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_netascii_translates_line_endings() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    // Long enough that a CR LF pair straddles a block boundary
+    let text = b"line\n".repeat(300);
+    let client_file = client_dir.join("unix.txt");
+    fs::write(&client_file, &text).unwrap();
+    fs::write(server_dir.join("served.txt"), &text).unwrap();
+
+    let port = 7014;
+    let _server_handle = start_test_server(port, server_dir.clone());
+    thread::sleep(Duration::from_millis(500));
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), port)
+        .with_block_size(512)
+        .with_timeout(Duration::from_secs(5))
+        .with_mode("netascii");
+    let client = Client::new(config).unwrap();
+
+    client.put(&client_file, "uploaded.txt").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let expected = b"line\r\n".repeat(300);
+    assert_eq!(fs::read(server_dir.join("uploaded.txt")).unwrap(), expected);
+
+    let local_file = client_dir.join("served.txt");
+    client.get("served.txt", &local_file).unwrap();
+    assert_eq!(fs::read(&local_file).unwrap(), expected);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+#[serial]
+fn test_verify_hash_checks_companion_manifest() {
+    let (server_dir, client_dir) = setup_test_env();
+    let test_dir = server_dir.parent().unwrap().to_path_buf();
+
+    // sha256("test")
+    let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    fs::write(server_dir.join("good.bin"), b"test").unwrap();
+    fs::write(server_dir.join("good.bin.sha256"), format!("{hash}  good.bin\n")).unwrap();
+    fs::write(server_dir.join("bad.bin"), b"tset").unwrap();
+    fs::write(server_dir.join("bad.bin.sha256"), format!("{hash}  bad.bin\n")).unwrap();
+    fs::write(server_dir.join("plain.bin"), b"test").unwrap();
+
+    let port = 7015;
+    let _server_handle = start_test_server(port, server_dir.clone());
+    thread::sleep(Duration::from_millis(500));
+
+    let config = ClientConfig::new("127.0.0.1".parse().unwrap(), port)
+        .with_timeout(Duration::from_secs(5))
+        .with_verify_hash(true);
+    let client = Client::new(config).unwrap();
+
+    client.get("good.bin", &client_dir.join("good.bin")).unwrap();
+    assert_eq!(client.last_negotiated().unwrap().transfer_size, Some(4));
+
+    let err = client.get("bad.bin", &client_dir.join("bad.bin")).unwrap_err();
+    assert!(err.to_string().contains("SHA-256 mismatch"), "{err}");
+
+    // No manifest on the server: nothing to check against
+    client.get("plain.bin", &client_dir.join("plain.bin")).unwrap();
+
+    cleanup_test_env(&test_dir);
+}